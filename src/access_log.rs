@@ -0,0 +1,213 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+use time::OffsetDateTime;
+use tracing::info;
+
+use crate::api::{ClientIp, RequestId};
+use crate::redact::redact_query;
+
+/// The format used before it became configurable
+const DEFAULT_FORMAT: &str = "{method} {path} \"{user_agent}\" {ip} {status} {length} {duration_ms}ms";
+const COMMON_FORMAT: &str = "{ip} - - [{time_clf}] \"{method} {path} {protocol}\" {status} {length}";
+const COMBINED_FORMAT: &str =
+    "{ip} - - [{time_clf}] \"{method} {path} {protocol}\" {status} {length} \"{referer}\" \"{user_agent}\"";
+
+/// Placeholders available in custom templates
+const FIELDS: &[&str] = &[
+    "method",
+    "path",
+    "protocol",
+    "user_agent",
+    "referer",
+    "ip",
+    "status",
+    "length",
+    "duration_ms",
+    "time",
+    "time_clf",
+    "request_id",
+];
+
+/// How each request is written to the `access` log target, from `server.access_log_format`
+pub enum AccessLogFormat {
+    Template(Vec<Segment>),
+    Json,
+}
+
+pub enum Segment {
+    Literal(String),
+    Field(&'static str),
+}
+
+impl AccessLogFormat {
+    /// Parses `default`, `common`, `combined`, `json` or a template with `{field}` placeholders.
+    pub fn parse(format: &str) -> Result<Self, String> {
+        let template = match format {
+            "default" => DEFAULT_FORMAT,
+            "common" => COMMON_FORMAT,
+            "combined" => COMBINED_FORMAT,
+            "json" => return Ok(Self::Json),
+            template => template,
+        };
+
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| format!("unclosed '{{' in \"{}\"", template))?;
+            let name = &rest[start + 1..end];
+            let field = FIELDS
+                .iter()
+                .find(|field| **field == name)
+                .ok_or_else(|| format!("unknown placeholder {{{}}} (expected one of: {})", name, FIELDS.join(", ")))?;
+
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+            segments.push(Segment::Field(field));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+
+        Ok(Self::Template(segments))
+    }
+}
+
+/// Values of one request for the log line
+struct Entry {
+    method: String,
+    path: String,
+    protocol: String,
+    user_agent: String,
+    referer: String,
+    ip: String,
+    status: u16,
+    length: String,
+    duration_ms: f64,
+    time: OffsetDateTime,
+    request_id: String,
+}
+
+impl Entry {
+    fn field(&self, name: &str) -> String {
+        match name {
+            "method" => self.method.clone(),
+            "path" => self.path.clone(),
+            "protocol" => self.protocol.clone(),
+            "user_agent" => self.user_agent.clone(),
+            "referer" => self.referer.clone(),
+            "ip" => self.ip.clone(),
+            "request_id" => self.request_id.clone(),
+            "status" => self.status.to_string(),
+            "length" => self.length.clone(),
+            "duration_ms" => format!("{:.3}", self.duration_ms),
+            "time" => self.time.format(&Rfc3339).unwrap_or_default(),
+            "time_clf" => self
+                .time
+                .format(format_description!(
+                    "[day]/[month repr:short]/[year]:[hour]:[minute]:[second] [offset_hour sign:mandatory][offset_minute]"
+                ))
+                .unwrap_or_default(),
+            _ => String::new(),
+        }
+    }
+}
+
+pub async fn access_log(State(format): State<Arc<AccessLogFormat>>, request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let time = OffsetDateTime::now_utc();
+
+    // Extract request info
+    let user_agent = header_or_dash(&request, header::USER_AGENT);
+    let referer = header_or_dash(&request, header::REFERER);
+    let method = request.method().to_string();
+    let uri = request.uri();
+    let path = match uri.query() {
+        Some(q) => format!("{}?{}", uri.path(), redact_query(q)),
+        None => uri.path().to_string(),
+    };
+    let protocol = format!("{:?}", request.version());
+    let ip = request
+        .extensions()
+        .get::<ClientIp>()
+        .and_then(|ClientIp(ip)| *ip)
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|RequestId(id)| id.clone())
+        .unwrap_or_else(|| "-".to_string());
+
+    // Process request
+    let response = next.run(request).await;
+
+    // Extract response info
+    let entry = Entry {
+        method,
+        path,
+        protocol,
+        user_agent,
+        referer,
+        ip,
+        status: response.status().as_u16(),
+        length: response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("-")
+            .to_string(),
+        duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+        time,
+        request_id,
+    };
+
+    let line = match *format {
+        AccessLogFormat::Template(ref segments) => segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Field(name) => entry.field(name),
+            })
+            .collect(),
+        AccessLogFormat::Json => serde_json::json!({
+            "time": entry.field("time"),
+            "ip": entry.ip,
+            "method": entry.method,
+            "path": entry.path,
+            "protocol": entry.protocol,
+            "status": entry.status,
+            "length": entry.length.parse::<u64>().ok(),
+            "duration_ms": entry.duration_ms,
+            "user_agent": entry.user_agent,
+            "referer": entry.referer,
+            "request_id": entry.request_id,
+        })
+        .to_string(),
+    };
+    info!(target: "access", "{}", line);
+
+    response
+}
+
+fn header_or_dash(request: &Request, name: header::HeaderName) -> String {
+    request
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("-")
+        .to_string()
+}
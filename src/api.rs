@@ -1,104 +1,847 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::Duration;
 
 use axum::{
-    extract::{Path, Query, Request, State},
-    http::StatusCode,
+    body::Body,
+    extract::{rejection::JsonRejection, ConnectInfo, Extension, OriginalUri, Path, Query, RawPathParams, Request, State},
+    http::{header, response::Parts, HeaderMap, HeaderValue, StatusCode, Uri},
     middleware::{self, Next},
-    response::{IntoResponse, Response},
-    routing::get,
+    response::{
+        sse::{KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
     Json, Router,
 };
-use log::{info, error, warn};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use tower_http::limit::RequestBodyLimitLayer;
+use tracing::{debug, error, info, info_span, warn, Instrument, Span};
 
-use crate::config::Config;
-use crate::provider;
+use crate::access_log::{access_log, AccessLogFormat};
+use crate::audit::{self, AuditLog, Change};
+use crate::auth::{self, SignatureCheck, SignatureVerifier, SignedRequest};
+use crate::cidr::{self, Cidr};
+use crate::compat;
+use crate::config::{Config, HostRecordType, HostSettings, OfflineAction, ProviderConfig, Scope};
+use crate::cooldown::Cooldowns;
+use crate::credentials;
+use crate::detect::CachedDetector;
+use crate::docker;
+use crate::events::EventBus;
+use crate::hardening;
+use crate::health::ReadinessChecker;
+use crate::hooks::Hooks;
+use crate::hostname;
+use crate::idempotency::{self, IdempotencyCache};
+#[cfg(feature = "kubernetes")]
+use crate::kubernetes;
+use crate::breaker::CircuitBreaker;
+use crate::leader::{self, Leader};
+use crate::maintenance::{Maintenance, MaintenanceReport};
+use crate::messages::Catalog;
+use crate::metrics;
+use crate::notify::Notifier;
+use crate::plan;
+use crate::poll;
+#[cfg(feature = "propagation")]
+use crate::propagation::PropagationChecker;
+use crate::provider::{self, classify, AddressUpdate, ChangeKind, DnsUpdateResult, MxData, Provider, ProviderError, RecordOptions, RecordType, SrvData};
+use crate::queue::UpdateQueue;
+use crate::ratelimit::RateLimiter;
+use crate::reconcile;
+use crate::redact::Redactor;
+use crate::retry::{self, RetryQueue};
+use crate::scripting::{self, Scripts, Verdict};
+use crate::shared::SharedState;
+use crate::state::{HostState, StateStore};
+use crate::status::StatusTracker;
+use crate::throttle::{self, Throttle};
+use crate::udp;
+use crate::ui;
 
 pub struct AppState {
     pub config: Config,
+    /// Provider instances keyed by provider name, sharing one HTTP client
+    pub providers: HashMap<String, Arc<dyn Provider>>,
+    /// Per source IP limiter (`None` when disabled)
+    pub ip_limiter: Option<RateLimiter>,
+    /// Per access key limiter (`None` when disabled)
+    pub key_limiter: Option<RateLimiter>,
+    /// Recent update results replayed to retries (`None` when disabled)
+    pub idempotency: Option<IdempotencyCache>,
+    /// Verifier for HMAC-signed requests
+    pub signatures: SignatureVerifier,
+    /// Per-host serialization of updates
+    pub updates: UpdateQueue,
+    /// Last pushed records, persisted across restarts (`None` without `state_file`)
+    pub state_file: Option<StateStore>,
+    /// `[redis]` state shared with other instances
+    pub shared: Arc<SharedState>,
+    /// Whether this instance runs the `[leader]`-only background tasks, shared by all tenants
+    pub leader: Arc<Leader>,
+    /// Update outcomes reported on `/status`
+    pub status: StatusTracker,
+    /// Record change and failure notifications
+    pub notifier: Notifier,
+    /// Provider call outcomes streamed on `/events`
+    pub events: EventBus,
+    /// `on_change` commands
+    pub hooks: Hooks,
+    /// `[scripting]` policy functions
+    pub scripts: Scripts,
+    /// `[retry_queue]` of updates that failed while their provider was down
+    pub retry_queue: RetryQueue,
+    /// Masks configured secrets in error messages
+    pub redactor: Redactor,
+    /// Cached provider checks for `/health/ready`
+    pub readiness: ReadinessChecker,
+    /// Providers backing off after a rate limit, with their queued updates
+    pub throttle: Throttle,
+    /// Providers not called after repeated outages
+    pub breaker: Arc<CircuitBreaker>,
+    /// `/admin/maintenance` mode, with the updates queued during it
+    pub maintenance: Maintenance,
+    /// Last update per host, for `min_update_interval_secs`
+    pub cooldowns: Cooldowns,
+    /// `[messages]` texts replacing the providers' messages
+    pub messages: Catalog,
+    /// Public IP detection answering `/checkip` for LAN clients (`None` unless `detect.checkip_proxy`)
+    pub checkip: Option<CachedDetector>,
+    /// Record changes written to `[audit].path` (`None` when disabled), shared by all tenants
+    pub audit: Option<Arc<AuditLog>>,
+    /// Post-update DNS check (`None` when disabled)
+    #[cfg(feature = "propagation")]
+    pub propagation: Option<PropagationChecker>,
 }
 
+impl AppState {
+    /// Writes the outcome of a provider call to the audit log, when enabled,
+    /// and hands it to the script's `after_update`.
+    pub(crate) fn audit(&self, action: &'static str, provider: &str, host: &str, old: Option<String>, new: String, result: &Result<DnsUpdateResult>) {
+        let (success, kind, message) = match result {
+            Ok(result) => (result.success, Some(result.change.clone()), result.message.clone()),
+            Err(e) => (false, None, self.redactor.redact(&e.to_string())),
+        };
+        // What the provider replaced beats what this service remembers
+        let old = kind.as_ref().and_then(ChangeKind::old).map(str::to_string).or(old);
+        let change = Change {
+            action,
+            provider,
+            host,
+            old,
+            new,
+            success,
+            change: kind,
+            message: &message,
+        };
+        self.scripts.after_update(&change);
+        self.events.publish(&change);
+        if let Some(ref audit) = self.audit {
+            audit.record(change);
+        }
+    }
+}
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Version of the API's response schema, bumped on breaking changes
+pub const API_VERSION: u32 = 1;
+const API_PREFIX: &str = "/api/v1";
+const API_VERSION_HEADER: &str = "x-api-version";
+
+/// The ID of a request, inserted by the `request_id` middleware
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// The resolved client IP of a request, inserted by the `resolve_client_ip` middleware
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub Option<IpAddr>);
+
 #[derive(Serialize)]
 struct ApiResponse {
     success: bool,
     message: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     record_id: Option<String>,
+    /// Propagation check result, when `[propagation]` is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verified: Option<bool>,
+    /// Set when the provider is rate limited and the update waits for a retry
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    queued: bool,
+    /// What the provider did, unless the update was queued
+    #[serde(skip_serializing_if = "Option::is_none")]
+    change: Option<ChangeReport>,
+}
+
+/// What an update did, e.g. `{"kind": "updated", "old": "1.1.1.1", "new": "1.2.3.4"}`
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ChangeReport {
+    #[serde(flatten)]
+    pub(crate) kind: ChangeKind,
+    /// The record's value afterwards, absent once deleted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) new: Option<String>,
+}
+
+impl ChangeReport {
+    fn of(result: &DnsUpdateResult) -> Self {
+        Self {
+            kind: result.change.clone(),
+            new: result.new.clone(),
+        }
+    }
+
+    fn unchanged(ip: IpAddr) -> Self {
+        Self {
+            kind: ChangeKind::NoChange,
+            new: Some(ip.to_string()),
+        }
+    }
+}
+
+/// What an update request points its host at
+enum UpdateTarget {
+    Ip(IpAddr),
+    /// Several addresses at once, e.g. one per WAN link
+    Ips(Vec<IpAddr>),
+    Cname(String),
+}
+
+#[derive(Serialize)]
+struct RecordsResponse {
+    success: bool,
+    records: Vec<provider::RecordInfo>,
+}
+
+#[derive(Serialize)]
+struct PlanResponse {
+    success: bool,
+    #[serde(flatten)]
+    plan: plan::Plan,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ErrorResponse {
+    pub(crate) success: bool,
+    pub(crate) code: ErrorCode,
+    pub(crate) error: String,
+}
+
+/// Machine-readable reason in error responses, stable across releases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request body is not the expected JSON
+    InvalidRequest,
+    InvalidIp,
+    InvalidHost,
+    RecordTypeNotEnabled,
+    /// `offline=yes` for a host whose `offline` setting is `refuse`
+    OfflineNotEnabled,
+    ProviderNotFound,
+    UnsupportedProvider,
+    /// The provider can't do what was asked, per its capabilities on `/status`
+    UnsupportedOperation,
+    NotFound,
+    Unauthorized,
+    Forbidden,
+    RateLimited,
+    /// A different IP within the host's `min_update_interval_secs`; see `Retry-After`
+    TooSoon,
+    /// The provider rejected its configured credentials
+    ProviderAuthFailed,
+    /// The provider is throttling requests; see `Retry-After`
+    ProviderRateLimited,
+    /// The provider API is unreachable or failing; see `Retry-After`
+    ProviderUnavailable,
+    /// The provider API refused the request for another reason
+    ProviderError,
+    /// Records other than single addresses can't be changed during maintenance; see `Retry-After`
+    Maintenance,
+    /// The record changed elsewhere since it was last written, see `on_conflict`
+    Conflict,
+    InternalError,
+}
+
+/// `Retry-After` sent when the provider did not give one
+const DEFAULT_RETRY_AFTER_SECS: u64 = 60;
+
+/// Body of `POST /api/v1/update`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UpdateRequest {
+    provider: String,
+    host: String,
+    /// Defaults to the address the request comes from
+    ip: Option<IpAddr>,
+    /// `A` or `AAAA`, checked against the IP
+    record_type: Option<String>,
+    /// Overrides the host's configured TTL
+    ttl: Option<u32>,
 }
 
 #[derive(Serialize)]
-struct ErrorResponse {
+struct UpdateResult {
     success: bool,
-    error: String,
+    provider: String,
+    host: String,
+    ip: IpAddr,
+    record_type: &'static str,
+    ttl: u32,
+    /// Whether the record was created or changed
+    changed: bool,
+    /// Held back by the provider's rate limit, to be retried
+    queued: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    change: Option<ChangeReport>,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    record_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verified: Option<bool>,
+}
+
+/// Body of `POST /api/v1/update/srv`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SrvUpdateRequest {
+    provider: String,
+    /// Service name, e.g. `_minecraft._tcp.example.com`
+    name: String,
+    priority: u16,
+    weight: u16,
+    port: u16,
+    target: String,
+    /// Overrides the host's configured TTL
+    ttl: Option<u32>,
+}
+
+/// Body of `POST /api/v1/update/mx`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MxUpdateRequest {
+    provider: String,
+    host: String,
+    priority: u16,
+    target: String,
+    /// Overrides the host's configured TTL
+    ttl: Option<u32>,
+}
+
+/// Content of an SRV or MX update
+enum RecordData {
+    Srv(SrvData),
+    Mx(MxData),
 }
 
 #[derive(Deserialize)]
-struct UpdateQuery {
+struct KeyQuery {
     key: Option<String>,
 }
 
-pub fn create_router(config: Config) -> Router {
-    let state = Arc::new(AppState { config });
+/// Query of `/admin/plan`; without `provider` every provider is planned
+#[derive(Deserialize)]
+struct PlanQuery {
+    provider: Option<String>,
+}
 
-    Router::new()
-        .route("/ddns/{provider}/{host}/{ip}", get(update_dns))
-        .route("/health", get(health_check))
-        .layer(middleware::from_fn(access_log))
-        .with_state(state)
+/// Body of `POST /admin/maintenance`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaintenanceRequest {
+    enabled: bool,
+    /// Shown on `GET /admin/maintenance`
+    reason: Option<String>,
+}
+
+/// Query of `/events`; without `provider` events of every provider are streamed
+#[derive(Deserialize)]
+struct EventsQuery {
+    key: Option<String>,
+    provider: Option<String>,
+}
+
+/// Query of `/ddns/{provider}/{host}?ipv4=...&ipv6=...`; an empty value is
+/// skipped, since routers leave out the address they do not have.
+#[derive(Deserialize)]
+struct DualQuery {
+    key: Option<String>,
+    #[serde(default)]
+    ipv4: String,
+    #[serde(default)]
+    ipv6: String,
+    /// `yes` takes the host offline instead, see [`take_offline`]
+    offline: Option<String>,
 }
 
-async fn access_log(request: Request, next: Next) -> Response {
-    let start = Instant::now();
+/// Builds the HTTP API, with each of `[[tenants]]` under `/t/{tenant}/`. This
+/// also starts background tasks (retries of updates held back by provider
+/// rate limits, and `[reconcile]`, `[[poll]]`, `[kubernetes]`, `[docker]`,
+/// `[retry_queue]`, `[leader]` election, the `[udp]` listener and
+/// `check_credentials` when configured), so it must be called within a Tokio
+/// runtime.
+pub fn create_router(config: Config) -> Result<Router> {
+    let access_log_format = AccessLogFormat::parse(&config.server.access_log_format).map_err(anyhow::Error::msg)?;
+    let audit = AuditLog::open(&config.audit)?.map(Arc::new);
+    let leader = Arc::new(Leader::new(&config)?);
+    leader::spawn(leader.clone());
+    let tenants = config
+        .tenants
+        .iter()
+        .map(|tenant| Ok((tenant.name.clone(), build_state(config.for_tenant(tenant), audit.clone(), leader.clone())?)))
+        .collect::<Result<Vec<_>>>()?;
+    let state = build_state(config, audit, leader)?;
 
-    // Extract request info
-    let method = request.method().clone();
-    let uri = request.uri();
-    let path = match uri.query() {
-        Some(q) => format!("{}?{}", uri.path(), q),
-        None => uri.path().to_string(),
+    let mut router = api_routes(state.clone())
+        .route("/status", get(status))
+        .route("/metrics", get(metrics))
+        .route("/health", get(health_check))
+        .route("/health/ready", get(readiness_check));
+    for (name, tenant) in tenants {
+        // A tenant's hosts and addresses are only shown to its own callers
+        let reports = Router::new()
+            .route("/status", get(status))
+            .route("/metrics", get(metrics))
+            .route_layer(middleware::from_fn_with_state(tenant.clone(), tenant_reader));
+        router = router.nest(&format!("/t/{}", name), api_routes(tenant.clone()).merge(reports).with_state(tenant));
+    }
+
+    if state.config.ui.enabled {
+        router = router.merge(ui::routes(state.clone()));
+    }
+
+    let mut router = router
+        .layer(middleware::from_fn(signed_request))
+        .layer(middleware::from_fn(audit::context))
+        .layer(middleware::from_fn_with_state(state.config.server.max_uri_length, hardening::check_uri))
+        .layer(RequestBodyLimitLayer::new(state.config.server.max_body_bytes))
+        .layer(middleware::from_fn_with_state(Arc::new(access_log_format), access_log))
+        .layer(middleware::from_fn_with_state(state.clone(), resolve_client_ip));
+    if state.config.server.security_headers {
+        router = hardening::security_headers(router);
+    }
+
+    Ok(router.layer(middleware::from_fn(request_id)).with_state(state))
+}
+
+/// Sets up the providers, limits and history for one config and starts its
+/// background tasks.
+fn build_state(config: Config, audit: Option<Arc<AuditLog>>, leader: Arc<Leader>) -> Result<Arc<AppState>> {
+    let providers = provider::create_all(&config)?;
+    let state_file = match config.server.state_file {
+        Some(ref path) => Some(StateStore::load(std::path::Path::new(path))?),
+        None => None,
     };
-    let user_agent = request
+    let status_tracker = StatusTracker::new();
+    for (provider, host, known) in state_file.iter().flat_map(|s| s.entries()) {
+        status_tracker.seed(&provider, &host, known.ip);
+    }
+    let shared = Arc::new(SharedState::new(&config.redis, &config.server)?);
+    let breaker = Arc::new(CircuitBreaker::new(&config));
+
+    let state = Arc::new(AppState {
+        ip_limiter: RateLimiter::per_minute(config.server.rate_limit_per_ip),
+        key_limiter: RateLimiter::per_minute(config.server.rate_limit_per_key),
+        idempotency: IdempotencyCache::new(config.server.idempotency_window_secs, config.server.dedupe_retries),
+        signatures: SignatureVerifier::new(config.server.signature_window_secs),
+        throttle: Throttle::new(),
+        breaker: breaker.clone(),
+        maintenance: Maintenance::new(),
+        cooldowns: Cooldowns::new(),
+        messages: Catalog::new(&config.messages),
+        checkip: match config.detect.checkip_proxy {
+            true => Some(CachedDetector::new(config.detect.clone(), provider::build_client(&config.server, None)?)),
+            false => None,
+        },
+        audit,
+        updates: UpdateQueue::new(
+            config.server.max_concurrent_updates,
+            config
+                .providers
+                .iter()
+                .filter_map(|p| Some((p.name.clone(), p.max_concurrent_updates?)))
+                .collect(),
+            shared.clone(),
+            breaker,
+        ),
+        state_file,
+        shared,
+        leader,
+        status: status_tracker,
+        notifier: Notifier::new(&config, provider::build_client(&config.server, None)?)?,
+        events: EventBus::new(),
+        hooks: Hooks::new(&config.hooks),
+        scripts: Scripts::load(&config.scripting)?,
+        retry_queue: RetryQueue::open(&config.retry_queue)?,
+        redactor: Redactor::new(&config),
+        readiness: ReadinessChecker::new(config.server.readiness_cache_secs),
+        #[cfg(feature = "propagation")]
+        propagation: match config.propagation.enabled {
+            true => Some(PropagationChecker::new(config.propagation.clone(), provider::build_client(&config.server, None)?)?),
+            false => None,
+        },
+        config,
+        providers,
+    });
+
+    reconcile::spawn(state.clone());
+    throttle::spawn(state.clone());
+    retry::spawn(state.clone());
+    credentials::spawn(state.clone());
+    poll::spawn(state.clone())?;
+    #[cfg(feature = "kubernetes")]
+    kubernetes::spawn(state.clone())?;
+    docker::spawn(state.clone())?;
+    udp::spawn(state.clone())?;
+
+    Ok(state)
+}
+
+/// The update, record and status routes answering for `state`'s providers
+/// and tokens.
+fn api_routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    let mut router = Router::new()
+        .route(&format!("{}/update", API_PREFIX), post(update_json))
+        .route(&format!("{}/update/srv", API_PREFIX), post(update_srv))
+        .route(&format!("{}/update/mx", API_PREFIX), post(update_mx));
+    // The unversioned paths predate `/api/v1` and answer exactly like it
+    for prefix in ["", API_PREFIX] {
+        router = router
+            .route(&format!("{}/ddns/{{provider}}/{{host}}", prefix), get(update_dual))
+            .route(&format!("{}/ddns/{{provider}}/{{host}}/{{ip}}", prefix), get(update_dns));
+    }
+    // Only updates are replayed to retries, reads always see the current state
+    router = router.route_layer(middleware::from_fn_with_state(state.clone(), idempotency::replay));
+    for prefix in ["", API_PREFIX] {
+        router = router
+            .route(&format!("{}/dns/{{provider}}/records", prefix), get(list_records))
+            .route(&format!("{}/debug/{{provider}}", prefix), get(debug_state))
+            .route(&format!("{}/ip", prefix), get(echo_ip));
+    }
+    router = router
+        .route("/admin/plan", post(plan_changes))
+        .route("/admin/maintenance", get(maintenance_status).post(set_maintenance))
+        .route("/events", get(events));
+
+    router
+        .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        .route_layer(middleware::from_fn(plain_text))
+        .route_layer(middleware::from_fn_with_state(state.clone(), response_template))
+        .route_layer(middleware::from_fn(api_version))
+        .merge(compat::routes(state))
+}
+
+/// Lets through requests allowed to read all of the tenant's providers, as
+/// its `/status` and `/metrics` cover them all.
+async fn tenant_reader(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<KeyQuery>,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
+    Extension(signed): Extension<SignedRequest>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let provider_configs: Vec<&ProviderConfig> = state.config.providers.iter().collect();
+    for provider_config in &provider_configs {
+        if let Some(response) = reject_source_ip(&state, provider_config, client_ip) {
+            return response;
+        }
+    }
+    if let Some(response) =
+        authorize_providers(&state, &provider_configs, request.headers(), &signed, query.key, &[], Scope::Read).await
+    {
+        return response;
+    }
+    next.run(request).await
+}
+
+/// Tags each request with an ID (the caller's `X-Request-Id` if usable), runs
+/// it inside a span carrying that ID and echoes it in the response.
+async fn request_id(mut request: Request, next: Next) -> Response {
+    let id = request
         .headers()
-        .get("user-agent")
+        .get(REQUEST_ID_HEADER)
         .and_then(|v| v.to_str().ok())
-        .unwrap_or("-")
-        .to_string();
-    let ip = request
+        .filter(|id| is_valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    let span = info_span!("request", id = %id, method = %request.method(), path = request.uri().path());
+    let mut response = next.run(request).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
+}
+
+/// Keeps what an `X-Signature` covers for [`authorize`], reading the body of
+/// signed requests up front (still bounded by `max_body_bytes`).
+async fn signed_request(mut request: Request, next: Next) -> Response {
+    if !request.headers().contains_key("x-signature") {
+        request.extensions_mut().insert(SignedRequest(None));
+        return next.run(request).await;
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, usize::MAX).await else {
+        return (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Json(ErrorResponse {
+                success: false,
+                code: ErrorCode::InvalidRequest,
+                error: "Request body too large".to_string(),
+            }),
+        )
+            .into_response();
+    };
+    // Nested routers such as `/t/{tenant}` see their path without the prefix
+    let uri = parts.extensions.get::<OriginalUri>().map_or(&parts.uri, |OriginalUri(uri)| uri);
+    let target = uri.path_and_query().map_or(uri.path(), |p| p.as_str());
+    let message = auth::canonical_request(parts.method.as_str(), target, &body);
+    parts.extensions.insert(SignedRequest(Some(message)));
+
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
+
+/// Accepts IDs that are safe to log and echo: up to 128 visible ASCII characters.
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Determines the client IP from the TCP peer address (available when served
+/// with `into_make_service_with_connect_info`), or from proxy headers when
+/// that peer is one of the `trusted_proxies`.
+async fn resolve_client_ip(State(state): State<Arc<AppState>>, mut request: Request, next: Next) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let trusted = &state.config.server.trusted_proxies;
+    let forwarded = match peer.is_some_and(|peer| trusted.iter().any(|c| c.contains(peer))) {
+        true => forwarded_ip(request.headers()),
+        false => None,
+    };
+    let ip = forwarded.or(peer);
+    request.extensions_mut().insert(ClientIp(ip));
+
+    next.run(request).await
+}
+
+/// Rejects requests with 429 once the source IP exceeds its rate limit; access
+/// keys are limited by [`authorize`] once they are known to be valid.
+pub(crate) async fn rate_limit(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    if let Some(ref limiter) = state.ip_limiter {
+        if let Some(ClientIp(Some(ip))) = request.extensions().get::<ClientIp>() {
+            let checked = match state.shared.is_enabled() {
+                true => state.shared.check_rate("ip", &ip.to_string(), state.config.server.rate_limit_per_ip).await,
+                false => limiter.check(&ip.to_string()),
+            };
+            if let Err(retry_after) = checked {
+                warn!("Rate limit exceeded for IP: {}", ip);
+                return too_many_requests(retry_after.as_secs() + 1);
+            }
+        }
+    }
+
+    next.run(request).await
+}
+
+/// Rewrites update responses as `OK <ip>` / `ERR <reason>` for clients asking
+/// with `?format=plain` or `Accept: text/plain`; several router firmwares'
+/// DDNS scripts only string-match plain text.
+async fn plain_text(params: RawPathParams, request: Request, next: Next) -> Response {
+    let target = params.iter().find(|(name, _)| *name == "ip").map(|(_, value)| value.to_string());
+    let Some(target) = target.filter(|_| wants_plain_text(&request)) else {
+        return next.run(request).await;
+    };
+
+    let (mut parts, body) = next.run(request).await.into_parts();
+    let text = if parts.status.is_success() {
+        format!("OK {}", target)
+    } else {
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap_or_default();
+        let error = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|body| body["error"].as_str().map(str::to_string));
+        format!("ERR {}", error.unwrap_or_else(|| parts.status.to_string()))
+    };
+
+    parts.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain; charset=utf-8"));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(text))
+}
+
+/// Tags API responses with the schema version: an `X-Api-Version` header, and
+/// an `api_version` field in JSON objects.
+async fn api_version(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(API_VERSION_HEADER, HeaderValue::from(API_VERSION));
+
+    let is_json = response
         .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX).await.unwrap_or_default();
+    let body = match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(serde_json::Value::Object(mut object)) => {
+            object.insert("api_version".to_string(), API_VERSION.into());
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Body::from(serde_json::Value::Object(object).to_string())
+        }
+        _ => Body::from(body),
+    };
+    Response::from_parts(parts, body)
+}
+
+/// Renders `/ddns` update responses with the `response` template of the token
+/// that authorized them, or else of the provider named in the URL, for
+/// clients expecting an exact body. `?format=plain` still answers plain text.
+async fn response_template(State(state): State<Arc<AppState>>, params: RawPathParams, request: Request, next: Next) -> Response {
+    let param = |name: &str| params.iter().find(|(param, _)| *param == name).map(|(_, value)| value.to_string());
+    let (Some(provider), Some(host)) = (param("provider"), param("host")) else {
+        return next.run(request).await;
+    };
+    if wants_plain_text(&request) {
+        return next.run(request).await;
+    }
+
+    // The addresses asked for, or the client's for `/ddns/{provider}/{host}` without any
+    let ip = param("ip").or_else(|| {
+        let query = Query::<DualQuery>::try_from_uri(request.uri()).ok()?.0;
+        let ips: Vec<&str> = [query.ipv4.as_str(), query.ipv6.as_str()].into_iter().filter(|ip| !ip.is_empty()).collect();
+        (!ips.is_empty()).then(|| ips.join(","))
+    });
+    let ip = ip
+        .or_else(|| request.extensions().get::<ClientIp>().and_then(|ClientIp(ip)| *ip).map(|ip| ip.to_string()))
+        .unwrap_or_default();
+
+    let response = next.run(request).await;
+    let (_, actor) = audit::requester();
+    let token = actor
+        .as_deref()
+        .and_then(|actor| actor.strip_prefix("token:"))
+        .and_then(|name| state.config.auth.tokens.iter().find(|token| token.name == name));
+    let template = token
+        .and_then(|token| token.response.as_ref())
+        .or_else(|| state.config.get_provider(&provider).and_then(|p| p.response.as_ref()));
+    let Some(template) = template else {
+        return response;
+    };
+
+    let (mut parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX).await.unwrap_or_default();
+    let body = serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_default();
+    let message = body["message"].as_str().or(body["error"].as_str()).unwrap_or_default();
+    let text = match parts.status.is_success() {
+        true => &template.success,
+        false => &template.failure,
+    }
+    .replace("{status}", parts.status.as_str())
+    .replace("{ip}", &ip)
+    .replace("{host}", &host)
+    .replace("{message}", message);
+
+    if let Ok(content_type) = HeaderValue::from_str(&template.content_type) {
+        parts.headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(text))
+}
+
+fn wants_plain_text(request: &Request) -> bool {
+    let query = request.uri().query().unwrap_or_default();
+    if query.split('&').any(|pair| pair.eq_ignore_ascii_case("format=plain")) {
+        return true;
+    }
+    // Only when JSON is not acceptable as well, so browsers and `*/*` keep JSON
+    let accept = request.headers().get(header::ACCEPT).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    accept.contains("text/plain") && !accept.contains("application/json")
+}
+
+fn too_many_requests(retry_after_secs: u64) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after_secs.to_string())],
+        Json(ErrorResponse {
+            success: false,
+            code: ErrorCode::RateLimited,
+            error: "Rate limit exceeded".to_string(),
+        }),
+    )
+        .into_response()
+}
+
+fn too_soon(host: &str, retry_after_secs: u64) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after_secs.to_string())],
+        Json(ErrorResponse {
+            success: false,
+            code: ErrorCode::TooSoon,
+            error: format!("{} was updated too recently, retry in {}s", host, retry_after_secs),
+        }),
+    )
+        .into_response()
+}
+
+/// Client IP as reported by a reverse proxy (`X-Forwarded-For` / `X-Real-IP`)
+fn forwarded_ip(headers: &HeaderMap) -> Option<IpAddr> {
+    headers
         .get("x-forwarded-for")
         .and_then(|v| v.to_str().ok())
-        .map(|s| s.split(',').next().unwrap_or("-").trim().to_string())
+        .and_then(|s| s.split(',').next())
+        .and_then(|s| s.trim().parse().ok())
         .or_else(|| {
-            request
-                .headers()
+            headers
                 .get("x-real-ip")
                 .and_then(|v| v.to_str().ok())
-                .map(|s| s.to_string())
+                .and_then(|s| s.trim().parse().ok())
         })
-        .unwrap_or_else(|| "-".to_string());
-
-    // Process request
-    let response = next.run(request).await;
+}
 
-    // Extract response info
-    let status = response.status().as_u16();
-    let length = response
-        .headers()
-        .get("content-length")
+/// Token from an `Authorization: Bearer <token>` header
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
         .and_then(|v| v.to_str().ok())
-        .unwrap_or("-");
-
-    let duration = start.elapsed();
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim())
+}
 
-    // Access log format: method path "user-agent" ip status length duration
-    info!(
-        target: "access",
-        "{} {} \"{}\" {} {} {} {:.3}ms",
-        method, path, user_agent, ip, status, length, duration.as_secs_f64() * 1000.0
-    );
+/// The caller's address as this server sees it, for use as an IP echo service:
+/// plain text, or JSON with `?format=json` or `Accept: application/json`.
+async fn echo_ip(Extension(ClientIp(client_ip)): Extension<ClientIp>, headers: HeaderMap, uri: Uri) -> Response {
+    let Some(ip) = client_ip else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                success: false,
+                code: ErrorCode::InternalError,
+                error: "Client address unknown".to_string(),
+            }),
+        )
+            .into_response();
+    };
 
-    response
+    let query = uri.query().unwrap_or_default();
+    let accept = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    if query.split('&').any(|pair| pair.eq_ignore_ascii_case("format=json")) || accept.contains("application/json") {
+        let family = if ip.is_ipv6() { "ipv6" } else { "ipv4" };
+        return Json(serde_json::json!({ "ip": ip, "family": family })).into_response();
+    }
+    ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], format!("{}\n", ip)).into_response()
 }
 
 async fn health_check() -> impl IntoResponse {
@@ -107,21 +850,46 @@ async fn health_check() -> impl IntoResponse {
     }))
 }
 
+/// Verifies every provider's credentials (cached), returning 503 if any fail.
+async fn readiness_check(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let report = state.readiness.check(&state.config, &state.providers, &state.redactor).await;
+    let status = if report.ready {
+        StatusCode::OK
+    } else {
+        warn!(
+            "Readiness check failed for: {:?}",
+            report.providers.iter().filter(|p| !p.ok).map(|p| &p.name).collect::<Vec<_>>()
+        );
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(report))
+}
+
+async fn status(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(state.status.report(&state.config, &state.throttle, &state.breaker, &state.providers))
+}
+
+async fn metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, metrics::CONTENT_TYPE)], metrics::render(&state.status, &state.retry_queue, &state.leader, &state.breaker))
+}
+
 async fn update_dns(
     State(state): State<Arc<AppState>>,
-    Path((provider_name, host, ip)): Path<(String, String, String)>,
-    Query(query): Query<UpdateQuery>,
+    Path((mut provider_name, host, ip)): Path<(String, String, String)>,
+    Query(query): Query<KeyQuery>,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
+    headers: HeaderMap,
+    Extension(signed): Extension<SignedRequest>,
 ) -> impl IntoResponse {
-    // Validate IP address format
-    if !is_valid_ipv4(&ip) {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                success: false,
-                error: format!("Invalid IP address: {}", ip),
-            }),
-        )
-            .into_response();
+    // Validate and normalize the hostname before it is matched or sent anywhere
+    let mut host = match hostname::normalize(&host) {
+        Ok(host) => host,
+        Err(reason) => return invalid_host(&reason),
+    };
+    // A `[scripting]` policy may send the update elsewhere
+    if let Some(response) = route(&state, &mut provider_name, &mut host) {
+        return response;
     }
 
     // Find provider config
@@ -132,6 +900,7 @@ async fn update_dns(
                 StatusCode::NOT_FOUND,
                 Json(ErrorResponse {
                     success: false,
+                    code: ErrorCode::ProviderNotFound,
                     error: format!("Provider not found: {}", provider_name),
                 }),
             )
@@ -139,64 +908,1976 @@ async fn update_dns(
         }
     };
 
-    // Verify access key (if configured)
-    if let Some(ref config_key) = provider_config.key {
-        let request_key = query.key.as_deref().unwrap_or("");
-        if request_key != config_key {
-            warn!("Invalid key for provider: {}", provider_name);
+    // CNAME hosts take a target hostname in place of the IP
+    let cname = provider_config.host_settings(&host).record_type == HostRecordType::Cname;
+    let target = if cname {
+        if let Ok(ip) = ip.parse::<IpAddr>() {
             return (
-                StatusCode::UNAUTHORIZED,
+                StatusCode::BAD_REQUEST,
                 Json(ErrorResponse {
                     success: false,
-                    error: "Invalid key".to_string(),
+                    code: ErrorCode::RecordTypeNotEnabled,
+                    error: format!("Record type {} not enabled for CNAME host: {}", RecordType::for_ip(&ip), host),
                 }),
             )
                 .into_response();
         }
+        match hostname::normalize(&ip) {
+            Ok(target) if !target.starts_with("*.") => UpdateTarget::Cname(target),
+            Ok(_) => return invalid_host("a CNAME target cannot be a wildcard"),
+            Err(reason) => return invalid_host(&format!("CNAME target: {}", reason)),
+        }
+    } else {
+        // Validate IP address format; a comma-separated list sets all of them
+        let mut ips: Vec<IpAddr> = Vec::new();
+        let parsed = ip.split(',').try_for_each(|part| {
+            let ip = part.trim().parse::<IpAddr>().map_err(|_| ())?;
+            if !ips.contains(&ip) {
+                ips.push(ip);
+            }
+            Ok(())
+        });
+        match parsed {
+            Ok(()) if ips.len() == 1 => UpdateTarget::Ip(ips[0]),
+            Ok(()) => UpdateTarget::Ips(ips),
+            Err(()) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse {
+                        success: false,
+                        code: ErrorCode::InvalidIp,
+                        error: format!("Invalid IP address: {}", ip),
+                    }),
+                )
+                    .into_response();
+            }
+        }
+    };
+
+    // Check source IP against allow/deny lists
+    if let Some(response) = reject_source_ip(&state, provider_config, client_ip) {
+        return response;
     }
 
-    // Update DNS record based on provider type
-    let result = match provider_config.provider_type.as_str() {
-        "cloudflare" => provider::cloudflare::update_record(provider_config, &host, &ip).await,
-        _ => {
+    // Verify bearer token or access key
+    if let Some(response) = authorize(&state, provider_config, &headers, &signed, query.key, &[&host], Scope::Write).await {
+        return response;
+    }
+
+    match target {
+        UpdateTarget::Ip(ip) => perform_update(&state, provider_config, &host, ip).await,
+        UpdateTarget::Ips(ips) => perform_set_update(&state, provider_config, &host, &ips).await,
+        UpdateTarget::Cname(target) => perform_cname_update(&state, provider_config, &host, &target).await,
+    }
+}
+
+/// Updates a host's A and AAAA records from one request, as routers that know
+/// both addresses send them. Both addresses are checked against the host before
+/// either record changes; with neither given the request's source IP is used.
+/// With `offline=yes` the host is taken offline instead.
+async fn update_dual(
+    State(state): State<Arc<AppState>>,
+    Path((mut provider_name, host)): Path<(String, String)>,
+    Query(query): Query<DualQuery>,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
+    headers: HeaderMap,
+    Extension(signed): Extension<SignedRequest>,
+) -> Response {
+    let mut host = match hostname::normalize(&host) {
+        Ok(host) => host,
+        Err(reason) => return invalid_host(&reason),
+    };
+    // A `[scripting]` policy may send the update elsewhere
+    if let Some(response) = route(&state, &mut provider_name, &mut host) {
+        return response;
+    }
+
+    let provider_config = match state.config.get_provider(&provider_name) {
+        Some(config) => config,
+        None => {
             return (
-                StatusCode::BAD_REQUEST,
+                StatusCode::NOT_FOUND,
                 Json(ErrorResponse {
                     success: false,
-                    error: format!("Unsupported provider type: {}", provider_config.provider_type),
+                    code: ErrorCode::ProviderNotFound,
+                    error: format!("Provider not found: {}", provider_name),
                 }),
             )
                 .into_response();
         }
     };
 
-    match result {
-        Ok(result) => {
-            info!("DNS update successful: {}", result.message);
-            (
-                StatusCode::OK,
-                Json(ApiResponse {
-                    success: result.success,
-                    message: result.message,
-                    record_id: result.record_id,
-                }),
-            )
-                .into_response()
+    if query.offline.as_deref().is_some_and(|v| v.eq_ignore_ascii_case("yes")) {
+        if let Some(response) = reject_source_ip(&state, provider_config, client_ip) {
+            return response;
         }
-        Err(e) => {
-            error!("DNS update failed: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
+        if let Some(response) = authorize(&state, provider_config, &headers, &signed, query.key, &[&host], Scope::Write).await {
+            return response;
+        }
+        return match take_offline(&state, provider_config, &host).await {
+            Ok(records) => {
+                let results: Vec<_> = records
+                    .into_iter()
+                    .map(|record| {
+                        serde_json::json!({
+                            "ip": record.ip,
+                            "record_type": record.record_type.as_str(),
+                            "success": record.updated.success,
+                            "message": record.updated.message,
+                            "changed": record.updated.changed(),
+                            "change": record.updated.change,
+                            "queued": record.updated.queued.is_some(),
+                        })
+                    })
+                    .collect();
+                Json(serde_json::json!({ "success": true, "host": host, "offline": true, "results": results })).into_response()
+            }
+            Err(response) => response,
+        };
+    }
+
+    let mut ips = Vec::new();
+    for (value, ipv6) in [(query.ipv4.trim(), false), (query.ipv6.trim(), true)] {
+        if value.is_empty() {
+            continue;
+        }
+        match value.parse::<IpAddr>() {
+            Ok(ip) if ip.is_ipv6() == ipv6 && !ip.is_unspecified() => ips.push(ip),
+            _ => return invalid_ip(&format!("Invalid IPv{} address: {}", if ipv6 { 6 } else { 4 }, value)),
+        }
+    }
+    if ips.is_empty() {
+        match client_ip {
+            Some(ip) => ips.push(ip),
+            None => return invalid_ip("No IP address given"),
+        }
+    }
+
+    let settings = provider_config.host_settings(&host);
+    if let Some(response) = ips.iter().find_map(|ip| reject_target_ip(&settings, ip, &host)) {
+        return response;
+    }
+
+    // Check source IP against allow/deny lists
+    if let Some(response) = reject_source_ip(&state, provider_config, client_ip) {
+        return response;
+    }
+
+    // Verify bearer token or access key
+    if let Some(response) = authorize(&state, provider_config, &headers, &signed, query.key, &[&host], Scope::Write).await {
+        return response;
+    }
+
+    let mut results = Vec::new();
+    let mut failure: Option<Parts> = None;
+    for ip in ips {
+        match apply_update(&state, provider_config, &host, ip, None).await {
+            Ok(updated) => results.push(serde_json::json!({
+                "ip": ip,
+                "record_type": RecordType::for_ip(&ip).as_str(),
+                "success": updated.success,
+                "message": updated.message,
+                "changed": updated.changed(),
+                "change": updated.change,
+                "queued": updated.queued.is_some(),
+            })),
+            Err(response) => {
+                let (parts, mut result) = into_json(response).await;
+                result["ip"] = serde_json::json!(ip);
+                result["record_type"] = serde_json::json!(RecordType::for_ip(&ip).as_str());
+                results.push(result);
+                // The first failure decides the status (and keeps its Retry-After)
+                if failure.is_none() {
+                    failure = Some(parts);
+                }
+            }
+        }
+    }
+
+    let body = Json(serde_json::json!({ "success": failure.is_none(), "host": host, "results": results }));
+    match failure {
+        Some(parts) => (parts, body).into_response(),
+        None => (StatusCode::OK, body).into_response(),
+    }
+}
+
+/// JSON update for programmatic clients, authorized by bearer token (or a
+/// signature) so no secret ends up in the URL.
+async fn update_json(
+    State(state): State<Arc<AppState>>,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
+    headers: HeaderMap,
+    Extension(signed): Extension<SignedRequest>,
+    body: Result<Json<UpdateRequest>, JsonRejection>,
+) -> Response {
+    let request = match body {
+        Ok(Json(request)) => request,
+        Err(rejection) => return rejected_body(&rejection),
+    };
+
+    let mut host = match hostname::normalize(request.host.trim()) {
+        Ok(host) => host,
+        Err(reason) => return invalid_host(&reason),
+    };
+    // A `[scripting]` policy may send the update elsewhere
+    let mut provider_name = request.provider.clone();
+    if let Some(response) = route(&state, &mut provider_name, &mut host) {
+        return response;
+    }
+
+    let provider_config = match state.config.get_provider(&provider_name) {
+        Some(config) => config,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
                 Json(ErrorResponse {
                     success: false,
-                    error: format!("DNS update failed: {}", e),
+                    code: ErrorCode::ProviderNotFound,
+                    error: format!("Provider not found: {}", provider_name),
                 }),
             )
-                .into_response()
+                .into_response();
         }
+    };
+
+    let Some(ip) = request.ip.or(client_ip) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                success: false,
+                code: ErrorCode::InvalidIp,
+                error: "No IP address given".to_string(),
+            }),
+        )
+            .into_response();
+    };
+    if let Some(ref record_type) = request.record_type {
+        if !record_type.eq_ignore_ascii_case(RecordType::for_ip(&ip).as_str()) {
+            return invalid_request(&format!("record_type {} does not match IP address {}", record_type, ip));
+        }
+    }
+    if request.ttl == Some(0) {
+        return invalid_request("ttl must be at least 1");
+    }
+
+    // Check source IP against allow/deny lists
+    if let Some(response) = reject_source_ip(&state, provider_config, client_ip) {
+        return response;
+    }
+
+    // Verify bearer token or signature; secrets are not taken from the body
+    if let Some(response) = authorize(&state, provider_config, &headers, &signed, None, &[&host], Scope::Write).await {
+        return response;
+    }
+
+    let ttl = request.ttl.unwrap_or(provider_config.host_settings(&host).ttl);
+    match apply_update(&state, provider_config, &host, ip, Some(ttl)).await {
+        Ok(updated) => (
+            if updated.queued.is_some() { StatusCode::ACCEPTED } else { StatusCode::OK },
+            Json(UpdateResult {
+                success: updated.success,
+                provider: provider_config.name.clone(),
+                host,
+                ip,
+                record_type: RecordType::for_ip(&ip).as_str(),
+                ttl,
+                changed: updated.changed(),
+                queued: updated.queued.is_some(),
+                change: updated.change,
+                message: updated.message,
+                record_id: updated.record_id,
+                verified: updated.verified,
+            }),
+        )
+            .into_response(),
+        Err(response) => response,
+    }
+}
+
+/// JSON SRV update, authorized like `POST /api/v1/update`.
+async fn update_srv(
+    State(state): State<Arc<AppState>>,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
+    headers: HeaderMap,
+    Extension(signed): Extension<SignedRequest>,
+    body: Result<Json<SrvUpdateRequest>, JsonRejection>,
+) -> Response {
+    let request = match body {
+        Ok(Json(request)) => request,
+        Err(rejection) => return rejected_body(&rejection),
+    };
+    let data = RecordData::Srv(SrvData {
+        priority: request.priority,
+        weight: request.weight,
+        port: request.port,
+        target: request.target,
+    });
+    update_record_data(&state, client_ip, &headers, &signed, &request.provider, &request.name, data, request.ttl).await
+}
+
+/// JSON MX update, authorized like `POST /api/v1/update`.
+async fn update_mx(
+    State(state): State<Arc<AppState>>,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
+    headers: HeaderMap,
+    Extension(signed): Extension<SignedRequest>,
+    body: Result<Json<MxUpdateRequest>, JsonRejection>,
+) -> Response {
+    let request = match body {
+        Ok(Json(request)) => request,
+        Err(rejection) => return rejected_body(&rejection),
+    };
+    let data = RecordData::Mx(MxData {
+        priority: request.priority,
+        target: request.target,
+    });
+    update_record_data(&state, client_ip, &headers, &signed, &request.provider, &request.host, data, request.ttl).await
+}
+
+/// Validates and applies an SRV or MX update of `name`.
+#[allow(clippy::too_many_arguments)]
+async fn update_record_data(
+    state: &AppState,
+    client_ip: Option<IpAddr>,
+    headers: &HeaderMap,
+    signed: &SignedRequest,
+    provider_name: &str,
+    name: &str,
+    mut data: RecordData,
+    ttl: Option<u32>,
+) -> Response {
+    let name = match hostname::normalize(name.trim()) {
+        Ok(name) => name,
+        Err(reason) => return invalid_host(&reason),
+    };
+    let target = match data {
+        RecordData::Srv(SrvData { ref mut target, .. }) | RecordData::Mx(MxData { ref mut target, .. }) => target,
+    };
+    match hostname::normalize(target.trim()) {
+        Ok(normalized) if !normalized.starts_with("*.") => *target = normalized,
+        _ => return invalid_request(&format!("target is not a valid hostname: {}", target)),
+    }
+    if ttl == Some(0) {
+        return invalid_request("ttl must be at least 1");
+    }
+
+    let provider_config = match state.config.get_provider(provider_name) {
+        Some(config) => config,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    success: false,
+                    code: ErrorCode::ProviderNotFound,
+                    error: format!("Provider not found: {}", provider_name),
+                }),
+            )
+                .into_response();
+        }
+    };
+
+    if let Some(response) = reject_source_ip(state, provider_config, client_ip) {
+        return response;
+    }
+    if let Some(response) = authorize(state, provider_config, headers, signed, None, &[&name], Scope::Write).await {
+        return response;
+    }
+
+    let Some(provider) = state.providers.get(&provider_config.name) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                success: false,
+                code: ErrorCode::UnsupportedProvider,
+                error: format!("Unsupported provider type: {}", provider_config.provider_type),
+            }),
+        )
+            .into_response();
+    };
+
+    // Only addresses and CNAMEs can be proxied
+    let options = RecordOptions {
+        ttl: ttl.unwrap_or(provider_config.host_settings(&name).ttl),
+        proxied: false,
+    };
+    let (record_type, action, content) = match data {
+        RecordData::Srv(ref srv) => (RecordType::Srv, "srv", srv.to_string()),
+        RecordData::Mx(ref mx) => (RecordType::Mx, "mx", mx.to_string()),
+    };
+    if let Some(response) = unsupported(state, provider_config, record_type, &options) {
+        return response;
+    }
+    if let Some(response) = in_maintenance(state) {
+        return response;
+    }
+    let mut result = state
+        .updates
+        .run(&provider_config.name, &name, &content, &options, || {
+            let span = info_span!("provider.update_data", provider = %provider_config.name, name = %name, action, content = %content);
+            async {
+                match data {
+                    RecordData::Srv(ref srv) => provider.update_srv(&name, srv, &options).await,
+                    RecordData::Mx(ref mx) => provider.update_mx(&name, mx, &options).await,
+                }
+            }
+            .instrument(span)
+        })
+        .await;
+    state.messages.localize(&provider_config.name, &name, &mut result);
+
+    state.audit(action, &provider_config.name, &name, None, content, &result);
+
+    match result {
+        Ok(result) => {
+            info!("DNS update successful: {}", result.message);
+            (
+                StatusCode::OK,
+                Json(ApiResponse {
+                    success: result.success,
+                    change: Some(ChangeReport::of(&result)),
+                    message: result.message,
+                    record_id: result.record_id,
+                    verified: None,
+                    queued: false,
+                }),
+            )
+                .into_response()
+        }
+        Err(e) => failure_response(state, "DNS update failed", &e),
+    }
+}
+
+/// Rejects writing a `record_type` record with `options` when the provider's
+/// capabilities rule it out.
+fn unsupported(
+    state: &AppState,
+    provider_config: &ProviderConfig,
+    record_type: RecordType,
+    options: &RecordOptions,
+) -> Option<Response> {
+    let capabilities = state.providers.get(&provider_config.name)?.capabilities();
+    let error = if !capabilities.supports(record_type) {
+        format!("{} records are not supported by provider {}", record_type, provider_config.name)
+    } else if options.proxied && !capabilities.proxied {
+        format!("Provider {} can't proxy records", provider_config.name)
+    } else {
+        capabilities.check_ttl(options.ttl)?
+    };
+    Some(unsupported_operation(error))
+}
+
+fn unsupported_operation(error: String) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            success: false,
+            code: ErrorCode::UnsupportedOperation,
+            error,
+        }),
+    )
+        .into_response()
+}
+
+/// Refuses a change that can't wait in the maintenance queue.
+fn in_maintenance(state: &AppState) -> Option<Response> {
+    if !state.maintenance.is_active() {
+        return None;
+    }
+    Some(
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, DEFAULT_RETRY_AFTER_SECS.to_string())],
+            Json(ErrorResponse {
+                success: false,
+                code: ErrorCode::Maintenance,
+                error: "Maintenance mode, only single-address updates are accepted".to_string(),
+            }),
+        )
+            .into_response(),
+    )
+}
+
+/// Rejects a JSON body that could not be read, with 413 when it exceeds
+/// `max_body_bytes`.
+fn rejected_body(rejection: &JsonRejection) -> Response {
+    let status = match rejection.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => StatusCode::PAYLOAD_TOO_LARGE,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    (
+        status,
+        Json(ErrorResponse {
+            success: false,
+            code: ErrorCode::InvalidRequest,
+            error: rejection.body_text(),
+        }),
+    )
+        .into_response()
+}
+
+fn invalid_request(error: &str) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            success: false,
+            code: ErrorCode::InvalidRequest,
+            error: error.to_string(),
+        }),
+    )
+        .into_response()
+}
+
+/// Rejects a target address that is missing, malformed or not allowed.
+pub(crate) fn invalid_ip(error: &str) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            success: false,
+            code: ErrorCode::InvalidIp,
+            error: error.to_string(),
+        }),
+    )
+        .into_response()
+}
+
+/// Checks that `host` may point at `ip`: its record types take the address
+/// family, and the address is public unless `allow_private_ips` is set.
+/// Routers sometimes report their LAN address, which must not be published.
+fn reject_target_ip(settings: &HostSettings, ip: &IpAddr, host: &str) -> Option<Response> {
+    if !settings.record_type.allows(ip) {
+        return Some(record_type_not_enabled(ip, host));
+    }
+    let range = cidr::non_public_range(*ip).filter(|_| !settings.allow_private_ips)?;
+    warn!("Refusing to point {} at {} address {}", host, range, ip);
+    Some(invalid_ip(&format!(
+        "{} is a {} address, not allowed for {} (see allow_private_ips)",
+        ip, range, host
+    )))
+}
+
+fn record_type_not_enabled(ip: &IpAddr, host: &str) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            success: false,
+            code: ErrorCode::RecordTypeNotEnabled,
+            error: format!("Record type {} not enabled for host: {}", RecordType::for_ip(ip), host),
+        }),
+    )
+        .into_response()
+}
+
+/// Splits a JSON API response into its parts (without `Content-Length`) and body.
+pub(crate) async fn into_json(response: Response) -> (Parts, serde_json::Value) {
+    let (mut parts, body) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let body = axum::body::to_bytes(body, usize::MAX).await.unwrap_or_default();
+    let body = serde_json::from_slice(&body).unwrap_or_else(|_| serde_json::json!({ "success": false }));
+    (parts, body)
+}
+
+/// Lets `[scripting]`'s `transform_host` and `choose_provider` pick the host
+/// and provider of an update, before it is authorized for them.
+pub(crate) fn route(state: &AppState, provider: &mut String, host: &mut String) -> Option<Response> {
+    let (routed_provider, routed_host) = match state.scripts.route(provider, host) {
+        Ok(routed) => routed,
+        Err(e) => return Some(script_failure(&e)),
+    };
+    if routed_host != *host {
+        debug!("Script routed update of {} to {}", host, routed_host);
+        match hostname::normalize(&routed_host) {
+            Ok(routed) => *host = routed,
+            Err(reason) => return Some(invalid_host(&format!("from transform_host: {}", reason))),
+        }
+    }
+    *provider = routed_provider;
+    None
+}
+
+/// Refuses an update of `host` to `value` that `[scripting]`'s `validate` refuses.
+fn refused_by_script(state: &AppState, provider: &str, host: &str, record_type: RecordType, value: &str) -> Option<Response> {
+    let (client_ip, actor) = audit::requester();
+    let request = scripting::Request {
+        provider,
+        host,
+        record_type: record_type.as_str(),
+        value,
+        client_ip,
+        actor,
+    };
+    match state.scripts.validate(&request) {
+        Ok(Verdict::Allow) => None,
+        Ok(Verdict::Refuse(reason)) => {
+            info!("Script refused update of {} to {}: {}", host, value, reason);
+            Some(
+                (
+                    StatusCode::FORBIDDEN,
+                    Json(ErrorResponse {
+                        success: false,
+                        code: ErrorCode::Forbidden,
+                        error: reason,
+                    }),
+                )
+                    .into_response(),
+            )
+        }
+        Err(e) => Some(script_failure(&e)),
+    }
+}
+
+/// A script that fails stops the update; the details stay in the log.
+fn script_failure(error: &str) -> Response {
+    error!("Script failed: {}", error);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ErrorResponse {
+            success: false,
+            code: ErrorCode::InternalError,
+            error: "policy script failed".to_string(),
+        }),
+    )
+        .into_response()
+}
+
+/// Rejects a hostname that [`hostname::normalize`] refused.
+pub(crate) fn invalid_host(reason: &str) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            success: false,
+            code: ErrorCode::InvalidHost,
+            error: format!("Invalid hostname: {}", reason),
+        }),
+    )
+        .into_response()
+}
+
+/// A successful single-address update
+pub(crate) struct Updated {
+    pub(crate) success: bool,
+    pub(crate) message: String,
+    pub(crate) record_id: Option<String>,
+    pub(crate) verified: Option<bool>,
+    /// What the provider did, `None` while the update waits to be applied
+    pub(crate) change: Option<ChangeReport>,
+    /// Set when held back by the provider's rate limit: the time until it is retried
+    pub(crate) queued: Option<Duration>,
+}
+
+impl Updated {
+    /// An update held back while `provider` is rate limited.
+    fn queued(provider: &str, retry_after: Duration) -> Self {
+        Self {
+            success: true,
+            message: format!(
+                "Provider {} is rate limited, update queued for retry in {}s",
+                provider,
+                retry_after.as_secs().max(1)
+            ),
+            record_id: None,
+            verified: None,
+            change: None,
+            queued: Some(retry_after),
+        }
+    }
+
+    /// An update acknowledged during maintenance, applied once it ends. Clients
+    /// see an unchanged record, so they don't retry.
+    fn maintenance(host: &str, ip: IpAddr) -> Self {
+        Self {
+            success: true,
+            message: format!("Maintenance mode, update of {} to {} queued", host, ip),
+            record_id: None,
+            verified: None,
+            change: None,
+            queued: None,
+        }
+    }
+
+    /// An update that failed while `provider` was down, kept in the retry queue.
+    fn retrying(provider: &str, delay: Duration) -> Self {
+        Self {
+            success: true,
+            message: format!(
+                "Provider {} is unavailable, update queued for retry in {}s",
+                provider,
+                delay.as_secs().max(1)
+            ),
+            record_id: None,
+            verified: None,
+            change: None,
+            queued: Some(delay),
+        }
+    }
+
+    /// Whether the provider's record was created, changed or deleted
+    pub(crate) fn changed(&self) -> bool {
+        self.change.as_ref().is_some_and(|change| change.kind.is_change())
+    }
+}
+
+impl IntoResponse for Updated {
+    fn into_response(self) -> Response {
+        let body = Json(ApiResponse {
+            success: self.success,
+            message: self.message,
+            record_id: self.record_id,
+            verified: self.verified,
+            queued: self.queued.is_some(),
+            change: self.change,
+        });
+        match self.queued {
+            Some(retry_after) => {
+                let secs = retry_after.as_secs().max(1).to_string();
+                (StatusCode::ACCEPTED, [(header::RETRY_AFTER, secs)], body).into_response()
+            }
+            None => (StatusCode::OK, body).into_response(),
+        }
+    }
+}
+
+/// Updates `host` (normalized with [`hostname::normalize`]) to `ip` once the
+/// request has been authorized, recording the outcome on `/status`.
+pub(crate) async fn perform_update(state: &AppState, provider_config: &ProviderConfig, host: &str, ip: IpAddr) -> Response {
+    match apply_update(state, provider_config, host, ip, None).await {
+        Ok(updated) => updated.into_response(),
+        Err(response) => response,
+    }
+}
+
+/// [`perform_update`] for callers that answer in their own format, with the
+/// error response on failure. `ttl` overrides the host's configured TTL.
+pub(crate) async fn apply_update(
+    state: &AppState,
+    provider_config: &ProviderConfig,
+    host: &str,
+    ip: IpAddr,
+    ttl: Option<u32>,
+) -> Result<Updated, Response> {
+    match prepare_update(state, provider_config, host, ip, ttl).await {
+        Prepared::Answered(answer) => answer,
+        Prepared::Call(options) => call_update(state, provider_config, host, ip, ttl, options).await,
+    }
+}
+
+/// [`apply_update`] for several hosts of one provider, e.g. every host of a
+/// `[[poll]]` job, with one answer per update in order. The updates that
+/// reach the provider are sent together, so a provider with a batch API
+/// applies them in one call.
+pub(crate) async fn apply_updates(
+    state: &AppState,
+    provider_config: &ProviderConfig,
+    updates: &[(String, IpAddr)],
+) -> Vec<Result<Updated, Response>> {
+    let mut answers = Vec::with_capacity(updates.len());
+    let mut calls = Vec::new();
+    for (index, (host, ip)) in updates.iter().enumerate() {
+        match prepare_update(state, provider_config, host, *ip, None).await {
+            Prepared::Answered(answer) => answers.push(Some(answer)),
+            Prepared::Call(options) => {
+                answers.push(None);
+                calls.push((index, AddressUpdate { host: host.clone(), ip: *ip, options }));
+            }
+        }
+    }
+
+    match state.providers.get(&provider_config.name) {
+        // With Redis the per-host locks shared with other instances are taken one at a time
+        Some(provider) if calls.len() > 1 && !state.shared.is_enabled() => {
+            let batch: Vec<AddressUpdate> = calls.iter().map(|(_, update)| update.clone()).collect();
+            let hosts: Vec<&str> = batch.iter().map(|update| update.host.as_str()).collect();
+            let span = info_span!("provider.update_records", provider = %provider_config.name, hosts = batch.len());
+            let results = state
+                .updates
+                .run_batch(&provider_config.name, &hosts, || provider.update_records(&batch).instrument(span))
+                .await;
+            for ((index, update), result) in calls.into_iter().zip(results) {
+                let answer = finish_update(state, provider_config, &update.host, update.ip, None, &update.options, result).await;
+                answers[index] = Some(answer);
+            }
+        }
+        _ => {
+            for (index, update) in calls {
+                answers[index] = Some(call_update(state, provider_config, &update.host, update.ip, None, update.options).await);
+            }
+        }
+    }
+    answers.into_iter().flatten().collect()
+}
+
+/// Where an address update stands after the checks that can answer it
+/// without calling the provider.
+enum Prepared {
+    Answered(Result<Updated, Response>),
+    /// The provider must be called with these options
+    Call(RecordOptions),
+}
+
+async fn prepare_update(
+    state: &AppState,
+    provider_config: &ProviderConfig,
+    host: &str,
+    ip: IpAddr,
+    ttl: Option<u32>,
+) -> Prepared {
+    let settings = provider_config.host_settings(host);
+    if let Some(response) = reject_target_ip(&settings, &ip, host) {
+        return Prepared::Answered(Err(response));
+    }
+
+    // Options the provider is called with
+    let options = RecordOptions {
+        ttl: ttl.unwrap_or(settings.ttl),
+        proxied: settings.proxied,
+    };
+    if let Some(response) = unsupported(state, provider_config, RecordType::for_ip(&ip), &options) {
+        return Prepared::Answered(Err(response));
+    }
+    if let Some(response) = refused_by_script(state, &provider_config.name, host, RecordType::for_ip(&ip), &ip.to_string()) {
+        return Prepared::Answered(Err(response));
+    }
+
+    // The state file (or Redis, shared with other instances) remembers what was
+    // last pushed, so unchanged updates skip the provider
+    let known = match state.shared.is_enabled() {
+        true => state.shared.get(&provider_config.name, host).await,
+        false => state.state_file.as_ref().and_then(|s| s.get(&provider_config.name, host)),
+    };
+    if let Some(known) = known {
+        if known.ip == ip && known.ttl == options.ttl && known.proxied == options.proxied {
+            let message = state
+                .messages
+                .message(&provider_config.name, host, &ChangeKind::NoChange, Some(&ip.to_string()))
+                .unwrap_or_else(|| format!("Record already up to date with IP {}", ip));
+            debug!("{} for {} (from saved state)", message, host);
+            let change = ChangeReport::unchanged(ip);
+            state.status.record_success(&provider_config.name, host, ip, None, &change.kind, &message);
+            state.notifier.record_success(&provider_config.name, host, Some(known.ip), ip, &change.kind);
+            return Prepared::Answered(Ok(Updated {
+                success: true,
+                message,
+                record_id: known.record_id,
+                verified: None,
+                change: Some(change),
+                queued: None,
+            }));
+        }
+    }
+
+    // A host updated moments ago keeps its record for a while
+    let interval = Duration::from_secs(settings.min_update_interval_secs);
+    if let Some((elapsed, last_ip)) = state.cooldowns.recent(&provider_config.name, host, interval) {
+        let wait = (interval - elapsed).as_secs().max(1);
+        if last_ip != ip {
+            debug!("Refusing update of {} to {}, updated {}s ago", host, ip, elapsed.as_secs());
+            return Prepared::Answered(Err(too_soon(host, wait)));
+        }
+        let message = state
+            .messages
+            .message(&provider_config.name, host, &ChangeKind::NoChange, Some(&ip.to_string()))
+            .unwrap_or_else(|| format!("Record already up to date with IP {} (updated {}s ago)", ip, elapsed.as_secs()));
+        debug!("{} for {}", message, host);
+        return Prepared::Answered(Ok(Updated {
+            success: true,
+            message,
+            record_id: None,
+            verified: None,
+            change: Some(ChangeReport::unchanged(ip)),
+            queued: None,
+        }));
+    }
+
+    // Nor any provider during maintenance; the latest update per record waits for its end
+    if state.maintenance.defer(&provider_config.name, host, ip, ttl) {
+        debug!("Queued update of {} to {} during maintenance", host, ip);
+        return Prepared::Answered(Ok(Updated::maintenance(host, ip)));
+    }
+
+    // Don't call a provider that asked us to back off; the latest update per host waits
+    if let Some(retry_after) = state.throttle.remaining(&provider_config.name) {
+        debug!("Queued update of {} to {} while {} is rate limited", host, ip, provider_config.name);
+        state.throttle.defer(&provider_config.name, host, ip, ttl);
+        return Prepared::Answered(Ok(Updated::queued(&provider_config.name, retry_after)));
+    }
+
+    Prepared::Call(options)
+}
+
+/// Calls the provider for an update that passed [`prepare_update`].
+async fn call_update(
+    state: &AppState,
+    provider_config: &ProviderConfig,
+    host: &str,
+    ip: IpAddr,
+    ttl: Option<u32>,
+    options: RecordOptions,
+) -> Result<Updated, Response> {
+    let Some(provider) = state.providers.get(&provider_config.name) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                success: false,
+                code: ErrorCode::UnsupportedProvider,
+                error: format!("Unsupported provider type: {}", provider_config.provider_type),
+            }),
+        )
+            .into_response());
+    };
+    let result = state
+        .updates
+        .run(&provider_config.name, host, &ip.to_string(), &options, || {
+            let span = info_span!("provider.update_record", provider = %provider_config.name, host, %ip);
+            provider.update_record(host, ip, &options).instrument(span)
+        })
+        .await;
+    finish_update(state, provider_config, host, ip, ttl, &options, result).await
+}
+
+/// Records the provider's answer to an address update everywhere it goes,
+/// queueing the update again if the provider is rate limited or down.
+async fn finish_update(
+    state: &AppState,
+    provider_config: &ProviderConfig,
+    host: &str,
+    ip: IpAddr,
+    ttl: Option<u32>,
+    options: &RecordOptions,
+    mut result: Result<DnsUpdateResult>,
+) -> Result<Updated, Response> {
+    state.messages.localize(&provider_config.name, host, &mut result);
+    let settings = provider_config.host_settings(host);
+    let old_ip = state.status.last_ip(&provider_config.name, host);
+    state.audit("update", &provider_config.name, host, old_ip.map(|ip| ip.to_string()), ip.to_string(), &result);
+
+    match result {
+        Ok(result) => {
+            info!("DNS update successful: {}", result.message);
+            state.retry_queue.remove(&provider_config.name, host, RecordType::for_ip(&ip));
+            if settings.min_update_interval_secs > 0 {
+                state.cooldowns.record(&provider_config.name, host, ip);
+            }
+            let known = HostState {
+                ip,
+                ttl: options.ttl,
+                proxied: options.proxied,
+                record_id: result.record_id.clone(),
+            };
+            state.shared.set(&provider_config.name, host, &known).await;
+            if let Some(ref store) = state.state_file {
+                store.set(&provider_config.name, host, known);
+            }
+            if result.change.is_change() && settings.update_ptr {
+                update_reverse(state, provider_config, host, &[ip], options.ttl).await;
+            }
+            let verified = check_propagation(state, host, ip, options).await;
+            // After a restart without a state file, the provider still knows the old address
+            let old_ip = old_ip.or_else(|| result.change.old()?.parse().ok());
+            state.status.record_success(&provider_config.name, host, ip, verified, &result.change, &result.message);
+            state.notifier.record_success(&provider_config.name, host, old_ip, ip, &result.change);
+            if result.change.is_change() {
+                state.hooks.on_change(provider_config, host, old_ip, &[ip]);
+            }
+            Ok(Updated {
+                success: result.success,
+                change: Some(ChangeReport::of(&result)),
+                message: result.message,
+                record_id: result.record_id,
+                verified,
+                queued: None,
+            })
+        }
+        Err(e) => {
+            if let Some(ProviderError::RateLimited { retry_after, .. }) = classify(&e) {
+                let retry_after = retry_after.unwrap_or(Duration::from_secs(DEFAULT_RETRY_AFTER_SECS));
+                warn!(
+                    "Provider {} is rate limiting, backing off for {}s: {}",
+                    provider_config.name,
+                    retry_after.as_secs(),
+                    state.redactor.redact(&format!("{:#}", e))
+                );
+                state.throttle.throttle(&provider_config.name, retry_after);
+                state.throttle.defer(&provider_config.name, host, ip, ttl);
+                return Ok(Updated::queued(&provider_config.name, retry_after));
+            }
+
+            let error = state.redactor.redact(&e.to_string());
+            state.status.record_failure(&provider_config.name, host, ip, &error);
+            state.notifier.record_failure(&provider_config.name, host, ip, &error);
+            state.shared.remove(&provider_config.name, host).await;
+            if let Some(ref store) = state.state_file {
+                store.remove(&provider_config.name, host);
+            }
+            // Only an outage is worth waiting out, other errors would fail again
+            if let Some(ProviderError::Unavailable { .. }) = classify(&e) {
+                if let Some(delay) = state.retry_queue.push(&provider_config.name, host, ip, ttl, &error) {
+                    warn!("Update of {} to {} failed, retrying in {}s: {}", host, ip, delay.as_secs(), error);
+                    return Ok(Updated::retrying(&provider_config.name, delay));
+                }
+            } else {
+                state.retry_queue.remove(&provider_config.name, host, RecordType::for_ip(&ip));
+            }
+            Err(failure_response(state, "DNS update failed", &e))
+        }
+    }
+}
+
+/// Reconciles the A/AAAA records of `host` with `ips`. `/status` and failure
+/// notifications report the first address; the state file entry is dropped
+/// since it holds a single address.
+async fn perform_set_update(state: &AppState, provider_config: &ProviderConfig, host: &str, ips: &[IpAddr]) -> Response {
+    let settings = provider_config.host_settings(host);
+    if let Some(response) = ips.iter().find_map(|ip| reject_target_ip(&settings, ip, host)) {
+        return response;
+    }
+
+    let options = RecordOptions {
+        ttl: settings.ttl,
+        proxied: settings.proxied,
+    };
+    if let Some(provider) = state.providers.get(&provider_config.name) {
+        if !provider.capabilities().record_sets {
+            return unsupported_operation(format!("Provider {} can't keep several records per host", provider_config.name));
+        }
+    }
+    if let Some(response) = ips.iter().find_map(|ip| unsupported(state, provider_config, RecordType::for_ip(ip), &options)) {
+        return response;
+    }
+    let content = ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>().join(",");
+    if let Some(response) = refused_by_script(state, &provider_config.name, host, RecordType::for_ip(&ips[0]), &content) {
+        return response;
+    }
+    if let Some(response) = in_maintenance(state) {
+        return response;
+    }
+
+    let mut result = match state.providers.get(&provider_config.name) {
+        Some(provider) => {
+            state
+                .updates
+                .run(&provider_config.name, host, &content, &options, || {
+                    let span = info_span!("provider.update_record_set", provider = %provider_config.name, host, ips = %content);
+                    provider.update_record_set(host, ips, &options).instrument(span)
+                })
+                .await
+        }
+        None => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    success: false,
+                    code: ErrorCode::UnsupportedProvider,
+                    error: format!("Unsupported provider type: {}", provider_config.provider_type),
+                }),
+            )
+                .into_response();
+        }
+    };
+
+    state.shared.remove(&provider_config.name, host).await;
+    if let Some(ref store) = state.state_file {
+        store.remove(&provider_config.name, host);
     }
+    state.messages.localize(&provider_config.name, host, &mut result);
+    state.audit("update_set", &provider_config.name, host, None, content, &result);
+
+    let ip = ips[0];
+    match result {
+        Ok(result) => {
+            info!("DNS update successful: {}", result.message);
+            if result.change.is_change() && settings.update_ptr {
+                update_reverse(state, provider_config, host, ips, options.ttl).await;
+            }
+            if result.change.is_change() {
+                state.hooks.on_change(provider_config, host, None, ips);
+            }
+            state.status.record_success(&provider_config.name, host, ip, None, &result.change, &result.message);
+            // Notifications carry a single address, the first of each set stands in for it
+            let old_ip = match &result.change {
+                ChangeKind::Updated { old: Some(old) } => old.split(',').next().and_then(|ip| ip.trim().parse().ok()),
+                _ => None,
+            };
+            state.notifier.record_success(&provider_config.name, host, old_ip, ip, &result.change);
+            (
+                StatusCode::OK,
+                Json(ApiResponse {
+                    success: result.success,
+                    change: Some(ChangeReport::of(&result)),
+                    message: result.message,
+                    record_id: result.record_id,
+                    verified: None,
+                    queued: false,
+                }),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            let error = state.redactor.redact(&e.to_string());
+            state.status.record_failure(&provider_config.name, host, ip, &error);
+            state.notifier.record_failure(&provider_config.name, host, ip, &error);
+            failure_response(state, "DNS update failed", &e)
+        }
+    }
+}
+
+/// Points the PTR records of `ips` at `host` after its addresses changed
+/// (`update_ptr`). Failures are logged; the forward update already succeeded.
+async fn update_reverse(state: &AppState, provider_config: &ProviderConfig, host: &str, ips: &[IpAddr], ttl: u32) {
+    let Some(provider) = state.providers.get(&provider_config.name) else {
+        return;
+    };
+    if !provider.capabilities().supports(RecordType::Ptr) {
+        warn!("Not updating PTR records of {}: provider {} doesn't support them", host, provider_config.name);
+        return;
+    }
+    let options = RecordOptions { ttl, proxied: false };
+
+    for ip in ips {
+        let span = info_span!("provider.update_ptr", provider = %provider_config.name, host, %ip);
+        let result = provider.update_ptr(*ip, host, &options).instrument(span).await;
+        state.audit("ptr", &provider_config.name, &provider::reverse_name(ip), None, host.to_string(), &result);
+
+        match result {
+            Ok(result) => info!("PTR update successful: {}", result.message),
+            Err(e) => warn!("Failed to update PTR record of {} for {}: {}", ip, host, state.redactor.redact(&format!("{:#}", e))),
+        }
+    }
+}
+
+/// One record of a host taken offline
+pub(crate) struct Offline {
+    pub(crate) record_type: RecordType,
+    /// The parking address, `None` when the records were deleted
+    pub(crate) ip: Option<IpAddr>,
+    pub(crate) updated: Updated,
+}
+
+/// Takes `host` offline as its `offline` setting says: points it at its
+/// `offline_ips`, or deletes its A/AAAA records. Stops at the first failure.
+pub(crate) async fn take_offline(state: &AppState, provider_config: &ProviderConfig, host: &str) -> Result<Vec<Offline>, Response> {
+    let settings = provider_config.host_settings(host);
+    let mut records = Vec::new();
+    match settings.offline {
+        OfflineAction::Refuse => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    success: false,
+                    code: ErrorCode::OfflineNotEnabled,
+                    error: format!("Offline mode not enabled for host: {}", host),
+                }),
+            )
+                .into_response());
+        }
+        OfflineAction::Park => {
+            for &ip in settings.offline_ips {
+                let updated = apply_update(state, provider_config, host, ip, None).await?;
+                records.push(Offline { record_type: RecordType::for_ip(&ip), ip: Some(ip), updated });
+            }
+        }
+        OfflineAction::Delete => {
+            let record_types = match settings.record_type {
+                HostRecordType::A => &[RecordType::A][..],
+                HostRecordType::Aaaa => &[RecordType::Aaaa][..],
+                HostRecordType::Both | HostRecordType::Cname => &[RecordType::A, RecordType::Aaaa][..],
+            };
+            records = delete_records(state, provider_config, host, record_types).await?;
+        }
+    }
+
+    Ok(records)
+}
+
+/// Deletes the `record_types` records of `host`, e.g. for a host going
+/// offline. Stops at the first failure.
+pub(crate) async fn delete_records(
+    state: &AppState,
+    provider_config: &ProviderConfig,
+    host: &str,
+    record_types: &[RecordType],
+) -> Result<Vec<Offline>, Response> {
+    let Some(provider) = state.providers.get(&provider_config.name) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                success: false,
+                code: ErrorCode::UnsupportedProvider,
+                error: format!("Unsupported provider type: {}", provider_config.provider_type),
+            }),
+        )
+            .into_response());
+    };
+    if !provider.capabilities().delete {
+        return Err(unsupported_operation(format!("Provider {} can't delete records", provider_config.name)));
+    }
+    if let Some(response) = in_maintenance(state) {
+        return Err(response);
+    }
+    let settings = provider_config.host_settings(host);
+    let options = RecordOptions {
+        ttl: settings.ttl,
+        proxied: settings.proxied,
+    };
+
+    let mut records = Vec::new();
+    for &record_type in record_types {
+        let mut result = state
+            .updates
+            .run(&provider_config.name, host, &format!("delete {}", record_type), &options, || {
+                let span = info_span!("provider.delete_records", provider = %provider_config.name, host, %record_type);
+                provider.delete_records(host, record_type).instrument(span)
+            })
+            .await;
+        state.messages.localize(&provider_config.name, host, &mut result);
+        state.audit("delete", &provider_config.name, host, None, record_type.to_string(), &result);
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => return Err(failure_response(state, "Deleting records failed", &e)),
+        };
+        info!("{}", result.message);
+        records.push(Offline {
+            record_type,
+            ip: None,
+            updated: Updated {
+                success: result.success,
+                change: Some(ChangeReport::of(&result)),
+                message: result.message,
+                record_id: None,
+                verified: None,
+                queued: None,
+            },
+        });
+    }
+    // The next update has to recreate the records
+    state.shared.remove(&provider_config.name, host).await;
+    if let Some(ref store) = state.state_file {
+        store.remove(&provider_config.name, host);
+    }
+
+    Ok(records)
+}
+
+/// Points `host` at `target` for hosts configured with `record_type = "CNAME"`.
+/// `/status`, the state file and notifications track addresses, so CNAME
+/// updates are only logged.
+async fn perform_cname_update(state: &AppState, provider_config: &ProviderConfig, host: &str, target: &str) -> Response {
+    let settings = provider_config.host_settings(host);
+    let options = RecordOptions {
+        ttl: settings.ttl,
+        proxied: settings.proxied,
+    };
+    if let Some(response) = unsupported(state, provider_config, RecordType::Cname, &options) {
+        return response;
+    }
+    if let Some(response) = refused_by_script(state, &provider_config.name, host, RecordType::Cname, target) {
+        return response;
+    }
+    if let Some(response) = in_maintenance(state) {
+        return response;
+    }
+
+    let mut result = match state.providers.get(&provider_config.name) {
+        Some(provider) => {
+            state
+                .updates
+                .run(&provider_config.name, host, target, &options, || {
+                    let span = info_span!("provider.update_cname", provider = %provider_config.name, host, target);
+                    provider.update_cname(host, target, &options).instrument(span)
+                })
+                .await
+        }
+        None => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    success: false,
+                    code: ErrorCode::UnsupportedProvider,
+                    error: format!("Unsupported provider type: {}", provider_config.provider_type),
+                }),
+            )
+                .into_response();
+        }
+    };
+
+    state.messages.localize(&provider_config.name, host, &mut result);
+    state.audit("cname", &provider_config.name, host, None, target.to_string(), &result);
+
+    match result {
+        Ok(result) => {
+            info!("DNS update successful: {}", result.message);
+            (
+                StatusCode::OK,
+                Json(ApiResponse {
+                    success: result.success,
+                    change: Some(ChangeReport::of(&result)),
+                    message: result.message,
+                    record_id: result.record_id,
+                    verified: None,
+                    queued: false,
+                }),
+            )
+                .into_response()
+        }
+        Err(e) => failure_response(state, "DNS update failed", &e),
+    }
+}
+
+/// Waits for the updated record to be served when `[propagation]` is enabled;
+/// `None` when no check was made.
+#[cfg(feature = "propagation")]
+async fn check_propagation(state: &AppState, host: &str, ip: IpAddr, options: &RecordOptions) -> Option<bool> {
+    // Proxied records resolve to the proxy's addresses, never to `ip`
+    match state.propagation {
+        Some(ref checker) if !options.proxied => Some(checker.verify(host, ip).await),
+        _ => None,
+    }
+}
+
+#[cfg(not(feature = "propagation"))]
+async fn check_propagation(_state: &AppState, _host: &str, _ip: IpAddr, _options: &RecordOptions) -> Option<bool> {
+    None
+}
+
+async fn list_records(
+    State(state): State<Arc<AppState>>,
+    Path(provider_name): Path<String>,
+    Query(query): Query<KeyQuery>,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
+    headers: HeaderMap,
+    Extension(signed): Extension<SignedRequest>,
+) -> impl IntoResponse {
+    // Find provider config
+    let provider_config = match state.config.get_provider(&provider_name) {
+        Some(config) => config,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    success: false,
+                    code: ErrorCode::ProviderNotFound,
+                    error: format!("Provider not found: {}", provider_name),
+                }),
+            )
+                .into_response();
+        }
+    };
+
+    // Check source IP against allow/deny lists
+    if let Some(response) = reject_source_ip(&state, provider_config, client_ip) {
+        return response;
+    }
+
+    // Verify bearer token or access key
+    if let Some(response) = authorize(&state, provider_config, &headers, &signed, query.key, &[], Scope::Read).await {
+        return response;
+    }
+
+    // List records via the provider instance
+    let result = match state.providers.get(&provider_name) {
+        Some(provider) => provider.list_records().await,
+        None => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    success: false,
+                    code: ErrorCode::UnsupportedProvider,
+                    error: format!("Unsupported provider type: {}", provider_config.provider_type),
+                }),
+            )
+                .into_response();
+        }
+    };
+
+    match result {
+        Ok(records) => (
+            StatusCode::OK,
+            Json(RecordsResponse {
+                success: true,
+                records,
+            }),
+        )
+            .into_response(),
+        Err(e) => failure_response(&state, "Listing records failed", &e),
+    }
+}
+
+/// Lists the changes that would bring the providers' A/AAAA records in line
+/// with the configured and last pushed addresses, without making them. The
+/// admin token must be good for every planned provider.
+async fn plan_changes(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<PlanQuery>,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
+    headers: HeaderMap,
+) -> Response {
+    let provider_configs: Vec<&ProviderConfig> = match query.provider {
+        Some(ref name) => match state.config.get_provider(name) {
+            Some(config) => vec![config],
+            None => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(ErrorResponse {
+                        success: false,
+                        code: ErrorCode::ProviderNotFound,
+                        error: format!("Provider not found: {}", name),
+                    }),
+                )
+                    .into_response();
+            }
+        },
+        None => state.config.providers.iter().collect(),
+    };
+
+    if let Some(response) = authorize_admin(&state, client_ip, &headers, &provider_configs).await {
+        return response;
+    }
+
+    let mut plan = plan::plan(&provider_configs, &state.providers, &state.status.known_ips()).await;
+    for failure in &mut plan.errors {
+        warn!("Failed to list records of {} for the plan: {}", failure.provider, state.redactor.redact(&failure.error));
+        failure.error = "listing records failed".to_string();
+    }
+
+    (StatusCode::OK, Json(PlanResponse { success: true, plan })).into_response()
+}
+
+/// Whether maintenance mode is on.
+async fn maintenance_status(
+    State(state): State<Arc<AppState>>,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
+    headers: HeaderMap,
+) -> Response {
+    let providers: Vec<&ProviderConfig> = state.config.providers.iter().collect();
+    if let Some(response) = authorize_admin(&state, client_ip, &headers, &providers).await {
+        return response;
+    }
+    (StatusCode::OK, Json(state.maintenance.report())).into_response()
+}
+
+/// Turns maintenance mode on or off. Turning it off applies the queued
+/// updates in the background.
+async fn set_maintenance(
+    State(state): State<Arc<AppState>>,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
+    headers: HeaderMap,
+    body: Result<Json<MaintenanceRequest>, JsonRejection>,
+) -> Response {
+    let request = match body {
+        Ok(Json(request)) => request,
+        Err(rejection) => return rejected_body(&rejection),
+    };
+    let providers: Vec<&ProviderConfig> = state.config.providers.iter().collect();
+    if let Some(response) = authorize_admin(&state, client_ip, &headers, &providers).await {
+        return response;
+    }
+
+    if request.enabled {
+        warn!("Maintenance mode on{}", request.reason.as_ref().map(|r| format!(": {}", r)).unwrap_or_default());
+        state.maintenance.enable(request.reason);
+        return (StatusCode::OK, Json(state.maintenance.report())).into_response();
+    }
+
+    let queued = state.maintenance.disable();
+    info!("Maintenance mode off, applying {} queued update(s)", queued.len());
+    let report = MaintenanceReport {
+        enabled: false,
+        since: None,
+        reason: None,
+        pending: queued.len(),
+    };
+    let state = state.clone();
+    tokio::spawn(async move {
+        for deferred in queued {
+            let Some(provider_config) = state.config.get_provider(&deferred.provider) else {
+                continue;
+            };
+            let _ = apply_update(&state, provider_config, &deferred.host, deferred.ip, deferred.ttl)
+                .instrument(info_span!("maintenance", provider = %deferred.provider, host = %deferred.host))
+                .await;
+        }
+    });
+    (StatusCode::OK, Json(report)).into_response()
+}
+
+/// Authorizes an `/admin` route acting on `providers`: the source IP must be
+/// allowed for each of them, and only a bearer token from `[auth]` with the
+/// `admin` scope will do, so these routes are closed until one is configured.
+async fn authorize_admin(
+    state: &AppState,
+    client_ip: Option<IpAddr>,
+    headers: &HeaderMap,
+    providers: &[&ProviderConfig],
+) -> Option<Response> {
+    for provider_config in providers {
+        if let Some(response) = reject_source_ip(state, provider_config, client_ip) {
+            return Some(response);
+        }
+    }
+
+    let check = match bearer_token(headers) {
+        Some(token) => {
+            // Hash verification is deliberately slow, keep it off the async workers
+            let auth = state.config.auth.clone();
+            let token = token.to_string();
+            let providers: Vec<String> = providers.iter().map(|p| p.name.clone()).collect();
+            let span = Span::current();
+            tokio::task::spawn_blocking(move || span.in_scope(|| auth::check_admin_token(&auth, &token, &providers)))
+                .await
+                .unwrap_or(auth::TokenCheck::Invalid)
+        }
+        None => auth::TokenCheck::Invalid,
+    };
+
+    let (status, code, error) = match check {
+        auth::TokenCheck::Allowed(name) => {
+            debug!("Admin request authorized by token: {}", name);
+            audit::set_actor(format!("token:{}", name));
+            return None;
+        }
+        auth::TokenCheck::Forbidden(name) => {
+            warn!("Token {} is not allowed to use admin routes", name);
+            (StatusCode::FORBIDDEN, ErrorCode::Forbidden, "Token not allowed for this operation")
+        }
+        auth::TokenCheck::Invalid => {
+            warn!("Admin request without a valid token");
+            (StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, "Admin token required")
+        }
+    };
+    Some(
+        (
+            status,
+            Json(ErrorResponse {
+                success: false,
+                code,
+                error: error.to_string(),
+            }),
+        )
+            .into_response(),
+    )
+}
+
+/// Streams the outcome of every provider call as Server-Sent Events, for the
+/// providers the caller may read.
+async fn events(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<EventsQuery>,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
+    headers: HeaderMap,
+    Extension(signed): Extension<SignedRequest>,
+) -> Response {
+    let provider_configs: Vec<&ProviderConfig> = match query.provider {
+        Some(ref name) => match state.config.get_provider(name) {
+            Some(config) => vec![config],
+            None => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(ErrorResponse {
+                        success: false,
+                        code: ErrorCode::ProviderNotFound,
+                        error: format!("Provider not found: {}", name),
+                    }),
+                )
+                    .into_response();
+            }
+        },
+        None => state.config.providers.iter().collect(),
+    };
+
+    for provider_config in &provider_configs {
+        if let Some(response) = reject_source_ip(&state, provider_config, client_ip) {
+            return response;
+        }
+    }
+    // Once for all providers, a signature can only be used once
+    if let Some(response) =
+        authorize_providers(&state, &provider_configs, &headers, &signed, query.key, &[], Scope::Read).await
+    {
+        return response;
+    }
+
+    let providers = provider_configs.iter().map(|p| p.name.clone()).collect();
+    Sse::new(state.events.subscribe(providers)).keep_alive(KeepAlive::default()).into_response()
+}
+
+/// Logs the full error chain, with secrets masked, and answers with a status
+/// and code for the kind of failure. Provider responses and internal details
+/// never reach the client.
+pub(crate) fn failure_response(state: &AppState, context: &str, e: &anyhow::Error) -> Response {
+    error!("{}: {}", context, state.redactor.redact(&format!("{:#}", e)));
+
+    let (status, code, reason, retry_after) = match classify(e) {
+        Some(ProviderError::InvalidHost(message)) => {
+            // Names the host only, which the client sent itself
+            (StatusCode::BAD_REQUEST, ErrorCode::InvalidHost, message, None)
+        }
+        Some(ProviderError::RecordNotFound(message)) => (StatusCode::NOT_FOUND, ErrorCode::NotFound, message, None),
+        Some(ProviderError::Auth(_)) => (
+            StatusCode::BAD_GATEWAY,
+            ErrorCode::ProviderAuthFailed,
+            "provider rejected the configured credentials".to_string(),
+            None,
+        ),
+        Some(ProviderError::RateLimited { retry_after, .. }) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::ProviderRateLimited,
+            "provider rate limit reached".to_string(),
+            Some(retry_after),
+        ),
+        Some(ProviderError::Unavailable { retry_after, .. }) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::ProviderUnavailable,
+            "provider API unavailable".to_string(),
+            Some(retry_after),
+        ),
+        Some(ProviderError::Conflict(_)) => (
+            StatusCode::CONFLICT,
+            ErrorCode::Conflict,
+            "record was changed elsewhere since it was last written".to_string(),
+            None,
+        ),
+        Some(ProviderError::Api(_)) => (
+            StatusCode::BAD_GATEWAY,
+            ErrorCode::ProviderError,
+            "provider API returned an error".to_string(),
+            None,
+        ),
+        None => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::InternalError,
+            "details in the server log".to_string(),
+            None,
+        ),
+    };
+
+    let body = Json(ErrorResponse {
+        success: false,
+        code,
+        error: format!("{} ({})", context, reason),
+    });
+    match retry_after {
+        Some(retry_after) => {
+            let secs = retry_after.map_or(DEFAULT_RETRY_AFTER_SECS, |d| d.as_secs().max(1));
+            (status, [(header::RETRY_AFTER, secs.to_string())], body).into_response()
+        }
+        None => (status, body).into_response(),
+    }
+}
+
+/// Returns a provider's internal state (e.g. the update log of the `mock` provider).
+async fn debug_state(
+    State(state): State<Arc<AppState>>,
+    Path(provider_name): Path<String>,
+    Query(query): Query<KeyQuery>,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
+    headers: HeaderMap,
+    Extension(signed): Extension<SignedRequest>,
+) -> impl IntoResponse {
+    let provider_config = match state.config.get_provider(&provider_name) {
+        Some(config) => config,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    success: false,
+                    code: ErrorCode::ProviderNotFound,
+                    error: format!("Provider not found: {}", provider_name),
+                }),
+            )
+                .into_response();
+        }
+    };
+
+    if let Some(response) = reject_source_ip(&state, provider_config, client_ip) {
+        return response;
+    }
+
+    if let Some(response) = authorize(&state, provider_config, &headers, &signed, query.key, &[], Scope::Read).await {
+        return response;
+    }
+
+    match state.providers.get(&provider_name).and_then(|p| p.debug_state()) {
+        Some(debug) => (StatusCode::OK, Json(debug)).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                success: false,
+                code: ErrorCode::NotFound,
+                error: format!("Provider has no debug state: {}", provider_name),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+/// Rejects the request with 403 if its source IP is denied, or not allowed,
+/// by the server or provider IP lists. `ip` is the [`ClientIp`], which only
+/// comes from proxy headers when the peer is one of the `trusted_proxies`.
+pub(crate) fn reject_source_ip(state: &AppState, provider_config: &ProviderConfig, ip: Option<IpAddr>) -> Option<Response> {
+    let server = &state.config.server;
+    let has_allowlist = !server.allowed_ips.is_empty() || !provider_config.allowed_ips.is_empty();
+    let has_denylist = !server.denied_ips.is_empty() || !provider_config.denied_ips.is_empty();
+    if !has_allowlist && !has_denylist {
+        return None;
+    }
+
+    let allowed = match ip {
+        Some(ip) => {
+            let denied = server.denied_ips.iter().chain(&provider_config.denied_ips).any(|c| c.contains(ip));
+            let in_list = |list: &[Cidr]| list.is_empty() || list.iter().any(|c| c.contains(ip));
+            !denied && in_list(&server.allowed_ips) && in_list(&provider_config.allowed_ips)
+        }
+        // Fail closed when an allowlist is configured but the source is unknown
+        None => !has_allowlist,
+    };
+
+    if allowed {
+        return None;
+    }
+
+    warn!(
+        "Source IP {} not allowed for provider: {}",
+        ip.map(|ip| ip.to_string()).unwrap_or_else(|| "-".to_string()),
+        provider_config.name
+    );
+    Some(
+        (
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                success: false,
+                code: ErrorCode::Forbidden,
+                error: "Source IP not allowed".to_string(),
+            }),
+        )
+            .into_response(),
+    )
+}
+
+/// Authorizes a request via a bearer token from `[auth]` or an HMAC signature
+/// when one is sent, falling back to the `key` / `key_hash` of the host blocks
+/// matching `hosts`, or else the provider's. A request for several hosts is
+/// authorized once, for all of them, so its signature is only used up once.
+/// Returns the rejection response when the request is not allowed, or is over
+/// the per-key rate limit.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn authorize(
+    state: &AppState,
+    provider_config: &ProviderConfig,
+    headers: &HeaderMap,
+    signed: &SignedRequest,
+    request_key: Option<String>,
+    hosts: &[&str],
+    scope: Scope,
+) -> Option<Response> {
+    authorize_providers(state, &[provider_config], headers, signed, request_key, hosts, scope).await
+}
+
+/// Like [`authorize`], for a request reaching several providers at once: the
+/// credentials have to be good for all of them and are checked, and counted
+/// against the rate limit, only once.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn authorize_providers(
+    state: &AppState,
+    provider_configs: &[&ProviderConfig],
+    headers: &HeaderMap,
+    signed: &SignedRequest,
+    request_key: Option<String>,
+    hosts: &[&str],
+    scope: Scope,
+) -> Option<Response> {
+    let checked = authenticate(state, provider_configs, headers, signed, request_key, hosts, scope).await;
+    // Refused credentials count against the client instead, so making up
+    // keys doesn't buy fresh buckets
+    let bucket = match &checked {
+        Ok(identity) => identity.clone(),
+        Err(_) => audit::requester().0.map(|ip| format!("ip:{}", ip)),
+    };
+    if let (Some(limiter), Some(bucket)) = (&state.key_limiter, bucket) {
+        let checked = match state.shared.is_enabled() {
+            true => state.shared.check_rate("key", &bucket, state.config.server.rate_limit_per_key).await,
+            false => limiter.check(&bucket),
+        };
+        if let Err(retry_after) = checked {
+            warn!("Rate limit exceeded for key on provider: {}", provider_names(provider_configs));
+            return Some(too_many_requests(retry_after.as_secs() + 1));
+        }
+    }
+    checked.err()
+}
+
+/// Checks the credentials of a request, returning what authenticated it for
+/// the per-key rate limit: the token or key, or nothing for signed and
+/// anonymous requests.
+#[allow(clippy::too_many_arguments)]
+async fn authenticate(
+    state: &AppState,
+    provider_configs: &[&ProviderConfig],
+    headers: &HeaderMap,
+    signed: &SignedRequest,
+    request_key: Option<String>,
+    hosts: &[&str],
+    scope: Scope,
+) -> Result<Option<String>, Response> {
+    if let Some(token) = bearer_token(headers) {
+        // Hash verification is deliberately slow, keep it off the async workers
+        let auth = state.config.auth.clone();
+        let token = token.to_string();
+        let providers: Vec<String> = provider_configs.iter().map(|p| p.name.clone()).collect();
+        let hosts: Vec<String> = hosts.iter().map(|h| h.to_string()).collect();
+        let span = Span::current();
+        let check = tokio::task::spawn_blocking(move || {
+            span.in_scope(|| auth::check_token(&auth, &token, &providers, &hosts, scope))
+        })
+        .await
+        .unwrap_or(auth::TokenCheck::Invalid);
+
+        return match check {
+            auth::TokenCheck::Allowed(name) => {
+                debug!("Request authorized by token: {}", name);
+                audit::set_actor(format!("token:{}", name));
+                Ok(Some(format!("token:{}", name)))
+            }
+            auth::TokenCheck::Forbidden(name) => {
+                warn!("Token {} is not allowed to access provider: {}", name, provider_names(provider_configs));
+                Err(
+                    (
+                        StatusCode::FORBIDDEN,
+                        Json(ErrorResponse {
+                            success: false,
+                            code: ErrorCode::Forbidden,
+                            error: "Token not allowed for this operation".to_string(),
+                        }),
+                    )
+                        .into_response(),
+                )
+            }
+            auth::TokenCheck::Invalid => {
+                warn!("Invalid token for provider: {}", provider_names(provider_configs));
+                Err(
+                    (
+                        StatusCode::UNAUTHORIZED,
+                        Json(ErrorResponse {
+                            success: false,
+                            code: ErrorCode::Unauthorized,
+                            error: "Invalid token".to_string(),
+                        }),
+                    )
+                        .into_response(),
+                )
+            }
+        };
+    }
+
+    let credentials: Vec<(Option<&str>, Option<&str>, String)> = provider_configs
+        .iter()
+        .flat_map(|provider_config| match hosts {
+            [] => vec![(
+                provider_config.key.as_deref(),
+                provider_config.key_hash.as_deref(),
+                format!("{}/*", provider_names(provider_configs)),
+            )],
+            hosts => hosts
+                .iter()
+                .map(|host| {
+                    let settings = provider_config.host_settings(host);
+                    (settings.key, settings.key_hash, format!("{}/{}", provider_config.name, host))
+                })
+                .collect(),
+        })
+        .collect();
+
+    if let Some(signature) = headers.get("x-signature").and_then(|v| v.to_str().ok()) {
+        let timestamp = headers
+            .get("x-timestamp")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        // Signatures need the shared secret itself, so only a plaintext `key`
+        // works, and one signature can only be made with one key
+        let keys: Option<Vec<&str>> = credentials.iter().map(|&(key, _, _)| key).collect();
+        let check = match (keys, &signed.0) {
+            (Some(keys), Some(message)) if keys.iter().all(|key| *key == keys[0]) => {
+                state.signatures.verify(keys[0], message, timestamp, signature)
+            }
+            _ => SignatureCheck::Invalid,
+        };
+
+        if check == SignatureCheck::Valid {
+            audit::set_actor("signature");
+            return Ok(None);
+        }
+
+        warn!("Rejected signed request for provider {}: {:?}", provider_names(provider_configs), check);
+        let error = match check {
+            SignatureCheck::Expired => "Signature timestamp outside allowed window",
+            SignatureCheck::Replayed => "Signature already used",
+            _ => "Invalid signature",
+        };
+        return Err(
+            (
+                StatusCode::UNAUTHORIZED,
+                Json(ErrorResponse {
+                    success: false,
+                    code: ErrorCode::Unauthorized,
+                    error: error.to_string(),
+                }),
+            )
+                .into_response(),
+        );
+    }
+
+    if provider_configs.iter().any(|p| p.require_signature) {
+        warn!("Unsigned request for provider requiring signatures: {}", provider_names(provider_configs));
+        return Err(
+            (
+                StatusCode::UNAUTHORIZED,
+                Json(ErrorResponse {
+                    success: false,
+                    code: ErrorCode::Unauthorized,
+                    error: "Signature required".to_string(),
+                }),
+            )
+                .into_response(),
+        );
+    }
+
+    if credentials.iter().all(|(key, key_hash, _)| key.is_none() && key_hash.is_none()) {
+        audit::set_actor("anonymous");
+        return Ok(None);
+    }
+
+    let credentials: Vec<(Option<String>, Option<String>, String)> = credentials
+        .into_iter()
+        .map(|(key, key_hash, owner)| (key.map(str::to_string), key_hash.map(str::to_string), owner))
+        .collect();
+    let identity = request_key.as_ref().map(|key| format!("key:{}", key));
+    let span = Span::current();
+    let valid = tokio::task::spawn_blocking(move || {
+        span.in_scope(|| {
+            credentials.iter().all(|(key, key_hash, owner)| {
+                auth::verify_key(key.as_deref(), key_hash.as_deref(), request_key.as_deref(), owner)
+            })
+        })
+    })
+    .await
+    .unwrap_or(false);
+
+    if valid {
+        audit::set_actor("key");
+        return Ok(identity);
+    }
+
+    warn!("Invalid key for provider: {}", provider_names(provider_configs));
+    Err(
+        (
+            StatusCode::UNAUTHORIZED,
+            Json(ErrorResponse {
+                success: false,
+                code: ErrorCode::Unauthorized,
+                error: "Invalid key".to_string(),
+            }),
+        )
+            .into_response(),
+    )
 }
 
-fn is_valid_ipv4(ip: &str) -> bool {
-    ip.parse::<std::net::Ipv4Addr>().is_ok()
+fn provider_names(provider_configs: &[&ProviderConfig]) -> String {
+    provider_configs.iter().map(|p| p.name.as_str()).collect::<Vec<_>>().join(", ")
 }
@@ -0,0 +1,238 @@
+//! Append-only audit log of record changes, one JSON object per line:
+//! who asked (token, key or signature), for which host, from which old to
+//! which new value, and what the provider answered.
+//!
+//! With `hash_chain`, each line carries the hash of the line before it and
+//! its own, so removing or editing lines breaks the chain; with a `key` the
+//! hashes are HMAC-SHA256 signatures only the key holder can recompute.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::Request,
+    middleware::Next,
+    response::Response,
+};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::error;
+
+use crate::api::{ClientIp, RequestId};
+use crate::config::AuditConfig;
+use crate::provider::ChangeKind;
+
+/// `prev_hash` of the first line of a chain
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+tokio::task_local! {
+    /// The request an update is made for, set around each request's handler
+    static REQUEST: RequestContext;
+}
+
+struct RequestContext {
+    request_id: Option<String>,
+    client_ip: Option<IpAddr>,
+    path: String,
+    /// Who the request was authorized as, filled in by `authorize`
+    actor: Mutex<Option<String>>,
+}
+
+/// Makes the request's ID, client IP and (once authorized) actor available
+/// to the audit entries written while handling it.
+pub async fn context(request: Request, next: Next) -> Response {
+    let context = RequestContext {
+        request_id: request.extensions().get::<RequestId>().map(|RequestId(id)| id.clone()),
+        client_ip: request.extensions().get::<ClientIp>().and_then(|ClientIp(ip)| *ip),
+        path: request.uri().path().to_string(),
+        actor: Mutex::new(None),
+    };
+    REQUEST.scope(context, next.run(request)).await
+}
+
+/// Records who the current request is authorized as, e.g. `token:home-router`.
+pub fn set_actor(actor: impl Into<String>) {
+    let _ = REQUEST.try_with(|request| *request.actor.lock().unwrap() = Some(actor.into()));
+}
+
+/// The current request's client IP and actor, `(None, None)` outside a request.
+pub fn requester() -> (Option<IpAddr>, Option<String>) {
+    REQUEST
+        .try_with(|request| (request.client_ip, request.actor.lock().unwrap().clone()))
+        .unwrap_or_default()
+}
+
+/// A change made, or attempted, through a provider
+pub struct Change<'a> {
+    /// `update`, `update_set`, `cname`, `srv`, `mx`, `ptr`, `delete` or `reconcile`
+    pub action: &'static str,
+    pub provider: &'a str,
+    pub host: &'a str,
+    /// The value known before, if any
+    pub old: Option<String>,
+    /// The IP(s) or target written, or the record type deleted
+    pub new: String,
+    pub success: bool,
+    /// What the provider did, `None` if the call failed
+    pub change: Option<ChangeKind>,
+    /// The provider's result or the (redacted) error
+    pub message: &'a str,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    time: String,
+    /// `None` for changes made in the background (retries, `[reconcile]`, `[[poll]]`)
+    request_id: Option<&'a str>,
+    actor: Option<String>,
+    client_ip: Option<IpAddr>,
+    path: Option<&'a str>,
+    action: &'a str,
+    provider: &'a str,
+    host: &'a str,
+    old: Option<&'a str>,
+    new: &'a str,
+    success: bool,
+    changed: bool,
+    /// `created`, `updated`, `no_change` or `deleted`
+    change: Option<&'static str>,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prev_hash: Option<String>,
+}
+
+pub struct AuditLog {
+    chain: bool,
+    key: Option<String>,
+    /// The file and the hash of its last line
+    file: Mutex<(File, String)>,
+}
+
+impl AuditLog {
+    /// Opens `[audit].path` for appending, or `None` when not configured. A
+    /// chain continues from the last line already in the file.
+    pub fn open(config: &AuditConfig) -> Result<Option<Self>> {
+        let Some(ref path) = config.path else {
+            return Ok(None);
+        };
+        let chain = config.hash_chain || config.key.is_some();
+
+        let last_hash = match chain {
+            true => last_hash(path)?,
+            false => String::new(),
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open audit log: {}", path))?;
+
+        Ok(Some(Self {
+            chain,
+            key: config.key.clone(),
+            file: Mutex::new((file, last_hash)),
+        }))
+    }
+
+    /// Appends an entry for `change`; a failed write is logged, not returned,
+    /// as the change itself has already been made.
+    pub fn record(&self, change: Change<'_>) {
+        let in_request = REQUEST.try_with(|request| self.write(change_entry(&change, Some(request)))).is_ok();
+        if !in_request {
+            self.write(change_entry(&change, None));
+        }
+    }
+
+    fn write(&self, mut entry: Entry<'_>) {
+        let mut file = self.file.lock().unwrap();
+        let (ref mut file, ref mut last_hash) = *file;
+
+        if self.chain {
+            entry.prev_hash = Some(last_hash.clone());
+        }
+        let mut line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize audit entry: {}", e);
+                return;
+            }
+        };
+        if self.chain {
+            // The hash covers the line as it reads without the hash field
+            let hash = self.hash(line.as_bytes());
+            line.truncate(line.len() - 1);
+            line.push_str(&format!(r#","hash":"{}"}}"#, hash));
+            *last_hash = hash;
+        }
+        line.push('\n');
+
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            error!("Failed to write audit log: {}", e);
+        }
+    }
+
+    fn hash(&self, line: &[u8]) -> String {
+        let digest: [u8; 32] = match self.key {
+            Some(ref key) => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+                mac.update(line);
+                mac.finalize().into_bytes().into()
+            }
+            None => Sha256::digest(line).into(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+fn change_entry<'a>(change: &'a Change<'a>, request: Option<&'a RequestContext>) -> Entry<'a> {
+    Entry {
+        time: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+        request_id: request.and_then(|r| r.request_id.as_deref()),
+        actor: request.and_then(|r| r.actor.lock().unwrap().clone()),
+        client_ip: request.and_then(|r| r.client_ip),
+        path: request.map(|r| r.path.as_str()),
+        action: change.action,
+        provider: change.provider,
+        host: change.host,
+        old: change.old.as_deref(),
+        new: &change.new,
+        success: change.success,
+        changed: change.change.as_ref().is_some_and(ChangeKind::is_change),
+        change: change.change.as_ref().map(ChangeKind::as_str),
+        message: change.message,
+        prev_hash: None,
+    }
+}
+
+/// The `hash` of the file's last line, where a chain continues.
+fn last_hash(path: &str) -> Result<String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(GENESIS_HASH.to_string()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read audit log: {}", path)),
+    };
+
+    let mut last = None;
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("Failed to read audit log: {}", path))?;
+        if !line.trim().is_empty() {
+            last = Some(line);
+        }
+    }
+    let Some(last) = last else {
+        return Ok(GENESIS_HASH.to_string());
+    };
+
+    let entry: serde_json::Value =
+        serde_json::from_str(&last).with_context(|| format!("Last line of audit log {} is not JSON", path))?;
+    entry["hash"]
+        .as_str()
+        .map(str::to_string)
+        .with_context(|| format!("Last line of audit log {} has no hash to continue the chain from", path))
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tracing::error;
+
+use crate::config::{host_matches, AuthConfig, Scope, TokenConfig};
+
+/// Outcome of checking a bearer token against `[auth]`
+pub enum TokenCheck {
+    /// The token is valid and allowed; carries the token name
+    Allowed(String),
+    /// The token is valid but not allowed for this provider, host or scope
+    Forbidden(String),
+    /// No configured token matches
+    Invalid,
+}
+
+/// Checks a request key against a configured `key` and/or `key_hash`;
+/// `owner` names the provider or host in log messages.
+///
+/// Without either setting every request is accepted. Hash verification is
+/// CPU-heavy by design, so callers should run this off the async executor.
+pub fn verify_key(key: Option<&str>, key_hash: Option<&str>, request_key: Option<&str>, owner: &str) -> bool {
+    if key.is_none() && key_hash.is_none() {
+        return true;
+    }
+
+    let request_key = request_key.unwrap_or("");
+
+    if let Some(key) = key {
+        if constant_time_eq(request_key, key) {
+            return true;
+        }
+    }
+
+    if let Some(hash) = key_hash {
+        match verify_hash(request_key, hash) {
+            Ok(true) => return true,
+            Ok(false) => {}
+            Err(e) => error!("Invalid key_hash for {}: {}", owner, e),
+        }
+    }
+
+    false
+}
+
+/// Compares two secrets without short-circuiting on the first differing byte.
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+/// Verifies `key` against an argon2 (PHC string) or bcrypt hash.
+pub fn verify_hash(key: &str, hash: &str) -> Result<bool, String> {
+    if hash.starts_with("$argon2") {
+        let parsed = PasswordHash::new(hash).map_err(|e| e.to_string())?;
+        Ok(Argon2::default().verify_password(key.as_bytes(), &parsed).is_ok())
+    } else if hash.starts_with("$2") {
+        bcrypt::verify(key, hash).map_err(|e| e.to_string())
+    } else {
+        Err("unsupported hash format (expected argon2 or bcrypt)".to_string())
+    }
+}
+
+/// Checks that `hash` is a well-formed argon2 (PHC string) or bcrypt hash.
+pub fn check_hash_format(hash: &str) -> Result<(), String> {
+    if hash.starts_with("$argon2") {
+        PasswordHash::new(hash).map(|_| ()).map_err(|e| e.to_string())
+    } else if hash.starts_with("$2") {
+        hash.parse::<bcrypt::HashParts>().map(|_| ()).map_err(|e| e.to_string())
+    } else {
+        Err("unsupported hash format (expected argon2 or bcrypt)".to_string())
+    }
+}
+
+/// Checks a bearer token and its scoping for an operation on every one of
+/// `providers` (and of `hosts`, for updates).
+pub fn check_token(
+    auth: &AuthConfig,
+    token: &str,
+    providers: &[String],
+    hosts: &[String],
+    scope: Scope,
+) -> TokenCheck {
+    let Some(config) = auth.tokens.iter().find(|t| token_matches(t, token)) else {
+        return TokenCheck::Invalid;
+    };
+
+    let provider_allowed = config.providers.is_empty() || providers.iter().all(|p| config.providers.contains(p));
+    let host_allowed = config.hosts.is_empty()
+        || hosts.iter().all(|host| config.hosts.iter().any(|pattern| host_matches(pattern, host)));
+    let scope_allowed = config.scopes.contains(&scope);
+
+    if provider_allowed && host_allowed && scope_allowed {
+        TokenCheck::Allowed(config.name.clone())
+    } else {
+        TokenCheck::Forbidden(config.name.clone())
+    }
+}
+
+/// Checks a bearer token for an `/admin` route acting on `providers`: it
+/// needs the `admin` scope and access to every one of them.
+pub fn check_admin_token(auth: &AuthConfig, token: &str, providers: &[String]) -> TokenCheck {
+    let Some(config) = auth.tokens.iter().find(|t| token_matches(t, token)) else {
+        return TokenCheck::Invalid;
+    };
+
+    let providers_allowed = config.providers.is_empty() || providers.iter().all(|p| config.providers.contains(p));
+    if providers_allowed && config.scopes.contains(&Scope::Admin) {
+        TokenCheck::Allowed(config.name.clone())
+    } else {
+        TokenCheck::Forbidden(config.name.clone())
+    }
+}
+
+fn token_matches(config: &TokenConfig, token: &str) -> bool {
+    if let Some(ref expected) = config.token {
+        if constant_time_eq(token, expected) {
+            return true;
+        }
+    }
+
+    if let Some(ref hash) = config.token_hash {
+        match verify_hash(token, hash) {
+            Ok(true) => return true,
+            Ok(false) => {}
+            Err(e) => error!("Invalid token_hash for token {}: {}", config.name, e),
+        }
+    }
+
+    false
+}
+
+/// Outcome of checking an `X-Signature` / `X-Timestamp` pair
+#[derive(Debug, PartialEq, Eq)]
+pub enum SignatureCheck {
+    Valid,
+    /// Signature does not match, is malformed, or the provider has no plaintext key
+    Invalid,
+    /// Timestamp is outside the allowed window
+    Expired,
+    /// Signature was already used within the window
+    Replayed,
+}
+
+/// What the `X-Signature` of an HTTP request covers, see [`canonical_request`];
+/// `None` for unsigned requests.
+#[derive(Clone)]
+pub struct SignedRequest(pub Option<String>);
+
+/// The string an HTTP request signs, followed by its `X-Timestamp`:
+/// `METHOD\nPATH?QUERY\nhex(SHA-256(body))\n`.
+pub fn canonical_request(method: &str, path_and_query: &str, body: &[u8]) -> String {
+    let digest: String = Sha256::digest(body).iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}\n{}\n{}\n", method, path_and_query, digest)
+}
+
+/// Verifies `signature = hex(HMAC-SHA256(message + timestamp, key))` with a
+/// replay window, remembering used signatures until they expire.
+pub struct SignatureVerifier {
+    window_secs: u64,
+    /// Signatures seen within the window, with their timestamps
+    seen: Mutex<HashMap<String, u64>>,
+}
+
+impl SignatureVerifier {
+    pub fn new(window_secs: u64) -> Self {
+        Self {
+            window_secs,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn verify(&self, key: &str, message: &str, timestamp: &str, signature: &str) -> SignatureCheck {
+        let Ok(ts) = timestamp.parse::<u64>() else {
+            return SignatureCheck::Invalid;
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if now.abs_diff(ts) > self.window_secs {
+            return SignatureCheck::Expired;
+        }
+
+        let Some(signature_bytes) = decode_hex(signature) else {
+            return SignatureCheck::Invalid;
+        };
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+        mac.update(message.as_bytes());
+        mac.update(timestamp.as_bytes());
+        if mac.verify_slice(&signature_bytes).is_err() {
+            return SignatureCheck::Invalid;
+        }
+
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|_, seen_ts| now.abs_diff(*seen_ts) <= self.window_secs);
+        if seen.insert(signature.to_ascii_lowercase(), ts).is_some() {
+            return SignatureCheck::Replayed;
+        }
+
+        SignatureCheck::Valid
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok().filter(|p| p.len() == 2)?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
@@ -0,0 +1,148 @@
+//! `[circuit_breaker]`: after `failure_threshold` consecutive outages of a
+//! provider (5xx, timeouts, connection failures), record changes fail fast
+//! with `503` instead of hammering its API. Once `open_secs` have passed one
+//! call is let through as a probe: its success closes the circuit, its
+//! failure opens it for another `open_secs`.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::provider::{classify, ProviderError};
+
+pub struct CircuitBreaker {
+    /// 0 when disabled
+    threshold: u32,
+    open: Duration,
+    /// Per configured provider
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+#[derive(Default)]
+struct Circuit {
+    /// Outages in a row, reset by any answer from the API
+    failures: u32,
+    /// When the circuit opened, `None` while closed
+    opened: Option<(Instant, OffsetDateTime)>,
+    /// When the probe in flight was let through
+    probe: Option<Instant>,
+}
+
+/// A provider's circuit on `/status`, while not closed
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitReport {
+    /// `open`, or `half_open` once a probe may be sent
+    pub state: &'static str,
+    pub failures: u32,
+    /// When the next probe may be sent
+    pub until: String,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            threshold: config.circuit_breaker.failure_threshold,
+            open: Duration::from_secs(config.circuit_breaker.open_secs),
+            circuits: Mutex::new(config.providers.iter().map(|p| (p.name.clone(), Circuit::default())).collect()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.threshold > 0
+    }
+
+    /// Fails with [`ProviderError::Unavailable`] if `provider` may not be
+    /// called now; otherwise the call must be followed by [`Self::record`].
+    pub fn allow(&self, provider: &str) -> Result<()> {
+        let mut circuits = self.circuits.lock().unwrap();
+        let Some(circuit) = circuits.get_mut(provider) else {
+            return Ok(());
+        };
+        let Some((opened, _)) = circuit.opened else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        let wait = match circuit.probe {
+            // An abandoned probe (its request went away) doesn't block the next one
+            Some(probe) if now.duration_since(probe) < self.open => Duration::from_secs(1),
+            _ => match (opened + self.open).checked_duration_since(now) {
+                Some(wait) if !wait.is_zero() => wait,
+                _ => {
+                    info!("Circuit of provider {} half open, probing", provider);
+                    circuit.probe = Some(now);
+                    return Ok(());
+                }
+            },
+        };
+        Err(anyhow!(ProviderError::Unavailable {
+            message: format!("Circuit of provider {} open after {} consecutive failures", provider, circuit.failures),
+            retry_after: Some(wait),
+        }))
+    }
+
+    /// Counts the outcome of a provider call let through by [`Self::allow`].
+    pub fn record<T>(&self, provider: &str, result: &Result<T>) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut circuits = self.circuits.lock().unwrap();
+        let Some(circuit) = circuits.get_mut(provider) else {
+            return;
+        };
+        let probing = circuit.probe.take().is_some();
+
+        match result.as_ref().map_err(classify) {
+            Err(Some(ProviderError::Unavailable { .. })) => {
+                circuit.failures += 1;
+                if probing || (circuit.opened.is_none() && circuit.failures >= self.threshold) {
+                    warn!(
+                        "Circuit of provider {} open for {}s after {} consecutive failures",
+                        provider,
+                        self.open.as_secs(),
+                        circuit.failures
+                    );
+                    circuit.opened = Some((Instant::now(), OffsetDateTime::now_utc()));
+                }
+            }
+            // Not the provider's answer; a probe that ends this way is simply retried
+            Err(None) => {}
+            // The API answered, even if with an error
+            _ => {
+                if circuit.opened.take().is_some() {
+                    info!("Circuit of provider {} closed", provider);
+                }
+                circuit.failures = 0;
+            }
+        }
+    }
+
+    pub fn report(&self, provider: &str) -> Option<CircuitReport> {
+        let circuits = self.circuits.lock().unwrap();
+        let circuit = circuits.get(provider)?;
+        let (opened, opened_utc) = circuit.opened?;
+        Some(CircuitReport {
+            state: match opened.elapsed() < self.open {
+                true => "open",
+                false => "half_open",
+            },
+            failures: circuit.failures,
+            until: (opened_utc + self.open).format(&Rfc3339).unwrap_or_default(),
+        })
+    }
+
+    /// Consecutive failures and whether the circuit is open, per provider.
+    pub fn states(&self) -> Vec<(String, u32, bool)> {
+        let circuits = self.circuits.lock().unwrap();
+        let mut states: Vec<_> = circuits.iter().map(|(name, c)| (name.clone(), c.failures, c.opened.is_some())).collect();
+        states.sort();
+        states
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer};
+
+/// An IP network in CIDR notation; a bare address is treated as a single-host network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+/// Ranges whose addresses never belong in public DNS, with the name used in errors
+const NON_PUBLIC: [(Cidr, &str); 15] = [
+    (Cidr::v4(0, 0, 0, 0, 8), "unspecified"),
+    (Cidr::v4(10, 0, 0, 0, 8), "private (RFC 1918)"),
+    (Cidr::v4(172, 16, 0, 0, 12), "private (RFC 1918)"),
+    (Cidr::v4(192, 168, 0, 0, 16), "private (RFC 1918)"),
+    (Cidr::v4(100, 64, 0, 0, 10), "shared CGNAT (RFC 6598)"),
+    (Cidr::v4(127, 0, 0, 0, 8), "loopback"),
+    (Cidr::v4(169, 254, 0, 0, 16), "link-local"),
+    (Cidr::v4(224, 0, 0, 0, 4), "multicast"),
+    (Cidr::v4(240, 0, 0, 0, 4), "reserved"),
+    (Cidr::v6(Ipv6Addr::UNSPECIFIED, 128), "unspecified"),
+    (Cidr::v6(Ipv6Addr::LOCALHOST, 128), "loopback"),
+    (Cidr::v6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), 10), "link-local"),
+    (Cidr::v6(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7), "unique local"),
+    (Cidr::v6(Ipv6Addr::new(0xff00, 0, 0, 0, 0, 0, 0, 0), 8), "multicast"),
+    (Cidr::v6(Ipv6Addr::new(0x0100, 0, 0, 0, 0, 0, 0, 0), 64), "discard-only"),
+];
+
+/// The kind of non-public range `ip` is in, such as `"private (RFC 1918)"`,
+/// or `None` for an address that may be published.
+pub fn non_public_range(ip: IpAddr) -> Option<&'static str> {
+    NON_PUBLIC.iter().find(|(cidr, _)| cidr.contains(ip)).map(|(_, name)| *name)
+}
+
+impl Cidr {
+    const fn v4(a: u8, b: u8, c: u8, d: u8, prefix: u8) -> Self {
+        Self {
+            addr: IpAddr::V4(Ipv4Addr::new(a, b, c, d)),
+            prefix,
+        }
+    }
+
+    const fn v6(addr: Ipv6Addr, prefix: u8) -> Self {
+        Self {
+            addr: IpAddr::V6(addr),
+            prefix,
+        }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // Treat IPv4-mapped IPv6 addresses (::ffff:a.b.c.d) as IPv4
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            v4 => v4,
+        };
+
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let addr: IpAddr = addr
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid IP address in CIDR: {}", s))?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max_prefix)
+                .ok_or_else(|| format!("invalid prefix length in CIDR: {}", s))?,
+            None => max_prefix,
+        };
+
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cidr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
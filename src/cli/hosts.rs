@@ -0,0 +1,35 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+
+use ddns_rust::config::Config;
+use ddns_rust::hosts::{self, HostsExport};
+
+/// Writes the managed hosts and their state as JSON to `output`, or stdout.
+pub fn export(config: &Config, output: Option<&str>) -> Result<()> {
+    let export = hosts::export(config)?;
+    let json = serde_json::to_string_pretty(&export)?;
+
+    match output {
+        Some(path) => {
+            fs::write(path, json + "\n").with_context(|| format!("Failed to write {}", path))?;
+            let count: usize = export.providers.iter().map(|p| p.hosts.len()).sum();
+            eprintln!("Exported {} host(s) of {} provider(s) to {}", count, export.providers.len(), path);
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+/// Restores the state of the hosts in the export at `path`.
+pub fn import(config: &Config, path: &str) -> Result<()> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+    let export: HostsExport = serde_json::from_str(&content).with_context(|| format!("{} is not a ddns-rust export", path))?;
+
+    let summary = hosts::import(config, &export)?;
+    for warning in &summary.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    println!("Restored state of {} host(s)", summary.restored);
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// Supported provider types for generated configs
+const PROVIDER_TYPES: [&str; 1] = ["cloudflare"];
+
+/// Values for the generated config; missing ones are prompted for when interactive
+#[derive(Debug, Default)]
+pub struct InitOptions {
+    pub provider_type: Option<String>,
+    pub name: Option<String>,
+    pub api_key: Option<String>,
+    pub zone_id: Option<String>,
+    pub key: Option<String>,
+    pub port: Option<u16>,
+    pub force: bool,
+}
+
+/// Generates a starter config at `path`, readable only by the current user.
+pub fn run(path: &str, mut opts: InitOptions) -> Result<()> {
+    let path = Path::new(path);
+    if path.exists() && !opts.force {
+        anyhow::bail!("{} already exists, use --force to overwrite", path.display());
+    }
+
+    let interactive = io::stdin().is_terminal();
+    let mut input = io::stdin().lock();
+
+    let provider_type = match opts.provider_type.take() {
+        Some(t) => t,
+        None if interactive => prompt(&mut input, "Provider type", Some("cloudflare"))?,
+        None => "cloudflare".to_string(),
+    };
+    if !PROVIDER_TYPES.contains(&provider_type.as_str()) {
+        anyhow::bail!(
+            "Unsupported provider type: {} (supported: {})",
+            provider_type,
+            PROVIDER_TYPES.join(", ")
+        );
+    }
+
+    let name = match opts.name.take() {
+        Some(n) => n,
+        None if interactive => prompt(&mut input, "Provider name", Some(&provider_type))?,
+        None => provider_type.clone(),
+    };
+    let api_key = match opts.api_key.take() {
+        Some(k) => k,
+        None if interactive => prompt(&mut input, "Cloudflare API token", None)?,
+        None => anyhow::bail!("--api-key is required when not running interactively"),
+    };
+    let zone_id = match opts.zone_id.take() {
+        Some(z) => Some(z),
+        None if interactive => {
+            Some(prompt(&mut input, "Zone ID (empty to discover from hostnames)", Some(""))?)
+        }
+        None => None,
+    }
+    .filter(|z| !z.is_empty());
+    let key = match opts.key.take() {
+        Some(k) => Some(k),
+        None if interactive => Some(prompt(&mut input, "Access key for update requests (empty for none)", Some(""))?),
+        None => None,
+    }
+    .filter(|k| !k.is_empty());
+    let port = opts.port.unwrap_or(3000);
+
+    let content = render(&provider_type, &name, &api_key, zone_id.as_deref(), key.as_deref(), port);
+    write_private(path, &content)?;
+
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+fn prompt(input: &mut impl BufRead, label: &str, default: Option<&str>) -> Result<String> {
+    loop {
+        match default {
+            Some(d) if !d.is_empty() => print!("{} [{}]: ", label, d),
+            _ => print!("{}: ", label),
+        }
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            anyhow::bail!("Unexpected end of input");
+        }
+
+        let value = line.trim();
+        match (value.is_empty(), default) {
+            (false, _) => return Ok(value.to_string()),
+            (true, Some(d)) => return Ok(d.to_string()),
+            (true, None) => println!("A value is required."),
+        }
+    }
+}
+
+fn render(
+    provider_type: &str,
+    name: &str,
+    api_key: &str,
+    zone_id: Option<&str>,
+    key: Option<&str>,
+    port: u16,
+) -> String {
+    // Quote through toml so arbitrary input is escaped correctly
+    let quote = |s: &str| toml::Value::String(s.to_string()).to_string();
+
+    let mut out = String::new();
+    out.push_str("# Generated by `ddns-rust init`, see config.example.toml for all options\n\n");
+    out.push_str("[server]\n");
+    out.push_str("host = \"0.0.0.0\"\n");
+    out.push_str(&format!("port = {}\n", port));
+    out.push_str("log_level = \"info\"\n\n");
+    out.push_str("[[providers]]\n");
+    out.push_str(&format!("name = {}\n", quote(name)));
+    out.push_str(&format!("type = {}\n", quote(provider_type)));
+    match key {
+        Some(key) => out.push_str(&format!("key = {}\n", quote(key))),
+        None => out.push_str("# key = \"your_secret_key\"\n"),
+    }
+    out.push_str(&format!("api_key = {}\n", quote(api_key)));
+    match zone_id {
+        Some(zone_id) => out.push_str(&format!("zone_id = {}\n", quote(zone_id))),
+        None => out.push_str("# zone_id = \"your_zone_id\"  # discovered from hostnames when unset\n"),
+    }
+    out
+}
+
+/// Writes `content` with 0600 permissions on Unix, since the file holds credentials.
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to create config file: {}", path.display()))?;
+    file.write_all(content.as_bytes())
+        .with_context(|| format!("Failed to write config file: {}", path.display()))?;
+
+    // `mode` only applies to newly created files, so tighten an overwritten one too
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("Failed to set permissions on: {}", path.display()))?;
+    }
+
+    Ok(())
+}
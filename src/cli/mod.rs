@@ -0,0 +1,6 @@
+pub mod hosts;
+pub mod init;
+pub mod plan;
+pub mod providers;
+#[cfg(windows)]
+pub mod service;
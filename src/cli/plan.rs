@@ -0,0 +1,107 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::net::IpAddr;
+use std::path::Path;
+
+use anyhow::Result;
+
+use ddns_rust::config::Config;
+use ddns_rust::plan::{self, Plan};
+use ddns_rust::provider;
+use ddns_rust::state::StateStore;
+
+/// Prints the changes `apply` would make.
+pub async fn plan(config: &Config, tenant: Option<&str>) -> Result<()> {
+    let config = scope(config, tenant)?;
+    let providers = provider::create_all(&config)?;
+    let plan = plan::plan(&config.providers.iter().collect::<Vec<_>>(), &providers, &known(&config)?).await;
+
+    print_plan(&plan);
+    if !plan.errors.is_empty() {
+        anyhow::bail!("Records of {} provider(s) could not be listed", plan.errors.len());
+    }
+    Ok(())
+}
+
+/// Plans and, once confirmed, makes the changes.
+pub async fn apply(config: &Config, tenant: Option<&str>, yes: bool) -> Result<()> {
+    let config = scope(config, tenant)?;
+    let providers = provider::create_all(&config)?;
+    let plan = plan::plan(&config.providers.iter().collect::<Vec<_>>(), &providers, &known(&config)?).await;
+
+    print_plan(&plan);
+    if plan.targets.is_empty() {
+        return Ok(());
+    }
+    if !yes && !confirm(plan.changes.len())? {
+        println!("Nothing applied");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for target in &plan.targets {
+        let (Some(provider), Some(provider_config)) = (providers.get(&target.provider), config.get_provider(&target.provider)) else {
+            continue;
+        };
+        match plan::apply(provider.as_ref(), provider_config, target).await {
+            Ok(result) => println!("[ OK ] {} ({}): {}", target.host, target.provider, result.message),
+            Err(e) => {
+                failed += 1;
+                println!("[FAIL] {} ({}): {:#}", target.host, target.provider, e);
+            }
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} of {} host(s) failed", failed, plan.targets.len());
+    }
+    Ok(())
+}
+
+fn print_plan(plan: &Plan) {
+    for failure in &plan.errors {
+        println!("[FAIL] {}: {}", failure.provider, failure.error);
+    }
+    if plan.changes.is_empty() {
+        println!("No changes, records match the desired addresses");
+        return;
+    }
+    for change in &plan.changes {
+        println!("{}  ({})", change, change.provider);
+    }
+    println!("{} change(s) to {} host(s)", plan.changes.len(), plan.targets.len());
+}
+
+/// Asks on the terminal; without one, nothing is applied unless `--yes` is given.
+fn confirm(count: usize) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        anyhow::bail!("Not applying without a terminal to confirm on, pass --yes");
+    }
+    print!("Apply {} change(s)? [y/N] ", count);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// The main config, or the view of one of its `[[tenants]]`.
+fn scope(config: &Config, tenant: Option<&str>) -> Result<Config> {
+    match tenant {
+        Some(name) => match config.tenants.iter().find(|t| t.name == name) {
+            Some(tenant) => Ok(config.for_tenant(tenant)),
+            None => anyhow::bail!("Tenant not configured: {}", name),
+        },
+        None => Ok(config.clone()),
+    }
+}
+
+/// The addresses last pushed per host, from the state file.
+fn known(config: &Config) -> Result<Vec<(String, String, IpAddr)>> {
+    Ok(match config.server.state_file {
+        Some(ref path) => StateStore::load(Path::new(path))?
+            .entries()
+            .into_iter()
+            .map(|(provider, host, state)| (provider, host, state.ip))
+            .collect(),
+        None => Vec::new(),
+    })
+}
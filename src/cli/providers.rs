@@ -0,0 +1,47 @@
+use anyhow::Result;
+
+use ddns_rust::config::{Config, ProviderConfig};
+use ddns_rust::provider;
+
+/// Prints the configured providers as a table.
+pub fn list(config: &Config) {
+    println!("{:<20} {:<12} ZONE", "NAME", "TYPE");
+    for p in &config.providers {
+        println!("{:<20} {:<12} {}", p.name, p.provider_type, zone_description(p));
+    }
+}
+
+/// Runs a credential check against every configured provider.
+pub async fn test(config: &Config) -> Result<()> {
+    let providers = provider::create_all(config)?;
+    let mut failed = 0;
+
+    for p in &config.providers {
+        let status = match providers.get(&p.name) {
+            Some(instance) => instance.verify().await,
+            None => Err(anyhow::anyhow!("Unsupported provider type: {}", p.provider_type)),
+        };
+
+        match status {
+            Ok(status) => println!("[ OK ] {} ({}): {}", p.name, p.provider_type, status),
+            Err(e) => {
+                failed += 1;
+                println!("[FAIL] {} ({}): {:#}", p.name, p.provider_type, e);
+            }
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} of {} provider(s) failed", failed, config.providers.len());
+    }
+
+    Ok(())
+}
+
+fn zone_description(p: &ProviderConfig) -> String {
+    match (&p.zone_id, &p.zone_name) {
+        (Some(zone_id), _) => zone_id.clone(),
+        (None, Some(zone_name)) => zone_name.clone(),
+        (None, None) => "(auto)".to_string(),
+    }
+}
@@ -0,0 +1,162 @@
+//! Running as a Windows service: `service install` registers the current
+//! executable with the service control manager, which then starts it with
+//! `--service`.
+
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use ddns_rust::{config, telemetry};
+use tokio::runtime::Handle;
+use tokio::sync::watch;
+use tracing::{error, info};
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode, ServiceInfo,
+    ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+const SERVICE_NAME: &str = "ddns-rust";
+const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+
+/// What the service main needs from the command line and the runtime
+struct Launch {
+    config_path: String,
+    format: Option<config::ConfigFormat>,
+    runtime: Handle,
+}
+
+static LAUNCH: OnceLock<Launch> = OnceLock::new();
+
+/// Registers the service to start automatically with the given config file.
+pub fn install(config_path: &str, format: Option<config::ConfigFormat>) -> Result<()> {
+    // Services start in the system directory, so the path must not be relative
+    let config_path = Path::new(config_path)
+        .canonicalize()
+        .with_context(|| format!("Config file {} not found", config_path))?;
+
+    let mut launch_arguments = vec![OsString::from("--service"), OsString::from("-c"), config_path.into_os_string()];
+    if let Some(format) = format {
+        launch_arguments.push(OsString::from("--format"));
+        launch_arguments.push(OsString::from(format_name(format)));
+    }
+
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE)?;
+    let info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from("DDNS Rust Service"),
+        service_type: SERVICE_TYPE,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments,
+        dependencies: vec![],
+        account_name: None, // LocalSystem
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+        .context("Failed to create the service (run as Administrator)")?;
+    service.set_description(env!("CARGO_PKG_DESCRIPTION"))?;
+
+    println!("Installed service {}; start it with `sc start {}`", SERVICE_NAME, SERVICE_NAME);
+    Ok(())
+}
+
+/// Stops the service if it is running and removes it.
+pub fn uninstall() -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let service = manager
+        .open_service(SERVICE_NAME, ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE)
+        .with_context(|| format!("Failed to open service {}", SERVICE_NAME))?;
+
+    service.delete()?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+
+    println!("Removed service {}", SERVICE_NAME);
+    Ok(())
+}
+
+/// Hands the process to the service control manager, which calls
+/// `service_main` and blocks until the service stops.
+pub fn run(config_path: String, format: Option<config::ConfigFormat>) -> Result<()> {
+    let launch = Launch { config_path, format, runtime: Handle::current() };
+    if LAUNCH.set(launch).is_err() {
+        anyhow::bail!("service already started");
+    }
+    // Blocks this thread, so keep it off the runtime's workers
+    tokio::task::block_in_place(|| service_dispatcher::start(SERVICE_NAME, ffi_service_main))
+        .context("Failed to connect to the service control manager (use `service install` and `sc start`)")
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+fn service_main(_arguments: Vec<OsString>) {
+    let Some(launch) = LAUNCH.get() else {
+        return;
+    };
+    if let Err(e) = run_service(launch) {
+        error!("Service failed: {:#}", e);
+    }
+}
+
+fn run_service(launch: &Launch) -> Result<()> {
+    let (stop_tx, mut stop_rx) = watch::channel(false);
+    let status = service_control_handler::register(SERVICE_NAME, move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            let _ = stop_tx.send(true);
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+    let set_state = |state: ServiceState, exit_code: u32| {
+        status.set_service_status(ServiceStatus {
+            service_type: SERVICE_TYPE,
+            current_state: state,
+            controls_accepted: if state == ServiceState::Running {
+                ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+            } else {
+                ServiceControlAccept::empty()
+            },
+            exit_code: ServiceExitCode::Win32(exit_code),
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        })
+    };
+
+    let result = config::Config::load(&launch.config_path, launch.format).and_then(|config| {
+        let _telemetry = telemetry::init(&config)?;
+        set_state(ServiceState::Running, 0)?;
+        info!("Running as Windows service {} with {}", SERVICE_NAME, launch.config_path);
+
+        launch.runtime.block_on(async {
+            tokio::select! {
+                result = ddns_rust::run_server(config) => result,
+                _ = stop_rx.wait_for(|&stop| stop) => {
+                    info!("Service stop requested");
+                    Ok(())
+                }
+            }
+        })
+    });
+
+    // A nonzero exit code makes the service manager report the failure
+    set_state(ServiceState::Stopped, if result.is_ok() { 0 } else { 1 })?;
+    result
+}
+
+fn format_name(format: config::ConfigFormat) -> &'static str {
+    match format {
+        config::ConfigFormat::Toml => "toml",
+        config::ConfigFormat::Yaml => "yaml",
+        config::ConfigFormat::Json => "json",
+    }
+}
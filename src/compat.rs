@@ -0,0 +1,400 @@
+//! Update endpoints in the URL conventions of router and NAS DDNS clients, which
+//! can only fill placeholders into a fixed URL.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Extension, Path, Query, Request, State},
+    http::{header, response::Parts, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::warn;
+
+use crate::api::{
+    apply_update, authorize, into_json, take_offline, invalid_host, invalid_ip, rate_limit, reject_source_ip, AppState, ClientIp,
+    ErrorCode, ErrorResponse,
+};
+use crate::auth::SignedRequest;
+use crate::cidr;
+use crate::config::{ProviderConfig, Scope};
+use crate::hostname;
+use crate::idempotency::{self, NoReplay};
+
+/// The compatibility routes, rate limited like the rest of the API.
+pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    // DynDNS2 clients only understand a rate limit answered as `abuse`
+    let dyndns = Router::new()
+        .route("/nic/update", get(nic_update))
+        .route_layer(middleware::from_fn_with_state(state.clone(), idempotency::replay))
+        .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        .route_layer(middleware::from_fn(abuse));
+
+    Router::new()
+        .route("/fritzbox/{provider}", get(fritzbox))
+        .route_layer(middleware::from_fn_with_state(state.clone(), idempotency::replay))
+        .route("/checkip", get(checkip))
+        .route_layer(middleware::from_fn_with_state(state, rate_limit))
+        .merge(dyndns)
+}
+
+/// Query of a FRITZ!Box update URL such as
+/// `/fritzbox/cf?host=<domain>&ipv4=<ipaddr>&ipv6=<ip6addr>&key=<pass>`.
+/// The box leaves a placeholder empty when it has no such address.
+#[derive(Deserialize)]
+struct FritzBoxQuery {
+    key: Option<String>,
+    #[serde(default)]
+    host: String,
+    #[serde(default)]
+    ipv4: String,
+    #[serde(default)]
+    ipv6: String,
+}
+
+/// Updates every comma-separated host to the IPv4 and IPv6 addresses given,
+/// skipping the families a host has no record type for.
+async fn fritzbox(
+    State(state): State<Arc<AppState>>,
+    Path(provider_name): Path<String>,
+    Query(query): Query<FritzBoxQuery>,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
+    headers: HeaderMap,
+    Extension(signed): Extension<SignedRequest>,
+) -> Response {
+    let mut hosts: Vec<String> = Vec::new();
+    for host in query.host.split(',').map(str::trim).filter(|h| !h.is_empty()) {
+        match hostname::normalize(host) {
+            Ok(host) if !hosts.contains(&host) => hosts.push(host),
+            Ok(_) => {}
+            Err(reason) => return invalid_host(&reason),
+        }
+    }
+    if hosts.is_empty() {
+        return invalid_host("no host given");
+    }
+
+    let provider_config = match state.config.get_provider(&provider_name) {
+        Some(config) => config,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    success: false,
+                    code: ErrorCode::ProviderNotFound,
+                    error: format!("Provider not found: {}", provider_name),
+                }),
+            )
+                .into_response();
+        }
+    };
+
+    let mut ips = Vec::new();
+    for (value, ipv6) in [(query.ipv4.trim(), false), (query.ipv6.trim(), true)] {
+        if value.is_empty() {
+            continue;
+        }
+        match value.parse::<IpAddr>() {
+            Ok(ip) if ip.is_ipv6() == ipv6 && !ip.is_unspecified() => ips.push(ip),
+            _ => return invalid_ip(&format!("Invalid IPv{} address: {}", if ipv6 { 6 } else { 4 }, value)),
+        }
+    }
+    if ips.is_empty() {
+        return invalid_ip("No IP address given");
+    }
+
+    // Check source IP against allow/deny lists
+    if let Some(response) = reject_source_ip(&state, provider_config, client_ip) {
+        return response;
+    }
+
+    // Every host has to accept the credentials before anything is changed
+    let names: Vec<&str> = hosts.iter().map(String::as_str).collect();
+    if let Some(response) = authorize(&state, provider_config, &headers, &signed, query.key, &names, Scope::Write).await {
+        return response;
+    }
+
+    let mut results = Vec::new();
+    let mut failure: Option<Parts> = None;
+    for host in &hosts {
+        let record_type = provider_config.host_settings(host).record_type;
+        let wanted: Vec<IpAddr> = ips.iter().copied().filter(|ip| record_type.allows(ip)).collect();
+        // A host taking neither family gets the usual record type error
+        let wanted = if wanted.is_empty() { vec![ips[0]] } else { wanted };
+
+        for ip in wanted {
+            match apply_update(&state, provider_config, host, ip, None).await {
+                Ok(updated) => results.push(json!({
+                    "host": host,
+                    "ip": ip,
+                    "success": updated.success,
+                    "message": updated.message,
+                    "changed": updated.changed(),
+                    "change": updated.change,
+                    "queued": updated.queued.is_some(),
+                })),
+                Err(response) => {
+                    let (parts, mut result) = into_json(response).await;
+                    result["host"] = json!(host);
+                    result["ip"] = json!(ip);
+                    results.push(result);
+                    // The first failure decides the status (and keeps its Retry-After)
+                    if failure.is_none() {
+                        failure = Some(parts);
+                    }
+                }
+            }
+        }
+    }
+
+    let body = Json(json!({ "success": failure.is_none(), "results": results }));
+    match failure {
+        Some(parts) => (parts, body).into_response(),
+        None => (StatusCode::OK, body).into_response(),
+    }
+}
+
+/// Hostnames a DynDNS2 request may update at once
+const MAX_DYNDNS_HOSTS: usize = 20;
+
+/// Query of a DynDNS2 style update (`/nic/update`), as sent by Synology DSM's
+/// custom provider with `username=__USERNAME__&password=__PASSWORD__&hostname=__HOSTNAME__&myip=__MYIP__`.
+/// The username names the provider and the password is its access key; ddclient
+/// and inadyn send both with HTTP Basic auth instead.
+#[derive(Deserialize)]
+struct DynDnsQuery {
+    username: Option<String>,
+    password: Option<String>,
+    #[serde(default)]
+    hostname: String,
+    /// One address, or an IPv4 and an IPv6 address separated by a comma
+    myip: Option<String>,
+    offline: Option<String>,
+}
+
+/// Updates each comma-separated host and answers with one DynDNS2 return code
+/// per host and line (`good <ip>`, `nochg <ip>`, `badauth`, `notfqdn`, `nohost`,
+/// `dnserr` or `911`), or a single `badauth`, `numhost`, `!donator` or `abuse`
+/// for the whole request. Always with status 200, the body is all these clients
+/// look at.
+async fn nic_update(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DynDnsQuery>,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
+    headers: HeaderMap,
+    Extension(signed): Extension<SignedRequest>,
+) -> Response {
+    let (username, password) = match basic_credentials(&headers) {
+        Some((username, password)) => (Some(username), Some(password)),
+        None => (query.username, query.password),
+    };
+    let Some(provider_config) = username.as_deref().and_then(|name| state.config.get_provider(name)) else {
+        return dyndns_text("badauth".to_string());
+    };
+
+    let hosts: Vec<Result<String, String>> = query
+        .hostname
+        .split(',')
+        .map(str::trim)
+        .filter(|h| !h.is_empty())
+        .map(hostname::normalize)
+        .collect();
+    if hosts.len() > MAX_DYNDNS_HOSTS {
+        return dyndns_text("numhost".to_string());
+    }
+    if hosts.is_empty() {
+        return dyndns_text("notfqdn".to_string());
+    }
+
+    // Hosts without an `offline` setting answer `!donator`, as offline redirection
+    // was a paid DynDNS feature refused to other accounts
+    if query.offline.as_deref().is_some_and(|v| v.eq_ignore_ascii_case("yes")) {
+        if let Some(response) = reject_source_ip(&state, provider_config, client_ip) {
+            return dyndns_text(dyndns_code(response).await.to_string());
+        }
+        if let Some(response) = authorize_dyndns(&state, provider_config, &headers, &signed, password, &hosts).await {
+            return response;
+        }
+        let mut lines = Vec::new();
+        for host in &hosts {
+            lines.push(match host {
+                Ok(host) => offline_dyndns_host(&state, provider_config, host).await,
+                Err(reason) => dyndns_code(invalid_host(reason)).await.to_string(),
+            });
+        }
+        return dyndns_lines(lines);
+    }
+
+    // Without `myip` the address the request comes from is meant
+    let myip = query.myip.as_deref().map(str::trim).unwrap_or_default();
+    let mut ips = Vec::new();
+    for value in myip.split(',').map(str::trim).filter(|ip| !ip.is_empty()) {
+        match value.parse::<IpAddr>() {
+            Ok(ip) if !ips.iter().any(|i: &IpAddr| i.is_ipv6() == ip.is_ipv6()) => ips.push(ip),
+            _ => return dyndns_text(dyndns_code(invalid_ip(&format!("Invalid IP address: {}", value))).await.to_string()),
+        }
+    }
+    if ips.is_empty() {
+        match client_ip {
+            Some(ip) => ips.push(ip),
+            None => return dyndns_text(dyndns_code(invalid_ip("No IP address given")).await.to_string()),
+        }
+    }
+
+    if let Some(response) = reject_source_ip(&state, provider_config, client_ip) {
+        return dyndns_text(dyndns_code(response).await.to_string());
+    }
+    if let Some(response) = authorize_dyndns(&state, provider_config, &headers, &signed, password, &hosts).await {
+        return response;
+    }
+
+    let mut lines = Vec::new();
+    for host in &hosts {
+        lines.push(match host {
+            Ok(host) => update_dyndns_host(&state, provider_config, host, &ips).await,
+            Err(reason) => dyndns_code(invalid_host(reason)).await.to_string(),
+        });
+    }
+    dyndns_lines(lines)
+}
+
+/// Authorizes a `/nic/update` once for all its valid hosts, answering with a
+/// single return code for the whole request when refused.
+async fn authorize_dyndns(
+    state: &AppState,
+    provider_config: &ProviderConfig,
+    headers: &HeaderMap,
+    signed: &SignedRequest,
+    password: Option<String>,
+    hosts: &[Result<String, String>],
+) -> Option<Response> {
+    let hosts: Vec<&str> = hosts.iter().filter_map(|host| host.as_deref().ok()).collect();
+    // Invalid hosts are answered with `notfqdn` whoever asks
+    if hosts.is_empty() {
+        return None;
+    }
+    let response = authorize(state, provider_config, headers, signed, password, &hosts, Scope::Write).await?;
+    Some(dyndns_text(dyndns_code(response).await.to_string()))
+}
+
+/// Return code line of one host of a `/nic/update` request.
+async fn update_dyndns_host(state: &AppState, provider_config: &ProviderConfig, host: &str, ips: &[IpAddr]) -> String {
+    let record_type = provider_config.host_settings(host).record_type;
+    let wanted: Vec<IpAddr> = ips.iter().copied().filter(|ip| record_type.allows(ip)).collect();
+    // A host taking neither family gets the usual record type error
+    let wanted = if wanted.is_empty() { vec![ips[0]] } else { wanted };
+
+    let mut changed = false;
+    for &ip in &wanted {
+        match apply_update(state, provider_config, host, ip, None).await {
+            // A queued update will be made, as far as the client is concerned
+            Ok(updated) => changed |= updated.changed() || updated.queued.is_some(),
+            Err(response) => return dyndns_code(response).await.to_string(),
+        }
+    }
+    let ips: Vec<String> = wanted.iter().map(IpAddr::to_string).collect();
+    format!("{} {}", if changed { "good" } else { "nochg" }, ips.join(","))
+}
+
+/// Return code line of one host of an `offline=yes` request: the parking
+/// addresses, or none when the records were deleted.
+async fn offline_dyndns_host(state: &AppState, provider_config: &ProviderConfig, host: &str) -> String {
+    match take_offline(state, provider_config, host).await {
+        Ok(records) => {
+            let changed = records.iter().any(|r| r.updated.changed() || r.updated.queued.is_some());
+            let ips: Vec<String> = records.iter().filter_map(|r| r.ip).map(|ip| ip.to_string()).collect();
+            let code = if changed { "good" } else { "nochg" };
+            match ips.is_empty() {
+                true => code.to_string(),
+                false => format!("{} {}", code, ips.join(",")),
+            }
+        }
+        Err(response) => dyndns_code(response).await.to_string(),
+    }
+}
+
+/// The DynDNS2 return code for an error response of the JSON API.
+async fn dyndns_code(response: Response) -> &'static str {
+    let (_, body) = into_json(response).await;
+    match body["code"].as_str().unwrap_or_default() {
+        "unauthorized" | "forbidden" | "provider_not_found" => "badauth",
+        "invalid_host" => "notfqdn",
+        "not_found" | "record_type_not_enabled" => "nohost",
+        "rate_limited" => "abuse",
+        "offline_not_enabled" => "!donator",
+        "provider_error" | "provider_auth_failed" | "conflict" => "dnserr",
+        _ => "911",
+    }
+}
+
+/// Turns rate limited `/nic/update` requests into `abuse`.
+async fn abuse(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    if response.status() != StatusCode::TOO_MANY_REQUESTS {
+        return response;
+    }
+    let mut text = dyndns_text("abuse".to_string());
+    if let Some(retry_after) = response.headers().get(header::RETRY_AFTER) {
+        text.headers_mut().insert(header::RETRY_AFTER, retry_after.clone());
+    }
+    text
+}
+
+/// Per host return codes, replayed to retries only when every host succeeded.
+fn dyndns_lines(lines: Vec<String>) -> Response {
+    let succeeded = lines.iter().all(|line| line.starts_with("good") || line.starts_with("nochg"));
+    let text = lines.join("\n");
+    match succeeded {
+        true => (StatusCode::OK, [(header::CONTENT_TYPE, "text/plain; charset=utf-8")], text).into_response(),
+        false => dyndns_text(text),
+    }
+}
+
+/// A return code answered with status 200, which is never replayed as the
+/// request may succeed when retried.
+fn dyndns_text(text: String) -> Response {
+    (StatusCode::OK, [(header::CONTENT_TYPE, "text/plain; charset=utf-8")], Extension(NoReplay), text).into_response()
+}
+
+/// Username and password of an `Authorization: Basic` header
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let encoded = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Basic "))?;
+    let decoded = String::from_utf8(base64::engine::general_purpose::STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+/// The address a client connects from, in the page format of checkip.dyndns.org
+/// that ddclient and inadyn parse. With `detect.checkip_proxy`, clients on the
+/// LAN get this network's public address instead of their private one.
+async fn checkip(State(state): State<Arc<AppState>>, Extension(ClientIp(client_ip)): Extension<ClientIp>) -> Response {
+    let Some(mut ip) = client_ip else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Client address unknown").into_response();
+    };
+    if let (Some(detector), Some(_)) = (&state.checkip, cidr::non_public_range(ip)) {
+        ip = match detector.detect(ip.is_ipv6()).await {
+            Ok(public) => public,
+            Err(e) => {
+                warn!("Failed to detect the public IP for /checkip: {:#}", e);
+                return (StatusCode::BAD_GATEWAY, "Public address unknown").into_response();
+            }
+        };
+    }
+    (
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+        format!(
+            "<html><head><title>Current IP Check</title></head><body>Current IP Address: {}</body></html>\n",
+            ip
+        ),
+    )
+        .into_response()
+}
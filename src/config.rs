@@ -1,71 +0,0 @@
-use anyhow::{Context, Result};
-use serde::Deserialize;
-use std::fs;
-use std::path::Path;
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Config {
-    #[serde(default)]
-    pub server: ServerConfig,
-    pub providers: Vec<ProviderConfig>,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct ServerConfig {
-    #[serde(default = "default_host")]
-    pub host: String,
-    #[serde(default = "default_port")]
-    pub port: u16,
-    #[serde(default = "default_log_level")]
-    pub log_level: String,
-}
-
-fn default_host() -> String {
-    "0.0.0.0".to_string()
-}
-
-fn default_port() -> u16 {
-    3000
-}
-
-fn default_log_level() -> String {
-    "info".to_string()
-}
-
-impl Default for ServerConfig {
-    fn default() -> Self {
-        Self {
-            host: default_host(),
-            port: default_port(),
-            log_level: default_log_level(),
-        }
-    }
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct ProviderConfig {
-    pub name: String,
-    #[serde(rename = "type")]
-    pub provider_type: String,
-    #[serde(default)]
-    pub key: Option<String>,  // 访问密钥，用于鉴权（可选）
-    pub api_key: String,
-    pub zone_id: String,
-}
-
-impl Config {
-    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let path = path.as_ref();
-        let content = fs::read_to_string(path)
-            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
-
-        let config: Config = toml::from_str(&content)
-            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
-
-        Ok(config)
-    }
-
-    pub fn get_provider(&self, name: &str) -> Option<&ProviderConfig> {
-        self.providers.iter().find(|p| p.name == name)
-    }
-}
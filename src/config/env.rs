@@ -0,0 +1,110 @@
+use std::env;
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+
+use super::ConfigFormat;
+
+/// Prefix for environment variables that override config values
+const PREFIX: &str = "DDNS_";
+
+/// Separator between path segments, e.g. `DDNS_PROVIDERS__0__API_KEY`
+const SEPARATOR: &str = "__";
+
+/// Variable holding a complete inline config document
+const INLINE_VAR: &str = "DDNS_CONFIG";
+
+/// Variable selecting the format of `DDNS_CONFIG` (default TOML)
+const INLINE_FORMAT_VAR: &str = "DDNS_CONFIG_FORMAT";
+
+/// Whether the environment carries any configuration, so a missing config file is acceptable.
+pub fn has_env_config() -> bool {
+    env::var_os(INLINE_VAR).is_some() || path_vars().next().is_some()
+}
+
+/// Merges `DDNS_CONFIG` and then the `DDNS_*__*` path variables over `value`.
+pub fn apply(value: &mut Value) -> Result<()> {
+    if let Ok(inline) = env::var(INLINE_VAR) {
+        let format = match env::var(INLINE_FORMAT_VAR) {
+            Ok(f) => f.parse::<ConfigFormat>().map_err(anyhow::Error::msg)?,
+            Err(_) => ConfigFormat::Toml,
+        };
+        let inline = format
+            .parse(&inline)
+            .with_context(|| format!("Failed to parse {}", INLINE_VAR))?;
+        merge(value, inline);
+    }
+
+    // Sort so array indices are filled in order
+    let mut vars: Vec<(String, String)> = path_vars().collect();
+    vars.sort();
+
+    for (name, raw) in vars {
+        let path: Vec<String> = name[PREFIX.len()..]
+            .split(SEPARATOR)
+            .map(|segment| segment.to_ascii_lowercase())
+            .collect();
+        set_path(value, &path, parse_scalar(&raw)).with_context(|| format!("Invalid config variable {}", name))?;
+    }
+
+    Ok(())
+}
+
+fn path_vars() -> impl Iterator<Item = (String, String)> {
+    env::vars().filter(|(name, _)| {
+        name.starts_with(PREFIX) && name[PREFIX.len()..].contains(SEPARATOR)
+    })
+}
+
+/// Interprets a variable as JSON when possible (numbers, booleans, arrays),
+/// falling back to a plain string. Quote a value (`"0123"`) to force a string.
+fn parse_scalar(raw: &str) -> Value {
+    match serde_json::from_str::<Value>(raw) {
+        Ok(Value::Null) | Err(_) => Value::String(raw.to_string()),
+        Ok(v) => v,
+    }
+}
+
+fn set_path(target: &mut Value, path: &[String], new: Value) -> Result<()> {
+    let Some((segment, rest)) = path.split_first() else {
+        *target = new;
+        return Ok(());
+    };
+    if segment.is_empty() {
+        anyhow::bail!("empty path segment");
+    }
+
+    // Numeric segments index into arrays (created on demand)
+    if let Ok(index) = segment.parse::<usize>() {
+        if target.is_null() {
+            *target = Value::Array(Vec::new());
+        }
+        if let Value::Array(items) = target {
+            if index > items.len() {
+                anyhow::bail!("array index {} skips entries (next index is {})", index, items.len());
+            }
+            if index == items.len() {
+                items.push(Value::Null);
+            }
+            return set_path(&mut items[index], rest, new);
+        }
+    }
+
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(map) = target else { unreachable!() };
+    set_path(map.entry(segment.clone()).or_insert(Value::Null), rest, new)
+}
+
+/// Deep-merges `overlay` into `base`; objects merge per key, everything else is replaced.
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use super::ConfigFormat;
+
+/// Maximum nesting of included files, guarding against include cycles
+const MAX_DEPTH: usize = 8;
+
+/// Resolves the top-level `include` list of a parsed config, merging every
+/// matched file into `value`. Patterns are relative to `base_dir` and may use
+/// `*` / `?` wildcards in the file name (e.g. `providers.d/*.toml`).
+///
+/// Arrays such as `providers` are concatenated; for other values the
+/// including file wins.
+pub fn resolve(value: &mut Value, base_dir: &Path) -> Result<()> {
+    resolve_at_depth(value, base_dir, 0)
+}
+
+fn resolve_at_depth(value: &mut Value, base_dir: &Path, depth: usize) -> Result<()> {
+    let Some(patterns) = value.as_object_mut().and_then(|table| table.remove("include")) else {
+        return Ok(());
+    };
+
+    if depth >= MAX_DEPTH {
+        anyhow::bail!("Config includes nested deeper than {} levels", MAX_DEPTH);
+    }
+
+    let patterns: Vec<String> = serde_json::from_value(patterns)
+        .context("`include` must be a list of file patterns")?;
+
+    for pattern in patterns {
+        for path in expand(base_dir, &pattern)? {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read included config file: {}", path.display()))?;
+            let mut included = ConfigFormat::from_path(&path)
+                .parse(&content)
+                .with_context(|| format!("Failed to parse included config file: {}", path.display()))?;
+
+            let dir = path.parent().unwrap_or(base_dir).to_path_buf();
+            resolve_at_depth(&mut included, &dir, depth + 1)?;
+            merge(value, included);
+        }
+    }
+
+    Ok(())
+}
+
+/// Expands a pattern to the matching files, sorted by name. A pattern without
+/// wildcards must name an existing file; a wildcard pattern may match nothing.
+fn expand(base_dir: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+    let full = base_dir.join(pattern);
+    let file_pattern = full
+        .file_name()
+        .and_then(|f| f.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid include pattern: {}", pattern))?;
+
+    if !file_pattern.contains(['*', '?']) {
+        return Ok(vec![full]);
+    }
+
+    let dir = match full.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read include directory: {}", dir.display()))
+        }
+    };
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else { continue };
+        if entry.file_type()?.is_file() && wildcard_match(file_pattern, name) {
+            paths.push(entry.path());
+        }
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
+/// Matches `name` against a pattern where `*` matches any run of characters and `?` one character.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn merge(base: &mut Value, included: Value) {
+    match (base, included) {
+        (Value::Object(base), Value::Object(included)) => {
+            for (key, value) in included {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(included)) => base.extend(included),
+        // The including file takes precedence for scalar values
+        _ => {}
+    }
+}
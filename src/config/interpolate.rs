@@ -0,0 +1,68 @@
+use std::env;
+
+use anyhow::Result;
+use serde_json::Value;
+
+/// Expands `${VAR}` and `${VAR:-default}` references in every string of a
+/// parsed config tree. `$${` produces a literal `${`.
+pub fn interpolate(value: &mut Value) -> Result<()> {
+    walk(value, "")
+}
+
+fn walk(value: &mut Value, path: &str) -> Result<()> {
+    match value {
+        Value::String(s) if s.contains('$') => {
+            *s = expand(s).map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                walk(item, &format!("{}[{}]", path, i))?;
+            }
+        }
+        Value::Object(table) => {
+            for (key, item) in table.iter_mut() {
+                let child = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                walk(item, &child)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn expand(input: &str) -> Result<String> {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+
+        if let Some(escaped) = after.strip_prefix("${") {
+            out.push_str("${");
+            rest = escaped;
+        } else if let Some(reference) = after.strip_prefix('{') {
+            let end = reference
+                .find('}')
+                .ok_or_else(|| anyhow::anyhow!("unterminated variable reference in \"{}\"", input))?;
+            let (name, default) = match reference[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&reference[..end], None),
+            };
+
+            match (env::var(name), default) {
+                (Ok(v), _) => out.push_str(&v),
+                (Err(_), Some(d)) => out.push_str(d),
+                (Err(_), None) => anyhow::bail!("environment variable {} is not set", name),
+            }
+            rest = &reference[end + 1..];
+        } else {
+            out.push('$');
+            rest = after;
+        }
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
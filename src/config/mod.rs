@@ -0,0 +1,1578 @@
+mod env;
+mod include;
+mod interpolate;
+mod validate;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::cidr::Cidr;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub ui: UiConfig,
+    #[serde(default)]
+    pub propagation: PropagationConfig,
+    #[serde(default)]
+    pub reconcile: ReconcileConfig,
+    #[serde(default)]
+    pub poll: Vec<PollJobConfig>,
+    #[serde(default)]
+    pub kubernetes: KubernetesConfig,
+    #[serde(default)]
+    pub docker: DockerConfig,
+    #[serde(default)]
+    pub detect: DetectConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    pub scripting: ScriptingConfig,
+    #[serde(default)]
+    pub retry_queue: RetryQueueConfig,
+    #[serde(default)]
+    pub redis: RedisConfig,
+    #[serde(default)]
+    pub leader: LeaderConfig,
+    #[serde(default)]
+    pub udp: UdpConfig,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    #[serde(default)]
+    pub messages: MessagesConfig,
+    #[serde(default)]
+    pub providers: Vec<ProviderConfig>,
+    #[serde(default)]
+    pub tenants: Vec<TenantConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerConfig {
+    #[serde(default = "default_host")]
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub listen: Vec<SocketAddr>,  // 同时监听的多个地址，如 ["0.0.0.0:3000", "[::]:3000"]，配置后忽略 host 与 port
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    #[serde(default = "default_pool_idle_timeout_secs")]
+    pub pool_idle_timeout_secs: u64,
+    #[serde(default = "default_pool_max_idle_per_host")]
+    pub pool_max_idle_per_host: usize,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,  // 提供商 API 请求总超时
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,  // 提供商 API 连接超时
+    #[serde(default)]
+    pub rate_limit_per_ip: u32,  // 每个来源 IP 每分钟请求数，0 表示不限制
+    #[serde(default)]
+    pub rate_limit_per_key: u32,  // 每个访问密钥每分钟请求数，0 表示不限制
+    #[serde(default = "default_signature_window_secs")]
+    pub signature_window_secs: u64,  // 签名请求时间戳允许的偏差
+    #[serde(default = "default_readiness_cache_secs")]
+    pub readiness_cache_secs: u64,  // /health/ready 检查结果的缓存时间
+    #[serde(default)]
+    pub allowed_ips: Vec<Cidr>,  // 允许访问的来源 IP（CIDR），为空表示全部
+    #[serde(default)]
+    pub denied_ips: Vec<Cidr>,  // 拒绝访问的来源 IP（CIDR）
+    #[serde(default)]
+    pub max_concurrent_updates: usize,  // 同时进行的提供商更新请求数上限，超出的排队等待，0 表示不限制
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,  // 请求体大小上限（字节），超出返回 413
+    #[serde(default = "default_max_uri_length")]
+    pub max_uri_length: usize,  // 请求 URI（路径加查询参数）长度上限，超出返回 414，0 表示不限制
+    #[serde(default = "default_security_headers")]
+    pub security_headers: bool,  // 为响应加上 X-Content-Type-Options、X-Frame-Options 等安全头
+    #[serde(default)]
+    pub trusted_proxies: Vec<Cidr>,  // 只采信这些反向代理发来的 X-Forwarded-For / X-Real-IP，为空表示都不采信、只用对端地址
+    #[serde(default)]
+    pub state_file: Option<String>,  // 保存各主机最近一次推送的 IP，重启后仍可跳过未变化的更新
+    #[serde(default = "default_access_log_format")]
+    pub access_log_format: String,  // 访问日志格式：default、common、combined、json 或自定义模板
+    #[serde(default = "default_idempotency_window_secs")]
+    pub idempotency_window_secs: u64,  // 带相同 Idempotency-Key 的重试在此时间内直接返回首次的结果，0 表示关闭
+    #[serde(default)]
+    pub dedupe_retries: bool,  // 没有 Idempotency-Key 时，把方法、URI、来源 IP、认证信息和请求体都相同的请求视为重试
+    #[serde(default)]
+    pub check_credentials: bool,  // 启动时及此后每天检查各提供商的凭据，失效或即将过期时警告并通知
+    #[serde(default = "default_credential_expiry_warning_days")]
+    pub credential_expiry_warning_days: u32,  // 凭据在这么多天内过期时提前警告
+}
+
+fn default_credential_expiry_warning_days() -> u32 {
+    14
+}
+
+fn default_host() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_port() -> u16 {
+    3000
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_pool_idle_timeout_secs() -> u64 {
+    90
+}
+
+fn default_pool_max_idle_per_host() -> usize {
+    8
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_idempotency_window_secs() -> u64 {
+    60
+}
+
+fn default_signature_window_secs() -> u64 {
+    300
+}
+
+fn default_readiness_cache_secs() -> u64 {
+    60
+}
+
+fn default_max_body_bytes() -> usize {
+    16 * 1024
+}
+
+fn default_max_uri_length() -> usize {
+    2048
+}
+
+fn default_security_headers() -> bool {
+    true
+}
+
+fn default_access_log_format() -> String {
+    "default".to_string()
+}
+
+fn default_ttl() -> u32 {
+    1
+}
+
+fn default_create_missing() -> bool {
+    true
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: default_host(),
+            port: default_port(),
+            listen: Vec::new(),
+            log_level: default_log_level(),
+            pool_idle_timeout_secs: default_pool_idle_timeout_secs(),
+            pool_max_idle_per_host: default_pool_max_idle_per_host(),
+            timeout_secs: default_timeout_secs(),
+            connect_timeout_secs: default_connect_timeout_secs(),
+            rate_limit_per_ip: 0,
+            rate_limit_per_key: 0,
+            signature_window_secs: default_signature_window_secs(),
+            readiness_cache_secs: default_readiness_cache_secs(),
+            allowed_ips: Vec::new(),
+            denied_ips: Vec::new(),
+            max_concurrent_updates: 0,
+            max_body_bytes: default_max_body_bytes(),
+            max_uri_length: default_max_uri_length(),
+            security_headers: default_security_headers(),
+            trusted_proxies: Vec::new(),
+            state_file: None,
+            access_log_format: default_access_log_format(),
+            idempotency_window_secs: default_idempotency_window_secs(),
+            dedupe_retries: false,
+            check_credentials: false,
+            credential_expiry_warning_days: default_credential_expiry_warning_days(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub tokens: Vec<TokenConfig>,
+}
+
+/// Web dashboard served at `/ui`, protected by HTTP Basic auth
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UiConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub password_hash: Option<String>,  // argon2/bcrypt 哈希，可代替明文 password
+}
+
+/// Post-update check that the new IP is actually served by DNS
+#[derive(Debug, Clone, Deserialize)]
+pub struct PropagationConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub resolvers: Vec<String>,  // 用于校验的 DNS 服务器（IP、DoH URL 或 cloudflare、google），为空时查询域名的权威 DNS
+    #[serde(default = "default_propagation_window_secs")]
+    pub window_secs: u64,  // 重试校验的最长时间
+    #[serde(default = "default_propagation_interval_secs")]
+    pub interval_secs: u64,  // 两次校验之间的间隔
+}
+
+/// Names usable in `propagation.resolvers` for well-known DNS-over-HTTPS resolvers
+pub const DOH_RESOLVERS: &[(&str, &str)] = &[
+    ("cloudflare", "https://cloudflare-dns.com/dns-query"),
+    ("google", "https://dns.google/dns-query"),
+    ("quad9", "https://dns.quad9.net/dns-query"),
+];
+
+fn default_propagation_window_secs() -> u64 {
+    60
+}
+
+fn default_propagation_interval_secs() -> u64 {
+    5
+}
+
+impl Default for PropagationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            resolvers: Vec::new(),
+            window_secs: default_propagation_window_secs(),
+            interval_secs: default_propagation_interval_secs(),
+        }
+    }
+}
+
+/// Periodic check that managed records still hold the IPs last set
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReconcileConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_reconcile_interval_secs")]
+    pub interval_secs: u64,  // 两次检查之间的间隔
+}
+
+fn default_reconcile_interval_secs() -> u64 {
+    300
+}
+
+impl Default for ReconcileConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: default_reconcile_interval_secs(),
+        }
+    }
+}
+
+/// Commands run after a host's address changed
+#[derive(Debug, Clone, Deserialize)]
+pub struct HooksConfig {
+    #[serde(default)]
+    pub on_change: Option<String>,  // 地址变化后执行的命令（Unix 上通过 sh -c，Windows 上通过 cmd /C），可被主机配置覆盖
+    #[serde(default = "default_hook_timeout_secs")]
+    pub timeout_secs: u64,  // 命令的超时时间，超时后终止该命令
+    #[serde(default = "default_wg_command")]
+    pub wg_command: String,  // 更新 WireGuard 对端时调用的 wg 程序
+    #[serde(default)]
+    pub wireguard: Vec<WireGuardPeerConfig>,  // 主机地址变化后更新的 WireGuard 对端 endpoint
+}
+
+fn default_hook_timeout_secs() -> u64 {
+    30
+}
+
+fn default_wg_command() -> String {
+    "wg".to_string()
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            on_change: None,
+            timeout_secs: default_hook_timeout_secs(),
+            wg_command: default_wg_command(),
+            wireguard: Vec::new(),
+        }
+    }
+}
+
+/// A WireGuard peer whose endpoint follows a host's address
+#[derive(Debug, Clone, Deserialize)]
+pub struct WireGuardPeerConfig {
+    pub interface: String,  // 本机 WireGuard 接口，如 "wg0"
+    pub peer: String,  // 对端公钥
+    pub host: String,  // 对端 endpoint 使用该主机更新后的地址
+    #[serde(default = "default_wireguard_port")]
+    pub port: u16,  // 对端监听端口
+    #[serde(default)]
+    pub record_type: HostRecordType,  // 使用哪种地址：A（IPv4）、AAAA（IPv6）或 both（最近一次变化的地址）
+}
+
+fn default_wireguard_port() -> u16 {
+    51820
+}
+
+/// A Rhai script deciding about updates (requires the `scripting` feature)
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScriptingConfig {
+    #[serde(default)]
+    pub path: Option<String>,  // 脚本文件，相对于配置文件所在目录
+    #[serde(default = "default_script_max_operations")]
+    pub max_operations: u64,  // 每次调用脚本函数最多执行的操作数，防止死循环拖住请求
+}
+
+fn default_script_max_operations() -> u64 {
+    100_000
+}
+
+impl Default for ScriptingConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_operations: default_script_max_operations(),
+        }
+    }
+}
+
+/// Failed updates persisted to SQLite and retried until they succeed or
+/// expire (requires the `sqlite` feature)
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetryQueueConfig {
+    #[serde(default)]
+    pub path: Option<String>,  // SQLite 数据库文件，配置后启用
+    #[serde(default = "default_retry_initial_delay_secs")]
+    pub initial_delay_secs: u64,  // 第一次重试前的等待时间，之后每次翻倍（最长 1 小时）
+    #[serde(default = "default_retry_expire_secs")]
+    pub expire_secs: u64,  // 第一次失败后超过这个时间仍未成功的更新会被丢弃
+}
+
+fn default_retry_initial_delay_secs() -> u64 {
+    30
+}
+
+fn default_retry_expire_secs() -> u64 {
+    24 * 60 * 60
+}
+
+impl Default for RetryQueueConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            initial_delay_secs: default_retry_initial_delay_secs(),
+            expire_secs: default_retry_expire_secs(),
+        }
+    }
+}
+
+/// Redis shared by instances behind a load balancer, for the last pushed
+/// records, rate limits and per-host update locks (requires the `redis` feature)
+#[derive(Debug, Clone, Deserialize)]
+pub struct RedisConfig {
+    #[serde(default)]
+    pub url: Option<String>,  // redis://[:password@]host:6379/0，配置后启用
+    #[serde(default = "default_redis_key_prefix")]
+    pub key_prefix: String,  // 键名前缀，几套部署共用一个 Redis 时用来区分
+    #[serde(default = "default_redis_lock_timeout_secs")]
+    pub lock_timeout_secs: u64,  // 更新锁的有效期，也是等待另一个实例更新同一主机的最长时间
+}
+
+fn default_redis_key_prefix() -> String {
+    "ddns:".to_string()
+}
+
+fn default_redis_lock_timeout_secs() -> u64 {
+    60
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            key_prefix: default_redis_key_prefix(),
+            lock_timeout_secs: default_redis_lock_timeout_secs(),
+        }
+    }
+}
+
+/// Leader election between instances run side by side for redundancy: only
+/// the leader runs `[reconcile]`, `[[poll]]` and `[kubernetes]` sync
+#[derive(Debug, Clone, Deserialize)]
+pub struct LeaderConfig {
+    #[serde(default)]
+    pub election: LeaderElection,
+    #[serde(default)]
+    pub lock_file: Option<String>,  // election = "file" 时加锁的文件，需位于各实例共享的目录
+    #[serde(default = "default_leader_lease_secs")]
+    pub lease_secs: u64,  // Redis 租约的有效期，每三分之一个周期续约；也是备用实例重新竞选的间隔
+}
+
+fn default_leader_lease_secs() -> u64 {
+    15
+}
+
+impl Default for LeaderConfig {
+    fn default() -> Self {
+        Self {
+            election: LeaderElection::default(),
+            lock_file: None,
+            lease_secs: default_leader_lease_secs(),
+        }
+    }
+}
+
+/// How the leader is chosen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LeaderElection {
+    /// Every instance runs the background tasks
+    #[default]
+    None,
+    /// Whoever holds an exclusive lock on `lock_file`
+    File,
+    /// Whoever holds a lease in `[redis]`
+    Redis,
+}
+
+/// Listener for signed update datagrams from devices too small for HTTPS
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UdpConfig {
+    #[serde(default)]
+    pub listen: Option<String>,  // 监听地址，如 0.0.0.0:5300，配置后启用
+}
+
+/// Stops calling a provider whose API keeps failing, until a probe succeeds
+#[derive(Debug, Clone, Deserialize)]
+pub struct CircuitBreakerConfig {
+    #[serde(default)]
+    pub failure_threshold: u32,  // 连续多少次提供商不可用后断开，默认 0 表示不启用
+    #[serde(default = "default_circuit_open_secs")]
+    pub open_secs: u64,  // 断开多久后放行一次试探请求
+}
+
+fn default_circuit_open_secs() -> u64 {
+    60
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 0,
+            open_secs: default_circuit_open_secs(),
+        }
+    }
+}
+
+/// Language of built-in texts, and custom `message`s of update results
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MessagesConfig {
+    #[serde(default)]
+    pub language: Language,  // 内置文字的语言：en（默认）或 zh，用于更新结果的 message 和未自定义的通知模板
+    #[serde(default)]
+    pub created: Option<String>,  // 新建记录时的 message，支持 {provider}、{host}、{old}、{new}
+    #[serde(default)]
+    pub updated: Option<String>,  // 修改记录时的 message
+    #[serde(default)]
+    pub no_change: Option<String>,  // 记录已是最新时的 message
+    #[serde(default)]
+    pub deleted: Option<String>,  // 删除记录时的 message
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    En,
+    Zh,
+}
+
+/// Append-only log of record changes, separate from the access log
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditConfig {
+    #[serde(default)]
+    pub path: Option<String>,  // 审计日志文件（JSON Lines），配置后启用
+    #[serde(default)]
+    pub hash_chain: bool,  // 每行记录上一行的哈希，删改记录会使链断开
+    #[serde(default)]
+    pub key: Option<String>,  // 用 HMAC-SHA256 签名代替普通哈希（隐含 hash_chain）
+}
+
+/// A `[[poll]]` job: detects the public IP on a schedule and updates `hosts` with it
+#[derive(Debug, Clone, Deserialize)]
+pub struct PollJobConfig {
+    pub provider: String,
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub record_type: HostRecordType,  // 检测哪种地址：A（IPv4）、AAAA（IPv6）或 both
+    #[serde(default)]
+    pub interval_secs: Option<u64>,  // 固定间隔，与 cron 二选一
+    #[serde(default)]
+    pub cron: Option<String>,  // 5 字段 cron 表达式（UTC），如 "*/15 * * * *"
+    #[serde(default)]
+    pub jitter_secs: u64,  // 每次运行前随机延迟 0 ~ jitter_secs 秒
+    #[serde(default)]
+    pub ipv6_prefix_len: Option<u8>,  // 设置后检测到的 IPv6 视为委派前缀，与各主机的 ipv6_suffix 组合
+    #[serde(default)]
+    pub ping_url: Option<String>,  // 每次运行全部成功后请求该地址，如 healthchecks.io 的 ping URL
+    #[serde(default)]
+    pub ping_failure_url: Option<String>,  // 检测或更新失败时请求该地址，如 healthchecks.io 的 .../fail
+}
+
+/// Hosts named in Kubernetes Ingress and Service annotations, kept pointed
+/// at the detected public IP (requires the `kubernetes` feature)
+#[derive(Debug, Clone, Deserialize)]
+pub struct KubernetesConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub provider: String,  // 更新记录使用的提供商名称
+    #[serde(default = "default_kubernetes_annotation")]
+    pub annotation: String,  // 注解名，值为逗号分隔的主机名
+    #[serde(default)]
+    pub namespace: Option<String>,  // 只查看该命名空间，未配置时查看全部
+    #[serde(default = "default_kubernetes_interval_secs")]
+    pub interval_secs: u64,  // 两次同步之间的间隔
+    #[serde(default)]
+    pub record_type: HostRecordType,  // 检测哪种地址：A（IPv4）、AAAA（IPv6）或 both
+    #[serde(default)]
+    pub api_server: Option<String>,  // API Server 地址，默认使用集群内的 KUBERNETES_SERVICE_HOST / KUBERNETES_SERVICE_PORT
+    #[serde(default = "default_kubernetes_token_file")]
+    pub token_file: String,  // ServiceAccount Token 文件
+    #[serde(default)]
+    pub ca_file: Option<String>,  // API Server 的 CA 证书，默认使用 ServiceAccount 的 ca.crt（存在时）
+}
+
+fn default_kubernetes_annotation() -> String {
+    "ddns-rust.io/hostname".to_string()
+}
+
+fn default_kubernetes_interval_secs() -> u64 {
+    300
+}
+
+fn default_kubernetes_token_file() -> String {
+    "/var/run/secrets/kubernetes.io/serviceaccount/token".to_string()
+}
+
+impl Default for KubernetesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            provider: String::new(),
+            annotation: default_kubernetes_annotation(),
+            namespace: None,
+            interval_secs: default_kubernetes_interval_secs(),
+            record_type: HostRecordType::default(),
+            api_server: None,
+            token_file: default_kubernetes_token_file(),
+            ca_file: None,
+        }
+    }
+}
+
+/// Hosts named in labels of running Docker containers, kept pointed at the
+/// detected public IP and deleted when their containers stop
+#[derive(Debug, Clone, Deserialize)]
+pub struct DockerConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub provider: String,  // 默认提供商，容器可以用 `<label_prefix>.provider` 标签指定其他提供商
+    #[serde(default = "default_docker_socket")]
+    pub socket: String,  // Docker API 地址：Unix socket 路径或 tcp://host:port
+    #[serde(default = "default_docker_label_prefix")]
+    pub label_prefix: String,  // 标签前缀，主机名写在 `<label_prefix>.host` 中（逗号分隔）
+    #[serde(default = "default_docker_interval_secs")]
+    pub interval_secs: u64,  // 除容器启停事件外，定期重新同步的间隔
+    #[serde(default)]
+    pub record_type: HostRecordType,  // 检测哪种地址：A（IPv4）、AAAA（IPv6）或 both
+    #[serde(default = "default_docker_remove_on_stop")]
+    pub remove_on_stop: bool,  // 容器停止后删除其主机的记录
+}
+
+fn default_docker_socket() -> String {
+    "/var/run/docker.sock".to_string()
+}
+
+fn default_docker_label_prefix() -> String {
+    "ddns".to_string()
+}
+
+fn default_docker_interval_secs() -> u64 {
+    300
+}
+
+fn default_docker_remove_on_stop() -> bool {
+    true
+}
+
+impl Default for DockerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            provider: String::new(),
+            socket: default_docker_socket(),
+            label_prefix: default_docker_label_prefix(),
+            interval_secs: default_docker_interval_secs(),
+            record_type: HostRecordType::default(),
+            remove_on_stop: default_docker_remove_on_stop(),
+        }
+    }
+}
+
+/// Echo services for `[[poll]]` jobs, queried together so one bad answer is outvoted
+#[derive(Debug, Clone, Deserialize)]
+pub struct DetectConfig {
+    #[serde(default = "default_detect_sources")]
+    pub ipv4_sources: Vec<String>,  // 内置来源名（ipify、icanhazip、cloudflare）或返回 IP 的 URL
+    #[serde(default = "default_detect_sources")]
+    pub ipv6_sources: Vec<String>,
+    #[serde(default)]
+    pub quorum: Option<usize>,  // 至少多少个来源结果一致，默认超过半数
+    #[serde(default)]
+    pub ipv6_interface: Option<String>,  // 直接读取该网卡的 IPv6 地址，代替 ipv6_sources（仅 Linux）
+    #[serde(default)]
+    pub ipv6_select: Ipv6Selection,  // 网卡有多个地址时选择哪一个
+    #[serde(default)]
+    pub checkip_proxy: bool,  // /checkip 收到内网地址的请求时，返回检测到的本机公网 IP
+    #[serde(default = "default_checkip_cache_secs")]
+    pub checkip_cache_secs: u64,  // 代理模式下缓存检测结果的时间
+}
+
+/// Which of an interface's public IPv6 addresses `detect.ipv6_interface` picks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ipv6Selection {
+    /// Any address that is not a rotating privacy address
+    #[default]
+    Stable,
+    /// A stable address derived from the MAC address
+    Eui64,
+    /// A privacy extension address
+    Temporary,
+    Any,
+}
+
+impl Ipv6Selection {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Ipv6Selection::Stable => "stable",
+            Ipv6Selection::Eui64 => "eui64",
+            Ipv6Selection::Temporary => "temporary",
+            Ipv6Selection::Any => "any",
+        }
+    }
+}
+
+fn default_checkip_cache_secs() -> u64 {
+    300
+}
+
+fn default_detect_sources() -> Vec<String> {
+    vec!["ipify".to_string(), "icanhazip".to_string(), "cloudflare".to_string()]
+}
+
+impl Default for DetectConfig {
+    fn default() -> Self {
+        Self {
+            ipv4_sources: default_detect_sources(),
+            ipv6_sources: default_detect_sources(),
+            quorum: None,
+            ipv6_interface: None,
+            ipv6_select: Ipv6Selection::default(),
+            checkip_proxy: false,
+            checkip_cache_secs: default_checkip_cache_secs(),
+        }
+    }
+}
+
+/// OpenTelemetry trace export (requires the `otel` feature)
+#[derive(Debug, Clone, Deserialize)]
+pub struct TelemetryConfig {
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,  // OTLP/HTTP 地址，如 http://localhost:4318，未设置时不导出
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    #[serde(default = "default_sample_ratio")]
+    pub sample_ratio: f64,  // 采样比例，0.0 ~ 1.0
+}
+
+fn default_service_name() -> String {
+    "ddns-rust".to_string()
+}
+
+fn default_sample_ratio() -> f64 {
+    1.0
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: default_service_name(),
+            sample_ratio: default_sample_ratio(),
+        }
+    }
+}
+
+/// Messages sent when a record changes or updates keep failing
+#[derive(Debug, Clone, Deserialize)]
+pub struct NotificationsConfig {
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,  // 同一提供商连续失败多少次后通知一次，0 表示不通知失败和恢复
+    #[serde(default)]
+    pub telegram: Option<TelegramConfig>,
+    #[serde(default)]
+    pub discord: Option<WebhookConfig>,
+    #[serde(default)]
+    pub slack: Option<WebhookConfig>,
+    #[serde(default)]
+    pub email: Option<EmailConfig>,
+    #[serde(default)]
+    pub ntfy: Option<NtfyConfig>,
+    #[serde(default)]
+    pub gotify: Option<GotifyConfig>,
+    #[serde(default)]
+    pub mqtt: Option<MqttConfig>,
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+impl NotificationsConfig {
+    /// Replaces the built-in English templates left unchanged with those of `language`.
+    fn localize(&mut self, language: Language) {
+        if language == Language::En {
+            return;
+        }
+        let templates = [
+            self.telegram.as_mut().map(|c| &mut c.templates),
+            self.discord.as_mut().map(|c| &mut c.templates),
+            self.slack.as_mut().map(|c| &mut c.templates),
+            self.email.as_mut().map(|c| &mut c.templates),
+            self.ntfy.as_mut().map(|c| &mut c.templates),
+            self.gotify.as_mut().map(|c| &mut c.templates),
+        ];
+        for templates in templates.into_iter().flatten() {
+            localize(&mut templates.template, default_change_template(), "DNS 记录 {host}（{provider}）已从 {old_ip} 变更为 {new_ip}");
+            localize(
+                &mut templates.failure_template,
+                default_failure_template(),
+                "通过 {provider} 的更新已连续失败 {failures} 次，最近一次为 {host} 更新到 {new_ip}：{error}",
+            );
+            localize(
+                &mut templates.recovery_template,
+                default_recovery_template(),
+                "通过 {provider} 的更新在失败 {failures} 次后已恢复：{host} 现在为 {new_ip}",
+            );
+            localize(
+                &mut templates.drift_template,
+                default_drift_template(),
+                "DNS 记录 {host}（{provider}）在 ddns-rust 之外被改为 {old_ip}，已恢复为 {new_ip}",
+            );
+            localize(&mut templates.credentials_template, default_credentials_template(), "{provider} 的凭据需要处理：{error}");
+        }
+        if let Some(ref mut email) = self.email {
+            localize(&mut email.subject, default_email_subject(), "[ddns-rust] {host} 已变更为 {new_ip}");
+            localize(&mut email.failure_subject, default_email_failure_subject(), "[ddns-rust] 通过 {provider} 的更新持续失败");
+            localize(&mut email.recovery_subject, default_email_recovery_subject(), "[ddns-rust] 通过 {provider} 的更新已恢复");
+            localize(&mut email.drift_subject, default_email_drift_subject(), "[ddns-rust] {host} 在 ddns-rust 之外被修改");
+            localize(&mut email.credentials_subject, default_email_credentials_subject(), "[ddns-rust] {provider} 的凭据需要处理");
+        }
+    }
+}
+
+/// Sets `field` to `localized` unless it was changed from `default`.
+fn localize(field: &mut String, default: String, localized: &str) {
+    if *field == default {
+        *field = localized.to_string();
+    }
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: default_failure_threshold(),
+            telegram: None,
+            discord: None,
+            slack: None,
+            email: None,
+            ntfy: None,
+            gotify: None,
+            mqtt: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+    #[serde(flatten)]
+    pub templates: MessageTemplates,
+    #[serde(default)]
+    pub api_base: Option<String>,  // 覆盖 Telegram Bot API 地址，用于测试或代理
+}
+
+/// A Discord or Slack incoming webhook
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    pub webhook_url: String,
+    #[serde(flatten)]
+    pub templates: MessageTemplates,
+}
+
+/// Email alerts sent through an SMTP server
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmailConfig {
+    pub server: String,
+    #[serde(default)]
+    pub port: Option<u16>,  // 默认按 tls 选择：starttls 587，tls 465，none 25
+    #[serde(default)]
+    pub tls: SmtpTls,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default = "default_email_subject")]
+    pub subject: String,  // 记录变更时的邮件标题模板
+    #[serde(default = "default_email_failure_subject")]
+    pub failure_subject: String,  // 连续失败时的邮件标题模板
+    #[serde(default = "default_email_recovery_subject")]
+    pub recovery_subject: String,  // 恢复时的邮件标题模板
+    #[serde(default = "default_email_drift_subject")]
+    pub drift_subject: String,  // 记录被外部修改并已恢复时的邮件标题模板
+    #[serde(default = "default_email_credentials_subject")]
+    pub credentials_subject: String,  // 凭据失效或即将过期时的邮件标题模板
+    #[serde(flatten)]
+    pub templates: MessageTemplates,
+}
+
+/// How the SMTP connection is secured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain connection upgraded with STARTTLS
+    #[default]
+    Starttls,
+    /// TLS from the start (SMTPS)
+    Tls,
+    /// No encryption, only for local relays
+    None,
+}
+
+/// Push notifications through an ntfy server
+#[derive(Debug, Clone, Deserialize)]
+pub struct NtfyConfig {
+    #[serde(default = "default_ntfy_url")]
+    pub url: String,  // ntfy 服务器地址，自建时修改
+    pub topic: String,
+    #[serde(default)]
+    pub token: Option<String>,  // 访问令牌，受保护的主题需要
+    #[serde(default)]
+    pub priority: Option<u8>,  // 1-5，未配置时使用服务器默认值
+    #[serde(default = "default_push_title")]
+    pub title: String,
+    #[serde(flatten)]
+    pub templates: MessageTemplates,
+}
+
+fn default_ntfy_url() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+/// Push notifications through a Gotify server
+#[derive(Debug, Clone, Deserialize)]
+pub struct GotifyConfig {
+    pub url: String,
+    pub token: String,  // Gotify 应用令牌
+    #[serde(default = "default_gotify_priority")]
+    pub priority: u8,
+    #[serde(default = "default_push_title")]
+    pub title: String,
+    #[serde(flatten)]
+    pub templates: MessageTemplates,
+}
+
+/// IP change events published to an MQTT broker, e.g. for Home Assistant
+#[derive(Debug, Clone, Deserialize)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default)]
+    pub port: Option<u16>,  // 默认 1883，开启 tls 时 8883
+    #[serde(default)]
+    pub tls: bool,
+    #[serde(default)]
+    pub ca_file: Option<String>,  // 自签名证书的 CA（PEM），未配置时使用系统证书
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    #[serde(default = "default_mqtt_topic")]
+    pub topic: String,  // 发布的主题，支持 {provider} 和 {host}
+    #[serde(default = "default_mqtt_qos")]
+    pub qos: u8,  // 0、1 或 2
+    #[serde(default = "default_mqtt_retain")]
+    pub retain: bool,  // 保留消息，订阅者连接后立即收到当前 IP
+}
+
+fn default_mqtt_client_id() -> String {
+    "ddns-rust".to_string()
+}
+
+fn default_mqtt_topic() -> String {
+    "ddns-rust/{provider}/{host}".to_string()
+}
+
+fn default_mqtt_qos() -> u8 {
+    1
+}
+
+fn default_mqtt_retain() -> bool {
+    true
+}
+
+fn default_gotify_priority() -> u8 {
+    5
+}
+
+fn default_push_title() -> String {
+    "ddns-rust".to_string()
+}
+
+fn default_email_subject() -> String {
+    "[ddns-rust] {host} changed to {new_ip}".to_string()
+}
+
+fn default_email_failure_subject() -> String {
+    "[ddns-rust] Updates via {provider} are failing".to_string()
+}
+
+fn default_email_recovery_subject() -> String {
+    "[ddns-rust] Updates via {provider} recovered".to_string()
+}
+
+fn default_email_drift_subject() -> String {
+    "[ddns-rust] {host} was changed outside ddns-rust".to_string()
+}
+
+fn default_email_credentials_subject() -> String {
+    "[ddns-rust] Credentials of {provider} need attention".to_string()
+}
+
+impl NotificationsConfig {
+    /// Names of the configured channels, as used in a provider's `notify` list.
+    pub fn channels(&self) -> Vec<&'static str> {
+        let mut channels = Vec::new();
+        if self.telegram.is_some() {
+            channels.push("telegram");
+        }
+        if self.discord.is_some() {
+            channels.push("discord");
+        }
+        if self.slack.is_some() {
+            channels.push("slack");
+        }
+        if self.email.is_some() {
+            channels.push("email");
+        }
+        if self.ntfy.is_some() {
+            channels.push("ntfy");
+        }
+        if self.gotify.is_some() {
+            channels.push("gotify");
+        }
+        if self.mqtt.is_some() {
+            channels.push("mqtt");
+        }
+        channels
+    }
+}
+
+/// Message bodies per kind of event, shared by the text channels
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageTemplates {
+    #[serde(default = "default_change_template")]
+    pub template: String,  // 记录变更时的消息模板
+    #[serde(default = "default_failure_template")]
+    pub failure_template: String,  // 连续失败时的消息模板
+    #[serde(default = "default_recovery_template")]
+    pub recovery_template: String,  // 失败后恢复时的消息模板
+    #[serde(default = "default_drift_template")]
+    pub drift_template: String,  // 记录被外部修改并已恢复时的消息模板
+    #[serde(default = "default_credentials_template")]
+    pub credentials_template: String,  // 提供商凭据失效或即将过期时的消息模板
+}
+
+fn default_change_template() -> String {
+    "DNS record {host} ({provider}) changed from {old_ip} to {new_ip}".to_string()
+}
+
+fn default_failure_template() -> String {
+    "Updates via {provider} failed {failures} times in a row, last for {host} to {new_ip}: {error}".to_string()
+}
+
+fn default_recovery_template() -> String {
+    "Updates via {provider} recovered after {failures} failure(s): {host} is now {new_ip}".to_string()
+}
+
+fn default_drift_template() -> String {
+    "DNS record {host} ({provider}) was changed to {old_ip} outside ddns-rust and has been restored to {new_ip}".to_string()
+}
+
+fn default_credentials_template() -> String {
+    "Credentials of {provider} need attention: {error}".to_string()
+}
+
+/// A named API token accepted via `Authorization: Bearer <token>`
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenConfig {
+    pub name: String,
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub token_hash: Option<String>,  // argon2/bcrypt 哈希，可代替明文 token
+    #[serde(default)]
+    pub providers: Vec<String>,  // 允许的提供商名称，为空表示全部
+    #[serde(default)]
+    pub hosts: Vec<String>,  // 允许的主机名（支持 *.example.com），为空表示全部
+    #[serde(default = "default_token_scopes")]
+    pub scopes: Vec<Scope>,
+    #[serde(default)]
+    pub response: Option<ResponseTemplate>,  // 用该令牌更新时的响应体模板（可选），优先于提供商的模板
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// List records and other read-only endpoints
+    Read,
+    /// Update records
+    Write,
+    /// `/admin` routes such as maintenance mode and plans; never granted by default
+    Admin,
+}
+
+fn default_token_scopes() -> Vec<Scope> {
+    vec![Scope::Read, Scope::Write]
+}
+
+/// A separate namespace served under `/t/{name}/`, with its own providers,
+/// tokens, rate limits and update history
+#[derive(Debug, Clone, Deserialize)]
+pub struct TenantConfig {
+    pub name: String,
+    #[serde(default)]
+    pub providers: Vec<ProviderConfig>,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub rate_limit_per_ip: Option<u32>,  // 默认沿用 server.rate_limit_per_ip
+    #[serde(default)]
+    pub rate_limit_per_key: Option<u32>,  // 默认沿用 server.rate_limit_per_key
+    #[serde(default)]
+    pub state_file: Option<String>,  // 该租户单独的状态文件（可选）
+    #[serde(default)]
+    pub hooks: HooksConfig,  // 该租户的主机地址变化后执行的命令，不沿用顶层的 [hooks]
+    #[serde(default)]
+    pub notifications: NotificationsConfig,  // 该租户的通知，不沿用顶层的 [notifications]
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderConfig {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub provider_type: String,
+    #[serde(default)]
+    pub key: Option<String>,  // 访问密钥，用于鉴权（可选）
+    #[serde(default)]
+    pub key_env: Option<String>,  // 从该环境变量读取 key（可选）
+    #[serde(default)]
+    pub key_file: Option<String>,  // 从该文件读取 key，如 Docker/K8s secret（可选）
+    #[serde(default)]
+    pub key_hash: Option<String>,  // 访问密钥的 argon2/bcrypt 哈希，可代替明文 key（可选）
+    #[serde(default)]
+    pub require_signature: bool,  // 只接受 HMAC 签名请求，不接受 ?key= 明文密钥
+    #[serde(default)]
+    pub allowed_ips: Vec<Cidr>,  // 允许访问该提供商的来源 IP（CIDR），为空表示全部
+    #[serde(default)]
+    pub denied_ips: Vec<Cidr>,  // 拒绝访问该提供商的来源 IP（CIDR）
+    #[serde(default)]
+    pub api_key: String,
+    #[serde(default)]
+    pub api_key_env: Option<String>,  // 从该环境变量读取 api_key（可选）
+    #[serde(default)]
+    pub api_key_file: Option<String>,  // 从该文件读取 api_key，如 Docker/K8s secret（可选）
+    #[serde(default)]
+    pub api_base: Option<String>,  // 覆盖提供商 API 地址，用于测试或代理（可选）
+    #[serde(default)]
+    pub zone_id: Option<String>,  // 未配置时根据 zone_name 或主机名自动查找
+    #[serde(default)]
+    pub zone_name: Option<String>,
+    #[serde(default)]
+    pub account_id: Option<String>,  // Cloudflare 账户 ID，使用账户拥有的 API Token 时填写
+    #[serde(default)]
+    pub reverse_zone_id: Option<String>,  // 反向解析 Zone（in-addr.arpa / ip6.arpa）的 ID，未配置时按名称自动查找
+    #[serde(default)]
+    pub auth_mode: CloudflareAuthMode,  // Cloudflare 鉴权方式：token（API Token）或 global_key（Global API Key + 邮箱）
+    #[serde(default)]
+    pub email: Option<String>,  // global_key 鉴权时的账户邮箱
+    #[serde(default)]
+    pub multiple_records: MultipleRecordsPolicy,
+    #[serde(default)]
+    pub on_conflict: ConflictPolicy,  // 记录在别处（控制台、其他客户端）被修改后的处理：overwrite（覆盖）或 refuse（拒绝并返回 409）
+    #[serde(default = "default_ttl")]
+    pub ttl: u32,  // 记录 TTL，1 表示自动
+    #[serde(default)]
+    pub notify: Option<Vec<String>>,  // 使用的通知渠道，未配置时使用全部已配置的渠道
+    #[serde(default)]
+    pub proxied: bool,  // 是否开启 Cloudflare 代理
+    #[serde(default)]
+    pub record_type: HostRecordType,  // 允许更新的记录类型：A、AAAA 或 both（CNAME 只能按主机配置）
+    #[serde(default)]
+    pub allow_private_ips: bool,  // 允许更新为内网、回环、链路本地、CGNAT、组播等非公网地址
+    #[serde(default)]
+    pub min_update_interval_secs: u64,  // 同一主机两次更新的最短间隔，间隔内相同 IP 直接返回未变化，不同 IP 返回 429，0 表示不限制
+    #[serde(default)]
+    pub offline: OfflineAction,  // offline=yes 请求的处理方式：refuse（拒绝）、park（指向 offline_ips）或 delete（删除记录）
+    #[serde(default)]
+    pub offline_ips: Vec<IpAddr>,  // park 时的停放地址，每个地址族最多一个
+    #[serde(default)]
+    pub comment: Option<String>,  // 写入记录的备注（Cloudflare comment），如 "managed-by: ddns-rust, site: garage"
+    #[serde(default)]
+    pub tags: Vec<String>,  // 写入记录的标签（Cloudflare tags，"name:value"）
+    #[serde(default)]
+    pub managed_only: bool,  // 只修改和删除带有 comment 与 tags 的记录，同名的其他记录原样保留
+    #[serde(default)]
+    pub update_ptr: bool,  // A/AAAA 记录变化后同时把新地址的 PTR 记录指向该主机，需要反向 Zone 也在该提供商下
+    #[serde(default = "default_create_missing")]
+    pub create_missing: bool,  // 主机还没有该类型的记录时是否新建，false 时只更新已有记录，避免拼错的主机名产生垃圾记录
+    #[serde(default)]
+    pub hosts: Vec<HostConfig>,  // 单个主机名的配置，未配置的项使用提供商级默认值
+    #[serde(default)]
+    pub latency_ms: u64,  // mock 提供商：每次更新前模拟的延迟
+    #[serde(default)]
+    pub fail_every: u32,  // mock 提供商：每 N 次更新模拟一次失败，0 表示不失败
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,  // 覆盖 server.timeout_secs（可选）
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,  // 覆盖 server.connect_timeout_secs（可选）
+    #[serde(default)]
+    pub max_concurrent_updates: Option<usize>,  // 该提供商同时进行的更新请求数上限（可选），与 server 的上限同时生效
+    #[serde(default)]
+    pub response: Option<ResponseTemplate>,  // 自定义 /ddns 更新接口的响应体（可选），令牌的模板优先
+}
+
+/// Body answering `/ddns` updates in place of the JSON response, for clients
+/// that only accept an exact string
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResponseTemplate {
+    #[serde(default = "default_response_template")]
+    pub success: String,  // 成功时的响应体，支持 {status}、{ip}、{host}、{message}
+    #[serde(default = "default_response_template")]
+    pub failure: String,  // 失败时的响应体，{message} 为错误信息
+    #[serde(default = "default_response_content_type")]
+    pub content_type: String,
+}
+
+fn default_response_template() -> String {
+    "{message}".to_string()
+}
+
+fn default_response_content_type() -> String {
+    "text/plain; charset=utf-8".to_string()
+}
+
+/// Per-host overrides of the provider-level record settings
+#[derive(Debug, Clone, Deserialize)]
+pub struct HostConfig {
+    /// Hostname, or a `*.example.com` pattern
+    pub name: String,
+    #[serde(default)]
+    pub ttl: Option<u32>,
+    #[serde(default)]
+    pub proxied: Option<bool>,
+    #[serde(default)]
+    pub record_type: Option<HostRecordType>,  // CNAME 时路径中的 {ip} 是目标主机名
+    #[serde(default)]
+    pub allow_private_ips: Option<bool>,  // 如只在内网解析的主机
+    #[serde(default)]
+    pub min_update_interval_secs: Option<u64>,
+    #[serde(default)]
+    pub key: Option<String>,  // 该主机专用的访问密钥，覆盖提供商的 key / key_hash
+    #[serde(default)]
+    pub key_hash: Option<String>,
+    #[serde(default)]
+    pub ipv6_suffix: Option<Ipv6Addr>,  // 前缀委派模式下该主机的接口标识，如 "::10"
+    #[serde(default)]
+    pub offline: Option<OfflineAction>,
+    #[serde(default)]
+    pub offline_ips: Option<Vec<IpAddr>>,
+    #[serde(default)]
+    pub comment: Option<String>,  // 如按站点区分多条同名记录："managed-by: ddns-rust, site: garage"
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub update_ptr: Option<bool>,
+    #[serde(default)]
+    pub on_change: Option<String>,  // 覆盖 [hooks] 的 on_change，空字符串表示该主机不执行
+    #[serde(default)]
+    pub ips: Option<Vec<IpAddr>>,  // 期望的地址，供 plan / apply 对照现有记录；未配置时使用最近一次推送的地址
+}
+
+/// What an `offline=yes` update does to a host, for connections that are
+/// intentionally down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OfflineAction {
+    /// Refuse the request and leave the records alone
+    #[default]
+    Refuse,
+    /// Point the host at its `offline_ips`
+    Park,
+    /// Delete the host's A/AAAA records
+    Delete,
+}
+
+/// Which record types a host may be updated with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum HostRecordType {
+    A,
+    #[serde(rename = "AAAA")]
+    Aaaa,
+    #[default]
+    #[serde(rename = "both")]
+    Both,
+    /// Points the host at another hostname instead of an address
+    #[serde(rename = "CNAME")]
+    Cname,
+}
+
+impl HostRecordType {
+    /// Whether an update to `ip` is allowed (A for IPv4, AAAA for IPv6)
+    pub fn allows(self, ip: &IpAddr) -> bool {
+        matches!(
+            (self, ip),
+            (HostRecordType::Both, _) | (HostRecordType::A, IpAddr::V4(_)) | (HostRecordType::Aaaa, IpAddr::V6(_))
+        )
+    }
+}
+
+/// Effective settings for one host, after applying host overrides to provider defaults
+#[derive(Debug, Clone)]
+pub struct HostSettings<'a> {
+    pub ttl: u32,
+    pub proxied: bool,
+    pub record_type: HostRecordType,
+    pub allow_private_ips: bool,
+    pub min_update_interval_secs: u64,
+    pub key: Option<&'a str>,
+    pub key_hash: Option<&'a str>,
+    pub offline: OfflineAction,
+    pub offline_ips: &'a [IpAddr],
+    /// Label written to and, with `managed_only`, required on the host's records
+    pub comment: Option<&'a str>,
+    pub tags: &'a [String],
+    /// Point the new address's PTR record back at the host
+    pub update_ptr: bool,
+}
+
+/// What to do when a host matches more than one A record
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MultipleRecordsPolicy {
+    /// Update every matching record to the new IP
+    UpdateAll,
+    /// Keep one record with the new IP and delete the rest
+    ReplaceWithOne,
+    /// Refuse to update and report an error
+    #[default]
+    Error,
+}
+
+/// What to do when a record changed elsewhere since this service last wrote it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Write the new content anyway
+    #[default]
+    Overwrite,
+    /// Leave the record alone and report a conflict
+    Refuse,
+}
+
+/// How the Cloudflare provider authenticates to the API
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloudflareAuthMode {
+    /// `api_key` is an API token, sent as `Authorization: Bearer`
+    #[default]
+    Token,
+    /// `api_key` is the legacy Global API Key, sent with `email` as
+    /// `X-Auth-Key` and `X-Auth-Email`
+    GlobalKey,
+}
+
+impl ProviderConfig {
+    /// Whether this provider overrides the server-level HTTP timeouts
+    pub fn has_timeout_overrides(&self) -> bool {
+        self.timeout_secs.is_some() || self.connect_timeout_secs.is_some()
+    }
+
+    /// The configured host block for `host`; exact names win over patterns.
+    pub fn find_host(&self, host: &str) -> Option<&HostConfig> {
+        self.hosts
+            .iter()
+            .find(|h| !h.name.starts_with("*.") && host_matches(&h.name, host))
+            .or_else(|| self.hosts.iter().find(|h| host_matches(&h.name, host)))
+    }
+
+    /// Record settings for `host`, falling back to provider-level defaults.
+    pub fn host_settings(&self, host: &str) -> HostSettings<'_> {
+        let block = self.find_host(host);
+        // A host-level key replaces the provider's key entirely
+        let (key, key_hash) = match block {
+            Some(h) if h.key.is_some() || h.key_hash.is_some() => (h.key.as_deref(), h.key_hash.as_deref()),
+            _ => (self.key.as_deref(), self.key_hash.as_deref()),
+        };
+
+        HostSettings {
+            ttl: block.and_then(|h| h.ttl).unwrap_or(self.ttl),
+            proxied: block.and_then(|h| h.proxied).unwrap_or(self.proxied),
+            record_type: block.and_then(|h| h.record_type).unwrap_or(self.record_type),
+            allow_private_ips: block.and_then(|h| h.allow_private_ips).unwrap_or(self.allow_private_ips),
+            min_update_interval_secs: block.and_then(|h| h.min_update_interval_secs).unwrap_or(self.min_update_interval_secs),
+            key,
+            key_hash,
+            offline: block.and_then(|h| h.offline).unwrap_or(self.offline),
+            offline_ips: block.and_then(|h| h.offline_ips.as_deref()).unwrap_or(&self.offline_ips),
+            comment: block.and_then(|h| h.comment.as_deref()).or(self.comment.as_deref()),
+            tags: block.and_then(|h| h.tags.as_deref()).unwrap_or(&self.tags),
+            update_ptr: block.and_then(|h| h.update_ptr).unwrap_or(self.update_ptr),
+        }
+    }
+}
+
+/// Matches a host against an exact name or a `*.example.com` suffix pattern.
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    let host = host.trim_end_matches('.');
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => {
+            host.len() > suffix.len()
+                && host[host.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+                && host.as_bytes()[host.len() - suffix.len() - 1] == b'.'
+        }
+        None => host.eq_ignore_ascii_case(pattern.trim_end_matches('.')),
+    }
+}
+
+/// Supported config file formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Detects the format from the file extension, defaulting to TOML.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            Some("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Toml,
+        }
+    }
+
+    fn parse(self, content: &str) -> Result<serde_json::Value> {
+        Ok(match self {
+            ConfigFormat::Toml => toml::from_str(content)?,
+            ConfigFormat::Yaml => serde_yaml::from_str(content)?,
+            ConfigFormat::Json => serde_json::from_str(content)?,
+        })
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "toml" => Ok(ConfigFormat::Toml),
+            "yaml" | "yml" => Ok(ConfigFormat::Yaml),
+            "json" => Ok(ConfigFormat::Json),
+            _ => Err(format!("unknown config format: {} (expected toml, yaml or json)", s)),
+        }
+    }
+}
+
+impl Config {
+    /// Loads a config file, parsing it as `format` or, if `None`, by its extension.
+    ///
+    /// `DDNS_CONFIG` and `DDNS_*__*` environment variables are merged over the
+    /// file; when they are set the file itself may be absent.
+    pub fn load<P: AsRef<Path>>(path: P, format: Option<ConfigFormat>) -> Result<Self> {
+        let path = path.as_ref();
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut value = if !path.exists() && env::has_env_config() {
+            serde_json::Value::Object(serde_json::Map::new())
+        } else {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+
+            let format = format.unwrap_or_else(|| ConfigFormat::from_path(path));
+            format
+                .parse(&content)
+                .with_context(|| format!("Failed to parse config file: {}", path.display()))?
+        };
+        include::resolve(&mut value, base_dir)?;
+        env::apply(&mut value)?;
+        interpolate::interpolate(&mut value)
+            .with_context(|| format!("Failed to expand variables in config file: {}", path.display()))?;
+
+        let mut config: Config = serde_json::from_value(value)
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+        resolve_secrets(&mut config.providers, base_dir)?;
+        if let Some(ref file) = config.server.state_file {
+            config.server.state_file = Some(base_dir.join(file).to_string_lossy().into_owned());
+        }
+        if let Some(ref file) = config.audit.path {
+            config.audit.path = Some(base_dir.join(file).to_string_lossy().into_owned());
+        }
+        if let Some(ref file) = config.scripting.path {
+            config.scripting.path = Some(base_dir.join(file).to_string_lossy().into_owned());
+        }
+        if let Some(ref file) = config.retry_queue.path {
+            config.retry_queue.path = Some(base_dir.join(file).to_string_lossy().into_owned());
+        }
+        if let Some(ref file) = config.leader.lock_file {
+            config.leader.lock_file = Some(base_dir.join(file).to_string_lossy().into_owned());
+        }
+        for tenant in &mut config.tenants {
+            resolve_secrets(&mut tenant.providers, base_dir)?;
+            if let Some(ref file) = tenant.state_file {
+                tenant.state_file = Some(base_dir.join(file).to_string_lossy().into_owned());
+            }
+            if let Some(file) = tenant.notifications.mqtt.as_mut().and_then(|m| m.ca_file.as_mut()) {
+                *file = base_dir.join(&*file).to_string_lossy().into_owned();
+            }
+            tenant.notifications.localize(config.messages.language);
+        }
+        if let Some(file) = config.notifications.mqtt.as_mut().and_then(|m| m.ca_file.as_mut()) {
+            *file = base_dir.join(&*file).to_string_lossy().into_owned();
+        }
+        config.kubernetes.token_file = base_dir.join(&config.kubernetes.token_file).to_string_lossy().into_owned();
+        config.notifications.localize(config.messages.language);
+        if let Some(ref mut file) = config.kubernetes.ca_file {
+            *file = base_dir.join(&*file).to_string_lossy().into_owned();
+        }
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn get_provider(&self, name: &str) -> Option<&ProviderConfig> {
+        self.providers.iter().find(|p| p.name == name)
+    }
+
+    /// The config a tenant is served with: its own providers, tokens, limits
+    /// and state file, sharing the rest. Tenants get no poll jobs, Kubernetes or
+    /// Docker sync, retry queue, UDP listener or UI.
+    pub fn for_tenant(&self, tenant: &TenantConfig) -> Config {
+        let mut config = self.clone();
+        config.providers = tenant.providers.clone();
+        config.auth = tenant.auth.clone();
+        config.server.rate_limit_per_ip = tenant.rate_limit_per_ip.unwrap_or(self.server.rate_limit_per_ip);
+        config.server.rate_limit_per_key = tenant.rate_limit_per_key.unwrap_or(self.server.rate_limit_per_key);
+        config.server.state_file = tenant.state_file.clone();
+        config.hooks = tenant.hooks.clone();
+        config.notifications = tenant.notifications.clone();
+        config.poll = Vec::new();
+        config.kubernetes.enabled = false;
+        config.docker.enabled = false;
+        config.retry_queue.path = None;
+        config.udp.listen = None;
+        config.redis.key_prefix = format!("{}tenant:{}:", self.redis.key_prefix, tenant.name);
+        config.ui.enabled = false;
+        config.tenants = Vec::new();
+        config
+    }
+}
+
+/// Fills secrets configured indirectly (`*_env`, `*_file`) into their plain
+/// fields. Relative secret file paths are resolved against `base_dir`.
+fn resolve_secrets(providers: &mut [ProviderConfig], base_dir: &Path) -> Result<()> {
+    for provider in providers {
+        if let Some(ref var) = provider.key_env {
+            provider.key = Some(read_env(var, &provider.name, "key_env")?);
+        }
+        if let Some(ref file) = provider.key_file {
+            provider.key = Some(read_secret_file(base_dir, file, &provider.name, "key_file")?);
+        }
+        if let Some(ref var) = provider.api_key_env {
+            provider.api_key = read_env(var, &provider.name, "api_key_env")?;
+        }
+        if let Some(ref file) = provider.api_key_file {
+            provider.api_key = read_secret_file(base_dir, file, &provider.name, "api_key_file")?;
+        }
+        // The mock provider talks to no API, so it needs no credentials
+        if provider.api_key.is_empty() && provider.provider_type != "mock" {
+            anyhow::bail!(
+                "Provider {}: one of api_key, api_key_env or api_key_file is required",
+                provider.name
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn read_env(var: &str, provider: &str, field: &str) -> Result<String> {
+    std::env::var(var).with_context(|| {
+        format!("Provider {}: environment variable {} (from {}) is not set", provider, var, field)
+    })
+}
+
+fn read_secret_file(base_dir: &Path, file: &str, provider: &str, field: &str) -> Result<String> {
+    let path = base_dir.join(file);
+    let secret = fs::read_to_string(&path).with_context(|| {
+        format!("Provider {}: failed to read {} {}", provider, field, path.display())
+    })?;
+
+    // Secret files commonly end with a newline
+    Ok(secret.trim_end_matches(['\r', '\n']).to_string())
+}
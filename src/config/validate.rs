@@ -0,0 +1,589 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::Result;
+use time::OffsetDateTime;
+
+use super::{CloudflareAuthMode, Config, DOH_RESOLVERS, HooksConfig, HostRecordType, LeaderElection, NotificationsConfig, OfflineAction, ProviderConfig, ResponseTemplate, TokenConfig};
+use crate::access_log::AccessLogFormat;
+use crate::auth;
+use crate::detect::KNOWN_SOURCES;
+use crate::hostname;
+use crate::schedule::Cron;
+use crate::provider::PROVIDER_TYPES;
+
+/// Longest record comment Cloudflare accepts on the free plan
+const MAX_COMMENT_LENGTH: usize = 100;
+
+impl Config {
+    /// Checks the loaded config for mistakes serde cannot catch, reporting
+    /// every problem at once with the path of the offending field.
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+
+        if self.server.listen.is_empty() && self.server.port == 0 {
+            errors.push("server.port: must be between 1 and 65535".to_string());
+        }
+        let mut listen = HashSet::new();
+        for (i, addr) in self.server.listen.iter().enumerate() {
+            if addr.port() == 0 {
+                errors.push(format!("server.listen[{}]: port must be between 1 and 65535", i));
+            } else if !listen.insert(addr) {
+                errors.push(format!("server.listen[{}]: duplicate address {}", i, addr));
+            }
+        }
+
+        if let Err(e) = AccessLogFormat::parse(&self.server.access_log_format) {
+            errors.push(format!("server.access_log_format: {}", e));
+        }
+        if self.audit.key.as_deref().is_some_and(str::is_empty) {
+            errors.push("audit.key: must not be empty".to_string());
+        }
+
+        if self.providers.is_empty() && self.tenants.is_empty() {
+            errors.push("providers: at least one provider is required".to_string());
+        }
+
+        let channels = self.notifications.channels();
+        check_providers(&self.providers, "providers", &channels, &mut errors);
+        check_tokens(&self.auth.tokens, "auth.tokens", &mut errors);
+
+        let mut tenant_names = HashSet::new();
+        for (i, tenant) in self.tenants.iter().enumerate() {
+            let field = format!("tenants[{}]", i);
+            if tenant.name.is_empty() || !tenant.name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') {
+                errors.push(format!("{}.name: expected letters, digits, '-' or '_' (got \"{}\")", field, tenant.name));
+            } else if !tenant_names.insert(tenant.name.as_str()) {
+                errors.push(format!("{}.name: duplicate tenant name \"{}\"", field, tenant.name));
+            }
+            if tenant.providers.is_empty() {
+                errors.push(format!("{}.providers: at least one provider is required", field));
+            }
+            check_providers(&tenant.providers, &format!("{}.providers", field), &tenant.notifications.channels(), &mut errors);
+            check_tokens(&tenant.auth.tokens, &format!("{}.auth.tokens", field), &mut errors);
+            check_hooks(&tenant.hooks, &format!("{}.hooks", field), &mut errors);
+            check_notifications(&tenant.notifications, &format!("{}.notifications", field), &mut errors);
+        }
+
+        if self.ui.enabled {
+            if self.ui.username.is_empty() {
+                errors.push("ui.username: required when the UI is enabled".to_string());
+            }
+            match (&self.ui.password, &self.ui.password_hash) {
+                (None, None) => errors.push("ui: one of password or password_hash is required".to_string()),
+                (_, Some(hash)) => {
+                    if let Err(e) = auth::check_hash_format(hash) {
+                        errors.push(format!("ui.password_hash: {}", e));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if self.scripting.path.is_some() && !cfg!(feature = "scripting") {
+            errors.push("scripting.path: this build does not include the \"scripting\" feature".to_string());
+        }
+        if self.scripting.max_operations == 0 {
+            errors.push("scripting.max_operations: must be at least 1".to_string());
+        }
+        if self.retry_queue.path.is_some() && !cfg!(feature = "sqlite") {
+            errors.push("retry_queue.path: this build does not include the \"sqlite\" feature".to_string());
+        }
+        if self.retry_queue.initial_delay_secs == 0 {
+            errors.push("retry_queue.initial_delay_secs: must be at least 1".to_string());
+        }
+        if self.retry_queue.expire_secs == 0 {
+            errors.push("retry_queue.expire_secs: must be at least 1".to_string());
+        }
+        if self.circuit_breaker.failure_threshold > 0 && self.circuit_breaker.open_secs == 0 {
+            errors.push("circuit_breaker.open_secs: must be at least 1".to_string());
+        }
+        if let Some(ref url) = self.redis.url {
+            if !cfg!(feature = "redis") {
+                errors.push("redis.url: this build does not include the \"redis\" feature".to_string());
+            }
+            if !["redis://", "redis+unix://", "unix://"].iter().any(|scheme| url.starts_with(scheme)) {
+                errors.push("redis.url: must start with redis://, redis+unix:// or unix://".to_string());
+            }
+        }
+        if self.redis.lock_timeout_secs == 0 {
+            errors.push("redis.lock_timeout_secs: must be at least 1".to_string());
+        }
+        match self.leader.election {
+            LeaderElection::None => {}
+            LeaderElection::File if self.leader.lock_file.is_none() => {
+                errors.push("leader.lock_file: required with election = \"file\"".to_string());
+            }
+            LeaderElection::File if !cfg!(unix) => {
+                errors.push("leader.election: \"file\" is only supported on Unix".to_string());
+            }
+            LeaderElection::File => {}
+            LeaderElection::Redis if self.redis.url.is_none() => {
+                errors.push("leader.election: \"redis\" requires redis.url".to_string());
+            }
+            LeaderElection::Redis => {}
+        }
+        if self.leader.lease_secs == 0 {
+            errors.push("leader.lease_secs: must be at least 1".to_string());
+        }
+        if let Some(ref listen) = self.udp.listen {
+            if listen.parse::<SocketAddr>().is_err() {
+                errors.push(format!("udp.listen: expected an IP address and port (got \"{}\")", listen));
+            }
+        }
+        check_hooks(&self.hooks, "hooks", &mut errors);
+        if self.reconcile.enabled && self.reconcile.interval_secs == 0 {
+            errors.push("reconcile.interval_secs: must be at least 1".to_string());
+        }
+
+        if self.kubernetes.enabled {
+            if !cfg!(feature = "kubernetes") {
+                errors.push("kubernetes.enabled: this build does not include the \"kubernetes\" feature".to_string());
+            }
+            if self.get_provider(&self.kubernetes.provider).is_none() {
+                errors.push(format!("kubernetes.provider: unknown provider \"{}\"", self.kubernetes.provider));
+            }
+            if self.kubernetes.annotation.is_empty() {
+                errors.push("kubernetes.annotation: must not be empty".to_string());
+            }
+            if self.kubernetes.interval_secs == 0 {
+                errors.push("kubernetes.interval_secs: must be at least 1".to_string());
+            }
+            if self.kubernetes.record_type == HostRecordType::Cname {
+                errors.push("kubernetes.record_type: must be A, AAAA or both".to_string());
+            }
+        }
+
+        if self.docker.enabled {
+            if self.get_provider(&self.docker.provider).is_none() {
+                errors.push(format!("docker.provider: unknown provider \"{}\"", self.docker.provider));
+            }
+            if self.docker.socket.is_empty() {
+                errors.push("docker.socket: must not be empty".to_string());
+            } else if !cfg!(unix) && !self.docker.socket.starts_with("tcp://") {
+                errors.push("docker.socket: only tcp:// addresses are supported on this platform".to_string());
+            }
+            if self.docker.label_prefix.is_empty() {
+                errors.push("docker.label_prefix: must not be empty".to_string());
+            }
+            if self.docker.interval_secs == 0 {
+                errors.push("docker.interval_secs: must be at least 1".to_string());
+            }
+            if self.docker.record_type == HostRecordType::Cname {
+                errors.push("docker.record_type: must be A, AAAA or both".to_string());
+            }
+        }
+
+        for (i, job) in self.poll.iter().enumerate() {
+            let field = format!("poll[{}]", i);
+            if self.get_provider(&job.provider).is_none() {
+                errors.push(format!("{}.provider: unknown provider \"{}\"", field, job.provider));
+            }
+            if job.hosts.is_empty() {
+                errors.push(format!("{}.hosts: at least one host is required", field));
+            }
+            for (j, host) in job.hosts.iter().enumerate() {
+                if let Err(e) = hostname::normalize(host) {
+                    errors.push(format!("{}.hosts[{}]: {}", field, j, e));
+                }
+            }
+            if job.record_type == HostRecordType::Cname {
+                errors.push(format!("{}.record_type: must be A, AAAA or both", field));
+            }
+            if let Some(prefix_len) = job.ipv6_prefix_len {
+                if !(1..=127).contains(&prefix_len) {
+                    errors.push(format!("{}.ipv6_prefix_len: must be between 1 and 127", field));
+                }
+                if job.record_type == HostRecordType::A {
+                    errors.push(format!("{}.ipv6_prefix_len: needs record_type AAAA or both", field));
+                }
+                let provider = self.get_provider(&job.provider);
+                for (j, host) in job.hosts.iter().enumerate() {
+                    let suffix = provider.and_then(|p| p.find_host(host)).and_then(|h| h.ipv6_suffix);
+                    if provider.is_some() && suffix.is_none() {
+                        errors.push(format!(
+                            "{}.hosts[{}]: \"{}\" needs an ipv6_suffix in [[providers.hosts]]",
+                            field, j, host
+                        ));
+                    }
+                }
+            }
+            for (name, url) in [("ping_url", &job.ping_url), ("ping_failure_url", &job.ping_failure_url)] {
+                if url.as_deref().is_some_and(|url| !is_http_url(url)) {
+                    errors.push(format!("{}.{}: must be an http(s) URL", field, name));
+                }
+            }
+            match (job.interval_secs, &job.cron) {
+                (Some(_), Some(_)) | (None, None) => {
+                    errors.push(format!("{}: exactly one of interval_secs or cron is required", field));
+                }
+                (Some(0), None) => errors.push(format!("{}.interval_secs: must be at least 1", field)),
+                (None, Some(text)) => match text.parse::<Cron>() {
+                    Ok(cron) if cron.next_after(OffsetDateTime::now_utc()).is_none() => {
+                        errors.push(format!("{}.cron: \"{}\" never matches", field, text));
+                    }
+                    Ok(_) => {}
+                    Err(e) => errors.push(format!("{}.cron: {}", field, e)),
+                },
+                (Some(_), None) => {}
+            }
+        }
+        for (name, sources) in [("ipv4_sources", &self.detect.ipv4_sources), ("ipv6_sources", &self.detect.ipv6_sources)] {
+            if sources.is_empty() {
+                errors.push(format!("detect.{}: at least one source is required", name));
+            }
+            if self.detect.quorum.is_some_and(|q| q > sources.len()) {
+                errors.push(format!("detect.quorum: larger than the {} entries of detect.{}", sources.len(), name));
+            }
+            for (i, source) in sources.iter().enumerate() {
+                if !is_http_url(source) && !KNOWN_SOURCES.iter().any(|(known, _, _)| known == source) {
+                    errors.push(format!(
+                        "detect.{}[{}]: expected an http(s) URL or one of: {} (got \"{}\")",
+                        name,
+                        i,
+                        KNOWN_SOURCES.iter().map(|(known, _, _)| *known).collect::<Vec<_>>().join(", "),
+                        source
+                    ));
+                }
+            }
+        }
+        if self.detect.ipv6_interface.as_deref() == Some("") {
+            errors.push("detect.ipv6_interface: must not be empty".to_string());
+        }
+        if self.detect.quorum == Some(0) {
+            errors.push("detect.quorum: must be at least 1".to_string());
+        }
+
+        check_notifications(&self.notifications, "notifications", &mut errors);
+
+        if self.propagation.enabled {
+            if !cfg!(feature = "propagation") {
+                errors.push("propagation.enabled: this build does not include the \"propagation\" feature".to_string());
+            }
+            for (i, server) in self.propagation.resolvers.iter().enumerate() {
+                let known = DOH_RESOLVERS.iter().any(|(name, _)| name == server);
+                if !known && !is_http_url(server) && server.parse::<SocketAddr>().is_err() && server.parse::<IpAddr>().is_err() {
+                    errors.push(format!(
+                        "propagation.resolvers[{}]: expected an IP address with optional port, a DNS-over-HTTPS URL or a known resolver name (got \"{}\")",
+                        i, server
+                    ));
+                }
+            }
+        }
+
+        if let Some(ref endpoint) = self.telemetry.otlp_endpoint {
+            if !cfg!(feature = "otel") {
+                errors.push("telemetry.otlp_endpoint: this build does not include the \"otel\" feature".to_string());
+            }
+            if !is_http_url(endpoint) {
+                errors.push(format!("telemetry.otlp_endpoint: expected an http(s) URL (got \"{}\")", endpoint));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.telemetry.sample_ratio) {
+            errors.push(format!(
+                "telemetry.sample_ratio: must be between 0.0 and 1.0 (got {})",
+                self.telemetry.sample_ratio
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            anyhow::bail!("Invalid configuration:\n  - {}", errors.join("\n  - "))
+        }
+    }
+}
+
+/// Checks each provider of a `providers` list; `prefix` is the list's path.
+fn check_providers(providers: &[ProviderConfig], prefix: &str, channels: &[&str], errors: &mut Vec<String>) {
+    let mut names = HashSet::new();
+    for (i, provider) in providers.iter().enumerate() {
+        let field = format!("{}[{}]", prefix, i);
+
+        if provider.name.is_empty() {
+            errors.push(format!("{}.name: must not be empty", field));
+        } else if provider.name.contains('/') {
+            errors.push(format!("{}.name: must not contain '/' (got \"{}\")", field, provider.name));
+        } else if !names.insert(provider.name.as_str()) {
+            errors.push(format!("{}.name: duplicate provider name \"{}\"", field, provider.name));
+        }
+
+        if !PROVIDER_TYPES.contains(&provider.provider_type.as_str()) {
+            errors.push(format!(
+                "{}.type: unknown provider type \"{}\" (expected one of: {})",
+                field,
+                provider.provider_type,
+                PROVIDER_TYPES.join(", ")
+            ));
+        }
+
+        if let Some(ref hash) = provider.key_hash {
+            if let Err(e) = auth::check_hash_format(hash) {
+                errors.push(format!("{}.key_hash: {}", field, e));
+            }
+        }
+
+        if provider.require_signature && provider.key.is_none() {
+            errors.push(format!("{}.require_signature: needs a plaintext key (key, key_env or key_file)", field));
+        }
+        if let Some(ref response) = provider.response {
+            check_response_template(response, &format!("{}.response", field), errors);
+        }
+
+        match (provider.auth_mode, provider.email.as_deref()) {
+            (CloudflareAuthMode::GlobalKey, None | Some("")) => {
+                errors.push(format!("{}.email: required with auth_mode = \"global_key\"", field));
+            }
+            (CloudflareAuthMode::Token, Some(_)) => {
+                errors.push(format!("{}.email: only used with auth_mode = \"global_key\"", field));
+            }
+            _ => {}
+        }
+
+        if provider.record_type == HostRecordType::Cname {
+            errors.push(format!("{}.record_type: CNAME can only be set for individual [[providers.hosts]]", field));
+        }
+
+        check_offline(provider.offline, &provider.offline_ips, &field, errors);
+        check_label(provider.comment.as_deref(), &provider.tags, provider.managed_only, &field, errors);
+
+        for channel in provider.notify.iter().flatten() {
+            if !channels.contains(&channel.as_str()) {
+                errors.push(format!(
+                    "{}.notify: channel \"{}\" is not configured in [notifications]",
+                    field, channel
+                ));
+            }
+        }
+
+        let mut hosts = HashSet::new();
+        for (j, host) in provider.hosts.iter().enumerate() {
+            let field = format!("{}.hosts[{}]", field, j);
+            if let Err(e) = check_host_pattern(&host.name) {
+                errors.push(format!("{}.name: {}", field, e));
+            } else if !hosts.insert(host.name.to_ascii_lowercase()) {
+                errors.push(format!("{}.name: duplicate host \"{}\"", field, host.name));
+            }
+            if let Some(ref hash) = host.key_hash {
+                if let Err(e) = auth::check_hash_format(hash) {
+                    errors.push(format!("{}.key_hash: {}", field, e));
+                }
+            }
+            if host.offline.is_some() || host.offline_ips.is_some() {
+                let settings = provider.host_settings(&host.name);
+                check_offline(settings.offline, settings.offline_ips, &field, errors);
+            }
+            if host.comment.is_some() || host.tags.is_some() {
+                let settings = provider.host_settings(&host.name);
+                check_label(settings.comment, settings.tags, provider.managed_only, &field, errors);
+            }
+            if let Some(ref ips) = host.ips {
+                check_desired_ips(&host.name, provider.host_settings(&host.name).record_type, ips, &field, errors);
+            }
+        }
+    }
+}
+
+/// Parking needs an address to park at, and a record holds one address per family.
+fn check_offline(action: OfflineAction, ips: &[IpAddr], field: &str, errors: &mut Vec<String>) {
+    if action == OfflineAction::Park && ips.is_empty() {
+        errors.push(format!("{}.offline_ips: required with offline = \"park\"", field));
+    }
+    if ips.iter().filter(|ip| ip.is_ipv4()).count() > 1 || ips.iter().filter(|ip| ip.is_ipv6()).count() > 1 {
+        errors.push(format!("{}.offline_ips: at most one IPv4 and one IPv6 address", field));
+    }
+}
+
+/// A WireGuard key is 32 bytes in standard base64: 43 characters and a `=`.
+fn is_wireguard_key(key: &str) -> bool {
+    key.len() == 44
+        && key.ends_with('=')
+        && key[..43].bytes().all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+}
+
+/// Desired addresses belong to one concrete host and must fit its record types.
+fn check_desired_ips(name: &str, record_type: HostRecordType, ips: &[IpAddr], field: &str, errors: &mut Vec<String>) {
+    if name.starts_with("*.") {
+        errors.push(format!("{}.ips: not allowed on a \"*.\" pattern", field));
+    } else if record_type == HostRecordType::Cname {
+        errors.push(format!("{}.ips: not allowed on a CNAME host", field));
+    } else if let Some(ip) = ips.iter().find(|ip| !record_type.allows(ip)) {
+        errors.push(format!("{}.ips: {} does not match record_type", field, ip));
+    }
+}
+
+/// Cloudflare limits comments to 100 characters on most plans, and with
+/// `managed_only` an unlabeled host would treat every record as its own.
+fn check_label(comment: Option<&str>, tags: &[String], managed_only: bool, field: &str, errors: &mut Vec<String>) {
+    if let Some(comment) = comment {
+        if comment.chars().count() > MAX_COMMENT_LENGTH {
+            errors.push(format!("{}.comment: longer than {} characters", field, MAX_COMMENT_LENGTH));
+        }
+    }
+    for (i, tag) in tags.iter().enumerate() {
+        if tag.trim().is_empty() {
+            errors.push(format!("{}.tags[{}]: must not be empty", field, i));
+        }
+    }
+    if managed_only && comment.is_none_or(str::is_empty) && tags.is_empty() {
+        errors.push(format!("{}.managed_only: needs a comment or tags to recognize managed records", field));
+    }
+}
+
+/// Checks each token of an `auth.tokens` list; `prefix` is the list's path.
+fn check_hooks(hooks: &HooksConfig, prefix: &str, errors: &mut Vec<String>) {
+    if hooks.timeout_secs == 0 {
+        errors.push(format!("{}.timeout_secs: must be at least 1", prefix));
+    }
+    for (i, peer) in hooks.wireguard.iter().enumerate() {
+        let field = format!("{}.wireguard[{}]", prefix, i);
+        if peer.interface.is_empty() {
+            errors.push(format!("{}.interface: must not be empty", field));
+        }
+        if !is_wireguard_key(&peer.peer) {
+            errors.push(format!("{}.peer: expected a base64 WireGuard public key", field));
+        }
+        if let Err(e) = hostname::normalize(&peer.host) {
+            errors.push(format!("{}.host: {}", field, e));
+        }
+        if peer.port == 0 {
+            errors.push(format!("{}.port: must be at least 1", field));
+        }
+        if peer.record_type == HostRecordType::Cname {
+            errors.push(format!("{}.record_type: must be A, AAAA or both", field));
+        }
+    }
+}
+
+fn check_notifications(notifications: &NotificationsConfig, prefix: &str, errors: &mut Vec<String>) {
+    if let Some(ref telegram) = notifications.telegram {
+        if telegram.bot_token.is_empty() {
+            errors.push(format!("{}.telegram.bot_token: must not be empty", prefix));
+        }
+        if telegram.chat_id.is_empty() {
+            errors.push(format!("{}.telegram.chat_id: must not be empty", prefix));
+        }
+    }
+    if let Some(ref email) = notifications.email {
+        if email.server.is_empty() {
+            errors.push(format!("{}.email.server: must not be empty", prefix));
+        }
+        if email.from.parse::<lettre::message::Mailbox>().is_err() {
+            errors.push(format!("{}.email.from: invalid address \"{}\"", prefix, email.from));
+        }
+        if email.to.is_empty() {
+            errors.push(format!("{}.email.to: at least one recipient is required", prefix));
+        }
+        for (i, to) in email.to.iter().enumerate() {
+            if to.parse::<lettre::message::Mailbox>().is_err() {
+                errors.push(format!("{}.email.to[{}]: invalid address \"{}\"", prefix, i, to));
+            }
+        }
+        if email.username.is_some() != email.password.is_some() {
+            errors.push(format!("{}.email: username and password must be set together", prefix));
+        }
+    }
+    if let Some(ref ntfy) = notifications.ntfy {
+        if !is_http_url(&ntfy.url) {
+            errors.push(format!("{}.ntfy.url: must be an http(s) URL", prefix));
+        }
+        if ntfy.topic.is_empty() || ntfy.topic.contains('/') {
+            errors.push(format!("{}.ntfy.topic: invalid topic \"{}\"", prefix, ntfy.topic));
+        }
+        if ntfy.priority.is_some_and(|p| !(1..=5).contains(&p)) {
+            errors.push(format!("{}.ntfy.priority: must be between 1 and 5", prefix));
+        }
+    }
+    if let Some(ref gotify) = notifications.gotify {
+        if !is_http_url(&gotify.url) {
+            errors.push(format!("{}.gotify.url: must be an http(s) URL", prefix));
+        }
+        if gotify.token.is_empty() {
+            errors.push(format!("{}.gotify.token: must not be empty", prefix));
+        }
+    }
+    if let Some(ref mqtt) = notifications.mqtt {
+        if mqtt.host.is_empty() {
+            errors.push(format!("{}.mqtt.host: must not be empty", prefix));
+        }
+        if mqtt.qos > 2 {
+            errors.push(format!("{}.mqtt.qos: must be 0, 1 or 2", prefix));
+        }
+        if mqtt.topic.is_empty() || mqtt.topic.contains(['+', '#']) {
+            errors.push(format!("{}.mqtt.topic: invalid topic \"{}\"", prefix, mqtt.topic));
+        }
+        if mqtt.ca_file.is_some() && !mqtt.tls {
+            errors.push(format!("{}.mqtt.ca_file: only used with tls = true", prefix));
+        }
+        if mqtt.password.is_some() && mqtt.username.is_none() {
+            errors.push(format!("{}.mqtt.password: needs a username", prefix));
+        }
+    }
+    for (name, webhook) in [("discord", &notifications.discord), ("slack", &notifications.slack)] {
+        if let Some(webhook) = webhook {
+            if !is_http_url(&webhook.webhook_url) {
+                errors.push(format!("{}.{}.webhook_url: must be an http(s) URL", prefix, name));
+            }
+        }
+    }
+}
+
+fn check_tokens(tokens: &[TokenConfig], prefix: &str, errors: &mut Vec<String>) {
+    let mut token_names = HashSet::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let field = format!("{}[{}]", prefix, i);
+
+        if token.name.is_empty() {
+            errors.push(format!("{}.name: must not be empty", field));
+        } else if !token_names.insert(token.name.as_str()) {
+            errors.push(format!("{}.name: duplicate token name \"{}\"", field, token.name));
+        }
+
+        match (&token.token, &token.token_hash) {
+            (None, None) => errors.push(format!("{}: one of token or token_hash is required", field)),
+            (_, Some(hash)) => {
+                if let Err(e) = auth::check_hash_format(hash) {
+                    errors.push(format!("{}.token_hash: {}", field, e));
+                }
+            }
+            _ => {}
+        }
+
+        for (j, pattern) in token.hosts.iter().enumerate() {
+            if let Err(e) = check_host_pattern(pattern) {
+                errors.push(format!("{}.hosts[{}]: {}", field, j, e));
+            }
+        }
+
+        if token.scopes.is_empty() {
+            errors.push(format!("{}.scopes: must not be empty", field));
+        }
+        if let Some(ref response) = token.response {
+            check_response_template(response, &format!("{}.response", field), errors);
+        }
+    }
+}
+
+/// The content type ends up in a header, so it must be visible ASCII.
+fn check_response_template(response: &ResponseTemplate, field: &str, errors: &mut Vec<String>) {
+    let valid = !response.content_type.is_empty() && response.content_type.bytes().all(|b| (0x20..0x7f).contains(&b));
+    if !valid {
+        errors.push(format!("{}.content_type: expected a media type such as \"text/plain\"", field));
+    }
+}
+
+/// A hostname or `*.example.com` pattern; wildcards are only allowed as the first label.
+fn check_host_pattern(pattern: &str) -> Result<(), String> {
+    let name = pattern.strip_prefix("*.").unwrap_or(pattern);
+    if name.is_empty() {
+        Err("must not be empty".to_string())
+    } else if name.contains('*') {
+        Err(format!("wildcards are only supported as a leading \"*.\" (got \"{}\")", pattern))
+    } else {
+        Ok(())
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
@@ -0,0 +1,31 @@
+//! Minimum time between provider updates of one host, so a chatty client
+//! cannot use up the provider's API quota.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// When each host was last updated successfully, and to which address
+#[derive(Default)]
+pub struct Cooldowns {
+    last: Mutex<HashMap<(String, String), (Instant, IpAddr)>>,
+}
+
+impl Cooldowns {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long ago `host` was updated and to which IP, if less than `interval` ago.
+    pub fn recent(&self, provider: &str, host: &str, interval: Duration) -> Option<(Duration, IpAddr)> {
+        let last = self.last.lock().unwrap();
+        let (at, ip) = last.get(&(provider.to_string(), host.to_string()))?;
+        let elapsed = at.elapsed();
+        (elapsed < interval).then_some((elapsed, *ip))
+    }
+
+    pub fn record(&self, provider: &str, host: &str, ip: IpAddr) {
+        self.last.lock().unwrap().insert((provider.to_string(), host.to_string()), (Instant::now(), ip));
+    }
+}
@@ -0,0 +1,59 @@
+//! `server.check_credentials`: verifies every provider's credentials at
+//! startup and daily, warning before an expiring API token makes updates fail.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::api::AppState;
+
+/// Time between checks
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Starts the check task when enabled.
+pub fn spawn(state: Arc<AppState>) {
+    if !state.config.server.check_credentials {
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            check(&state).await;
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+/// Checks each provider once, logging the outcome and notifying about
+/// credentials that are unusable or expire within the warning period.
+async fn check(state: &AppState) {
+    let warning_days = i64::from(state.config.server.credential_expiry_warning_days);
+
+    for provider_config in &state.config.providers {
+        let Some(provider) = state.providers.get(&provider_config.name) else {
+            continue;
+        };
+        let name = &provider_config.name;
+
+        let problem = match provider.credentials_expiry().await {
+            Ok(None) => {
+                info!("Credentials of provider {} verified", name);
+                continue;
+            }
+            Ok(Some(expires)) => {
+                let days = (expires - OffsetDateTime::now_utc()).whole_days();
+                if days >= warning_days {
+                    info!("Credentials of provider {} verified, valid until {}", name, expires.date());
+                    continue;
+                }
+                format!("API token expires on {} (in {} day(s))", expires.date(), days.max(0))
+            }
+            Err(e) => state.redactor.redact(&format!("{:#}", e)),
+        };
+
+        warn!("Credentials of provider {} need attention: {}", name, problem);
+        state.notifier.record_credentials(name, &problem);
+    }
+}
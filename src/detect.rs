@@ -0,0 +1,165 @@
+//! Looks up this machine's public IP for `[[poll]]` jobs and the `/checkip`
+//! proxy: through several echo services, trusting only an address a quorum
+//! of them agree on, or for IPv6 from a local interface.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use reqwest::Client;
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use tracing::{debug, warn};
+
+use crate::config::DetectConfig;
+use crate::interface;
+
+/// Built-in sources usable by name in `detect.ipv4_sources` / `ipv6_sources`,
+/// with their IPv4 and IPv6 URLs
+pub const KNOWN_SOURCES: &[(&str, &str, &str)] = &[
+    ("ipify", "https://api.ipify.org", "https://api6.ipify.org"),
+    ("icanhazip", "https://ipv4.icanhazip.com", "https://ipv6.icanhazip.com"),
+    (
+        "cloudflare",
+        "https://1.1.1.1/cdn-cgi/trace",
+        "https://[2606:4700:4700::1111]/cdn-cgi/trace",
+    ),
+];
+
+pub struct Detector {
+    client: Client,
+    config: DetectConfig,
+}
+
+impl Detector {
+    pub fn new(config: DetectConfig, client: Client) -> Self {
+        Self { client, config }
+    }
+
+    /// Queries every IPv4 or IPv6 source at once and returns the address
+    /// reported by at least `quorum` of them. IPv6 comes from
+    /// `detect.ipv6_interface` instead when it is set.
+    pub async fn detect(&self, ipv6: bool) -> Result<IpAddr> {
+        // Echo services often see a privacy address; the interface knows the stable one
+        if let (true, Some(interface)) = (ipv6, &self.config.ipv6_interface) {
+            return Ok(IpAddr::V6(interface::ipv6_address(interface, self.config.ipv6_select)?));
+        }
+
+        let sources = if ipv6 { &self.config.ipv6_sources } else { &self.config.ipv4_sources };
+        let quorum = self.config.quorum.unwrap_or(sources.len() / 2 + 1);
+
+        let mut queries = JoinSet::new();
+        for source in sources {
+            let client = self.client.clone();
+            let source = source.clone();
+            queries.spawn(async move {
+                let result = query(&client, source_url(&source, ipv6), ipv6).await;
+                (source, result)
+            });
+        }
+
+        let mut votes: HashMap<IpAddr, Vec<String>> = HashMap::new();
+        let mut failures = Vec::new();
+        while let Some(joined) = queries.join_next().await {
+            let (source, result) = joined.context("IP detection task failed")?;
+            match result {
+                Ok(ip) => {
+                    debug!("{} reports {}", source, ip);
+                    votes.entry(ip).or_default().push(source);
+                }
+                Err(e) => {
+                    warn!("IP detection via {} failed: {:#}", source, e);
+                    failures.push(source);
+                }
+            }
+        }
+
+        if let Some((ip, _)) = votes.iter().find(|(_, sources)| sources.len() >= quorum) {
+            return Ok(*ip);
+        }
+
+        let mut answers: Vec<String> = votes
+            .iter()
+            .map(|(ip, sources)| format!("{} from {}", ip, sources.join(", ")))
+            .collect();
+        if !failures.is_empty() {
+            answers.push(format!("no answer from {}", failures.join(", ")));
+        }
+        anyhow::bail!(
+            "fewer than {} of {} sources agree on the public IPv{} address ({})",
+            quorum,
+            sources.len(),
+            if ipv6 { 6 } else { 4 },
+            answers.join("; ")
+        )
+    }
+}
+
+/// A detector that remembers its answers for a while, so clients asking
+/// `/checkip` often don't multiply the queries to the echo services.
+pub struct CachedDetector {
+    detector: Detector,
+    ttl: Duration,
+    /// Last IPv4 and IPv6 answers; holding a lock also makes concurrent
+    /// callers wait for one lookup
+    cached: [Mutex<Option<(Instant, IpAddr)>>; 2],
+}
+
+impl CachedDetector {
+    pub fn new(config: DetectConfig, client: Client) -> Self {
+        Self {
+            ttl: Duration::from_secs(config.checkip_cache_secs),
+            detector: Detector::new(config, client),
+            cached: [Mutex::new(None), Mutex::new(None)],
+        }
+    }
+
+    /// The public address of the family asked for, detected at most once per
+    /// `detect.checkip_cache_secs`.
+    pub async fn detect(&self, ipv6: bool) -> Result<IpAddr> {
+        let mut cached = self.cached[usize::from(ipv6)].lock().await;
+        if let Some((at, ip)) = *cached {
+            if at.elapsed() < self.ttl {
+                return Ok(ip);
+            }
+        }
+
+        let ip = self.detector.detect(ipv6).await?;
+        *cached = Some((Instant::now(), ip));
+        Ok(ip)
+    }
+}
+
+/// The URL of a built-in source name, or the source itself when it is a URL.
+fn source_url(source: &str, ipv6: bool) -> String {
+    match KNOWN_SOURCES.iter().find(|(name, _, _)| *name == source) {
+        Some((_, v4, v6)) => if ipv6 { v6 } else { v4 }.to_string(),
+        None => source.to_string(),
+    }
+}
+
+/// Asks one source for the address it sees us connect from. Accepts a plain
+/// IP or a Cloudflare trace style body with an `ip=` line.
+async fn query(client: &Client, url: String, ipv6: bool) -> Result<IpAddr> {
+    let body = client
+        .get(&url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Failed to query {}", url))?
+        .text()
+        .await
+        .with_context(|| format!("Failed to read response from {}", url))?;
+
+    let body = body.trim();
+    let ip: IpAddr = body
+        .parse()
+        .ok()
+        .or_else(|| body.lines().find_map(|line| line.strip_prefix("ip=")?.trim().parse().ok()))
+        .with_context(|| format!("{} did not return an IP address", url))?;
+    if ip.is_ipv6() != ipv6 {
+        anyhow::bail!("{} returned {} instead of an IPv{} address", url, ip, if ipv6 { 6 } else { 4 });
+    }
+    Ok(ip)
+}
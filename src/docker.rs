@@ -0,0 +1,285 @@
+//! `[docker]`: keeps the hosts named in a label of the running containers
+//! (`ddns.host=app.example.com`) pointed at the detected public IP, and
+//! deletes their records once no running container names them any more.
+//! Containers are listed through the Docker Engine API, again whenever one
+//! starts or stops and otherwise every `interval_secs`.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use reqwest::Url;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, Lines};
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::api::{delete_records, perform_update, AppState};
+use crate::config::{DockerConfig, HostRecordType};
+use crate::detect::Detector;
+use crate::hostname;
+use crate::provider::{self, RecordType};
+
+/// A connection to the Docker API
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+type Events = Lines<BufReader<Box<dyn Connection>>>;
+
+/// Where the Docker API listens
+enum Endpoint {
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+    Tcp(String),
+}
+
+impl Endpoint {
+    fn parse(socket: &str) -> Result<Self> {
+        if let Some(address) = socket.strip_prefix("tcp://") {
+            return Ok(Self::Tcp(address.trim_end_matches('/').to_string()));
+        }
+        #[cfg(unix)]
+        return Ok(Self::Unix(socket.into()));
+        #[cfg(not(unix))]
+        anyhow::bail!("docker.socket: only tcp:// addresses are supported on this platform");
+    }
+
+    async fn connect(&self) -> Result<Box<dyn Connection>> {
+        Ok(match self {
+            #[cfg(unix)]
+            Self::Unix(path) => Box::new(
+                tokio::net::UnixStream::connect(path)
+                    .await
+                    .with_context(|| format!("Failed to connect to {}", path.display()))?,
+            ),
+            Self::Tcp(address) => Box::new(
+                tokio::net::TcpStream::connect(address)
+                    .await
+                    .with_context(|| format!("Failed to connect to {}", address))?,
+            ),
+        })
+    }
+
+    /// Sends `GET path` and returns the response body once the status is
+    /// checked. HTTP/1.0 keeps the body unchunked and ends it with the
+    /// connection, which also suits the event stream.
+    async fn get(&self, path: &str) -> Result<BufReader<Box<dyn Connection>>> {
+        let mut connection = self.connect().await?;
+        connection
+            .write_all(format!("GET {} HTTP/1.0\r\nHost: docker\r\n\r\n", path).as_bytes())
+            .await
+            .context("Failed to send request to Docker")?;
+
+        let mut reader = BufReader::new(connection);
+        let mut line = String::new();
+        reader.read_line(&mut line).await.context("Failed to read Docker response")?;
+        let status: u16 = line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .with_context(|| format!("Invalid response from Docker: {}", line.trim()))?;
+        // Skip the headers
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await.context("Failed to read Docker response")? == 0 {
+                anyhow::bail!("Docker closed the connection before the response body");
+            }
+            if line.trim_end().is_empty() {
+                break;
+            }
+        }
+
+        if status != 200 {
+            let mut body = String::new();
+            reader.read_to_string(&mut body).await.ok();
+            anyhow::bail!("Docker returned {} for {}: {}", status, path, body.trim());
+        }
+        Ok(reader)
+    }
+}
+
+/// `path` with `filters` as its (URL-encoded) query.
+fn filtered(path: &str, filters: Value) -> String {
+    let mut url = Url::parse("http://docker").unwrap().join(path).unwrap();
+    url.query_pairs_mut().append_pair("filters", &filters.to_string());
+    format!("{}?{}", url.path(), url.query().unwrap_or_default())
+}
+
+/// Starts the sync loop when `[docker]` is enabled.
+pub fn spawn(state: Arc<AppState>) -> Result<()> {
+    let config = &state.config.docker;
+    if !config.enabled {
+        return Ok(());
+    }
+
+    let endpoint = Endpoint::parse(&config.socket)?;
+    let detector = Detector::new(state.config.detect.clone(), provider::build_client(&state.config.server, None)?);
+    let interval = Duration::from_secs(config.interval_secs);
+    info!("Syncing hosts labelled {}.host on Docker containers via {}", config.label_prefix, config.socket);
+
+    tokio::spawn(async move {
+        let config = &state.config.docker;
+        // Hosts this task updated, deleted once no container names them
+        let mut managed = BTreeSet::new();
+        let mut events: Option<Events> = None;
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                event = next_event(&mut events) => match event {
+                    Some(event) => debug!("Docker event: {}", event),
+                    None => {
+                        // Reconnected on the next tick
+                        events = None;
+                        continue;
+                    }
+                },
+            }
+
+            async {
+                // Subscribe before listing so no start or stop goes unnoticed
+                if events.is_none() {
+                    let path = filtered("/events", json!({ "type": ["container"], "event": ["start", "die"] }));
+                    match endpoint.get(&path).await {
+                        Ok(reader) => events = Some(reader.lines()),
+                        Err(e) => warn!("Failed to watch Docker events: {:#}", e),
+                    }
+                }
+                sync(&state, config, &endpoint, &detector, &mut managed).await;
+            }
+            .instrument(info_span!("docker"))
+            .await;
+        }
+    });
+
+    Ok(())
+}
+
+/// The next line of the event stream, `None` once it ends. Never completes
+/// without a stream.
+async fn next_event(events: &mut Option<Events>) -> Option<String> {
+    match events {
+        Some(lines) => lines.next_line().await.ok().flatten(),
+        None => std::future::pending().await,
+    }
+}
+
+/// Updates the hosts of the running containers, and deletes the records of
+/// `managed` hosts no container names any more.
+async fn sync(
+    state: &AppState,
+    config: &DockerConfig,
+    endpoint: &Endpoint,
+    detector: &Detector,
+    managed: &mut BTreeSet<(String, String)>,
+) {
+    let timeout = Duration::from_secs(state.config.server.timeout_secs);
+    let hosts = match tokio::time::timeout(timeout, labelled_hosts(state, config, endpoint)).await {
+        Ok(Ok(hosts)) => hosts,
+        Ok(Err(e)) => {
+            warn!("Failed to list Docker containers: {:#}", e);
+            return;
+        }
+        Err(_) => {
+            warn!("Listing Docker containers timed out after {}s", timeout.as_secs());
+            return;
+        }
+    };
+
+    let record_types = match config.record_type {
+        HostRecordType::A => &[RecordType::A][..],
+        HostRecordType::Aaaa => &[RecordType::Aaaa][..],
+        HostRecordType::Both | HostRecordType::Cname => &[RecordType::A, RecordType::Aaaa][..],
+    };
+
+    if config.remove_on_stop {
+        let gone: Vec<_> = managed.difference(&hosts).cloned().collect();
+        for (provider, host) in gone {
+            let Some(provider_config) = state.config.get_provider(&provider) else {
+                managed.remove(&(provider, host));
+                continue;
+            };
+            if !state.providers.get(&provider).is_some_and(|p| p.capabilities().delete) {
+                warn!("No running container names {} any more, but provider {} can't delete records", host, provider);
+                managed.remove(&(provider, host));
+                continue;
+            }
+            info!("No running container names {} any more, deleting its records", host);
+            match delete_records(state, provider_config, &host, record_types).await {
+                Ok(_) => {
+                    managed.remove(&(provider, host));
+                }
+                // Retried on the next sync
+                Err(response) => warn!("Deleting records of {} failed with status {}", host, response.status()),
+            }
+        }
+    }
+    if hosts.is_empty() {
+        return;
+    }
+
+    for &record_type in record_types {
+        let ipv6 = record_type == RecordType::Aaaa;
+        let ip = match detector.detect(ipv6).await {
+            Ok(ip) => ip,
+            Err(e) => {
+                warn!("Failed to detect the public IPv{} address: {:#}", if ipv6 { 6 } else { 4 }, e);
+                continue;
+            }
+        };
+        for (provider, host) in &hosts {
+            let Some(provider_config) = state.config.get_provider(provider) else {
+                continue;
+            };
+            let response = perform_update(state, provider_config, host, ip).await;
+            if !response.status().is_success() {
+                warn!("Update of labelled host {} to {} failed with status {}", host, ip, response.status());
+            }
+        }
+    }
+    managed.extend(hosts);
+}
+
+/// The provider and host named in the labels of every running container,
+/// normalized. Invalid names and unknown providers are skipped with a warning.
+async fn labelled_hosts(state: &AppState, config: &DockerConfig, endpoint: &Endpoint) -> Result<BTreeSet<(String, String)>> {
+    let host_label = format!("{}.host", config.label_prefix);
+    let provider_label = format!("{}.provider", config.label_prefix);
+
+    let mut body = String::new();
+    endpoint
+        .get(&filtered("/containers/json", json!({ "label": [host_label] })))
+        .await?
+        .read_to_string(&mut body)
+        .await
+        .context("Failed to read Docker containers")?;
+    let containers: Vec<Value> = serde_json::from_str(&body).context("Invalid container list from Docker")?;
+
+    let mut hosts = BTreeSet::new();
+    for container in &containers {
+        let labels = &container["Labels"];
+        let Some(value) = labels[&host_label].as_str() else {
+            continue;
+        };
+        let name = container["Names"][0].as_str().unwrap_or_default().trim_start_matches('/');
+        let provider = labels[&provider_label].as_str().unwrap_or(&config.provider);
+        if state.config.get_provider(provider).is_none() {
+            warn!("Skipping container {}: unknown provider {}", name, provider);
+            continue;
+        }
+        for host in value.split(',').map(str::trim).filter(|host| !host.is_empty()) {
+            match hostname::normalize(host) {
+                Ok(normalized) if !normalized.starts_with("*.") => {
+                    hosts.insert((provider.to_string(), normalized));
+                }
+                Ok(_) => warn!("Skipping wildcard {} labelled on container {}", host, name),
+                Err(e) => warn!("Skipping {} labelled on container {}: {}", host, name, e),
+            }
+        }
+    }
+
+    Ok(hosts)
+}
@@ -0,0 +1,89 @@
+//! `/events`: the outcome of every provider call streamed as Server-Sent
+//! Events while it happens, so dashboards and companion tools can react to
+//! updates and IP changes without polling `/status`.
+
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::response::sse;
+use futures_util::stream::{self, Stream};
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::audit::Change;
+use crate::provider::ChangeKind;
+
+/// Events buffered for a slow subscriber before it misses some
+const CAPACITY: usize = 256;
+
+/// One `data:` line of the stream, with the fields of an audit log entry
+#[derive(Debug, Serialize)]
+pub struct Event {
+    time: String,
+    action: &'static str,
+    provider: String,
+    host: String,
+    old: Option<String>,
+    new: String,
+    success: bool,
+    changed: bool,
+    /// `created`, `updated`, `no_change` or `deleted`
+    change: Option<&'static str>,
+    message: String,
+}
+
+pub struct EventBus {
+    sender: broadcast::Sender<Arc<Event>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+
+    /// Hands `change` to the current subscribers, if there are any.
+    pub fn publish(&self, change: &Change) {
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+        let event = Event {
+            time: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            action: change.action,
+            provider: change.provider.to_string(),
+            host: change.host.to_string(),
+            old: change.old.clone(),
+            new: change.new.clone(),
+            success: change.success,
+            changed: change.change.as_ref().is_some_and(ChangeKind::is_change),
+            change: change.change.as_ref().map(ChangeKind::as_str),
+            message: change.message.to_string(),
+        };
+        let _ = self.sender.send(Arc::new(event));
+    }
+
+    /// The events of `providers` published from now on. A subscriber
+    /// falling too far behind gets a comment saying how many it missed.
+    pub fn subscribe(&self, providers: HashSet<String>) -> impl Stream<Item = Result<sse::Event, Infallible>> {
+        stream::unfold(self.sender.subscribe(), move |mut receiver| {
+            let providers = providers.clone();
+            async move {
+                loop {
+                    let event = match receiver.recv().await {
+                        Ok(event) if providers.contains(&event.provider) => {
+                            sse::Event::default().data(serde_json::to_string(&*event).unwrap_or_default())
+                        }
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(missed)) => sse::Event::default().comment(format!("missed {} events", missed)),
+                        Err(RecvError::Closed) => return None,
+                    };
+                    return Some((Ok(event), receiver));
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,97 @@
+//! Limits and response headers for a service that is often exposed directly
+//! to the internet.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use tower_http::set_header::SetResponseHeaderLayer;
+
+use crate::api::{AppState, ErrorCode, ErrorResponse};
+
+/// Rejects URIs longer than the limit with 414, and with 400 paths whose
+/// percent-encoding does not decode to UTF-8, hides a separator or control
+/// character, or that contain `.` / `..` segments.
+pub async fn check_uri(State(max_uri_length): State<usize>, request: Request, next: Next) -> Response {
+    let uri = request.uri();
+    let length = uri.path_and_query().map_or(0, |p| p.as_str().len());
+    if max_uri_length > 0 && length > max_uri_length {
+        return reject(StatusCode::URI_TOO_LONG, format!("URI longer than {} bytes", max_uri_length));
+    }
+    if let Err(reason) = check_path(uri.path()) {
+        return reject(StatusCode::BAD_REQUEST, format!("Invalid path: {}", reason));
+    }
+
+    next.run(request).await
+}
+
+fn check_path(path: &str) -> Result<(), &'static str> {
+    for segment in path.split('/') {
+        let decoded = decode_segment(segment)?;
+        if decoded == "." || decoded == ".." {
+            return Err("dot segments are not allowed");
+        }
+    }
+    Ok(())
+}
+
+fn decode_segment(segment: &str) -> Result<String, &'static str> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = match bytes[i] {
+            b'%' => {
+                let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+                let byte = hex.and_then(|h| u8::from_str_radix(h, 16).ok()).ok_or("malformed percent-encoding")?;
+                if byte == b'/' || byte == b'\\' {
+                    return Err("encoded path separators are not allowed");
+                }
+                i += 3;
+                byte
+            }
+            byte => {
+                i += 1;
+                byte
+            }
+        };
+        if byte.is_ascii_control() {
+            return Err("control characters are not allowed");
+        }
+        decoded.push(byte);
+    }
+    String::from_utf8(decoded).map_err(|_| "not valid UTF-8")
+}
+
+fn reject(status: StatusCode, error: String) -> Response {
+    (
+        status,
+        Json(ErrorResponse {
+            success: false,
+            code: ErrorCode::InvalidRequest,
+            error,
+        }),
+    )
+        .into_response()
+}
+
+/// Adds headers that keep browsers from sniffing, framing, caching or leaking
+/// the referrer of responses, unless a handler set them itself.
+pub fn security_headers(router: Router<Arc<AppState>>) -> Router<Arc<AppState>> {
+    [
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        (header::X_FRAME_OPTIONS, "DENY"),
+        (header::CONTENT_SECURITY_POLICY, "frame-ancestors 'none'"),
+        (header::REFERRER_POLICY, "no-referrer"),
+        (header::CACHE_CONTROL, "no-store"),
+    ]
+    .into_iter()
+    .fold(router, |router, (name, value)| {
+        router.layer(SetResponseHeaderLayer::if_not_present(name, HeaderValue::from_static(value)))
+    })
+}
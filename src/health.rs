@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tracing::Instrument;
+
+use crate::config::Config;
+use crate::provider::Provider;
+use crate::redact::Redactor;
+
+/// Runs provider credential checks for `/health/ready`, caching the result so
+/// frequent probes do not hit provider APIs (or their rate limits) every time.
+pub struct ReadinessChecker {
+    ttl: Duration,
+    /// Last report and when it was taken; held across a check so concurrent
+    /// probes wait for one run instead of starting their own
+    cached: Mutex<Option<(Instant, ReadinessReport)>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessReport {
+    pub ready: bool,
+    /// Seconds since the checks ran
+    pub age_secs: u64,
+    pub providers: Vec<ProviderCheck>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderCheck {
+    pub name: String,
+    pub ok: bool,
+    /// Status description on success, error on failure
+    pub message: String,
+}
+
+impl ReadinessChecker {
+    pub fn new(ttl_secs: u64) -> Self {
+        Self {
+            ttl: Duration::from_secs(ttl_secs),
+            cached: Mutex::new(None),
+        }
+    }
+
+    pub async fn check(
+        &self,
+        config: &Config,
+        providers: &HashMap<String, Arc<dyn Provider>>,
+        redactor: &Redactor,
+    ) -> ReadinessReport {
+        let mut cached = self.cached.lock().await;
+
+        if let Some((checked_at, ref report)) = *cached {
+            if checked_at.elapsed() < self.ttl {
+                return ReadinessReport {
+                    age_secs: checked_at.elapsed().as_secs(),
+                    ..report.clone()
+                };
+            }
+        }
+
+        // Verify all providers concurrently, keeping results in config order
+        let handles: Vec<_> = config
+            .providers
+            .iter()
+            .map(|p| {
+                let provider = providers.get(&p.name).cloned();
+                tokio::spawn(
+                    async move {
+                        match provider {
+                            Some(provider) => provider.verify().await,
+                            None => Err(anyhow::anyhow!("Provider not initialized")),
+                        }
+                    }
+                    .in_current_span(),
+                )
+            })
+            .collect();
+
+        let mut checks = Vec::new();
+        for (p, handle) in config.providers.iter().zip(handles) {
+            let (ok, message) = match handle.await {
+                Ok(Ok(status)) => (true, status),
+                Ok(Err(e)) => (false, redactor.redact(&format!("{:#}", e))),
+                Err(e) => (false, format!("Check panicked: {}", e)),
+            };
+            checks.push(ProviderCheck {
+                name: p.name.clone(),
+                ok,
+                message,
+            });
+        }
+
+        let report = ReadinessReport {
+            ready: checks.iter().all(|c| c.ok),
+            age_secs: 0,
+            providers: checks,
+        };
+        *cached = Some((Instant::now(), report.clone()));
+
+        report
+    }
+}
@@ -0,0 +1,129 @@
+//! `[hooks]` and per-host `on_change`: runs a command after a host's address
+//! changed, e.g. to restart a VPN or reload firewall rules, and points the
+//! `[[hooks.wireguard]]` peers following the host at its new address.
+
+use std::net::{IpAddr, SocketAddr};
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::Command;
+use tracing::{info, warn, Instrument};
+
+use crate::config::{HooksConfig, ProviderConfig};
+
+/// Characters of a command's output kept in the log
+const MAX_OUTPUT_CHARS: usize = 2000;
+
+pub struct Hooks {
+    config: HooksConfig,
+}
+
+impl Hooks {
+    pub fn new(config: &HooksConfig) -> Self {
+        Self { config: config.clone() }
+    }
+
+    /// Runs the `on_change` command for `host` in the background, with the
+    /// change in `DDNS_PROVIDER`, `DDNS_HOST`, `DDNS_OLD_IP` (empty if unknown)
+    /// and `DDNS_NEW_IP` (comma-separated for a set of records), and updates
+    /// the endpoints of the WireGuard peers at `host`.
+    pub fn on_change(&self, provider: &ProviderConfig, host: &str, old_ip: Option<IpAddr>, new_ips: &[IpAddr]) {
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+
+        if let Some(command) = self.command_for(provider, host) {
+            let mut hook = shell(&command);
+            hook.env("DDNS_PROVIDER", &provider.name)
+                .env("DDNS_HOST", host)
+                .env("DDNS_OLD_IP", old_ip.map(|ip| ip.to_string()).unwrap_or_default())
+                .env("DDNS_NEW_IP", new_ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>().join(","));
+            spawn(format!("on_change hook of {}", host), hook, timeout);
+        }
+
+        for peer in self.config.wireguard.iter().filter(|p| p.host.trim_end_matches('.').eq_ignore_ascii_case(host)) {
+            let Some(ip) = new_ips.iter().find(|ip| peer.record_type.allows(ip)) else {
+                continue;
+            };
+            let mut wg = Command::new(&self.config.wg_command);
+            wg.args(["set", &peer.interface, "peer", &peer.peer, "endpoint"])
+                .arg(SocketAddr::new(*ip, peer.port).to_string());
+            spawn(format!("WireGuard endpoint update of peer {} on {}", peer.peer, peer.interface), wg, timeout);
+        }
+    }
+
+    /// The host's own command, else the global one; an empty one disables the hook.
+    fn command_for(&self, provider: &ProviderConfig, host: &str) -> Option<String> {
+        provider
+            .find_host(host)
+            .and_then(|h| h.on_change.as_deref())
+            .or(self.config.on_change.as_deref())
+            .filter(|command| !command.trim().is_empty())
+            .map(str::to_string)
+    }
+}
+
+/// Runs `command` in the background, logging how it went under `label`.
+fn spawn(label: String, command: Command, timeout: Duration) {
+    // Keep the request ID of the update on the command's logs
+    tokio::spawn(async move { run(&label, command, timeout).await }.in_current_span());
+}
+
+async fn run(label: &str, mut command: Command, timeout: Duration) {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Failed to start {}: {}", label, e);
+            return;
+        }
+    };
+
+    // Dropping the timed out wait kills the command
+    match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(Ok(output)) => {
+            let captured = captured(&output.stdout, &output.stderr);
+            if output.status.success() {
+                info!("{} finished{}", label, captured);
+            } else {
+                warn!("{} failed ({}){}", label, output.status, captured);
+            }
+        }
+        Ok(Err(e)) => warn!("{} failed: {}", label, e),
+        Err(_) => warn!("{} timed out after {}s and was killed", label, timeout.as_secs()),
+    }
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+/// The command's stdout and stderr for the log, shortened: empty, or `: ...`.
+fn captured(stdout: &[u8], stderr: &[u8]) -> String {
+    let output = [stdout, stderr]
+        .iter()
+        .map(|bytes| String::from_utf8_lossy(bytes).trim().to_string())
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if output.is_empty() {
+        return String::new();
+    }
+
+    match output.char_indices().nth(MAX_OUTPUT_CHARS) {
+        Some((end, _)) => format!(": {}...", &output[..end]),
+        None => format!(": {}", output),
+    }
+}
@@ -0,0 +1,44 @@
+//! Validation and normalization of hostnames received in requests.
+
+/// Longest DNS name in presentation form, without the trailing dot
+const MAX_NAME_LEN: usize = 253;
+const MAX_LABEL_LEN: usize = 63;
+
+/// Converts `host` to the form sent to providers: lowercase ASCII, IDNs in
+/// punycode (`xn--`), no trailing dot. A leading `*.` wildcard label is kept.
+pub fn normalize(host: &str) -> Result<String, String> {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    let (wildcard, name) = match host.strip_prefix("*.") {
+        Some(name) => (true, name),
+        None => (false, host),
+    };
+
+    let name = idna::domain_to_ascii(name).map_err(|_| format!("invalid internationalized name: {}", host))?;
+    let name = if wildcard { format!("*.{}", name) } else { name };
+
+    if name.is_empty() {
+        return Err("hostname is empty".to_string());
+    }
+    if name.len() > MAX_NAME_LEN {
+        return Err(format!("hostname is longer than {} characters", MAX_NAME_LEN));
+    }
+
+    let labels = name.split('.').skip(usize::from(wildcard));
+    for label in labels {
+        if label.is_empty() {
+            return Err(format!("empty label in {}", host));
+        }
+        if label.len() > MAX_LABEL_LEN {
+            return Err(format!("label longer than {} characters in {}", MAX_LABEL_LEN, host));
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(format!("label starts or ends with '-' in {}", host));
+        }
+        // `_` appears in service names such as `_acme-challenge`
+        if !label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') {
+            return Err(format!("invalid character in {}", host));
+        }
+    }
+
+    Ok(name)
+}
@@ -0,0 +1,156 @@
+//! `ddns-rust export` / `ddns-rust import`: the managed hosts of every
+//! provider and their last known state as one JSON document, for moving to
+//! another machine or seeding a fresh install.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::state::{HostState, StateStore};
+
+/// Version of the export format, bumped on incompatible changes
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HostsExport {
+    pub version: u32,
+    pub providers: Vec<ProviderHosts>,
+}
+
+/// The hosts of one provider, of the main config or of a `[[tenants]]` entry
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProviderHosts {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub provider_type: String,
+    pub hosts: Vec<ExportedHost>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedHost {
+    pub name: String,
+    /// Whether the host has a `[[providers.hosts]]` block
+    #[serde(default)]
+    pub configured: bool,
+    /// Last pushed record, from the state file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<HostState>,
+}
+
+/// What an import did
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// Hosts whose state was written to a state file
+    pub restored: usize,
+    /// Why parts of the export were not imported
+    pub warnings: Vec<String>,
+}
+
+/// Collects the configured hosts and state file entries of every provider.
+pub fn export(config: &Config) -> Result<HostsExport> {
+    let mut providers = Vec::new();
+
+    for (tenant, scope) in scopes(config) {
+        let known = match scope.server.state_file {
+            Some(ref path) => StateStore::load(Path::new(path))?.entries(),
+            None => Vec::new(),
+        };
+
+        for provider in &scope.providers {
+            let mut hosts: BTreeMap<String, ExportedHost> = BTreeMap::new();
+            for host in &provider.hosts {
+                let name = host.name.to_ascii_lowercase();
+                hosts.insert(name.clone(), ExportedHost { name, configured: true, state: None });
+            }
+            for (_, host, state) in known.iter().filter(|(p, _, _)| *p == provider.name) {
+                hosts
+                    .entry(host.clone())
+                    .or_insert_with(|| ExportedHost { name: host.clone(), configured: false, state: None })
+                    .state = Some(state.clone());
+            }
+
+            providers.push(ProviderHosts {
+                tenant: tenant.clone(),
+                name: provider.name.clone(),
+                provider_type: provider.provider_type.clone(),
+                hosts: hosts.into_values().collect(),
+            });
+        }
+    }
+
+    Ok(HostsExport { version: FORMAT_VERSION, providers })
+}
+
+/// Writes the exported state into the state files of `config`'s matching
+/// providers. Run it while the server is stopped, as the server keeps its
+/// own copy of the state file.
+pub fn import(config: &Config, export: &HostsExport) -> Result<ImportSummary> {
+    if export.version != FORMAT_VERSION {
+        anyhow::bail!("Unsupported export format version {} (expected {})", export.version, FORMAT_VERSION);
+    }
+
+    let scopes = scopes(config);
+    let mut summary = ImportSummary::default();
+    // Entries to write, per state file
+    let mut restore: BTreeMap<String, Vec<(String, String, HostState)>> = BTreeMap::new();
+
+    for provider in &export.providers {
+        let label = match provider.tenant {
+            Some(ref tenant) => format!("{}/{}", tenant, provider.name),
+            None => provider.name.clone(),
+        };
+        let Some((_, scope)) = scopes.iter().find(|(tenant, _)| *tenant == provider.tenant) else {
+            summary.warnings.push(format!("{}: tenant is not configured", label));
+            continue;
+        };
+        let Some(provider_config) = scope.get_provider(&provider.name) else {
+            summary.warnings.push(format!("{}: provider is not configured", label));
+            continue;
+        };
+        if provider_config.provider_type != provider.provider_type {
+            summary.warnings.push(format!(
+                "{}: provider type is {} here, not {}",
+                label, provider_config.provider_type, provider.provider_type
+            ));
+            continue;
+        }
+
+        for host in &provider.hosts {
+            if host.configured && provider_config.find_host(&host.name).is_none() {
+                summary.warnings.push(format!("{}: host {} has no [[providers.hosts]] block here", label, host.name));
+            }
+        }
+
+        let states: Vec<_> = provider.hosts.iter().filter_map(|h| h.state.as_ref().map(|s| (h, s))).collect();
+        if states.is_empty() {
+            continue;
+        }
+        let Some(ref path) = scope.server.state_file else {
+            summary.warnings.push(format!("{}: state of {} host(s) skipped, no state_file is configured", label, states.len()));
+            continue;
+        };
+        summary.restored += states.len();
+        restore
+            .entry(path.clone())
+            .or_default()
+            .extend(states.into_iter().map(|(h, s)| (provider.name.clone(), h.name.clone(), s.clone())));
+    }
+
+    for (path, entries) in restore {
+        StateStore::load(Path::new(&path))?.set_all(entries)?;
+    }
+
+    Ok(summary)
+}
+
+/// The main config and each tenant's view of it, keyed by tenant name.
+fn scopes(config: &Config) -> Vec<(Option<String>, Config)> {
+    std::iter::once((None, config.clone()))
+        .chain(config.tenants.iter().map(|t| (Some(t.name.clone()), config.for_tenant(t))))
+        .collect()
+}
@@ -0,0 +1,229 @@
+//! Replaying the result of an update to clients that retry it: routers on
+//! flaky links often resend a request whose response they never received.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Query, Request, State},
+    http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+use tracing::debug;
+
+use crate::api::{AppState, ClientIp, ErrorCode, ErrorResponse};
+
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+/// Set on responses answered from the cache
+const REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// Number of remembered requests above which expired ones are pruned
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Marks a successful response that must not be replayed, such as a DynDNS2
+/// error code sent with status 200.
+#[derive(Debug, Clone, Copy)]
+pub struct NoReplay;
+
+/// Results of recent update requests, keyed by their fingerprint.
+pub struct IdempotencyCache {
+    window: Duration,
+    /// Also identify requests without an `Idempotency-Key` by their content
+    fingerprint_all: bool,
+    slots: Mutex<HashMap<[u8; 32], Arc<Slot>>>,
+}
+
+/// One request's result, filled by the first request; retries arriving
+/// meanwhile wait for it. `None` when the result is not replayed.
+struct Slot {
+    created: Instant,
+    /// Digest of the method, URI and body it was made with
+    content: [u8; 32],
+    result: OnceCell<Option<Stored>>,
+}
+
+/// The credentials a request may carry in its query
+#[derive(Deserialize)]
+struct QueryCredentials {
+    key: Option<String>,
+    password: Option<String>,
+}
+
+#[derive(Clone)]
+struct Stored {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl Stored {
+    fn response(&self, replayed: bool) -> Response {
+        let mut response = Response::new(Body::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        if replayed {
+            response.headers_mut().insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+        }
+        response
+    }
+}
+
+impl IdempotencyCache {
+    /// Creates a cache remembering results for `window_secs`, or `None` if disabled (0).
+    pub fn new(window_secs: u64, fingerprint_all: bool) -> Option<Self> {
+        if window_secs == 0 {
+            return None;
+        }
+
+        Some(Self {
+            window: Duration::from_secs(window_secs),
+            fingerprint_all,
+            slots: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The slot of `fingerprint`, replacing one that has expired; `None` if
+    /// it was made with different `content`.
+    fn slot(&self, fingerprint: [u8; 32], content: [u8; 32]) -> Option<Arc<Slot>> {
+        let now = Instant::now();
+        let mut slots = self.slots.lock().unwrap();
+
+        if slots.len() > PRUNE_THRESHOLD {
+            slots.retain(|_, slot| now.duration_since(slot.created) < self.window);
+        }
+
+        let slot = slots.entry(fingerprint).or_insert_with(|| Arc::new(Slot::new(now, content)));
+        if now.duration_since(slot.created) >= self.window {
+            *slot = Arc::new(Slot::new(now, content));
+        }
+        (slot.content == content).then(|| slot.clone())
+    }
+
+    /// Forgets a result that is not replayed, so the next retry runs again.
+    fn release(&self, fingerprint: &[u8; 32], slot: &Arc<Slot>) {
+        let mut slots = self.slots.lock().unwrap();
+        if slots.get(fingerprint).is_some_and(|s| Arc::ptr_eq(s, slot)) {
+            slots.remove(fingerprint);
+        }
+    }
+}
+
+impl Slot {
+    fn new(created: Instant, content: [u8; 32]) -> Self {
+        Self {
+            created,
+            content,
+            result: OnceCell::new(),
+        }
+    }
+}
+
+/// Answers a retried update with the first attempt's response instead of
+/// updating again. A request is identified by its `Idempotency-Key` header,
+/// path and credentials, or with `dedupe_retries` by its method, URI, client
+/// IP, credentials and body. Reusing a key for a different method, URI or body is refused
+/// with 422. Only successful responses are replayed, so a failed update can
+/// be retried.
+pub async fn replay(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(ref cache) = state.idempotency else {
+        return next.run(request).await;
+    };
+
+    let key = request.headers().get(IDEMPOTENCY_KEY_HEADER).map(|v| v.as_bytes().to_vec());
+    if key.is_none() && !cache.fingerprint_all {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    // The body limit layer has already capped its size, failing the read past it
+    let Ok(body) = axum::body::to_bytes(body, usize::MAX).await else {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    };
+    let content = digest(&[parts.method.as_str().as_bytes(), parts.uri.to_string().as_bytes(), &body]);
+    let fingerprint = match key {
+        // Only the caller who made the request gets its result back
+        Some(key) => digest(&[b"key", parts.uri.path().as_bytes(), &key, &credentials(&parts)]),
+        None => {
+            let client_ip = parts.extensions.get::<ClientIp>().and_then(|ClientIp(ip)| *ip).map(|ip| ip.to_string());
+            digest(&[b"request", &content, client_ip.unwrap_or_default().as_bytes(), &credentials(&parts)])
+        }
+    };
+    let request = Request::from_parts(parts, Body::from(body));
+
+    let Some(slot) = cache.slot(fingerprint, content) else {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ErrorResponse {
+                success: false,
+                code: ErrorCode::InvalidRequest,
+                error: "Idempotency-Key already used for a different request".to_string(),
+            }),
+        )
+            .into_response();
+    };
+    let mut pending = Some((request, next));
+    let mut own = None;
+    let (pending_ref, own_ref) = (&mut pending, &mut own);
+    let stored = slot
+        .result
+        .get_or_init(|| async move {
+            let (request, next) = pending_ref.take().expect("initialized once");
+            let response = next.run(request).await;
+            if !response.status().is_success() || response.extensions().get::<NoReplay>().is_some() {
+                *own_ref = Some(response);
+                return None;
+            }
+            let (parts, body) = response.into_parts();
+            let body = axum::body::to_bytes(body, usize::MAX).await.unwrap_or_default();
+            Some(Stored {
+                status: parts.status,
+                headers: parts.headers,
+                body,
+            })
+        })
+        .await
+        .clone();
+
+    match (pending, stored) {
+        // This request ran the update
+        (None, Some(stored)) => stored.response(false),
+        (None, None) => {
+            cache.release(&fingerprint, &slot);
+            own.expect("response kept when not stored")
+        }
+        (Some(_), Some(stored)) => {
+            debug!("Replaying the response of an earlier identical request");
+            stored.response(true)
+        }
+        // The first attempt failed, so this one tries again
+        (Some((request, next)), None) => next.run(request).await,
+    }
+}
+
+/// Digest of whatever authenticates a request: its `Authorization` and
+/// `X-Signature` headers and a `key` or `password` query parameter.
+fn credentials(parts: &Parts) -> [u8; 32] {
+    let header = |name: &str| parts.headers.get(name).map(|v| v.as_bytes()).unwrap_or_default();
+    let query = Query::<QueryCredentials>::try_from_uri(&parts.uri).ok();
+    let (key, password) = match query {
+        Some(Query(query)) => (query.key.unwrap_or_default(), query.password.unwrap_or_default()),
+        None => Default::default(),
+    };
+    digest(&[header(header::AUTHORIZATION.as_str()), header("x-signature"), key.as_bytes(), password.as_bytes()])
+}
+
+fn digest(fields: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for field in fields {
+        hasher.update((field.len() as u64).to_be_bytes());
+        hasher.update(field);
+    }
+    hasher.finalize().into()
+}
@@ -0,0 +1,91 @@
+//! IPv6 addresses of local network interfaces, read from `/proc/net/if_inet6` (Linux).
+
+use std::net::Ipv6Addr;
+
+use anyhow::{Context, Result};
+
+use crate::config::Ipv6Selection;
+
+const IF_INET6: &str = "/proc/net/if_inet6";
+
+/// Scope value of globally routable addresses
+const SCOPE_GLOBAL: u8 = 0x00;
+
+/// Address flags (`IFA_F_*`) as listed in `/proc/net/if_inet6`
+const IFA_F_TEMPORARY: u8 = 0x01;
+const IFA_F_DADFAILED: u8 = 0x08;
+const IFA_F_DEPRECATED: u8 = 0x20;
+const IFA_F_TENTATIVE: u8 = 0x40;
+
+/// One address line of `/proc/net/if_inet6`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceAddress {
+    pub interface: String,
+    pub address: Ipv6Addr,
+    pub prefix_len: u8,
+    pub scope: u8,
+    pub flags: u8,
+}
+
+impl InterfaceAddress {
+    /// A privacy extension address (RFC 8981), replaced every day or so
+    pub fn is_temporary(&self) -> bool {
+        self.flags & IFA_F_TEMPORARY != 0
+    }
+
+    /// An interface identifier derived from the MAC address (`xx:xxff:fexx:xxxx`)
+    pub fn is_eui64(&self) -> bool {
+        let octets = self.address.octets();
+        octets[11] == 0xff && octets[12] == 0xfe
+    }
+
+    /// Global, not unique local (`fc00::/7`), and neither deprecated nor still
+    /// in (or failed) duplicate address detection.
+    pub fn is_public(&self) -> bool {
+        self.scope == SCOPE_GLOBAL
+            && (self.address.segments()[0] & 0xfe00) != 0xfc00
+            && self.flags & (IFA_F_DADFAILED | IFA_F_DEPRECATED | IFA_F_TENTATIVE) == 0
+    }
+}
+
+/// Parses the contents of `/proc/net/if_inet6`, skipping malformed lines.
+pub fn parse_if_inet6(content: &str) -> Vec<InterfaceAddress> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [address, _index, prefix_len, scope, flags, interface] = fields[..] else {
+                return None;
+            };
+            Some(InterfaceAddress {
+                interface: interface.to_string(),
+                address: Ipv6Addr::from(u128::from_str_radix(address, 16).ok()?),
+                prefix_len: u8::from_str_radix(prefix_len, 16).ok()?,
+                scope: u8::from_str_radix(scope, 16).ok()?,
+                flags: u8::from_str_radix(flags, 16).ok()?,
+            })
+        })
+        .collect()
+}
+
+/// The first public address of `interface` that `selection` accepts.
+pub fn select(addresses: &[InterfaceAddress], interface: &str, selection: Ipv6Selection) -> Option<Ipv6Addr> {
+    addresses
+        .iter()
+        .filter(|a| a.interface == interface && a.is_public())
+        .find(|a| match selection {
+            Ipv6Selection::Stable => !a.is_temporary(),
+            Ipv6Selection::Eui64 => !a.is_temporary() && a.is_eui64(),
+            Ipv6Selection::Temporary => a.is_temporary(),
+            Ipv6Selection::Any => true,
+        })
+        .map(|a| a.address)
+}
+
+/// Reads the current addresses and picks one of `interface`'s.
+pub fn ipv6_address(interface: &str, selection: Ipv6Selection) -> Result<Ipv6Addr> {
+    let content = std::fs::read_to_string(IF_INET6)
+        .with_context(|| format!("Failed to read {} (interface addresses need Linux)", IF_INET6))?;
+    select(&parse_if_inet6(&content), interface, selection)
+        .with_context(|| format!("No public IPv6 address on interface {} matches ipv6_select = \"{}\"", interface, selection.as_str()))
+}
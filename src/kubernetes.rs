@@ -0,0 +1,206 @@
+//! `[kubernetes]`: keeps the hosts named in an annotation of the cluster's
+//! Ingresses and Services pointed at the detected public IP, for clusters
+//! behind a dynamic address. Objects are listed through the API server with
+//! the pod's ServiceAccount, so it needs `list` on `ingresses` and `services`.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use reqwest::{Certificate, Client};
+use serde_json::Value;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, info_span, warn, Instrument};
+
+use crate::api::{apply_updates, AppState};
+use crate::config::{HostRecordType, KubernetesConfig, ServerConfig};
+use crate::detect::Detector;
+use crate::hostname;
+use crate::provider;
+
+/// CA of the API server, mounted with the ServiceAccount token
+const SERVICE_ACCOUNT_CA: &str = "/var/run/secrets/kubernetes.io/serviceaccount/ca.crt";
+
+/// Objects listed per request
+const PAGE_SIZE: u32 = 500;
+
+/// Starts the sync loop when `[kubernetes]` is enabled.
+pub fn spawn(state: Arc<AppState>) -> Result<()> {
+    let config = &state.config.kubernetes;
+    if !config.enabled {
+        return Ok(());
+    }
+
+    let api_server = api_server(config)?;
+    let client = api_client(config, &state.config.server)?;
+    let detector = Detector::new(state.config.detect.clone(), provider::build_client(&state.config.server, None)?);
+    let interval = Duration::from_secs(config.interval_secs);
+    info!(
+        "Syncing hosts annotated {} in Kubernetes via {} every {}s",
+        config.annotation,
+        config.provider,
+        interval.as_secs()
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if !state.leader.is_leader().await {
+                continue;
+            }
+            sync(&state, &client, &api_server, &detector).instrument(info_span!("kubernetes")).await;
+        }
+    });
+
+    Ok(())
+}
+
+/// Lists the annotated hosts and pushes the detected addresses to each.
+async fn sync(state: &AppState, client: &Client, api_server: &str, detector: &Detector) {
+    let config = &state.config.kubernetes;
+    let Some(provider_config) = state.config.get_provider(&config.provider) else {
+        return;
+    };
+
+    let hosts = match annotated_hosts(config, client, api_server).await {
+        Ok(hosts) => hosts,
+        Err(e) => {
+            warn!("Failed to list annotated Kubernetes objects: {}", state.redactor.redact(&format!("{:#}", e)));
+            return;
+        }
+    };
+    if hosts.is_empty() {
+        return;
+    }
+
+    let families = match config.record_type {
+        HostRecordType::A => &[false][..],
+        HostRecordType::Aaaa => &[true][..],
+        HostRecordType::Both | HostRecordType::Cname => &[false, true][..],
+    };
+    for &ipv6 in families {
+        let ip = match detector.detect(ipv6).await {
+            Ok(ip) => ip,
+            Err(e) => {
+                warn!("Failed to detect the public IPv{} address: {:#}", if ipv6 { 6 } else { 4 }, e);
+                continue;
+            }
+        };
+        let updates: Vec<_> = hosts.iter().map(|host| (host.clone(), ip)).collect();
+        for ((host, ip), answer) in updates.iter().zip(apply_updates(state, provider_config, &updates).await) {
+            if let Err(response) = answer {
+                warn!("Update of annotated host {} to {} failed with status {}", host, ip, response.status());
+            }
+        }
+    }
+}
+
+/// The hosts named in the annotation of every Ingress and Service, sorted and
+/// normalized. Names that aren't valid hostnames are skipped with a warning.
+async fn annotated_hosts(config: &KubernetesConfig, client: &Client, api_server: &str) -> Result<BTreeSet<String>> {
+    let token_file = &config.token_file;
+    let token = std::fs::read_to_string(token_file).with_context(|| format!("Failed to read {}", token_file))?;
+    let scope = match config.namespace {
+        Some(ref namespace) => format!("/namespaces/{}", namespace),
+        None => String::new(),
+    };
+
+    let mut hosts = BTreeSet::new();
+    for (kind, path) in [
+        ("Ingress", format!("/apis/networking.k8s.io/v1{}/ingresses", scope)),
+        ("Service", format!("/api/v1{}/services", scope)),
+    ] {
+        for item in list(client, &format!("{}{}", api_server, path), token.trim()).await? {
+            let Some(value) = item["metadata"]["annotations"][&config.annotation].as_str() else {
+                continue;
+            };
+            for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+                match hostname::normalize(name) {
+                    Ok(host) if !host.starts_with("*.") => {
+                        hosts.insert(host);
+                    }
+                    Ok(_) => warn!("Skipping wildcard {} annotated on {} {}", name, kind, object_name(&item)),
+                    Err(e) => warn!("Skipping {} annotated on {} {}: {}", name, kind, object_name(&item), e),
+                }
+            }
+        }
+    }
+
+    Ok(hosts)
+}
+
+/// Every item of a list endpoint, following `continue` tokens.
+async fn list(client: &Client, url: &str, token: &str) -> Result<Vec<Value>> {
+    let mut items = Vec::new();
+    let mut next: Option<String> = None;
+    loop {
+        let mut query = vec![("limit", PAGE_SIZE.to_string())];
+        query.extend(next.take().map(|token| ("continue", token)));
+        let body: Value = client
+            .get(url)
+            .bearer_auth(token)
+            .query(&query)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", url))?
+            .error_for_status()
+            .with_context(|| format!("Listing {} failed", url))?
+            .json()
+            .await
+            .with_context(|| format!("Invalid response from {}", url))?;
+
+        if let Some(page) = body["items"].as_array() {
+            items.extend(page.iter().cloned());
+        }
+        match body["metadata"]["continue"].as_str() {
+            Some(token) if !token.is_empty() => next = Some(token.to_string()),
+            _ => return Ok(items),
+        }
+    }
+}
+
+fn object_name(item: &Value) -> String {
+    let metadata = &item["metadata"];
+    format!(
+        "{}/{}",
+        metadata["namespace"].as_str().unwrap_or_default(),
+        metadata["name"].as_str().unwrap_or_default()
+    )
+}
+
+/// `api_server`, or the in-cluster address from the environment.
+fn api_server(config: &KubernetesConfig) -> Result<String> {
+    if let Some(ref url) = config.api_server {
+        return Ok(url.trim_end_matches('/').to_string());
+    }
+    let host = std::env::var("KUBERNETES_SERVICE_HOST")
+        .context("kubernetes.api_server is not set and KUBERNETES_SERVICE_HOST is missing (not running in a pod?)")?;
+    let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_string());
+    // An IPv6 service address needs brackets in the URL
+    Ok(match host.contains(':') {
+        true => format!("https://[{}]:{}", host, port),
+        false => format!("https://{}:{}", host, port),
+    })
+}
+
+/// A client trusting the cluster's CA in addition to the system roots.
+fn api_client(config: &KubernetesConfig, server: &ServerConfig) -> Result<Client> {
+    let ca_file = match config.ca_file {
+        Some(ref file) => Some(file.as_str()),
+        None => Some(SERVICE_ACCOUNT_CA).filter(|file| Path::new(file).exists()),
+    };
+
+    let mut builder = Client::builder()
+        .timeout(Duration::from_secs(server.timeout_secs))
+        .connect_timeout(Duration::from_secs(server.connect_timeout_secs));
+    if let Some(file) = ca_file {
+        let pem = std::fs::read(file).with_context(|| format!("Failed to read {}", file))?;
+        let ca = Certificate::from_pem(&pem).with_context(|| format!("{} is not a PEM certificate", file))?;
+        builder = builder.add_root_certificate(ca);
+    }
+    builder.build().context("Failed to build Kubernetes API client")
+}
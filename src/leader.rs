@@ -0,0 +1,152 @@
+//! `[leader]`: leader election for instances run side by side for
+//! redundancy. Every instance answers requests, but only the leader runs
+//! `[reconcile]`, `[[poll]]` and `[kubernetes]` sync, so the standby doesn't
+//! repeat its work. The leader is whoever holds an exclusive lock on a shared
+//! file or a lease in Redis; when it dies the lock or lease is freed and a
+//! standby takes over on its next attempt.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::config::{Config, LeaderElection};
+use crate::shared::SharedState;
+
+/// Key of the lease under `[redis].key_prefix`
+const LEASE_KEY: &str = "leader";
+
+pub struct Leader {
+    /// `None` until the first attempt to become leader
+    elected: watch::Sender<Option<bool>>,
+    election: Option<Election>,
+    lease: Duration,
+}
+
+enum Election {
+    #[cfg(unix)]
+    File {
+        path: String,
+        /// The locked file, held open for as long as this instance leads
+        held: std::sync::Mutex<Option<std::fs::File>>,
+    },
+    Redis {
+        shared: Box<SharedState>,
+        token: String,
+    },
+}
+
+impl Leader {
+    pub fn new(config: &Config) -> Result<Self> {
+        let election = match config.leader.election {
+            LeaderElection::None => None,
+            #[cfg(unix)]
+            LeaderElection::File => Some(Election::File {
+                path: config.leader.lock_file.clone().unwrap_or_default(),
+                held: std::sync::Mutex::new(None),
+            }),
+            // Validation refuses file locks elsewhere
+            #[cfg(not(unix))]
+            LeaderElection::File => None,
+            LeaderElection::Redis => Some(Election::Redis {
+                shared: Box::new(SharedState::new(&config.redis, &config.server)?),
+                token: uuid::Uuid::new_v4().to_string(),
+            }),
+        };
+        Ok(Self {
+            elected: watch::Sender::new(election.is_none().then_some(true)),
+            election,
+            lease: Duration::from_secs(config.leader.lease_secs),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.election.is_some()
+    }
+
+    /// Whether this instance runs the leader's tasks, once the first attempt
+    /// to become leader is done. Always true without `[leader]`.
+    pub async fn is_leader(&self) -> bool {
+        let mut elected = self.elected.subscribe();
+        let result = elected.wait_for(Option::is_some).await.map(|elected| *elected == Some(true));
+        result.unwrap_or(false)
+    }
+
+    /// Whether this instance currently leads, for `/metrics`.
+    pub fn is_elected(&self) -> bool {
+        *self.elected.borrow() == Some(true)
+    }
+
+    /// Takes the lock or lease if it is free, or keeps it if held.
+    async fn campaign(&self) -> bool {
+        match self.election {
+            None => true,
+            #[cfg(unix)]
+            Some(Election::File { ref path, ref held }) => {
+                let mut held = held.lock().unwrap();
+                if held.is_some() {
+                    return true;
+                }
+                match lock::try_lock(path) {
+                    Ok(file) => {
+                        *held = file;
+                        held.is_some()
+                    }
+                    Err(e) => {
+                        warn!("Failed to lock {}: {}", path, e);
+                        false
+                    }
+                }
+            }
+            Some(Election::Redis { ref shared, ref token }) => shared.hold_lease(LEASE_KEY, token, self.lease).await,
+        }
+    }
+}
+
+/// Tries to become leader, and stay leader, every third of `lease_secs`.
+pub fn spawn(leader: Arc<Leader>) {
+    if !leader.is_enabled() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(leader.lease / 3);
+        loop {
+            ticker.tick().await;
+            let elected = leader.campaign().await;
+            match (leader.elected.send_replace(Some(elected)), elected) {
+                (Some(false) | None, true) => info!("Elected leader, running reconciliation and scheduled updates"),
+                (Some(true), false) => warn!("No longer the leader, pausing reconciliation and scheduled updates"),
+                (None, false) => info!("Another instance is the leader, standing by"),
+                _ => {}
+            }
+        }
+    });
+}
+
+#[cfg(unix)]
+mod lock {
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Write};
+    use std::os::fd::AsRawFd;
+
+    /// Locks `path`, creating it, unless another process (or another open
+    /// of it) holds the lock. The lock lasts until the file is closed.
+    pub fn try_lock(path: &str) -> io::Result<Option<File>> {
+        let mut file = OpenOptions::new().create(true).truncate(false).write(true).open(path)?;
+        // SAFETY: the descriptor belongs to `file`, which outlives the call
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let error = io::Error::last_os_error();
+            return match error.kind() {
+                io::ErrorKind::WouldBlock => Ok(None),
+                _ => Err(error),
+            };
+        }
+        // Tells whoever looks which process leads
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(Some(file))
+    }
+}
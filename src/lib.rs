@@ -0,0 +1,106 @@
+//! DDNS service updating DNS records through pluggable providers.
+//!
+//! The HTTP server is available via [`run_server`] / [`api::create_router`],
+//! and providers can be used directly through the [`provider::Provider`] trait:
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use ddns_rust::config::Config;
+//! use ddns_rust::provider::{self, RecordOptions};
+//!
+//! let config = Config::load("config.toml", None)?;
+//! let providers = provider::create_all(&config)?;
+//! let options = RecordOptions { ttl: 1, proxied: false };
+//! providers["cloudflare"]
+//!     .update_record("home.example.com", "1.2.3.4".parse()?, &options)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod access_log;
+pub mod api;
+mod audit;
+mod auth;
+mod breaker;
+pub mod cidr;
+mod compat;
+pub mod config;
+mod cooldown;
+mod credentials;
+mod detect;
+mod docker;
+mod events;
+mod hardening;
+mod health;
+mod hooks;
+pub mod hosts;
+mod hostname;
+mod idempotency;
+pub mod interface;
+#[cfg(feature = "kubernetes")]
+mod kubernetes;
+mod leader;
+mod listen;
+mod maintenance;
+mod messages;
+mod metrics;
+mod notify;
+pub mod plan;
+mod poll;
+#[cfg(feature = "propagation")]
+mod propagation;
+pub mod provider;
+mod queue;
+mod ratelimit;
+mod reconcile;
+mod retry;
+mod scripting;
+mod redact;
+pub mod schedule;
+mod shared;
+pub mod state;
+mod status;
+pub mod systemd;
+mod throttle;
+pub mod telemetry;
+mod udp;
+mod ui;
+
+use std::net::SocketAddr;
+
+use anyhow::Result;
+use tokio::task::JoinSet;
+use tracing::info;
+
+use config::Config;
+
+/// Binds every `server.listen` address, or `server.host:server.port` (or
+/// takes the sockets systemd passed), and serves the DDNS HTTP API on all of
+/// them until one fails.
+pub async fn run_server(config: Config) -> Result<()> {
+    let server = config.server.clone();
+    let app = api::create_router(config)?;
+    let passed = systemd::listeners()?;
+    let listeners = if passed.is_empty() {
+        listen::bind(&server).await?
+    } else {
+        info!("Using {} socket(s) passed by systemd", passed.len());
+        passed.into_iter().map(tokio::net::TcpListener::from_std).collect::<std::io::Result<_>>()?
+    };
+
+    let mut servers = JoinSet::new();
+    for listener in listeners {
+        info!("Server listening on http://{}", listener.local_addr()?);
+        let service = app.clone().into_make_service_with_connect_info::<SocketAddr>();
+        servers.spawn(async move { axum::serve(listener, service).await });
+    }
+    info!("DDNS endpoint: GET /api/v1/ddns/{{provider}}/{{host}}/{{ip}}");
+    systemd::ready();
+
+    if let Some(result) = servers.join_next().await {
+        result??;
+    }
+
+    Ok(())
+}
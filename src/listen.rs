@@ -0,0 +1,42 @@
+//! Binding the HTTP listeners: every `server.listen` address, or
+//! `server.host:server.port` when none is configured.
+
+use std::net::SocketAddr;
+
+use anyhow::{Context, Result};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::TcpListener;
+
+use crate::config::ServerConfig;
+
+/// Pending connections queued by the kernel per listener
+const BACKLOG: i32 = 1024;
+
+/// Binds the configured addresses, failing if any of them cannot be bound.
+pub async fn bind(server: &ServerConfig) -> Result<Vec<TcpListener>> {
+    if server.listen.is_empty() {
+        let addr = format!("{}:{}", server.host, server.port);
+        let listener = TcpListener::bind(&addr).await.with_context(|| format!("Failed to bind {}", addr))?;
+        return Ok(vec![listener]);
+    }
+
+    server
+        .listen
+        .iter()
+        .map(|&addr| bind_addr(addr).with_context(|| format!("Failed to bind {}", addr)))
+        .collect()
+}
+
+fn bind_addr(addr: SocketAddr) -> Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // `[::]` would otherwise also take the IPv4 port, clashing with a `0.0.0.0` listener
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(BACKLOG)?;
+    Ok(TcpListener::from_std(socket.into())?)
+}
@@ -1,18 +1,113 @@
-mod api;
-mod config;
-mod provider;
+mod cli;
 
 use anyhow::Result;
-use clap::Parser;
-use log::info;
+use clap::{Parser, Subcommand};
+use ddns_rust::{config, telemetry};
+use tracing::info;
 
 #[derive(Parser, Debug)]
 #[command(name = "ddns-rust")]
 #[command(about = "A simple DDNS service supporting multiple DNS providers")]
 struct Args {
     /// Path to the configuration file
-    #[arg(short, long, default_value = "config.toml")]
+    #[arg(short, long, default_value = "config.toml", global = true)]
     config: String,
+
+    /// Configuration file format (toml, yaml, json); detected from the extension by default
+    #[arg(long, global = true)]
+    format: Option<config::ConfigFormat>,
+
+    /// Run under the Windows service control manager (set by `service install`)
+    #[cfg(windows)]
+    #[arg(long, hide = true)]
+    service: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the DDNS HTTP server (default)
+    Serve,
+    /// Generate a starter configuration file at the --config path
+    Init {
+        /// Provider type
+        #[arg(long = "type")]
+        provider_type: Option<String>,
+        /// Provider name used in API paths
+        #[arg(long)]
+        name: Option<String>,
+        /// Provider API token
+        #[arg(long)]
+        api_key: Option<String>,
+        /// Zone ID (discovered from hostnames when omitted)
+        #[arg(long)]
+        zone_id: Option<String>,
+        /// Access key required on update requests
+        #[arg(long)]
+        key: Option<String>,
+        /// Listen port
+        #[arg(long)]
+        port: Option<u16>,
+        /// Overwrite an existing file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the managed hosts and their last known state as JSON
+    Export {
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Restore host state from a file written by `export` (stop the server first)
+    Import {
+        /// Export file
+        file: String,
+    },
+    /// Show the record changes that would bring providers in line with the desired addresses
+    Plan {
+        /// Plan a [[tenants]] entry instead of the main providers
+        #[arg(long)]
+        tenant: Option<String>,
+    },
+    /// Make the changes shown by `plan`
+    Apply {
+        /// Apply to a [[tenants]] entry instead of the main providers
+        #[arg(long)]
+        tenant: Option<String>,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Inspect configured providers
+    Providers {
+        #[command(subcommand)]
+        action: ProvidersCommand,
+    },
+    /// Manage the Windows service
+    #[cfg(windows)]
+    Service {
+        #[command(subcommand)]
+        action: ServiceCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ProvidersCommand {
+    /// List configured providers
+    List,
+    /// Check each provider's credentials against its API
+    Test,
+}
+
+#[cfg(windows)]
+#[derive(Subcommand, Debug)]
+enum ServiceCommand {
+    /// Register ddns-rust as a service started at boot with the --config file
+    Install,
+    /// Stop and remove the service
+    Uninstall,
 }
 
 #[tokio::main]
@@ -20,32 +115,61 @@ async fn main() -> Result<()> {
     // Parse command line arguments
     let args = Args::parse();
 
+    // `init` creates the config, so it runs before loading one
+    if let Some(Command::Init { provider_type, name, api_key, zone_id, key, port, force }) = args.command {
+        let opts = cli::init::InitOptions { provider_type, name, api_key, zone_id, key, port, force };
+        return cli::init::run(&args.config, opts);
+    }
+
+    // The service loads its config and logger itself, once the service manager starts it
+    #[cfg(windows)]
+    {
+        if args.service {
+            return cli::service::run(args.config, args.format);
+        }
+        match args.command {
+            Some(Command::Service { action: ServiceCommand::Install }) => return cli::service::install(&args.config, args.format),
+            Some(Command::Service { action: ServiceCommand::Uninstall }) => return cli::service::uninstall(),
+            _ => {}
+        }
+    }
+
     // Load configuration first (before logger init)
-    let config = config::Config::load(&args.config)?;
+    let config = config::Config::load(&args.config, args.format)?;
+
+    // Without a logger, so nothing but the export ends up on stdout
+    match args.command {
+        Some(Command::Export { ref output }) => return cli::hosts::export(&config, output.as_deref()),
+        Some(Command::Import { ref file }) => return cli::hosts::import(&config, file),
+        _ => {}
+    }
 
-    // Initialize logger with config log level (env var takes precedence)
-    env_logger::Builder::from_env(
-        env_logger::Env::default().default_filter_or(&config.server.log_level)
-    ).init();
+    // Initialize logger with config log level (env var takes precedence) and trace export
+    let _telemetry = telemetry::init(&config)?;
+
+    match args.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(&args.config, config).await,
+        Command::Providers { action: ProvidersCommand::List } => {
+            cli::providers::list(&config);
+            Ok(())
+        }
+        Command::Providers { action: ProvidersCommand::Test } => cli::providers::test(&config).await,
+        Command::Plan { tenant } => cli::plan::plan(&config, tenant.as_deref()).await,
+        Command::Apply { tenant, yes } => cli::plan::apply(&config, tenant.as_deref(), yes).await,
+        Command::Init { .. } => unreachable!("handled before loading config"),
+        Command::Export { .. } | Command::Import { .. } => unreachable!("handled before logger init"),
+        #[cfg(windows)]
+        Command::Service { .. } => unreachable!("handled before loading config"),
+    }
+}
 
-    info!("Loading configuration from: {}", args.config);
+async fn serve(config_path: &str, config: config::Config) -> Result<()> {
+    info!("Loading configuration from: {}", config_path);
     info!(
         "Loaded {} provider(s): {:?}",
         config.providers.len(),
         config.providers.iter().map(|p| &p.name).collect::<Vec<_>>()
     );
 
-    // Create router
-    let app = api::create_router(config.clone());
-
-    // Start server
-    let addr = format!("{}:{}", config.server.host, config.server.port);
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-
-    info!("Server listening on http://{}", addr);
-    info!("DDNS endpoint: GET /ddns/{{provider}}/{{host}}/{{ip}}");
-
-    axum::serve(listener, app).await?;
-
-    Ok(())
+    ddns_rust::run_server(config).await
 }
@@ -0,0 +1,98 @@
+//! Maintenance mode, toggled on `/admin/maintenance`: during a provider
+//! incident or a zone migration, single-address updates are acknowledged
+//! without calling any provider, and the latest one per record is applied
+//! once maintenance ends. Everything else that would change records is
+//! refused meanwhile.
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::provider::RecordType;
+use crate::throttle::Deferred;
+
+#[derive(Default)]
+pub struct Maintenance {
+    active: Mutex<Option<Active>>,
+}
+
+struct Active {
+    since: OffsetDateTime,
+    reason: Option<String>,
+    /// Latest wanted IP and TTL override per provider, host and record type
+    pending: BTreeMap<(String, String, RecordType), (IpAddr, Option<u32>)>,
+}
+
+/// Answer of `GET /admin/maintenance`
+#[derive(Debug, Serialize)]
+pub struct MaintenanceReport {
+    pub enabled: bool,
+    pub since: Option<String>,
+    pub reason: Option<String>,
+    /// Updates waiting for maintenance to end
+    pub pending: usize,
+}
+
+impl Maintenance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.lock().unwrap().is_some()
+    }
+
+    /// Starts maintenance, or updates the reason of the one under way.
+    pub fn enable(&self, reason: Option<String>) {
+        let mut active = self.active.lock().unwrap();
+        match *active {
+            Some(ref mut active) => active.reason = reason,
+            None => {
+                *active = Some(Active {
+                    since: OffsetDateTime::now_utc(),
+                    reason,
+                    pending: BTreeMap::new(),
+                })
+            }
+        }
+    }
+
+    /// Ends maintenance, returning the updates queued during it.
+    pub fn disable(&self) -> Vec<Deferred> {
+        let Some(active) = self.active.lock().unwrap().take() else {
+            return Vec::new();
+        };
+        active
+            .pending
+            .into_iter()
+            .map(|((provider, host, _), (ip, ttl))| Deferred { provider, host, ip, ttl })
+            .collect()
+    }
+
+    /// Queues an update while in maintenance, replacing an older one for the
+    /// same record. Returns false, queueing nothing, outside maintenance.
+    pub fn defer(&self, provider: &str, host: &str, ip: IpAddr, ttl: Option<u32>) -> bool {
+        let mut active = self.active.lock().unwrap();
+        let Some(ref mut active) = *active else {
+            return false;
+        };
+        active
+            .pending
+            .insert((provider.to_string(), host.to_string(), RecordType::for_ip(&ip)), (ip, ttl));
+        true
+    }
+
+    pub fn report(&self) -> MaintenanceReport {
+        let active = self.active.lock().unwrap();
+        MaintenanceReport {
+            enabled: active.is_some(),
+            since: active.as_ref().map(|a| a.since.format(&Rfc3339).unwrap_or_default()),
+            reason: active.as_ref().and_then(|a| a.reason.clone()),
+            pending: active.as_ref().map_or(0, |a| a.pending.len()),
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! `[messages]`: the `message` of update results in the configured language,
+//! or as configured per kind of change. With English and nothing configured,
+//! providers' own messages are kept. Notification templates follow the
+//! language when the config is loaded.
+
+use anyhow::Result;
+
+use crate::config::{Language, MessagesConfig};
+use crate::provider::{ChangeKind, DnsUpdateResult};
+
+pub struct Catalog {
+    created: Option<String>,
+    updated: Option<String>,
+    no_change: Option<String>,
+    deleted: Option<String>,
+}
+
+impl Catalog {
+    pub fn new(config: &MessagesConfig) -> Self {
+        let builtin = |zh: &str| match config.language {
+            Language::En => None,
+            Language::Zh => Some(zh.to_string()),
+        };
+        Self {
+            created: config.created.clone().or_else(|| builtin("已新建记录 {host}，值为 {new}")),
+            updated: config.updated.clone().or_else(|| builtin("已将记录 {host} 更新为 {new}")),
+            no_change: config.no_change.clone().or_else(|| builtin("记录 {host} 已是最新，无需修改")),
+            deleted: config.deleted.clone().or_else(|| builtin("已删除记录 {host}")),
+        }
+    }
+
+    /// The message for a `change` of `host`, `None` to keep the provider's own.
+    pub fn message(&self, provider: &str, host: &str, change: &ChangeKind, new: Option<&str>) -> Option<String> {
+        let template = match change {
+            ChangeKind::Created => &self.created,
+            ChangeKind::Updated { .. } => &self.updated,
+            ChangeKind::NoChange => &self.no_change,
+            ChangeKind::Deleted => &self.deleted,
+        };
+        Some(
+            template
+                .as_ref()?
+                .replace("{provider}", provider)
+                .replace("{host}", host)
+                .replace("{old}", change.old().unwrap_or("unknown"))
+                .replace("{new}", new.unwrap_or_default()),
+        )
+    }
+
+    /// Replaces the message of a successful provider call, if configured.
+    pub fn localize(&self, provider: &str, host: &str, result: &mut Result<DnsUpdateResult>) {
+        if let Ok(ref mut result) = *result {
+            if let Some(message) = self.message(provider, host, &result.change, result.new.as_deref()) {
+                result.message = message;
+            }
+        }
+    }
+}
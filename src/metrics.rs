@@ -0,0 +1,106 @@
+//! `/metrics` in the Prometheus text format: when each host was last updated
+//! and to which address, so alerts can fire when a client goes quiet, how
+//! many updates created, changed or left records alone, how many failed
+//! updates wait in the retry queue, which providers' circuits are open and
+//! whether this instance leads.
+
+use std::fmt::Write;
+
+use crate::breaker::CircuitBreaker;
+use crate::leader::Leader;
+use crate::provider::RecordType;
+use crate::retry::RetryQueue;
+use crate::status::StatusTracker;
+
+/// Content type of the Prometheus text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Renders the freshness gauges of every host `status` knows about.
+pub fn render(status: &StatusTracker, retry_queue: &RetryQueue, leader: &Leader, breaker: &CircuitBreaker) -> String {
+    let hosts = status.freshness();
+    let mut out = String::new();
+
+    gauge_header(&mut out, "ddns_start_time_seconds", "Unix time the server started");
+    let _ = writeln!(out, "ddns_start_time_seconds {}", status.started_at());
+
+    gauge_header(
+        &mut out,
+        "ddns_last_update_timestamp_seconds",
+        "Unix time of the last successful update of a host, changed or not",
+    );
+    for host in &hosts {
+        if let Some(at) = host.success_at {
+            let _ = writeln!(out, "ddns_last_update_timestamp_seconds{{{}}} {}", labels(&host.provider, &host.host), at);
+        }
+    }
+
+    gauge_header(&mut out, "ddns_last_failure_timestamp_seconds", "Unix time of the last failed update of a host");
+    for host in &hosts {
+        if let Some(at) = host.failure_at {
+            let _ = writeln!(out, "ddns_last_failure_timestamp_seconds{{{}}} {}", labels(&host.provider, &host.host), at);
+        }
+    }
+
+    gauge_header(&mut out, "ddns_record_ip_info", "Address a host's record was last set to");
+    for host in &hosts {
+        if let Some(ip) = host.ip {
+            let _ = writeln!(
+                out,
+                "ddns_record_ip_info{{{},ip=\"{}\",type=\"{}\"}} 1",
+                labels(&host.provider, &host.host),
+                ip,
+                RecordType::for_ip(&ip)
+            );
+        }
+    }
+
+    counter_header(&mut out, "ddns_record_changes_total", "Successful updates by the kind of change they made");
+    for (provider, kind, count) in status.change_counts() {
+        let _ = writeln!(out, "ddns_record_changes_total{{provider=\"{}\",change=\"{}\"}} {}", escape(&provider), kind, count);
+    }
+
+    if retry_queue.is_enabled() {
+        gauge_header(&mut out, "ddns_retry_queue_depth", "Failed updates waiting in the retry queue");
+        for (provider, depth) in retry_queue.depth() {
+            let _ = writeln!(out, "ddns_retry_queue_depth{{provider=\"{}\"}} {}", escape(&provider), depth);
+        }
+    }
+
+    if breaker.is_enabled() {
+        let states = breaker.states();
+        gauge_header(&mut out, "ddns_circuit_open", "Whether a provider's circuit breaker is open (or half open)");
+        for (provider, _, open) in &states {
+            let _ = writeln!(out, "ddns_circuit_open{{provider=\"{}\"}} {}", escape(provider), u8::from(*open));
+        }
+        gauge_header(&mut out, "ddns_provider_consecutive_failures", "Provider outages in a row, reset by any answer");
+        for (provider, failures, _) in &states {
+            let _ = writeln!(out, "ddns_provider_consecutive_failures{{provider=\"{}\"}} {}", escape(provider), failures);
+        }
+    }
+
+    if leader.is_enabled() {
+        gauge_header(&mut out, "ddns_leader", "Whether this instance is the elected leader");
+        let _ = writeln!(out, "ddns_leader {}", u8::from(leader.is_elected()));
+    }
+
+    out
+}
+
+fn gauge_header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+}
+
+fn counter_header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+}
+
+fn labels(provider: &str, host: &str) -> String {
+    format!("provider=\"{}\",host=\"{}\"", escape(provider), escape(host))
+}
+
+/// Escapes a label value: backslash, double quote and line feed.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use tracing::debug;
+
+use super::{Channel, Event};
+use crate::config::{EmailConfig, SmtpTls};
+
+/// Sends alerts as plain-text email through an SMTP server
+pub struct Email {
+    config: EmailConfig,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl Email {
+    pub fn new(config: EmailConfig, timeout: Duration) -> Result<Self> {
+        let from = config
+            .from
+            .parse()
+            .with_context(|| format!("Invalid email sender: {}", config.from))?;
+        let to = config
+            .to
+            .iter()
+            .map(|to| to.parse().with_context(|| format!("Invalid email recipient: {}", to)))
+            .collect::<Result<_>>()?;
+
+        // The relay constructors pick the standard port for each mode
+        let mut builder = match config.tls {
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.server)?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.server)?,
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.server),
+        };
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        let transport = builder.timeout(Some(timeout)).build();
+
+        Ok(Self {
+            config,
+            from,
+            to,
+            transport,
+        })
+    }
+}
+
+#[async_trait]
+impl Channel for Email {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    async fn send(&self, event: &Event) -> Result<()> {
+        let subject = event.render(match event {
+            Event::Changed { .. } => &self.config.subject,
+            Event::Failing { .. } => &self.config.failure_subject,
+            Event::Recovered { .. } => &self.config.recovery_subject,
+            Event::Drifted { .. } => &self.config.drift_subject,
+            Event::Credentials { .. } => &self.config.credentials_subject,
+        });
+        let body = event.message(&self.config.templates);
+
+        let mut message = Message::builder().from(self.from.clone()).subject(subject);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let message = message.header(ContentType::TEXT_PLAIN).body(body)?;
+
+        self.transport
+            .send(message)
+            .await
+            .with_context(|| format!("Failed to send email via {}", self.config.server))?;
+
+        debug!("Sent email notification to {} recipient(s)", self.to.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use tracing::debug;
+
+use super::{Channel, Event};
+use crate::config::GotifyConfig;
+
+/// Sends messages to a Gotify application
+pub struct Gotify {
+    config: GotifyConfig,
+    client: Client,
+}
+
+impl Gotify {
+    pub fn new(config: GotifyConfig, client: Client) -> Self {
+        Self { config, client }
+    }
+}
+
+#[async_trait]
+impl Channel for Gotify {
+    fn name(&self) -> &'static str {
+        "gotify"
+    }
+
+    async fn send(&self, event: &Event) -> Result<()> {
+        let url = format!("{}/message", self.config.url.trim_end_matches('/'));
+        let response = self
+            .client
+            .post(&url)
+            .header("X-Gotify-Key", &self.config.token)
+            .json(&serde_json::json!({
+                "title": event.render(&self.config.title),
+                "message": event.message(&self.config.templates),
+                "priority": self.config.priority,
+            }))
+            .send()
+            .await
+            .context("Failed to send Gotify message")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Gotify returned {}: {}", status, body.trim());
+        }
+
+        debug!("Sent Gotify notification");
+        Ok(())
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::Client;
+use tracing::{warn, Instrument};
+
+use crate::config::{Config, MessageTemplates};
+use crate::provider::ChangeKind;
+
+mod email;
+mod gotify;
+mod mqtt;
+mod ntfy;
+mod telegram;
+mod webhook;
+
+/// Something worth telling the user about
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A record was created or now points to a new IP
+    Changed {
+        provider: String,
+        host: String,
+        /// `created` or `updated`
+        change: &'static str,
+        old_ip: Option<IpAddr>,
+        new_ip: IpAddr,
+    },
+    /// Updates via a provider failed `failures` times in a row, last for `host`
+    Failing {
+        provider: String,
+        host: String,
+        ip: IpAddr,
+        error: String,
+        failures: u32,
+    },
+    /// An update via a provider succeeded again after a `Failing` alert
+    Recovered {
+        provider: String,
+        host: String,
+        ip: IpAddr,
+        failures: u32,
+    },
+    /// A record was changed outside this service and has been set back to `ip`
+    Drifted {
+        provider: String,
+        host: String,
+        /// Address the record held, `None` if it was deleted or not an address
+        found: Option<IpAddr>,
+        ip: IpAddr,
+    },
+    /// A provider's credentials no longer work or expire soon
+    Credentials {
+        provider: String,
+        problem: String,
+    },
+}
+
+impl Event {
+    /// Renders the template matching the kind of event.
+    pub fn message(&self, templates: &MessageTemplates) -> String {
+        self.render(match self {
+            Event::Changed { .. } => &templates.template,
+            Event::Failing { .. } => &templates.failure_template,
+            Event::Recovered { .. } => &templates.recovery_template,
+            Event::Drifted { .. } => &templates.drift_template,
+            Event::Credentials { .. } => &templates.credentials_template,
+        })
+    }
+
+    /// Fills `{provider}`, `{host}`, `{change}`, `{old_ip}`, `{new_ip}`, `{error}` and `{failures}` in `template`.
+    pub fn render(&self, template: &str) -> String {
+        let (provider, host, old_ip, new_ip, error, failures) = match self {
+            Event::Changed {
+                provider,
+                host,
+                old_ip,
+                new_ip,
+                ..
+            } => (provider, host.as_str(), *old_ip, Some(*new_ip), "", 0),
+            Event::Failing {
+                provider,
+                host,
+                ip,
+                error,
+                failures,
+            } => (provider, host.as_str(), None, Some(*ip), error.as_str(), *failures),
+            Event::Recovered {
+                provider,
+                host,
+                ip,
+                failures,
+            } => (provider, host.as_str(), None, Some(*ip), "", *failures),
+            Event::Drifted {
+                provider,
+                host,
+                found,
+                ip,
+            } => (provider, host.as_str(), *found, Some(*ip), "", 0),
+            Event::Credentials { provider, problem } => (provider, "", None, None, problem.as_str(), 0),
+        };
+        let unknown = |ip: Option<IpAddr>| ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+        let change = match self {
+            Event::Changed { change, .. } => change,
+            _ => "",
+        };
+
+        template
+            .replace("{provider}", provider)
+            .replace("{change}", change)
+            .replace("{host}", host)
+            .replace("{old_ip}", &unknown(old_ip))
+            .replace("{new_ip}", &unknown(new_ip))
+            .replace("{error}", error)
+            .replace("{failures}", &failures.to_string())
+    }
+}
+
+#[async_trait]
+trait Channel: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn send(&self, event: &Event) -> Result<()>;
+}
+
+/// Sends events to every configured channel, in the background so updates
+/// never wait on (or fail because of) a notification.
+pub struct Notifier {
+    channels: Vec<Arc<dyn Channel>>,
+    /// Channel names per provider with a `notify` list; others use every channel
+    selected: HashMap<String, Vec<String>>,
+    failure_threshold: u32,
+    /// Current run of failures per provider
+    failures: Mutex<HashMap<String, FailureRun>>,
+}
+
+#[derive(Default)]
+struct FailureRun {
+    count: u32,
+    /// Whether a `Failing` alert went out for this run
+    alerted: bool,
+}
+
+impl Notifier {
+    pub fn new(config: &Config, client: Client) -> Result<Self> {
+        let notifications = &config.notifications;
+        let mut channels: Vec<Arc<dyn Channel>> = Vec::new();
+        if let Some(ref telegram) = notifications.telegram {
+            channels.push(Arc::new(telegram::Telegram::new(telegram.clone(), client.clone())));
+        }
+        if let Some(ref discord) = notifications.discord {
+            channels.push(Arc::new(webhook::Webhook::discord(discord.clone(), client.clone())));
+        }
+        if let Some(ref slack) = notifications.slack {
+            channels.push(Arc::new(webhook::Webhook::slack(slack.clone(), client.clone())));
+        }
+        if let Some(ref ntfy) = notifications.ntfy {
+            channels.push(Arc::new(ntfy::Ntfy::new(ntfy.clone(), client.clone())));
+        }
+        if let Some(ref gotify) = notifications.gotify {
+            channels.push(Arc::new(gotify::Gotify::new(gotify.clone(), client)));
+        }
+        let timeout = Duration::from_secs(config.server.timeout_secs);
+        if let Some(ref email) = notifications.email {
+            channels.push(Arc::new(email::Email::new(email.clone(), timeout)?));
+        }
+        if let Some(ref mqtt) = notifications.mqtt {
+            channels.push(Arc::new(mqtt::Mqtt::new(mqtt.clone(), timeout)?));
+        }
+
+        let selected = config
+            .providers
+            .iter()
+            .filter_map(|p| p.notify.clone().map(|notify| (p.name.clone(), notify)))
+            .collect();
+
+        Ok(Self {
+            channels,
+            selected,
+            failure_threshold: notifications.failure_threshold,
+            failures: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn record_success(&self, provider: &str, host: &str, old_ip: Option<IpAddr>, new_ip: IpAddr, change: &ChangeKind) {
+        let run = self.failures.lock().unwrap().remove(provider);
+        if let Some(run) = run.filter(|run| run.alerted) {
+            self.dispatch(provider, Event::Recovered {
+                provider: provider.to_string(),
+                host: host.to_string(),
+                ip: new_ip,
+                failures: run.count,
+            });
+        }
+
+        if change.is_change() {
+            self.dispatch(provider, Event::Changed {
+                provider: provider.to_string(),
+                host: host.to_string(),
+                change: change.as_str(),
+                old_ip,
+                new_ip,
+            });
+        }
+    }
+
+    /// Reports a record that was changed outside this service and has been restored.
+    pub fn record_drift(&self, provider: &str, host: &str, found: Option<IpAddr>, ip: IpAddr) {
+        self.dispatch(provider, Event::Drifted {
+            provider: provider.to_string(),
+            host: host.to_string(),
+            found,
+            ip,
+        });
+    }
+
+    /// Alerts once when a provider reaches `failure_threshold` consecutive
+    /// failures, however many hosts fail during the outage.
+    pub fn record_failure(&self, provider: &str, host: &str, ip: IpAddr, error: &str) {
+        let failures = {
+            let mut runs = self.failures.lock().unwrap();
+            let run = runs.entry(provider.to_string()).or_default();
+            run.count += 1;
+            if self.failure_threshold == 0 || run.alerted || run.count < self.failure_threshold {
+                return;
+            }
+            run.alerted = true;
+            run.count
+        };
+
+        self.dispatch(provider, Event::Failing {
+            provider: provider.to_string(),
+            host: host.to_string(),
+            ip,
+            error: error.to_string(),
+            failures,
+        });
+    }
+
+    /// Reports credentials of `provider` that stopped working or expire soon.
+    pub fn record_credentials(&self, provider: &str, problem: &str) {
+        self.dispatch(provider, Event::Credentials {
+            provider: provider.to_string(),
+            problem: problem.to_string(),
+        });
+    }
+
+    fn dispatch(&self, provider: &str, event: Event) {
+        let selected = self.selected.get(provider);
+        for channel in &self.channels {
+            if selected.is_some_and(|names| !names.iter().any(|name| name == channel.name())) {
+                continue;
+            }
+            let channel = channel.clone();
+            let event = event.clone();
+            // Keep the request ID of the update on notification logs
+            tokio::spawn(
+                async move {
+                    if let Err(e) = channel.send(&event).await {
+                        warn!("Failed to send {} notification: {:#}", channel.name(), e);
+                    }
+                }
+                .in_current_span(),
+            );
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use rumqttc::{AsyncClient, MqttOptions, Outgoing, Packet, QoS, TlsConfiguration, Transport};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::debug;
+
+use super::{Channel, Event};
+use crate::config::MqttConfig;
+
+/// Publishes IP changes as retained JSON messages to an MQTT broker
+pub struct Mqtt {
+    config: MqttConfig,
+    qos: QoS,
+    tls: Option<TlsConfiguration>,
+    timeout: Duration,
+    /// One connection at a time, so the client ID is never in use twice
+    connection: tokio::sync::Mutex<()>,
+}
+
+impl Mqtt {
+    pub fn new(config: MqttConfig, timeout: Duration) -> Result<Self> {
+        let qos = match config.qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            _ => QoS::ExactlyOnce,
+        };
+
+        let tls = match (config.tls, &config.ca_file) {
+            (false, _) => None,
+            (true, Some(file)) => {
+                let ca = std::fs::read(file).with_context(|| format!("Failed to read MQTT ca_file {}", file))?;
+                Some(TlsConfiguration::Simple {
+                    ca,
+                    alpn: None,
+                    client_auth: None,
+                })
+            }
+            (true, None) => Some(TlsConfiguration::default()),
+        };
+
+        Ok(Self {
+            config,
+            qos,
+            tls,
+            timeout,
+            connection: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Connects, publishes one message and disconnects once the broker has
+    /// acknowledged it. IP changes are rare, so no connection is kept open.
+    async fn publish(&self, topic: String, payload: Vec<u8>) -> Result<()> {
+        let _connection = self.connection.lock().await;
+
+        let port = self.config.port.unwrap_or(if self.config.tls { 8883 } else { 1883 });
+        let mut options = MqttOptions::new(&self.config.client_id, &self.config.host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some(ref username) = self.config.username {
+            options.set_credentials(username, self.config.password.as_deref().unwrap_or_default());
+        }
+        if let Some(ref tls) = self.tls {
+            options.set_transport(Transport::tls_with_config(tls.clone()));
+        }
+
+        let (client, mut events) = AsyncClient::new(options, 10);
+        client.publish(topic, self.qos, self.config.retain, payload).await?;
+
+        let qos = self.qos;
+        tokio::time::timeout(self.timeout, async {
+            loop {
+                let delivered = match events.poll().await? {
+                    rumqttc::Event::Outgoing(Outgoing::Publish(_)) => qos == QoS::AtMostOnce,
+                    rumqttc::Event::Incoming(Packet::PubAck(_)) => qos == QoS::AtLeastOnce,
+                    rumqttc::Event::Incoming(Packet::PubComp(_)) => true,
+                    _ => false,
+                };
+                if delivered {
+                    break;
+                }
+            }
+
+            client.disconnect().await?;
+            while !matches!(events.poll().await?, rumqttc::Event::Outgoing(Outgoing::Disconnect)) {}
+            anyhow::Ok(())
+        })
+        .await
+        .context("Timed out talking to the MQTT broker")?
+        .with_context(|| format!("Failed to publish to MQTT broker {}:{}", self.config.host, port))
+    }
+}
+
+#[async_trait]
+impl Channel for Mqtt {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    async fn send(&self, event: &Event) -> Result<()> {
+        // Automations react to the current IP; failures go to the other channels
+        let Event::Changed {
+            provider,
+            host,
+            change,
+            old_ip,
+            new_ip,
+        } = event
+        else {
+            return Ok(());
+        };
+
+        let topic = self.config.topic.replace("{provider}", provider).replace("{host}", host);
+        let payload = serde_json::to_vec(&serde_json::json!({
+            "provider": provider,
+            "host": host,
+            "change": change,
+            "old_ip": old_ip,
+            "new_ip": new_ip,
+            "time": OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+        }))?;
+
+        self.publish(topic.clone(), payload).await?;
+        debug!("Published IP change of {} to MQTT topic {}", host, topic);
+        Ok(())
+    }
+}
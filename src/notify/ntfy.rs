@@ -0,0 +1,52 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use tracing::debug;
+
+use super::{Channel, Event};
+use crate::config::NtfyConfig;
+
+/// Publishes messages to an ntfy topic
+pub struct Ntfy {
+    config: NtfyConfig,
+    client: Client,
+}
+
+impl Ntfy {
+    pub fn new(config: NtfyConfig, client: Client) -> Self {
+        Self { config, client }
+    }
+}
+
+#[async_trait]
+impl Channel for Ntfy {
+    fn name(&self) -> &'static str {
+        "ntfy"
+    }
+
+    async fn send(&self, event: &Event) -> Result<()> {
+        // JSON publishing keeps non-ASCII titles out of HTTP headers
+        let mut message = serde_json::json!({
+            "topic": self.config.topic,
+            "title": event.render(&self.config.title),
+            "message": event.message(&self.config.templates),
+        });
+        if let Some(priority) = self.config.priority {
+            message["priority"] = priority.into();
+        }
+        let mut request = self.client.post(self.config.url.trim_end_matches('/')).json(&message);
+        if let Some(ref token) = self.config.token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await.context("Failed to publish ntfy message")?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("ntfy returned {}: {}", status, body.trim());
+        }
+
+        debug!("Published ntfy notification to {}", self.config.topic);
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use tracing::debug;
+
+use super::{Channel, Event};
+use crate::config::TelegramConfig;
+
+const TELEGRAM_API_BASE: &str = "https://api.telegram.org";
+
+/// Sends messages to a chat through a Telegram bot
+pub struct Telegram {
+    config: TelegramConfig,
+    client: Client,
+    api_base: String,
+}
+
+#[derive(Deserialize)]
+struct TelegramResponse {
+    ok: bool,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+impl Telegram {
+    pub fn new(config: TelegramConfig, client: Client) -> Self {
+        let api_base = config
+            .api_base
+            .as_deref()
+            .unwrap_or(TELEGRAM_API_BASE)
+            .trim_end_matches('/')
+            .to_string();
+        Self {
+            config,
+            client,
+            api_base,
+        }
+    }
+}
+
+#[async_trait]
+impl Channel for Telegram {
+    fn name(&self) -> &'static str {
+        "telegram"
+    }
+
+    async fn send(&self, event: &Event) -> Result<()> {
+        let text = event.message(&self.config.templates);
+
+        let url = format!("{}/bot{}/sendMessage", self.api_base, self.config.bot_token);
+        let response: TelegramResponse = self
+            .client
+            .post(&url)
+            .json(&serde_json::json!({ "chat_id": self.config.chat_id, "text": text }))
+            .send()
+            .await
+            // The URL contains the bot token, keep it out of logs
+            .map_err(|e| e.without_url())
+            .context("Failed to send Telegram message")?
+            .json()
+            .await
+            .map_err(|e| e.without_url())
+            .context("Failed to parse Telegram response")?;
+
+        if !response.ok {
+            anyhow::bail!(
+                "Telegram API error: {}",
+                response.description.unwrap_or_else(|| "unknown error".to_string())
+            );
+        }
+
+        debug!("Sent Telegram notification to chat {}", self.config.chat_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use tracing::debug;
+
+use super::{Channel, Event};
+use crate::config::WebhookConfig;
+
+/// Posts messages to a Discord or Slack incoming webhook
+pub struct Webhook {
+    name: &'static str,
+    /// JSON field holding the message text
+    text_field: &'static str,
+    config: WebhookConfig,
+    client: Client,
+}
+
+impl Webhook {
+    pub fn discord(config: WebhookConfig, client: Client) -> Self {
+        Self {
+            name: "discord",
+            text_field: "content",
+            config,
+            client,
+        }
+    }
+
+    pub fn slack(config: WebhookConfig, client: Client) -> Self {
+        Self {
+            name: "slack",
+            text_field: "text",
+            config,
+            client,
+        }
+    }
+}
+
+#[async_trait]
+impl Channel for Webhook {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn send(&self, event: &Event) -> Result<()> {
+        let text = event.message(&self.config.templates);
+
+        let response = self
+            .client
+            .post(&self.config.webhook_url)
+            .json(&serde_json::json!({ self.text_field: text }))
+            .send()
+            .await
+            // Webhook URLs are secrets, keep them out of logs
+            .map_err(|e| e.without_url())
+            .with_context(|| format!("Failed to send {} message", self.name))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("{} webhook returned {}: {}", self.name, status, body.trim());
+        }
+
+        debug!("Sent {} notification", self.name);
+        Ok(())
+    }
+}
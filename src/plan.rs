@@ -0,0 +1,212 @@
+//! `ddns-rust plan` / `ddns-rust apply` and `POST /admin/plan`: compares the
+//! addresses each host should have with its provider's A/AAAA records and
+//! lists (or makes) the changes between them, for taking over a zone that was
+//! edited by hand.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::config::{HostRecordType, ProviderConfig};
+use crate::provider::{DnsUpdateResult, Provider, RecordInfo, RecordOptions, RecordType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Update,
+    Delete,
+}
+
+/// One record change
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    pub provider: String,
+    pub host: String,
+    #[serde(rename = "type")]
+    pub record_type: RecordType,
+    pub action: Action,
+    /// Current content, for updates and deletes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<String>,
+    /// Desired content, for creates and updates
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<String>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let old = self.old.as_deref().unwrap_or_default();
+        let new = self.new.as_deref().unwrap_or_default();
+        match self.action {
+            Action::Create => write!(f, "+ {} {} {}", self.record_type, self.host, new),
+            Action::Update => write!(f, "~ {} {} {} -> {}", self.record_type, self.host, old, new),
+            Action::Delete => write!(f, "- {} {} {}", self.record_type, self.host, old),
+        }
+    }
+}
+
+/// The addresses one host should have in one family
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub provider: String,
+    pub host: String,
+    pub record_type: RecordType,
+    pub ips: Vec<IpAddr>,
+}
+
+/// A provider whose records could not be listed
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanError {
+    pub provider: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Plan {
+    pub changes: Vec<Change>,
+    pub errors: Vec<PlanError>,
+    /// Hosts with at least one change, in the order they are applied
+    #[serde(skip)]
+    pub targets: Vec<Target>,
+}
+
+/// Diffs the desired addresses of `provider_configs`' hosts against their
+/// records. A host's `ips` are desired if configured, otherwise the address
+/// last pushed for it in `known` (provider, host, IP).
+pub async fn plan(
+    provider_configs: &[&ProviderConfig],
+    providers: &HashMap<String, Arc<dyn Provider>>,
+    known: &[(String, String, IpAddr)],
+) -> Plan {
+    let mut plan = Plan::default();
+
+    for provider_config in provider_configs {
+        let targets = targets(provider_config, known);
+        if targets.is_empty() {
+            continue;
+        }
+        let Some(provider) = providers.get(&provider_config.name) else {
+            plan.errors.push(PlanError {
+                provider: provider_config.name.clone(),
+                error: format!("Unsupported provider type: {}", provider_config.provider_type),
+            });
+            continue;
+        };
+        let records = match provider.list_records().await {
+            Ok(records) => records,
+            Err(e) => {
+                plan.errors.push(PlanError {
+                    provider: provider_config.name.clone(),
+                    error: format!("{:#}", e),
+                });
+                continue;
+            }
+        };
+
+        for target in targets {
+            let changes = diff(&target, &records);
+            if !changes.is_empty() {
+                plan.changes.extend(changes);
+                plan.targets.push(target);
+            }
+        }
+    }
+
+    plan
+}
+
+/// Makes `target`'s records exactly its addresses, deleting stale and
+/// duplicate records of its family.
+pub async fn apply(provider: &dyn Provider, provider_config: &ProviderConfig, target: &Target) -> Result<DnsUpdateResult> {
+    let settings = provider_config.host_settings(&target.host);
+    let options = RecordOptions {
+        ttl: settings.ttl,
+        proxied: settings.proxied,
+    };
+    // Cached record IDs may predate whatever the plan found
+    provider.forget(&target.host);
+
+    if provider.capabilities().record_sets {
+        return provider.update_record_set(&target.host, &target.ips, &options).await;
+    }
+    match target.ips[..] {
+        [ip] => provider.update_record(&target.host, ip, &options).await,
+        _ => anyhow::bail!("Provider {} can't keep several records per host", provider_config.name),
+    }
+}
+
+/// The desired addresses of each host of `provider_config`, per family and
+/// sorted by host.
+fn targets(provider_config: &ProviderConfig, known: &[(String, String, IpAddr)]) -> Vec<Target> {
+    let mut wanted: BTreeMap<(String, RecordType), Vec<IpAddr>> = BTreeMap::new();
+
+    for host in &provider_config.hosts {
+        for ip in host.ips.iter().flatten() {
+            let ips = wanted.entry((host.name.to_ascii_lowercase(), RecordType::for_ip(ip))).or_default();
+            if !ips.contains(ip) {
+                ips.push(*ip);
+            }
+        }
+    }
+    for (_, host, ip) in known.iter().filter(|(p, _, _)| *p == provider_config.name) {
+        let block = provider_config.find_host(host);
+        // Configured addresses replace the pushed ones, and a CNAME host has none
+        if block.is_some_and(|h| h.ips.is_some()) || provider_config.host_settings(host).record_type == HostRecordType::Cname {
+            continue;
+        }
+        wanted.entry((host.to_ascii_lowercase(), RecordType::for_ip(ip))).or_insert_with(|| vec![*ip]);
+    }
+
+    wanted
+        .into_iter()
+        .map(|((host, record_type), ips)| Target {
+            provider: provider_config.name.clone(),
+            host,
+            record_type,
+            ips,
+        })
+        .collect()
+}
+
+/// The changes that turn `target`'s records among `records` into its addresses:
+/// a stale record is updated to a missing address, left over ones and
+/// duplicates are deleted and addresses still missing are created.
+fn diff(target: &Target, records: &[RecordInfo]) -> Vec<Change> {
+    let mut missing = target.ips.clone();
+    let mut stale = Vec::new();
+    for record in records
+        .iter()
+        .filter(|r| r.record_type == target.record_type.as_str() && r.name.eq_ignore_ascii_case(&target.host))
+    {
+        match record.content.parse::<IpAddr>().ok().and_then(|ip| missing.iter().position(|w| *w == ip)) {
+            Some(index) => {
+                missing.remove(index);
+            }
+            None => stale.push(record.content.clone()),
+        }
+    }
+
+    let change = |action, old: Option<String>, new: Option<IpAddr>| Change {
+        provider: target.provider.clone(),
+        host: target.host.clone(),
+        record_type: target.record_type,
+        action,
+        old,
+        new: new.map(|ip| ip.to_string()),
+    };
+    let mut stale = stale.into_iter();
+    let mut changes = Vec::new();
+    for ip in missing {
+        changes.push(match stale.next() {
+            Some(old) => change(Action::Update, Some(old), Some(ip)),
+            None => change(Action::Create, None, Some(ip)),
+        });
+    }
+    changes.extend(stale.map(|old| change(Action::Delete, Some(old), None)));
+    changes
+}
@@ -0,0 +1,155 @@
+//! `[[poll]]` jobs: detect this machine's public IP on a schedule and update
+//! hosts with it, for running ddns-rust on the network whose IP changes. With
+//! `ipv6_prefix_len` the detected IPv6 address only supplies the delegated
+//! prefix, and each host keeps its own `ipv6_suffix`.
+
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use time::OffsetDateTime;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::api::{apply_updates, AppState};
+use crate::config::{HostRecordType, PollJobConfig};
+use crate::detect::Detector;
+use crate::hostname;
+use crate::provider;
+use crate::schedule::Schedule;
+
+/// Starts one task per configured job.
+pub fn spawn(state: Arc<AppState>) -> Result<()> {
+    if state.config.poll.is_empty() {
+        return Ok(());
+    }
+
+    let client = provider::build_client(&state.config.server, None)?;
+    let detector = Arc::new(Detector::new(state.config.detect.clone(), client.clone()));
+
+    for (index, job) in state.config.poll.iter().enumerate() {
+        let (schedule, when) = match (job.interval_secs, &job.cron) {
+            (Some(secs), _) => (Schedule::Every(Duration::from_secs(secs)), format!("every {}s", secs)),
+            (None, Some(cron)) => (Schedule::Cron(cron.parse().map_err(anyhow::Error::msg)?), format!("at \"{}\"", cron)),
+            (None, None) => anyhow::bail!("poll[{}]: one of interval_secs or cron is required", index),
+        };
+        info!("Polling the public IP for {} via {} {}", job.hosts.join(", "), job.provider, when);
+
+        let state = state.clone();
+        let detector = detector.clone();
+        let client = client.clone();
+        tokio::spawn(async move {
+            let job = &state.config.poll[index];
+            let mut first = true;
+            while let Some(delay) = schedule.delay(OffsetDateTime::now_utc(), first) {
+                first = false;
+                // Spread out jobs of many machines that share a schedule
+                let jitter = Duration::from_millis(fastrand::u64(0..=job.jitter_secs * 1000));
+                tokio::time::sleep(delay + jitter).await;
+                if !state.leader.is_leader().await {
+                    continue;
+                }
+
+                async {
+                    let ok = run(&state, &detector, job).await;
+                    let url = match ok {
+                        true => &job.ping_url,
+                        false => &job.ping_failure_url,
+                    };
+                    if let Some(url) = url {
+                        ping(&state, &client, url).await;
+                    }
+                }
+                .instrument(info_span!("poll", job = index, provider = %job.provider))
+                .await;
+            }
+            warn!("poll[{}]: schedule has no further runs", index);
+        });
+    }
+
+    Ok(())
+}
+
+/// Detects the addresses the job asks for and pushes each to every host.
+/// Returns whether every detection and update succeeded.
+async fn run(state: &AppState, detector: &Detector, job: &PollJobConfig) -> bool {
+    let Some(provider_config) = state.config.get_provider(&job.provider) else {
+        return false;
+    };
+
+    let mut ok = true;
+    let families = match job.record_type {
+        HostRecordType::A => &[false][..],
+        HostRecordType::Aaaa => &[true][..],
+        HostRecordType::Both | HostRecordType::Cname => &[false, true][..],
+    };
+    for &ipv6 in families {
+        let ip = match detector.detect(ipv6).await {
+            Ok(ip) => ip,
+            Err(e) => {
+                warn!("Failed to detect the public IPv{} address: {:#}", if ipv6 { 6 } else { 4 }, e);
+                ok = false;
+                continue;
+            }
+        };
+
+        let prefix = match (ip, job.ipv6_prefix_len) {
+            (IpAddr::V6(ip), Some(len)) => {
+                debug!("Delegated prefix is {}/{}", prefix_of(ip, len), len);
+                Some((ip, len))
+            }
+            _ => None,
+        };
+
+        let mut updates = Vec::new();
+        for host in &job.hosts {
+            let Ok(host) = hostname::normalize(host) else {
+                continue;
+            };
+            // Each host's suffix goes after the current delegated prefix
+            let ip = match prefix {
+                Some((detected, len)) => match provider_config.find_host(&host).and_then(|h| h.ipv6_suffix) {
+                    Some(suffix) => IpAddr::V6(with_prefix(detected, len, suffix)),
+                    None => continue,
+                },
+                None => ip,
+            };
+            updates.push((host, ip));
+        }
+        // Sent together, so a changed prefix takes one call on providers with a batch API
+        let answers = apply_updates(state, provider_config, &updates).await;
+        for ((host, ip), answer) in updates.iter().zip(answers) {
+            if let Err(response) = answer {
+                warn!("Scheduled update of {} to {} failed with status {}", host, ip, response.status());
+                ok = false;
+            }
+        }
+    }
+
+    ok
+}
+
+/// Reports a run to a dead man's switch such as healthchecks.io, which
+/// alerts when the pings stop. Failing to reach it is only logged.
+async fn ping(state: &AppState, client: &reqwest::Client, url: &str) {
+    let result = client.get(url).send().await.and_then(|response| response.error_for_status());
+    match result {
+        Ok(_) => debug!("Pinged the job's health check"),
+        Err(e) => warn!("Failed to ping the job's health check: {}", state.redactor.redact(&e.to_string())),
+    }
+}
+
+/// The first `len` bits of `ip`.
+fn prefix_of(ip: Ipv6Addr, len: u8) -> Ipv6Addr {
+    Ipv6Addr::from(u128::from(ip) & prefix_mask(len))
+}
+
+/// The first `len` bits of `prefix` followed by the remaining bits of `suffix`.
+fn with_prefix(prefix: Ipv6Addr, len: u8, suffix: Ipv6Addr) -> Ipv6Addr {
+    let mask = prefix_mask(len);
+    Ipv6Addr::from((u128::from(prefix) & mask) | (u128::from(suffix) & !mask))
+}
+
+fn prefix_mask(len: u8) -> u128 {
+    u128::MAX.checked_shl(128 - u32::from(len)).unwrap_or(0)
+}
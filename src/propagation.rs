@@ -0,0 +1,220 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use hickory_resolver::proto::op::{Message, Query};
+use hickory_resolver::proto::rr::{Name, RData, RecordType};
+use hickory_resolver::proto::serialize::binary::BinEncodable;
+use hickory_resolver::TokioAsyncResolver;
+use tracing::{debug, info, warn};
+
+use crate::config::{PropagationConfig, DOH_RESOLVERS};
+
+/// Media type of DNS messages sent over HTTPS (RFC 8484)
+const DNS_MESSAGE: &str = "application/dns-message";
+
+/// Checks that an updated record is actually served, by querying the zone's
+/// authoritative nameservers (or the configured resolvers) directly.
+pub struct PropagationChecker {
+    config: PropagationConfig,
+    /// System resolver, used to find authoritative nameservers
+    system: TokioAsyncResolver,
+    /// Client for DNS-over-HTTPS resolvers
+    client: reqwest::Client,
+}
+
+/// Where a record is checked
+enum Resolver {
+    Dns(Box<TokioAsyncResolver>),
+    /// A DNS-over-HTTPS endpoint, for networks that intercept port 53
+    Https(String),
+}
+
+impl PropagationChecker {
+    pub fn new(config: PropagationConfig, client: reqwest::Client) -> Result<Self> {
+        let system = TokioAsyncResolver::tokio_from_system_conf()
+            .context("Failed to read system DNS configuration")?;
+        Ok(Self { config, system, client })
+    }
+
+    /// Polls until every nameserver returns `ip` for `host`, giving up after
+    /// `window_secs`. Returns whether the record was verified.
+    pub async fn verify(&self, host: &str, ip: IpAddr) -> bool {
+        let resolvers = match self.resolvers_for(host).await {
+            Ok(resolvers) if !resolvers.is_empty() => resolvers,
+            Ok(_) => {
+                warn!("No nameservers found to verify {}", host);
+                return false;
+            }
+            Err(e) => {
+                warn!("Failed to find nameservers to verify {}: {:#}", host, e);
+                return false;
+            }
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(self.config.window_secs);
+        let interval = Duration::from_secs(self.config.interval_secs.max(1));
+
+        loop {
+            let mut pending = 0;
+            for (server, resolver) in &resolvers {
+                if !self.serves(resolver, host, ip).await {
+                    debug!("{} does not serve {} for {} yet", server, ip, host);
+                    pending += 1;
+                }
+            }
+
+            if pending == 0 {
+                info!("Verified {} -> {} on {} nameserver(s)", host, ip, resolvers.len());
+                return true;
+            }
+
+            if Instant::now() + interval > deadline {
+                warn!(
+                    "{} of {} nameserver(s) still not serving {} for {} after {}s",
+                    pending,
+                    resolvers.len(),
+                    ip,
+                    host,
+                    self.config.window_secs
+                );
+                return false;
+            }
+
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// One uncached resolver per nameserver to check, labelled for logging.
+    async fn resolvers_for(&self, host: &str) -> Result<Vec<(String, Resolver)>> {
+        if !self.config.resolvers.is_empty() {
+            return self.config.resolvers.iter().map(|server| Ok((server.clone(), configured_resolver(server)?))).collect();
+        }
+
+        let mut resolvers = Vec::new();
+        for nameserver in self.authoritative_nameservers(host).await? {
+            // The first address of each nameserver is enough to see what it serves
+            match self.system.lookup_ip(nameserver.as_str()).await {
+                Ok(addrs) => {
+                    if let Some(addr) = addrs.iter().next() {
+                        resolvers.push((nameserver, Resolver::Dns(Box::new(direct_resolver(SocketAddr::new(addr, 53))))));
+                    }
+                }
+                Err(e) => warn!("Failed to resolve nameserver {}: {}", nameserver, e),
+            }
+        }
+
+        Ok(resolvers)
+    }
+
+    /// NS records of the closest enclosing zone of `host`.
+    async fn authoritative_nameservers(&self, host: &str) -> Result<Vec<String>> {
+        let labels: Vec<&str> = host.trim_end_matches('.').split('.').collect();
+
+        // Walk up from the host itself, stopping before the TLD
+        for i in 0..labels.len().saturating_sub(1) {
+            let zone = format!("{}.", labels[i..].join("."));
+            if let Ok(lookup) = self.system.ns_lookup(zone.as_str()).await {
+                let nameservers: Vec<String> = lookup.iter().map(|ns| ns.to_utf8()).collect();
+                if !nameservers.is_empty() {
+                    debug!("Authoritative nameservers for {}: {:?}", zone, nameservers);
+                    return Ok(nameservers);
+                }
+            }
+        }
+
+        anyhow::bail!("no NS records found for any parent zone of {}", host)
+    }
+
+    /// Whether `resolver` currently answers `ip` among the addresses of `host`.
+    async fn serves(&self, resolver: &Resolver, host: &str, ip: IpAddr) -> bool {
+        let name = format!("{}.", host.trim_end_matches('.'));
+        let resolver = match resolver {
+            Resolver::Dns(resolver) => resolver,
+            Resolver::Https(url) => match self.https_lookup(url, &name, ip).await {
+                Ok(addrs) => return addrs.contains(&ip),
+                Err(e) => {
+                    debug!("DNS-over-HTTPS query to {} failed: {:#}", url, e);
+                    return false;
+                }
+            },
+        };
+        match ip {
+            IpAddr::V4(v4) => match resolver.ipv4_lookup(name.as_str()).await {
+                Ok(lookup) => lookup.iter().any(|a| a.0 == v4),
+                Err(_) => false,
+            },
+            IpAddr::V6(v6) => match resolver.ipv6_lookup(name.as_str()).await {
+                Ok(lookup) => lookup.iter().any(|a| a.0 == v6),
+                Err(_) => false,
+            },
+        }
+    }
+
+    /// The addresses of `name` of `ip`'s family, as answered by the
+    /// DNS-over-HTTPS endpoint `url`.
+    async fn https_lookup(&self, url: &str, name: &str, ip: IpAddr) -> Result<Vec<IpAddr>> {
+        let record_type = match ip {
+            IpAddr::V4(_) => RecordType::A,
+            IpAddr::V6(_) => RecordType::AAAA,
+        };
+        let mut query = Message::new();
+        query.set_recursion_desired(true);
+        query.add_query(Query::query(Name::from_ascii(name)?, record_type));
+
+        // POST, so HTTP caches on the way don't answer for the resolver
+        let response = self
+            .client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, DNS_MESSAGE)
+            .header(reqwest::header::ACCEPT, DNS_MESSAGE)
+            .body(query.to_bytes()?)
+            .send()
+            .await?
+            .error_for_status()?;
+        let response = Message::from_vec(&response.bytes().await?).context("invalid DNS message in response")?;
+
+        Ok(response
+            .answers()
+            .iter()
+            .filter_map(|record| match record.data()? {
+                RData::A(a) => Some(IpAddr::V4(a.0)),
+                RData::AAAA(aaaa) => Some(IpAddr::V6(aaaa.0)),
+                _ => None,
+            })
+            .collect())
+    }
+}
+
+/// A resolver from `propagation.resolvers`: a DNS server address, a
+/// DNS-over-HTTPS URL or the name of a well-known DNS-over-HTTPS resolver.
+fn configured_resolver(server: &str) -> Result<Resolver> {
+    if let Some((_, url)) = DOH_RESOLVERS.iter().find(|(name, _)| *name == server) {
+        return Ok(Resolver::Https(url.to_string()));
+    }
+    if server.starts_with("https://") || server.starts_with("http://") {
+        return Ok(Resolver::Https(server.to_string()));
+    }
+    Ok(Resolver::Dns(Box::new(direct_resolver(parse_server(server)?))))
+}
+
+fn direct_resolver(server: SocketAddr) -> TokioAsyncResolver {
+    let servers = NameServerConfigGroup::from_ips_clear(&[server.ip()], server.port(), true);
+    let mut options = ResolverOpts::default();
+    // Every poll must reach the nameserver, not a cached answer
+    options.cache_size = 0;
+    options.attempts = 1;
+    TokioAsyncResolver::tokio(ResolverConfig::from_parts(None, vec![], servers), options)
+}
+
+/// Parses `1.1.1.1`, `1.1.1.1:53`, `2606:4700::1111` or `[2606:4700::1111]:53`.
+pub fn parse_server(server: &str) -> Result<SocketAddr> {
+    if let Ok(addr) = server.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    server
+        .parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, 53))
+        .with_context(|| format!("invalid resolver address: {}", server))
+}
@@ -1,147 +1,1067 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::Mutex;
+
 use anyhow::{Context, Result};
-use log::info;
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
 use reqwest::Client;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::{debug, info, instrument, warn};
 
-use crate::config::ProviderConfig;
-use super::DnsUpdateResult;
+use crate::config::{CloudflareAuthMode, ConflictPolicy, MultipleRecordsPolicy, ProviderConfig};
+use super::{
+    classify, missing_record, retry_after, reverse_name, shared_error, AddressUpdate, Capabilities, ChangeKind, DnsUpdateResult, MxData, Provider,
+    ProviderError, RecordInfo, RecordOptions, RecordType, SrvData,
+};
 
+/// Default API base URL, overridable with `api_base`
 const CLOUDFLARE_API_BASE: &str = "https://api.cloudflare.com/client/v4";
 
-pub async fn update_record(config: &ProviderConfig, host: &str, ip: &str) -> Result<DnsUpdateResult> {
-    let client = Client::new();
+/// Record types managed by DDNS updates
+const MANAGED_RECORD_TYPES: [&str; 5] = ["A", "AAAA", "CNAME", "SRV", "MX"];
+
+/// Page size used for all list calls
+const PER_PAGE: u32 = 100;
+
+/// API error codes for missing, malformed or invalid API tokens
+const AUTH_ERROR_CODES: [i32; 3] = [6003, 9109, 10000];
+
+pub struct Cloudflare {
+    config: ProviderConfig,
+    client: Client,
+    api_base: String,
+    /// Authentication headers sent with every request, per `auth_mode`
+    auth: HeaderMap,
+    /// Zones visible to the API token, fetched lazily when `zone_id` is not configured
+    zones: Mutex<Option<Vec<Zone>>>,
+    /// The record last written or seen per (type, host), so updates can skip the list call
+    records: Mutex<HashMap<(RecordType, String), Cached>>,
+}
+
+impl Cloudflare {
+    pub fn new(config: ProviderConfig, client: Client) -> Self {
+        let api_base = config
+            .api_base
+            .as_deref()
+            .unwrap_or(CLOUDFLARE_API_BASE)
+            .trim_end_matches('/')
+            .to_string();
+
+        let auth = auth_headers(&config);
+
+        Self {
+            config,
+            client,
+            api_base,
+            auth,
+            zones: Mutex::new(None),
+            records: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Resolves the zone ID that should contain `host`.
+    ///
+    /// An explicit `zone_id` always wins; otherwise the zone is looked up by
+    /// `zone_name`, or by the longest zone name that is a suffix of `host`.
+    /// Reverse names use `reverse_zone_id` instead, or the suffix lookup.
+    async fn zone_id_for(&self, host: &str) -> Result<String> {
+        let reverse = host.to_ascii_lowercase().ends_with(".arpa");
+        let zone_id = match reverse {
+            true => &self.config.reverse_zone_id,
+            false => &self.config.zone_id,
+        };
+        if let Some(zone_id) = zone_id {
+            return Ok(zone_id.clone());
+        }
+
+        let wanted = self.config.zone_name.as_deref().filter(|_| !reverse);
+        if let Some(zone) = self.find_cached_zone(host, wanted) {
+            return Ok(zone.id);
+        }
+
+        // Cache miss: the zone may have been added since the last fetch
+        let zones = self.fetch_zones().await?;
+        *self.zones.lock().unwrap() = Some(zones);
+
+        self.find_cached_zone(host, wanted)
+            .map(|zone| zone.id)
+            .ok_or_else(|| match wanted {
+                Some(name) => anyhow::anyhow!("Zone not found in Cloudflare account: {}", name),
+                None => ProviderError::InvalidHost(format!("No Cloudflare zone found for host: {}", host)).into(),
+            })
+    }
+
+    fn find_cached_zone(&self, host: &str, wanted: Option<&str>) -> Option<Zone> {
+        let zones = self.zones.lock().unwrap();
+        let zones = zones.as_ref()?;
+
+        match wanted {
+            Some(name) => zones.iter().find(|z| z.name.eq_ignore_ascii_case(name)).cloned(),
+            None => {
+                let host = host.trim_end_matches('.').to_ascii_lowercase();
+                zones
+                    .iter()
+                    .filter(|z| {
+                        let zone = z.name.to_ascii_lowercase();
+                        host == zone || host.ends_with(&format!(".{}", zone))
+                    })
+                    .max_by_key(|z| z.name.len())
+                    .cloned()
+            }
+        }
+    }
+
+    /// The status of the API token. Tokens owned by an account rather than a
+    /// user are verified under that account's `account_id`.
+    async fn verify_token(&self) -> Result<TokenStatus> {
+        let url = match self.config.account_id {
+            Some(ref account_id) => format!("{}/accounts/{}/tokens/verify", self.api_base, account_id),
+            None => format!("{}/user/tokens/verify", self.api_base),
+        };
+
+        let response = self
+            .client
+            .get(&url)
+            .headers(self.auth.clone())
+            .send()
+            .await
+            .context("Failed to send token verify request to Cloudflare")?;
+        let response: CloudflareResponse<TokenStatus> = read_response(response, "token verify").await?;
+
+        response
+            .result
+            .ok_or_else(|| anyhow::anyhow!("No result in Cloudflare response"))
+    }
+
+    /// Checks a Global API Key by fetching the user it belongs to, returning
+    /// the user's email.
+    async fn verify_global_key(&self) -> Result<String> {
+        let url = format!("{}/user", self.api_base);
+
+        let response = self
+            .client
+            .get(&url)
+            .headers(self.auth.clone())
+            .send()
+            .await
+            .context("Failed to send user request to Cloudflare")?;
+        let response: CloudflareResponse<User> = read_response(response, "user").await?;
+
+        response
+            .result
+            .map(|user| user.email)
+            .ok_or_else(|| anyhow::anyhow!("No result in Cloudflare response"))
+    }
+
+    async fn fetch_zones(&self) -> Result<Vec<Zone>> {
+        let url = format!("{}/zones", self.api_base);
+        let zones: Vec<Zone> = self.fetch_all(&url, &[]).await?;
+
+        info!("Discovered {} Cloudflare zone(s) for provider {}", zones.len(), self.config.name);
+        Ok(zones)
+    }
+
+    /// Fetches every page of a Cloudflare list endpoint.
+    #[instrument(name = "cloudflare.list", skip_all, fields(url = %url))]
+    async fn fetch_all<T: DeserializeOwned>(&self, url: &str, query: &[(&str, &str)]) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let response = self
+                .client
+                .get(url)
+                .headers(self.auth.clone())
+                .header("Content-Type", "application/json")
+                .query(query)
+                .query(&[("page", page), ("per_page", PER_PAGE)])
+                .send()
+                .await
+                .context("Failed to send list request to Cloudflare")?;
+            let response: CloudflareResponse<Vec<T>> = read_response(response, "list").await?;
+
+            items.extend(response.result.unwrap_or_default());
+
+            match response.result_info {
+                Some(info) if info.page < info.total_pages => page += 1,
+                _ => break,
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// The host's records of `record_type`; with `managed_only`, just those
+    /// carrying the host's comment and tags.
+    async fn get_records(&self, zone_id: &str, record_type: RecordType, host: &str) -> Result<Vec<DnsRecord>> {
+        let url = format!("{}/zones/{}/dns_records", self.api_base, zone_id);
+        let mut records: Vec<DnsRecord> = self.fetch_all(&url, &[("type", record_type.as_str()), ("name", host)]).await?;
+        self.retain_managed(host, &mut records);
+        Ok(records)
+    }
+
+    /// With `managed_only`, drops the records of `host` lacking its comment and tags.
+    fn retain_managed(&self, host: &str, records: &mut Vec<DnsRecord>) {
+        if !self.config.managed_only {
+            return;
+        }
+        let settings = self.config.host_settings(host);
+        records.retain(|record| {
+            let managed = record.carries_label(settings.comment, settings.tags);
+            if !managed {
+                debug!("Leaving unmanaged record {} ({}) with {} alone", host, record.id, record.content);
+            }
+            managed
+        });
+    }
+
+    /// The record for `host`, labeled with its comment and tags.
+    fn desired(&self, record_type: RecordType, host: &str, content: String, options: &RecordOptions) -> DesiredRecord {
+        let settings = self.config.host_settings(host);
+        DesiredRecord {
+            record_type,
+            name: host.to_string(),
+            content,
+            ttl: options.ttl,
+            proxied: options.proxied,
+            comment: settings.comment.map(str::to_string),
+            tags: settings.tags.to_vec(),
+            priority: None,
+            data: None,
+        }
+    }
+
+    #[instrument(name = "cloudflare.create_record", skip_all, fields(zone_id = %zone_id, host = %desired.name))]
+    async fn create_record(&self, zone_id: &str, desired: &DesiredRecord) -> Result<DnsRecord> {
+        let url = format!(
+            "{}/zones/{}/dns_records",
+            self.api_base, zone_id
+        );
+
+        let body = desired.request();
+
+        let response = self
+            .client
+            .post(&url)
+            .headers(self.auth.clone())
+            .header("Content-Type", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .context("Failed to send create request to Cloudflare")?;
+        let response: CloudflareResponse<DnsRecord> = read_response(response, "create").await?;
+
+        response
+            .result
+            .ok_or_else(|| anyhow::anyhow!("No result in Cloudflare response"))
+    }
+
+    #[instrument(name = "cloudflare.update_record", skip_all, fields(zone_id = %zone_id, record_id = %record_id))]
+    async fn update_existing_record(
+        &self,
+        zone_id: &str,
+        record_id: &str,
+        desired: &DesiredRecord,
+    ) -> Result<DnsRecord> {
+        let url = format!(
+            "{}/zones/{}/dns_records/{}",
+            self.api_base, zone_id, record_id
+        );
+
+        let body = desired.request();
+
+        let response = self
+            .client
+            .put(&url)
+            .headers(self.auth.clone())
+            .header("Content-Type", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .context("Failed to send update request to Cloudflare")?;
+        let response: CloudflareResponse<DnsRecord> = read_response(response, "update").await?;
+
+        response
+            .result
+            .ok_or_else(|| anyhow::anyhow!("No result in Cloudflare response"))
+    }
+
+    #[instrument(name = "cloudflare.delete_record", skip_all, fields(zone_id = %zone_id, record_id = %record_id))]
+    async fn delete_record(&self, zone_id: &str, record_id: &str) -> Result<()> {
+        let url = format!(
+            "{}/zones/{}/dns_records/{}",
+            self.api_base, zone_id, record_id
+        );
+
+        let response = self
+            .client
+            .delete(&url)
+            .headers(self.auth.clone())
+            .send()
+            .await
+            .context("Failed to send delete request to Cloudflare")?;
+        read_response::<DeletedRecord>(response, "delete").await?;
+
+        Ok(())
+    }
+
+    /// Applies `changes` in one call to the batch endpoint, or with the single
+    /// call they need. Cloudflare applies a batch as a whole or not at all.
+    async fn write(&self, zone_id: &str, changes: Changes) -> Result<Written> {
+        if changes.len() > 1 {
+            return self.write_batch(zone_id, changes).await;
+        }
+
+        let mut written = Written::default();
+        for record_id in &changes.deletes {
+            self.delete_record(zone_id, record_id).await?;
+        }
+        for (record_id, desired) in &changes.puts {
+            written.puts.push(self.update_existing_record(zone_id, record_id, desired).await?);
+        }
+        for desired in &changes.posts {
+            written.posts.push(self.create_record(zone_id, desired).await?);
+        }
+        Ok(written)
+    }
+
+    #[instrument(name = "cloudflare.batch", skip_all, fields(zone_id = %zone_id, changes = changes.len()))]
+    async fn write_batch(&self, zone_id: &str, changes: Changes) -> Result<Written> {
+        let url = format!("{}/zones/{}/dns_records/batch", self.api_base, zone_id);
+        let (puts, posts) = (changes.puts.len(), changes.posts.len());
+
+        let body = BatchRequest {
+            deletes: changes.deletes.into_iter().map(|id| BatchDelete { id }).collect(),
+            puts: changes
+                .puts
+                .iter()
+                .map(|(id, desired)| BatchPut {
+                    id: id.clone(),
+                    record: desired.request(),
+                })
+                .collect(),
+            posts: changes.posts.iter().map(DesiredRecord::request).collect(),
+        };
+
+        let response = self
+            .client
+            .post(&url)
+            .headers(self.auth.clone())
+            .header("Content-Type", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .context("Failed to send batch request to Cloudflare")?;
+        let response: CloudflareResponse<Written> = read_response(response, "batch").await?;
 
-    // Check if record exists
-    if let Some(existing) = get_record(&client, config, host).await? {
-        if existing.content == ip {
-            info!("Record {} already has IP {}, no update needed", host, ip);
+        let written = response
+            .result
+            .ok_or_else(|| anyhow::anyhow!("No result in Cloudflare response"))?;
+        if written.puts.len() != puts || written.posts.len() != posts {
+            anyhow::bail!("Cloudflare batch response doesn't match the {} updated and {} created records", puts, posts);
+        }
+        Ok(written)
+    }
+
+    fn remember(&self, key: (RecordType, String), record: &DnsRecord, written: DesiredRecord) {
+        let cached = Cached {
+            record_id: record.id.clone(),
+            written,
+            modified_on: record.modified_on.clone(),
+        };
+        self.records.lock().unwrap().insert(key, cached);
+    }
+
+    /// With `on_conflict = "refuse"`, the error for overwriting `existing`
+    /// (or recreating it, when it's gone) although it changed since it was
+    /// last written or seen here. Cloudflare has no conditional writes, so
+    /// its modification time is compared instead.
+    fn conflict(&self, key: &(RecordType, String), existing: Option<&DnsRecord>) -> Option<anyhow::Error> {
+        if self.config.on_conflict != ConflictPolicy::Refuse {
+            return None;
+        }
+        let records = self.records.lock().unwrap();
+        let cached = records.get(key)?;
+        let unchanged = existing.is_some_and(|record| record.id == cached.record_id && record.modified_on == cached.modified_on);
+        if unchanged || cached.modified_on.is_none() {
+            return None;
+        }
+
+        let message = match existing {
+            Some(record) => format!(
+                "{} record {} was changed elsewhere to {} since it was last written",
+                key.0, record.name, record.content
+            ),
+            None => format!("{} record {} was deleted elsewhere since it was last written", key.0, key.1),
+        };
+        warn!("Refusing to overwrite: {}", message);
+        Some(ProviderError::Conflict(message).into())
+    }
+
+    /// Creates or updates the record of `desired.record_type` for `desired.name`.
+    async fn upsert(&self, desired: DesiredRecord) -> Result<DnsUpdateResult> {
+        let host = desired.name.clone();
+        let target = desired.describe();
+        let zone_id = self.zone_id_for(&host).await?;
+        let key = (desired.record_type, host.to_ascii_lowercase());
+
+        // Steady state: update the record we wrote last time directly
+        let cached = self.records.lock().unwrap().get(&key).cloned();
+        if let Some(cached) = cached.as_ref().filter(|cached| cached.written.same_as(&desired)) {
+            info!("Record {} already has {}, no update needed (cached)", host, target);
             return Ok(DnsUpdateResult {
                 success: true,
-                message: format!("Record already up to date with IP {}", ip),
-                record_id: Some(existing.id),
+                change: ChangeKind::NoChange,
+                new: Some(desired.value()),
+                message: format!("Record already up to date with {}", target),
+                record_id: Some(cached.record_id.clone()),
             });
         }
+        // Checking for changes made elsewhere takes a fresh look at the record
+        let cached = cached.filter(|_| self.config.on_conflict == ConflictPolicy::Overwrite);
+        if let Some(Cached { record_id, written, .. }) = cached {
+            info!("Updating cached record {} ({}) from {} to {}", host, record_id, written.content, desired.content);
+            match self.update_existing_record(&zone_id, &record_id, &desired).await {
+                Ok(record) => {
+                    let new = Some(desired.value());
+                    self.remember(key, &record, desired);
+                    return Ok(DnsUpdateResult {
+                        success: true,
+                        change: ChangeKind::Updated { old: Some(written.value()) },
+                        new,
+                        message: format!("Updated record {} to {}", host, target),
+                        record_id: Some(record.id),
+                    });
+                }
+                // The record may have been deleted or changed elsewhere; look it up again
+                Err(e) => {
+                    warn!("Cached record {} for {} could not be updated, refreshing: {:#}", record_id, host, e);
+                    self.records.lock().unwrap().remove(&key);
+                }
+            }
+        }
+
+        // Check if record exists
+        let mut existing = self.get_records(&zone_id, desired.record_type, &host).await?;
+        if existing.len() > 1 {
+            return self.update_multiple(&zone_id, &desired, existing).await;
+        }
+
+        if let Some(existing) = existing.pop() {
+            if desired.matches(&existing) {
+                info!("Record {} already has {}, no update needed", host, target);
+                let new = Some(desired.value());
+                self.remember(key, &existing, desired);
+                return Ok(DnsUpdateResult {
+                    success: true,
+                    change: ChangeKind::NoChange,
+                    new,
+                    message: format!("Record already up to date with {}", target),
+                    record_id: Some(existing.id),
+                });
+            }
+
+            if let Some(e) = self.conflict(&key, Some(&existing)) {
+                return Err(e);
+            }
+            info!("Updating existing record {} from {} to {}", host, existing.content, desired.content);
+            let record = self.update_existing_record(&zone_id, &existing.id, &desired).await?;
+            let new = Some(desired.value());
+            self.remember(key, &record, desired);
+
+            Ok(DnsUpdateResult {
+                success: true,
+                change: ChangeKind::Updated { old: Some(existing.value()) },
+                new,
+                message: format!("Updated record {} to {}", host, target),
+                record_id: Some(record.id),
+            })
+        } else {
+            if !self.config.create_missing {
+                return Err(missing_record(&host, desired.record_type));
+            }
+            if let Some(e) = self.conflict(&key, None) {
+                return Err(e);
+            }
+            info!("Creating new {} record {} with {}", desired.record_type, host, target);
+            let record = self.create_record(&zone_id, &desired).await?;
+            let new = Some(desired.value());
+            self.remember(key, &record, desired);
+
+            Ok(DnsUpdateResult {
+                success: true,
+                change: ChangeKind::Created,
+                new,
+                message: format!("Created new record {} with {}", host, target),
+                record_id: Some(record.id),
+            })
+        }
+    }
+
+    /// [`Self::upsert`] for records of several hosts in `zone_id`, written in
+    /// one batch. Hosts with more than one record of the type are left to
+    /// `upsert` and its `multiple_records` policy.
+    async fn upsert_zone(&self, zone_id: &str, desired: Vec<DesiredRecord>) -> Vec<Result<DnsUpdateResult>> {
+        let mut results: Vec<Option<Result<DnsUpdateResult>>> = desired.iter().map(|_| None).collect();
+        let unchanged = |desired: &DesiredRecord, record_id: String| DnsUpdateResult {
+            success: true,
+            change: ChangeKind::NoChange,
+            new: Some(desired.value()),
+            message: format!("Record already up to date with {}", desired.describe()),
+            record_id: Some(record_id),
+        };
+
+        // Records to write by index into `desired`, with the ID and value of the one to update
+        let mut puts: Vec<(usize, String, String)> = Vec::new();
+        let mut posts: Vec<usize> = Vec::new();
+        let mut lookups: BTreeMap<RecordType, Vec<usize>> = BTreeMap::new();
+        let mut singles: Vec<usize> = Vec::new();
+        for (index, record) in desired.iter().enumerate() {
+            let cached = self.records.lock().unwrap().get(&(record.record_type, record.name.to_ascii_lowercase())).cloned();
+            match cached {
+                Some(cached) if cached.written.same_as(record) => {
+                    info!("Record {} already has {}, no update needed (cached)", record.name, record.describe());
+                    results[index] = Some(Ok(unchanged(record, cached.record_id)));
+                }
+                // Checking for changes made elsewhere takes a fresh look at the record
+                Some(cached) if self.config.on_conflict == ConflictPolicy::Overwrite => {
+                    puts.push((index, cached.record_id, cached.written.value()))
+                }
+                _ => lookups.entry(record.record_type).or_default().push(index),
+            }
+        }
+
+        // One listing per record type rather than one per host
+        for (record_type, indexes) in lookups {
+            let listed = match indexes[..] {
+                [index] => self.get_records(zone_id, record_type, &desired[index].name).await,
+                _ => {
+                    let url = format!("{}/zones/{}/dns_records", self.api_base, zone_id);
+                    self.fetch_all::<DnsRecord>(&url, &[("type", record_type.as_str())]).await
+                }
+            };
+            let listed = match listed {
+                Ok(listed) => listed,
+                Err(e) => {
+                    for index in indexes {
+                        results[index] = Some(Err(shared_error(&e)));
+                    }
+                    continue;
+                }
+            };
+
+            for index in indexes {
+                let record = &desired[index];
+                let key = (record_type, record.name.to_ascii_lowercase());
+                let mut existing: Vec<DnsRecord> =
+                    listed.iter().filter(|r| r.name.eq_ignore_ascii_case(&record.name)).cloned().collect();
+                self.retain_managed(&record.name, &mut existing);
+                match existing.len() {
+                    0 if !self.config.create_missing => results[index] = Some(Err(missing_record(&record.name, record_type))),
+                    0 => match self.conflict(&key, None) {
+                        Some(e) => results[index] = Some(Err(e)),
+                        None => posts.push(index),
+                    },
+                    1 if record.matches(&existing[0]) => {
+                        info!("Record {} already has {}, no update needed", record.name, record.describe());
+                        self.remember(key, &existing[0], record.clone());
+                        results[index] = Some(Ok(unchanged(record, existing.remove(0).id)));
+                    }
+                    1 => match self.conflict(&key, Some(&existing[0])) {
+                        Some(e) => results[index] = Some(Err(e)),
+                        None => {
+                            let record = existing.remove(0);
+                            puts.push((index, record.id.clone(), record.value()))
+                        }
+                    },
+                    _ => singles.push(index),
+                }
+            }
+        }
+
+        let mut changes = Changes::default();
+        for (index, record_id, _) in &puts {
+            let record = &desired[*index];
+            info!("Updating record {} ({}) to {}", record.name, record_id, record.describe());
+            changes.puts.push((record_id.clone(), record.clone()));
+        }
+        for &index in &posts {
+            let record = &desired[index];
+            info!("Creating new {} record {} with {}", record.record_type, record.name, record.describe());
+            changes.posts.push(record.clone());
+        }
+        if !changes.is_empty() {
+            match self.write(zone_id, changes).await {
+                Ok(written) => {
+                    let puts = puts
+                        .into_iter()
+                        .map(|(index, _, old)| (index, ChangeKind::Updated { old: Some(old) }, "Updated record", "to"))
+                        .zip(written.puts);
+                    let posts = posts
+                        .into_iter()
+                        .map(|index| (index, ChangeKind::Created, "Created new record", "with"))
+                        .zip(written.posts);
+                    for ((index, change, action, preposition), written) in puts.chain(posts) {
+                        let record = &desired[index];
+                        self.remember((record.record_type, record.name.to_ascii_lowercase()), &written, record.clone());
+                        results[index] = Some(Ok(DnsUpdateResult {
+                            success: true,
+                            change,
+                            new: Some(record.value()),
+                            message: format!("{} {} {} {}", action, record.name, preposition, record.describe()),
+                            record_id: Some(written.id),
+                        }));
+                    }
+                }
+                // A cached record may be gone; each host then looks its record up again
+                Err(e) if matches!(classify(&e), Some(ProviderError::Api(_))) => {
+                    warn!("Batch update in zone {} failed, updating records one by one: {:#}", zone_id, e);
+                    for index in puts.into_iter().map(|(index, ..)| index).chain(posts) {
+                        // Only records written from the cache are stale; the others were just listed
+                        if self.config.on_conflict == ConflictPolicy::Overwrite {
+                            let record = &desired[index];
+                            self.records.lock().unwrap().remove(&(record.record_type, record.name.to_ascii_lowercase()));
+                        }
+                        singles.push(index);
+                    }
+                }
+                Err(e) => {
+                    for index in puts.into_iter().map(|(index, ..)| index).chain(posts) {
+                        results[index] = Some(Err(shared_error(&e)));
+                    }
+                }
+            }
+        }
+
+        for index in singles {
+            results[index] = Some(self.upsert(desired[index].clone()).await);
+        }
+        results.into_iter().flatten().collect()
+    }
+
+    /// Applies the configured policy when a host has more than one record of the type.
+    async fn update_multiple(
+        &self,
+        zone_id: &str,
+        desired: &DesiredRecord,
+        existing: Vec<DnsRecord>,
+    ) -> Result<DnsUpdateResult> {
+        let host = &desired.name;
+        let target = desired.describe();
+
+        match self.config.multiple_records {
+            MultipleRecordsPolicy::Error => anyhow::bail!(
+                "Found {} {} records for {}, refusing to update (multiple_records = \"error\")",
+                existing.len(),
+                desired.record_type,
+                host
+            ),
+            MultipleRecordsPolicy::UpdateAll => {
+                let record_id = existing.first().map(|record| record.id.clone());
+                let mut changes = Changes::default();
+                for record in &existing {
+                    if !desired.matches(record) {
+                        info!("Updating record {} ({}) from {} to {}", host, record.id, record.content, desired.content);
+                        changes.puts.push((record.id.clone(), desired.clone()));
+                    }
+                }
+                let updated = changes.len();
+                self.write(zone_id, changes).await?;
+
+                let message = if updated == 0 {
+                    format!("All {} records already up to date with {}", existing.len(), target)
+                } else {
+                    format!("Updated {} of {} records {} to {}", updated, existing.len(), host, target)
+                };
+
+                Ok(DnsUpdateResult {
+                    success: true,
+                    change: match updated {
+                        0 => ChangeKind::NoChange,
+                        _ => ChangeKind::Updated { old: None },
+                    },
+                    new: Some(desired.value()),
+                    message,
+                    record_id,
+                })
+            }
+            MultipleRecordsPolicy::ReplaceWithOne => {
+                // Keep a record that already matches if there is one, otherwise the first
+                let keep_index = existing.iter().position(|r| desired.matches(r)).unwrap_or(0);
+                let keep = &existing[keep_index];
+
+                let mut changes = Changes::default();
+                for (index, record) in existing.iter().enumerate() {
+                    if index != keep_index {
+                        warn!("Deleting extra record {} ({}) with {}", host, record.id, record.content);
+                        changes.deletes.push(record.id.clone());
+                    }
+                }
+                if !desired.matches(keep) {
+                    info!("Updating record {} from {} to {}", host, keep.content, desired.content);
+                    changes.puts.push((keep.id.clone(), desired.clone()));
+                }
+                self.write(zone_id, changes).await?;
+                let record_id = keep.id.clone();
+
+                Ok(DnsUpdateResult {
+                    success: true,
+                    change: ChangeKind::Updated { old: None },
+                    new: Some(desired.value()),
+                    message: format!(
+                        "Replaced {} records {} with a single record for {}",
+                        existing.len(),
+                        host,
+                        target
+                    ),
+                    record_id: Some(record_id),
+                })
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for Cloudflare {
+    async fn update_record(&self, host: &str, ip: IpAddr, options: &RecordOptions) -> Result<DnsUpdateResult> {
+        self.upsert(self.desired(RecordType::for_ip(&ip), host, ip.to_string(), options)).await
+    }
+
+    async fn update_records(&self, updates: &[AddressUpdate]) -> Vec<Result<DnsUpdateResult>> {
+        let mut results: Vec<Option<Result<DnsUpdateResult>>> = updates.iter().map(|_| None).collect();
+
+        // Each zone's changes go in one batch
+        let mut zones: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (index, update) in updates.iter().enumerate() {
+            match self.zone_id_for(&update.host).await {
+                Ok(zone_id) => zones.entry(zone_id).or_default().push(index),
+                Err(e) => results[index] = Some(Err(e)),
+            }
+        }
+        for (zone_id, indexes) in zones {
+            let desired = indexes
+                .iter()
+                .map(|&index| {
+                    let update = &updates[index];
+                    self.desired(RecordType::for_ip(&update.ip), &update.host, update.ip.to_string(), &update.options)
+                })
+                .collect();
+            for (index, result) in indexes.into_iter().zip(self.upsert_zone(&zone_id, desired).await) {
+                results[index] = Some(result);
+            }
+        }
+
+        results.into_iter().flatten().collect()
+    }
+
+    async fn update_cname(&self, host: &str, target: &str, options: &RecordOptions) -> Result<DnsUpdateResult> {
+        // Cloudflare flattens a CNAME at the zone apex into A/AAAA answers
+        self.upsert(self.desired(RecordType::Cname, host, target.to_string(), options)).await
+    }
+
+    async fn update_ptr(&self, ip: IpAddr, host: &str, options: &RecordOptions) -> Result<DnsUpdateResult> {
+        self.upsert(self.desired(RecordType::Ptr, &reverse_name(&ip), host.to_string(), options)).await
+    }
+
+    async fn update_srv(&self, name: &str, srv: &SrvData, options: &RecordOptions) -> Result<DnsUpdateResult> {
+        // Cloudflare reports the priority separately from `weight port target`
+        let content = format!("{} {} {}", srv.weight, srv.port, srv.target);
+        let mut desired = self.desired(RecordType::Srv, name, content, options);
+        desired.priority = Some(srv.priority);
+        desired.data = Some(srv.clone());
+        self.upsert(desired).await
+    }
+
+    async fn update_mx(&self, host: &str, mx: &MxData, options: &RecordOptions) -> Result<DnsUpdateResult> {
+        let mut desired = self.desired(RecordType::Mx, host, mx.target.clone(), options);
+        desired.priority = Some(mx.priority);
+        self.upsert(desired).await
+    }
+
+    async fn update_record_set(&self, host: &str, ips: &[IpAddr], options: &RecordOptions) -> Result<DnsUpdateResult> {
+        let zone_id = self.zone_id_for(host).await?;
+        let mut changes = Changes::default();
+        let mut record_id = None;
+        // The addresses held before
+        let mut old = Vec::new();
+
+        for record_type in [RecordType::A, RecordType::Aaaa] {
+            let mut missing: Vec<IpAddr> = ips.iter().copied().filter(|ip| RecordType::for_ip(ip) == record_type).collect();
+            if missing.is_empty() {
+                continue;
+            }
+            // The single-record cache cannot describe a set
+            self.records.lock().unwrap().remove(&(record_type, host.to_ascii_lowercase()));
+
+            let desired_for = |ip: IpAddr| self.desired(record_type, host, ip.to_string(), options);
+
+            let existing = self.get_records(&zone_id, record_type, host).await?;
+            old.extend(existing.iter().map(|record| record.content.clone()));
+            // Growing an existing set is fine, starting one is creating the host
+            if existing.is_empty() && !self.config.create_missing {
+                return Err(missing_record(host, record_type));
+            }
+
+            // Keep records that already hold a wanted address
+            let mut stale = Vec::new();
+            for record in existing {
+                let wanted = record
+                    .content
+                    .parse::<IpAddr>()
+                    .ok()
+                    .and_then(|ip| missing.iter().position(|w| *w == ip));
+                let Some(index) = wanted else {
+                    stale.push(record);
+                    continue;
+                };
+
+                let desired = desired_for(missing.remove(index));
+                if !desired.matches(&record) {
+                    info!("Updating settings of record {} ({}) with {}", host, record.id, record.content);
+                    changes.puts.push((record.id.clone(), desired));
+                }
+                record_id.get_or_insert(record.id);
+            }
+
+            // Reuse stale records for missing addresses before creating new ones
+            for ip in missing {
+                let desired = desired_for(ip);
+                match stale.pop() {
+                    Some(record) => {
+                        info!("Updating record {} ({}) from {} to {}", host, record.id, record.content, ip);
+                        record_id.get_or_insert(record.id.clone());
+                        changes.puts.push((record.id, desired));
+                    }
+                    None => {
+                        info!("Creating new {} record {} with IP {}", record_type, host, ip);
+                        changes.posts.push(desired);
+                    }
+                }
+            }
+
+            for record in stale {
+                warn!("Deleting stale record {} ({}) with {}", host, record.id, record.content);
+                changes.deletes.push(record.id);
+            }
+        }
 
-        info!("Updating existing record {} from {} to {}", host, existing.content, ip);
-        let record = update_existing_record(&client, config, &existing.id, host, ip).await?;
+        let (created, updated, deleted) = (changes.posts.len(), changes.puts.len(), changes.deletes.len());
+        let written = self.write(&zone_id, changes).await?;
+        let record_id = record_id.or_else(|| written.posts.into_iter().next().map(|record| record.id));
+
+        let list = ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>().join(", ");
+        let changed = created + updated + deleted > 0;
+        let message = if changed {
+            format!(
+                "Reconciled records {} to IPs {} ({} created, {} updated, {} deleted)",
+                host, list, created, updated, deleted
+            )
+        } else {
+            format!("Records already up to date with IPs {}", list)
+        };
 
         Ok(DnsUpdateResult {
             success: true,
-            message: format!("Updated record {} to IP {}", host, ip),
-            record_id: Some(record.id),
+            change: if !changed {
+                ChangeKind::NoChange
+            } else if old.is_empty() {
+                ChangeKind::Created
+            } else {
+                ChangeKind::Updated { old: Some(old.join(", ")) }
+            },
+            new: Some(list),
+            message,
+            record_id,
         })
-    } else {
-        info!("Creating new record {} with IP {}", host, ip);
-        let record = create_record(&client, config, host, ip).await?;
+    }
+
+    async fn delete_records(&self, host: &str, record_type: RecordType) -> Result<DnsUpdateResult> {
+        let zone_id = self.zone_id_for(host).await?;
+        self.records.lock().unwrap().remove(&(record_type, host.to_ascii_lowercase()));
+
+        let records = self.get_records(&zone_id, record_type, host).await?;
+        let mut changes = Changes::default();
+        for record in &records {
+            info!("Deleting {} record {} ({}) with {}", record_type, host, record.id, record.content);
+            changes.deletes.push(record.id.clone());
+        }
+        self.write(&zone_id, changes).await?;
 
         Ok(DnsUpdateResult {
             success: true,
-            message: format!("Created new record {} with IP {}", host, ip),
-            record_id: Some(record.id),
+            change: match records.len() {
+                0 => ChangeKind::NoChange,
+                _ => ChangeKind::Deleted,
+            },
+            new: None,
+            message: match records.len() {
+                0 => format!("No {} records of {} to delete", record_type, host),
+                n => format!("Deleted {} {} record(s) of {}", n, record_type, host),
+            },
+            record_id: None,
         })
     }
-}
 
-async fn get_record(client: &Client, config: &ProviderConfig, host: &str) -> Result<Option<DnsRecord>> {
-    let url = format!(
-        "{}/zones/{}/dns_records?type=A&name={}",
-        CLOUDFLARE_API_BASE, config.zone_id, host
-    );
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            record_types: &[RecordType::A, RecordType::Aaaa, RecordType::Cname, RecordType::Srv, RecordType::Mx, RecordType::Ptr],
+            // 30s on Enterprise zones; others reject TTLs under 60s themselves
+            min_ttl: 30,
+            max_ttl: 86400,
+            auto_ttl: true,
+            proxied: true,
+            delete: true,
+            record_sets: true,
+        }
+    }
 
-    let response: CloudflareListResponse = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", config.api_key))
-        .header("Content-Type", "application/json")
-        .send()
-        .await
-        .context("Failed to send request to Cloudflare")?
-        .json()
-        .await
-        .context("Failed to parse Cloudflare response")?;
+    fn forget(&self, host: &str) {
+        let host = host.to_ascii_lowercase();
+        self.records.lock().unwrap().retain(|(_, name), _| *name != host);
+    }
 
-    if !response.success {
-        let errors: Vec<String> = response
-            .errors
-            .iter()
-            .map(|e| format!("{}: {}", e.code, e.message))
-            .collect();
-        anyhow::bail!("Cloudflare API error: {}", errors.join(", "));
+    #[instrument(name = "cloudflare.verify", skip_all, fields(provider = %self.config.name))]
+    async fn verify(&self) -> Result<String> {
+        let credentials = match self.config.auth_mode {
+            CloudflareAuthMode::Token => {
+                let token = self.verify_token().await?;
+                if token.status != "active" {
+                    anyhow::bail!("API token is {}", token.status);
+                }
+                let expiry = token
+                    .expires_on
+                    .map(|e| format!(", expires {}", e))
+                    .unwrap_or_default();
+                format!("token active{}", expiry)
+            }
+            CloudflareAuthMode::GlobalKey => format!("global API key of {}", self.verify_global_key().await?),
+        };
+
+        // The token may be valid but lack access to the configured zone
+        let zone = if let Some(ref zone_id) = self.config.zone_id {
+            let url = format!("{}/zones/{}", self.api_base, zone_id);
+            let response = self
+                .client
+                .get(&url)
+                .headers(self.auth.clone())
+                .send()
+                .await
+                .context("Failed to send zone request to Cloudflare")?;
+            let response: CloudflareResponse<Zone> = read_response(response, "zone").await?;
+
+            let zone = response
+                .result
+                .ok_or_else(|| anyhow::anyhow!("No result in Cloudflare response"))?;
+            format!("zone {} ({})", zone.name, zone.id)
+        } else if let Some(ref zone_name) = self.config.zone_name {
+            format!("zone {} ({})", zone_name, self.zone_id_for(zone_name).await?)
+        } else {
+            let zones = self.fetch_zones().await?.len();
+            if zones == 0 {
+                anyhow::bail!("Credentials are valid but can't access any zone");
+            }
+            format!("{} zone(s) visible", zones)
+        };
+
+        Ok(format!("{}, {}", credentials, zone))
     }
 
-    Ok(response.result.into_iter().next())
-}
+    async fn credentials_expiry(&self) -> Result<Option<OffsetDateTime>> {
+        // A Global API Key never expires, but may have been rotated
+        if self.config.auth_mode == CloudflareAuthMode::GlobalKey {
+            self.verify_global_key().await?;
+            return Ok(None);
+        }
 
-async fn create_record(client: &Client, config: &ProviderConfig, host: &str, ip: &str) -> Result<DnsRecord> {
-    let url = format!(
-        "{}/zones/{}/dns_records",
-        CLOUDFLARE_API_BASE, config.zone_id
-    );
+        let token = self.verify_token().await?;
+        if token.status != "active" {
+            return Err(ProviderError::Auth(format!("API token is {}", token.status)).into());
+        }
+        token
+            .expires_on
+            .map(|expires_on| {
+                OffsetDateTime::parse(&expires_on, &Rfc3339)
+                    .with_context(|| format!("Invalid token expiry from Cloudflare: {}", expires_on))
+            })
+            .transpose()
+    }
 
-    let body = CreateRecordRequest {
-        record_type: "A".to_string(),
-        name: host.to_string(),
-        content: ip.to_string(),
-        ttl: 1,
-        proxied: false,
-    };
+    async fn list_records(&self) -> Result<Vec<RecordInfo>> {
+        // Without a configured zone, list across every zone the token can see
+        let zone_ids = match (&self.config.zone_id, &self.config.zone_name) {
+            (Some(zone_id), _) => vec![zone_id.clone()],
+            (None, Some(zone_name)) => vec![self.zone_id_for(zone_name).await?],
+            (None, None) => self.fetch_zones().await?.into_iter().map(|z| z.id).collect(),
+        };
 
-    let response: CloudflareResponse = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", config.api_key))
-        .header("Content-Type", "application/json")
-        .json(&body)
-        .send()
-        .await
-        .context("Failed to send create request to Cloudflare")?
-        .json()
-        .await
-        .context("Failed to parse Cloudflare create response")?;
+        let mut records = Vec::new();
 
-    if !response.success {
-        let errors: Vec<String> = response
-            .errors
-            .iter()
-            .map(|e| format!("{}: {}", e.code, e.message))
-            .collect();
-        anyhow::bail!("Cloudflare API error: {}", errors.join(", "));
-    }
-
-    response
-        .result
-        .ok_or_else(|| anyhow::anyhow!("No result in Cloudflare response"))
-}
-
-async fn update_existing_record(
-    client: &Client,
-    config: &ProviderConfig,
-    record_id: &str,
-    host: &str,
-    ip: &str,
-) -> Result<DnsRecord> {
-    let url = format!(
-        "{}/zones/{}/dns_records/{}",
-        CLOUDFLARE_API_BASE, config.zone_id, record_id
-    );
-
-    let body = UpdateRecordRequest {
-        record_type: "A".to_string(),
-        name: host.to_string(),
-        content: ip.to_string(),
-        ttl: 1,
-        proxied: false,
+        for zone_id in &zone_ids {
+            let url = format!("{}/zones/{}/dns_records", self.api_base, zone_id);
+
+            for record_type in MANAGED_RECORD_TYPES {
+                let result: Vec<DnsRecord> = self.fetch_all(&url, &[("type", record_type)]).await?;
+
+                records.extend(result.into_iter().map(|r| RecordInfo {
+                    content: r.value(),
+                    id: r.id,
+                    record_type: r.record_type,
+                    name: r.name,
+                    ttl: r.ttl,
+                    proxied: r.proxied,
+                }));
+            }
+        }
+
+        Ok(records)
+    }
+}
+
+/// The headers authenticating requests as configured by `auth_mode`.
+fn auth_headers(config: &ProviderConfig) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let mut insert = |name, value: &str| {
+        // Invalid characters only make Cloudflare reject the request as unauthenticated
+        if let Ok(mut value) = HeaderValue::from_str(value) {
+            value.set_sensitive(true);
+            headers.insert(name, value);
+        }
     };
 
-    let response: CloudflareResponse = client
-        .put(&url)
-        .header("Authorization", format!("Bearer {}", config.api_key))
-        .header("Content-Type", "application/json")
-        .json(&body)
-        .send()
-        .await
-        .context("Failed to send update request to Cloudflare")?
-        .json()
+    match config.auth_mode {
+        CloudflareAuthMode::Token => insert(AUTHORIZATION, &format!("Bearer {}", config.api_key)),
+        CloudflareAuthMode::GlobalKey => {
+            insert(HeaderName::from_static("x-auth-key"), &config.api_key);
+            insert(HeaderName::from_static("x-auth-email"), config.email.as_deref().unwrap_or_default());
+        }
+    }
+    headers
+}
+
+/// Decodes an API response, turning HTTP and API errors into [`ProviderError`]s.
+async fn read_response<T: DeserializeOwned>(response: reqwest::Response, action: &str) -> Result<CloudflareResponse<T>> {
+    let status = response.status();
+    let retry_after = retry_after(response.headers());
+    let body = response
+        .bytes()
         .await
-        .context("Failed to parse Cloudflare update response")?;
+        .with_context(|| format!("Failed to read Cloudflare {} response", action))?;
+
+    let response: CloudflareResponse<T> = match serde_json::from_slice(&body) {
+        Ok(response) => response,
+        Err(e) if status.is_success() => {
+            return Err(e).with_context(|| format!("Failed to parse Cloudflare {} response", action));
+        }
+        // Proxies and outages answer with HTML error pages
+        Err(_) => {
+            let message = format!("Cloudflare API returned {}", status);
+            return Err(ProviderError::from_status(status, retry_after, message).into());
+        }
+    };
 
     if !response.success {
         let errors: Vec<String> = response
@@ -149,50 +1069,204 @@ async fn update_existing_record(
             .iter()
             .map(|e| format!("{}: {}", e.code, e.message))
             .collect();
-        anyhow::bail!("Cloudflare API error: {}", errors.join(", "));
+        let message = format!("Cloudflare API error: {}", errors.join(", "));
+        let error = if response.errors.iter().any(|e| AUTH_ERROR_CODES.contains(&e.code)) {
+            ProviderError::Auth(message)
+        } else {
+            ProviderError::from_status(status, retry_after, message)
+        };
+        return Err(error.into());
     }
 
-    response
-        .result
-        .ok_or_else(|| anyhow::anyhow!("No result in Cloudflare response"))
+    Ok(response)
 }
 
-// Cloudflare API types
+/// A record as last written or seen by this service
+#[derive(Clone)]
+struct Cached {
+    record_id: String,
+    written: DesiredRecord,
+    /// Cloudflare's modification time of the record then
+    modified_on: Option<String>,
+}
 
-#[derive(Debug, Serialize)]
-struct CreateRecordRequest {
-    #[serde(rename = "type")]
-    record_type: String,
+/// Record changes in one zone, applied together by [`Cloudflare::write`]
+#[derive(Default)]
+struct Changes {
+    /// IDs of records to delete
+    deletes: Vec<String>,
+    /// Records to overwrite, by ID
+    puts: Vec<(String, DesiredRecord)>,
+    /// Records to create
+    posts: Vec<DesiredRecord>,
+}
+
+impl Changes {
+    fn len(&self) -> usize {
+        self.deletes.len() + self.puts.len() + self.posts.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The records written by [`Changes`], in order; also the result of a batch
+#[derive(Debug, Default, Deserialize)]
+struct Written {
+    #[serde(default)]
+    puts: Vec<DnsRecord>,
+    #[serde(default)]
+    posts: Vec<DnsRecord>,
+}
+
+/// The record state an update should converge to
+#[derive(Clone)]
+struct DesiredRecord {
+    record_type: RecordType,
     name: String,
+    /// IP address, target hostname for CNAME, MX and PTR records, or
+    /// `weight port target` for SRV records, as Cloudflare reports them
     content: String,
     ttl: u32,
     proxied: bool,
+    comment: Option<String>,
+    tags: Vec<String>,
+    /// SRV and MX priority
+    priority: Option<u16>,
+    /// SRV fields, sent instead of the content
+    data: Option<SrvData>,
+}
+
+impl DesiredRecord {
+    /// The body creating or overwriting a record with this state.
+    fn request(&self) -> RecordRequest {
+        RecordRequest {
+            record_type: self.record_type.to_string(),
+            name: self.name.clone(),
+            // Cloudflare derives an SRV record's content from its data
+            content: self.data.is_none().then(|| self.content.clone()),
+            ttl: self.ttl,
+            proxied: self.proxied,
+            comment: self.comment.clone(),
+            tags: self.tags.clone(),
+            priority: self.priority,
+            data: self.data.clone(),
+        }
+    }
+
+    /// Whether an existing record already has the desired content and settings.
+    /// Addresses are compared parsed, since IPv6 has several textual forms.
+    fn matches(&self, record: &DnsRecord) -> bool {
+        let same_content = match self.record_type {
+            RecordType::Cname | RecordType::Srv | RecordType::Mx | RecordType::Ptr => {
+                record.content.trim_end_matches('.').eq_ignore_ascii_case(&self.content)
+            }
+            RecordType::A | RecordType::Aaaa => {
+                record.content.parse::<IpAddr>().ok() == self.content.parse::<IpAddr>().ok()
+            }
+        };
+        same_content
+            && record.priority == self.priority
+            && record.ttl == self.ttl
+            && record.proxied.unwrap_or(false) == self.proxied
+            && record.carries_label(self.comment.as_deref(), &self.tags)
+    }
+
+    /// Whether `other` would write the same content and settings.
+    fn same_as(&self, other: &DesiredRecord) -> bool {
+        self.content == other.content
+            && self.priority == other.priority
+            && self.ttl == other.ttl
+            && self.proxied == other.proxied
+            && self.comment == other.comment
+            && self.tags == other.tags
+    }
+
+    /// The content as in a zone file, with the priority of SRV and MX records first
+    fn value(&self) -> String {
+        zone_content(self.priority, &self.content)
+    }
+
+    /// The content for messages, e.g. `IP 1.2.3.4` or `CNAME target.example.net`
+    fn describe(&self) -> String {
+        match (self.record_type, self.priority) {
+            (RecordType::A | RecordType::Aaaa, _) => format!("IP {}", self.content),
+            (record_type, Some(priority)) => format!("{} {} {}", record_type, priority, self.content),
+            (record_type, None) => format!("{} {}", record_type, self.content),
+        }
+    }
+}
+
+fn zone_content(priority: Option<u16>, content: &str) -> String {
+    match priority {
+        Some(priority) => format!("{} {}", priority, content),
+        None => content.to_string(),
+    }
 }
 
+// Cloudflare API types
+
+/// Body of record create and overwrite calls, also inside batches
 #[derive(Debug, Serialize)]
-struct UpdateRecordRequest {
+struct RecordRequest {
     #[serde(rename = "type")]
     record_type: String,
     name: String,
-    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
     ttl: u32,
     proxied: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    priority: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<SrvData>,
+}
+
+/// Body of `dns_records/batch`; Cloudflare applies the deletes, then the
+/// puts, then the posts
+#[derive(Debug, Serialize)]
+struct BatchRequest {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    deletes: Vec<BatchDelete>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    puts: Vec<BatchPut>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    posts: Vec<RecordRequest>,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchDelete {
+    id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchPut {
+    id: String,
+    #[serde(flatten)]
+    record: RecordRequest,
 }
 
 #[derive(Debug, Deserialize)]
-struct CloudflareResponse {
+struct CloudflareResponse<T> {
     success: bool,
     #[serde(default)]
     errors: Vec<CloudflareError>,
-    result: Option<DnsRecord>,
+    /// `null` on error responses
+    result: Option<T>,
+    /// Paging of list responses
+    #[serde(default)]
+    result_info: Option<ResultInfo>,
 }
 
 #[derive(Debug, Deserialize)]
-struct CloudflareListResponse {
-    success: bool,
-    #[serde(default)]
-    errors: Vec<CloudflareError>,
-    result: Vec<DnsRecord>,
+struct ResultInfo {
+    page: u32,
+    total_pages: u32,
 }
 
 #[derive(Debug, Deserialize)]
@@ -201,13 +1275,63 @@ struct CloudflareError {
     message: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
+struct Zone {
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 struct DnsRecord {
     id: String,
-    #[allow(dead_code)]
     #[serde(rename = "type")]
     record_type: String,
-    #[allow(dead_code)]
     name: String,
     content: String,
+    #[serde(default)]
+    ttl: u32,
+    #[serde(default)]
+    proxied: Option<bool>,
+    /// Set on SRV and MX records
+    #[serde(default)]
+    priority: Option<u16>,
+    #[serde(default)]
+    comment: Option<String>,
+    /// `null` on zones without tag support
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+    #[serde(default)]
+    modified_on: Option<String>,
+}
+
+impl DnsRecord {
+    /// The content as in a zone file, with the priority of SRV and MX records first
+    fn value(&self) -> String {
+        zone_content(self.priority, &self.content)
+    }
+
+    /// Whether the record has `comment` (if any) and every one of `tags`.
+    fn carries_label(&self, comment: Option<&str>, tags: &[String]) -> bool {
+        let has_comment = comment.is_none_or(|c| self.comment.as_deref() == Some(c));
+        let record_tags = self.tags.as_deref().unwrap_or_default();
+        has_comment && tags.iter().all(|tag| record_tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenStatus {
+    status: String,
+    #[serde(default)]
+    expires_on: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct User {
+    email: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeletedRecord {
+    #[allow(dead_code)]
+    id: String,
 }
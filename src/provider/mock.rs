@@ -0,0 +1,306 @@
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use tracing::info;
+
+use crate::config::ProviderConfig;
+use super::{missing_record, reverse_name, Capabilities, ChangeKind, DnsUpdateResult, MxData, Provider, RecordInfo, RecordOptions, RecordType, SrvData};
+
+/// In-memory provider for tests and demos. Records live only as long as the
+/// process; every update attempt is logged and exposed via `debug_state`.
+pub struct Mock {
+    config: ProviderConfig,
+    state: Mutex<MockState>,
+}
+
+#[derive(Default)]
+struct MockState {
+    records: Vec<RecordInfo>,
+    updates: Vec<MockUpdate>,
+    next_id: u64,
+}
+
+/// One update attempt, as recorded by the mock provider
+#[derive(Debug, Clone, Serialize)]
+pub struct MockUpdate {
+    pub host: String,
+    pub ip: IpAddr,
+    pub ttl: u32,
+    pub proxied: bool,
+    pub success: bool,
+    /// Unix timestamp of the attempt
+    pub timestamp: u64,
+}
+
+impl Mock {
+    pub fn new(config: ProviderConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(MockState::default()),
+        }
+    }
+
+    /// All update attempts so far, oldest first.
+    pub fn updates(&self) -> Vec<MockUpdate> {
+        self.state.lock().unwrap().updates.clone()
+    }
+}
+
+#[async_trait]
+impl Provider for Mock {
+    async fn update_record(&self, host: &str, ip: IpAddr, options: &RecordOptions) -> Result<DnsUpdateResult> {
+        if self.config.latency_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(self.config.latency_ms)).await;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let attempt = state.updates.len() as u32 + 1;
+        // `checked_rem` is `None` for fail_every = 0, i.e. never fail
+        let fail = attempt.checked_rem(self.config.fail_every) == Some(0);
+
+        state.updates.push(MockUpdate {
+            host: host.to_string(),
+            ip,
+            ttl: options.ttl,
+            proxied: options.proxied,
+            success: !fail,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        });
+
+        if fail {
+            anyhow::bail!("Simulated failure for update #{} (fail_every = {})", attempt, self.config.fail_every);
+        }
+
+        if !self.config.create_missing && !state.has_record(host, RecordType::for_ip(&ip)) {
+            return Err(missing_record(host, RecordType::for_ip(&ip)));
+        }
+        Ok(state.upsert(host, RecordType::for_ip(&ip), ip.to_string(), options))
+    }
+
+    async fn update_cname(&self, host: &str, target: &str, options: &RecordOptions) -> Result<DnsUpdateResult> {
+        let mut state = self.state.lock().unwrap();
+        if !self.config.create_missing && !state.has_record(host, RecordType::Cname) {
+            return Err(missing_record(host, RecordType::Cname));
+        }
+        Ok(state.upsert(host, RecordType::Cname, target.to_string(), options))
+    }
+
+    async fn update_srv(&self, name: &str, srv: &SrvData, options: &RecordOptions) -> Result<DnsUpdateResult> {
+        let mut state = self.state.lock().unwrap();
+        if !self.config.create_missing && !state.has_record(name, RecordType::Srv) {
+            return Err(missing_record(name, RecordType::Srv));
+        }
+        Ok(state.upsert(name, RecordType::Srv, srv.to_string(), options))
+    }
+
+    async fn update_mx(&self, host: &str, mx: &MxData, options: &RecordOptions) -> Result<DnsUpdateResult> {
+        let mut state = self.state.lock().unwrap();
+        if !self.config.create_missing && !state.has_record(host, RecordType::Mx) {
+            return Err(missing_record(host, RecordType::Mx));
+        }
+        Ok(state.upsert(host, RecordType::Mx, mx.to_string(), options))
+    }
+
+    async fn update_ptr(&self, ip: IpAddr, host: &str, options: &RecordOptions) -> Result<DnsUpdateResult> {
+        Ok(self.state.lock().unwrap().upsert(&reverse_name(&ip), RecordType::Ptr, host.to_string(), options))
+    }
+
+    async fn update_record_set(&self, host: &str, ips: &[IpAddr], options: &RecordOptions) -> Result<DnsUpdateResult> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(ip) = ips.iter().find(|ip| !self.config.create_missing && !state.has_record(host, RecordType::for_ip(ip))) {
+            return Err(missing_record(host, RecordType::for_ip(ip)));
+        }
+        let families: Vec<String> = ips.iter().map(|ip| RecordType::for_ip(ip).to_string()).collect();
+        let old: Vec<String> = state
+            .records
+            .iter()
+            .filter(|r| r.name.eq_ignore_ascii_case(host) && families.contains(&r.record_type))
+            .map(|r| r.content.clone())
+            .collect();
+        let before = state.records.len();
+        state.records.retain(|r| {
+            let stale = r.name.eq_ignore_ascii_case(host)
+                && families.contains(&r.record_type)
+                && !ips.iter().any(|ip| r.content.parse::<IpAddr>().ok() == Some(*ip));
+            if stale {
+                info!("Mock: deleting stale record {} with {}", host, r.content);
+            }
+            !stale
+        });
+        let mut changed = state.records.len() != before;
+
+        let mut record_id = None;
+        for ip in ips {
+            let (id, written) = state.set_address(host, *ip, options);
+            changed |= written;
+            record_id.get_or_insert(id);
+        }
+
+        let list = ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>().join(", ");
+        Ok(DnsUpdateResult {
+            success: true,
+            change: if !changed {
+                ChangeKind::NoChange
+            } else if old.is_empty() {
+                ChangeKind::Created
+            } else {
+                ChangeKind::Updated { old: Some(old.join(", ")) }
+            },
+            message: if changed {
+                format!("Reconciled records {} to IPs {}", host, list)
+            } else {
+                format!("Records already up to date with IPs {}", list)
+            },
+            new: Some(list),
+            record_id,
+        })
+    }
+
+    async fn delete_records(&self, host: &str, record_type: RecordType) -> Result<DnsUpdateResult> {
+        let mut state = self.state.lock().unwrap();
+        let record_type = record_type.to_string();
+        let before = state.records.len();
+        state.records.retain(|r| !(r.record_type == record_type && r.name.eq_ignore_ascii_case(host)));
+        let deleted = before - state.records.len();
+        if deleted > 0 {
+            info!("Mock: deleted {} {} record(s) of {}", deleted, record_type, host);
+        }
+
+        Ok(DnsUpdateResult {
+            success: true,
+            change: match deleted {
+                0 => ChangeKind::NoChange,
+                _ => ChangeKind::Deleted,
+            },
+            new: None,
+            message: match deleted {
+                0 => format!("No {} records of {} to delete", record_type, host),
+                n => format!("Deleted {} {} record(s) of {}", n, record_type, host),
+            },
+            record_id: None,
+        })
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            record_types: &[RecordType::A, RecordType::Aaaa, RecordType::Cname, RecordType::Srv, RecordType::Mx, RecordType::Ptr],
+            min_ttl: 1,
+            // RFC 2181 limits TTLs to 31 bits
+            max_ttl: i32::MAX as u32,
+            auto_ttl: false,
+            proxied: true,
+            delete: true,
+            record_sets: true,
+        }
+    }
+
+    async fn list_records(&self) -> Result<Vec<RecordInfo>> {
+        Ok(self.state.lock().unwrap().records.clone())
+    }
+
+    async fn verify(&self) -> Result<String> {
+        Ok("mock provider, records are kept in memory".to_string())
+    }
+
+    fn debug_state(&self) -> Option<serde_json::Value> {
+        let state = self.state.lock().unwrap();
+        Some(serde_json::json!({
+            "records": state.records,
+            "updates": state.updates,
+        }))
+    }
+}
+
+impl MockState {
+    fn has_record(&self, host: &str, record_type: RecordType) -> bool {
+        let record_type = record_type.to_string();
+        self.records.iter().any(|r| r.record_type == record_type && r.name.eq_ignore_ascii_case(host))
+    }
+
+    /// Creates or updates the record of `record_type` for `host`.
+    fn upsert(&mut self, host: &str, record_type: RecordType, content: String, options: &RecordOptions) -> DnsUpdateResult {
+        let target = match record_type {
+            RecordType::A | RecordType::Aaaa => format!("IP {}", content),
+            RecordType::Cname | RecordType::Srv | RecordType::Mx | RecordType::Ptr => format!("{} {}", record_type, content),
+        };
+        let record_type = record_type.to_string();
+
+        if let Some(record) = self
+            .records
+            .iter_mut()
+            .find(|r| r.record_type == record_type && r.name.eq_ignore_ascii_case(host))
+        {
+            if record.content == content && record.ttl == options.ttl && record.proxied == Some(options.proxied) {
+                return DnsUpdateResult {
+                    success: true,
+                    change: ChangeKind::NoChange,
+                    new: Some(content),
+                    message: format!("Record already up to date with {}", target),
+                    record_id: Some(record.id.clone()),
+                };
+            }
+
+            info!("Mock: updating record {} from {} to {}", host, record.content, content);
+            let old = std::mem::replace(&mut record.content, content.clone());
+            record.ttl = options.ttl;
+            record.proxied = Some(options.proxied);
+
+            return DnsUpdateResult {
+                success: true,
+                change: ChangeKind::Updated { old: Some(old) },
+                new: Some(content),
+                message: format!("Updated record {} to {}", host, target),
+                record_id: Some(record.id.clone()),
+            };
+        }
+
+        info!("Mock: creating {} record {} with {}", record_type, host, target);
+        let id = self.create(host, record_type, content.clone(), options);
+
+        DnsUpdateResult {
+            success: true,
+            change: ChangeKind::Created,
+            new: Some(content),
+            message: format!("Created new record {} with {}", host, target),
+            record_id: Some(id),
+        }
+    }
+
+    /// Ensures one of possibly several records of `host` holds `ip`, returning its ID
+    /// and whether anything was written.
+    fn set_address(&mut self, host: &str, ip: IpAddr, options: &RecordOptions) -> (String, bool) {
+        let record_type = RecordType::for_ip(&ip).to_string();
+        if let Some(record) = self.records.iter_mut().find(|r| {
+            r.record_type == record_type && r.name.eq_ignore_ascii_case(host) && r.content.parse::<IpAddr>().ok() == Some(ip)
+        }) {
+            let changed = record.ttl != options.ttl || record.proxied != Some(options.proxied);
+            record.ttl = options.ttl;
+            record.proxied = Some(options.proxied);
+            return (record.id.clone(), changed);
+        }
+
+        info!("Mock: creating {} record {} with IP {}", record_type, host, ip);
+        (self.create(host, record_type, ip.to_string(), options), true)
+    }
+
+    fn create(&mut self, host: &str, record_type: String, content: String, options: &RecordOptions) -> String {
+        self.next_id += 1;
+        let id = format!("mock-{}", self.next_id);
+        self.records.push(RecordInfo {
+            id: id.clone(),
+            record_type,
+            name: host.to_string(),
+            content,
+            ttl: options.ttl,
+            proxied: Some(options.proxied),
+        });
+        id
+    }
+}
@@ -1,8 +1,444 @@
 pub mod cloudflare;
+pub mod mock;
 
-#[derive(Debug)]
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::config::{Config, ProviderConfig, ServerConfig};
+
+#[derive(Debug, Clone)]
 pub struct DnsUpdateResult {
     pub success: bool,
+    pub change: ChangeKind,
+    /// The record's value afterwards, as in a zone file (IPs comma separated); `None` once deleted
+    pub new: Option<String>,
+    /// Human-readable summary for logs and clients; match on `change` instead
     pub message: String,
     pub record_id: Option<String>,
 }
+
+/// What an update did to the provider's records
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ChangeKind {
+    Created,
+    Updated {
+        /// The value it replaced, in the form of `new`, when known
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        old: Option<String>,
+    },
+    NoChange,
+    Deleted,
+}
+
+impl ChangeKind {
+    /// Whether the records were created, changed or deleted, as opposed to already up to date
+    pub fn is_change(&self) -> bool {
+        *self != ChangeKind::NoChange
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Updated { .. } => "updated",
+            ChangeKind::NoChange => "no_change",
+            ChangeKind::Deleted => "deleted",
+        }
+    }
+
+    /// The value replaced by an update, if known.
+    pub fn old(&self) -> Option<&str> {
+        match self {
+            ChangeKind::Updated { old } => old.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordInfo {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub record_type: String,
+    pub name: String,
+    /// As in a zone file, so SRV and MX content starts with the priority
+    pub content: String,
+    pub ttl: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxied: Option<bool>,
+}
+
+/// Record type of an update: derived from the IP family, CNAME for hosts
+/// configured so, SRV and MX from their own endpoints, or PTR for reverse DNS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RecordType {
+    A,
+    Aaaa,
+    Cname,
+    Srv,
+    Mx,
+    Ptr,
+}
+
+impl RecordType {
+    pub fn for_ip(ip: &IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(_) => RecordType::A,
+            IpAddr::V6(_) => RecordType::Aaaa,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RecordType::A => "A",
+            RecordType::Aaaa => "AAAA",
+            RecordType::Cname => "CNAME",
+            RecordType::Srv => "SRV",
+            RecordType::Mx => "MX",
+            RecordType::Ptr => "PTR",
+        }
+    }
+}
+
+/// The reverse DNS name of `ip`, e.g. `4.3.2.1.in-addr.arpa` for 1.2.3.4.
+pub fn reverse_name(ip: &IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, d] = ip.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        }
+        IpAddr::V6(ip) => {
+            let mut name = String::with_capacity(72);
+            for byte in ip.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", byte & 0xf, byte >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        }
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for RecordType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// What a provider supports, shown on `/status`. The API checks requests
+/// against it and answers `400 unsupported_operation` instead of calling
+/// the provider with something it would reject.
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    pub record_types: &'static [RecordType],
+    /// Lowest and highest TTL in seconds, apart from `auto_ttl`
+    pub min_ttl: u32,
+    pub max_ttl: u32,
+    /// Whether TTL 1 means "automatic"
+    pub auto_ttl: bool,
+    /// Whether records can be proxied
+    pub proxied: bool,
+    /// Whether records can be deleted, as `offline = "delete"` does
+    pub delete: bool,
+    /// Whether a host can have several A/AAAA records (`/ddns/.../ip1,ip2`)
+    pub record_sets: bool,
+}
+
+impl Capabilities {
+    pub fn supports(&self, record_type: RecordType) -> bool {
+        self.record_types.contains(&record_type)
+    }
+
+    /// Why `ttl` can't be used, if it can't.
+    pub fn check_ttl(&self, ttl: u32) -> Option<String> {
+        if (self.auto_ttl && ttl == 1) || (self.min_ttl..=self.max_ttl).contains(&ttl) {
+            return None;
+        }
+        Some(format!(
+            "TTL {} is out of range ({} to {} seconds{})",
+            ttl,
+            self.min_ttl,
+            self.max_ttl,
+            if self.auto_ttl { ", or 1 for automatic" } else { "" }
+        ))
+    }
+}
+
+/// Content of an SRV record
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SrvData {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    /// Host offering the service
+    pub target: String,
+}
+
+/// Zone file form, e.g. `10 5 25565 home.example.com`
+impl fmt::Display for SrvData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.priority, self.weight, self.port, self.target)
+    }
+}
+
+/// Content of an MX record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MxData {
+    pub priority: u16,
+    /// Host accepting mail for the domain
+    pub target: String,
+}
+
+/// Zone file form, e.g. `10 mail.example.com`
+impl fmt::Display for MxData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.priority, self.target)
+    }
+}
+
+/// Provider failures the API reports with a specific status code. Providers
+/// return these inside their `anyhow::Error`s; anything else is a generic failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderError {
+    /// The host does not belong to any zone the provider manages
+    InvalidHost(String),
+    /// The host has no record to update and `create_missing` is off
+    RecordNotFound(String),
+    /// The provider rejected the configured credentials
+    Auth(String),
+    /// The provider is throttling requests
+    RateLimited { message: String, retry_after: Option<Duration> },
+    /// The provider API is down or overloaded
+    Unavailable { message: String, retry_after: Option<Duration> },
+    /// The record changed elsewhere since it was last written here, and
+    /// `on_conflict = "refuse"` keeps it
+    Conflict(String),
+    /// Any other error reported by the provider API
+    Api(String),
+}
+
+impl ProviderError {
+    /// Classifies an unsuccessful API response by its HTTP status.
+    pub fn from_status(status: reqwest::StatusCode, retry_after: Option<Duration>, message: String) -> Self {
+        match status.as_u16() {
+            401 | 403 => ProviderError::Auth(message),
+            429 => ProviderError::RateLimited { message, retry_after },
+            500..=599 => ProviderError::Unavailable { message, retry_after },
+            _ => ProviderError::Api(message),
+        }
+    }
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderError::InvalidHost(message)
+            | ProviderError::RecordNotFound(message)
+            | ProviderError::Auth(message)
+            | ProviderError::RateLimited { message, .. }
+            | ProviderError::Unavailable { message, .. }
+            | ProviderError::Conflict(message)
+            | ProviderError::Api(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+/// Finds the provider error in a chain; timeouts and connection failures
+/// count as the provider being unavailable.
+pub fn classify(e: &anyhow::Error) -> Option<ProviderError> {
+    e.chain().find_map(|cause| {
+        if let Some(error) = cause.downcast_ref::<ProviderError>() {
+            return Some(error.clone());
+        }
+        let error = cause.downcast_ref::<reqwest::Error>()?;
+        (error.is_timeout() || error.is_connect()).then(|| ProviderError::Unavailable {
+            message: error.to_string(),
+            retry_after: None,
+        })
+    })
+}
+
+/// A copy of `e` for each of several updates that failed together, keeping
+/// its classification.
+pub(crate) fn shared_error(e: &anyhow::Error) -> anyhow::Error {
+    match classify(e) {
+        Some(error) => error.into(),
+        None => anyhow::anyhow!("{:#}", e),
+    }
+}
+
+/// The error for an update that would create `host`'s first record of
+/// `record_type` while `create_missing = false`.
+pub(crate) fn missing_record(host: &str, record_type: RecordType) -> anyhow::Error {
+    ProviderError::RecordNotFound(format!("{} has no {} record to update (create_missing = false)", host, record_type)).into()
+}
+
+/// Parses a `Retry-After` header given in seconds.
+pub fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Record attributes applied when creating or updating a record
+#[derive(Debug, Clone)]
+pub struct RecordOptions {
+    /// TTL in seconds (1 = provider automatic)
+    pub ttl: u32,
+    /// Whether the record is proxied (Cloudflare only)
+    pub proxied: bool,
+}
+
+/// One host's address in [`Provider::update_records`]
+#[derive(Debug, Clone)]
+pub struct AddressUpdate {
+    pub host: String,
+    pub ip: IpAddr,
+    pub options: RecordOptions,
+}
+
+/// A DNS provider capable of managing DDNS records.
+#[async_trait]
+pub trait Provider: Send + Sync {
+    /// Creates or updates the A/AAAA record for `host` so that it points at `ip`.
+    async fn update_record(&self, host: &str, ip: IpAddr, options: &RecordOptions) -> Result<DnsUpdateResult>;
+
+    /// [`Self::update_record`] for several hosts at once, e.g. after a
+    /// delegated prefix changed, with one result per update in order.
+    /// Providers with a batch API apply them in as few calls as they can.
+    async fn update_records(&self, updates: &[AddressUpdate]) -> Vec<Result<DnsUpdateResult>> {
+        let mut results = Vec::with_capacity(updates.len());
+        for update in updates {
+            results.push(self.update_record(&update.host, update.ip, &update.options).await);
+        }
+        results
+    }
+
+    /// Creates or updates the CNAME record for `host` so that it points at `target`.
+    async fn update_cname(&self, host: &str, _target: &str, _options: &RecordOptions) -> Result<DnsUpdateResult> {
+        anyhow::bail!("CNAME records are not supported by this provider (host {})", host)
+    }
+
+    /// Creates or updates the SRV record `name`, e.g. `_minecraft._tcp.example.com`.
+    async fn update_srv(&self, name: &str, _srv: &SrvData, _options: &RecordOptions) -> Result<DnsUpdateResult> {
+        anyhow::bail!("SRV records are not supported by this provider (name {})", name)
+    }
+
+    /// Creates or updates the MX record of `host`.
+    async fn update_mx(&self, host: &str, _mx: &MxData, _options: &RecordOptions) -> Result<DnsUpdateResult> {
+        anyhow::bail!("MX records are not supported by this provider (host {})", host)
+    }
+
+    /// Creates or updates the PTR record of `ip` so that it points back at `host`.
+    async fn update_ptr(&self, ip: IpAddr, host: &str, _options: &RecordOptions) -> Result<DnsUpdateResult> {
+        anyhow::bail!("PTR records are not supported by this provider (IP {}, host {})", ip, host)
+    }
+
+    /// Makes the A/AAAA records of `host` exactly `ips`, creating missing
+    /// records and deleting stale ones. Only the address families present in
+    /// `ips` are touched.
+    async fn update_record_set(&self, host: &str, _ips: &[IpAddr], _options: &RecordOptions) -> Result<DnsUpdateResult> {
+        anyhow::bail!("Multiple records per host are not supported by this provider (host {})", host)
+    }
+
+    /// Deletes the records of `record_type` (A or AAAA) for `host`, e.g. when it goes offline.
+    async fn delete_records(&self, host: &str, _record_type: RecordType) -> Result<DnsUpdateResult> {
+        anyhow::bail!("Deleting records is not supported by this provider (host {})", host)
+    }
+
+    /// What the provider supports.
+    fn capabilities(&self) -> Capabilities;
+
+    /// Drops cached details of `host`'s records, after they changed outside this service.
+    fn forget(&self, _host: &str) {}
+
+    /// Lists the records relevant to DDNS (A/AAAA/CNAME/SRV/MX) in the provider's zone.
+    async fn list_records(&self) -> Result<Vec<RecordInfo>>;
+
+    /// Checks that the configured credentials are valid, returning a short status description.
+    async fn verify(&self) -> Result<String>;
+
+    /// When the configured credentials expire, `None` if they don't (or the
+    /// provider can't tell). Fails when they no longer work.
+    async fn credentials_expiry(&self) -> Result<Option<OffsetDateTime>> {
+        Ok(None)
+    }
+
+    /// Internal state exposed on the debug endpoint, for providers that have any.
+    fn debug_state(&self) -> Option<serde_json::Value> {
+        None
+    }
+}
+
+/// Builds an HTTP client for provider API calls. Timeouts come from the
+/// server config unless the given provider overrides them.
+pub fn build_client(server: &ServerConfig, provider: Option<&ProviderConfig>) -> Result<Client> {
+    let timeout = provider
+        .and_then(|p| p.timeout_secs)
+        .unwrap_or(server.timeout_secs);
+    let connect_timeout = provider
+        .and_then(|p| p.connect_timeout_secs)
+        .unwrap_or(server.connect_timeout_secs);
+
+    Client::builder()
+        .timeout(Duration::from_secs(timeout))
+        .connect_timeout(Duration::from_secs(connect_timeout))
+        .pool_idle_timeout(Duration::from_secs(server.pool_idle_timeout_secs))
+        .pool_max_idle_per_host(server.pool_max_idle_per_host)
+        .build()
+        .context("Failed to build HTTP client")
+}
+
+/// Provider types accepted in the `type` field
+pub const PROVIDER_TYPES: &[&str] = &["cloudflare", "mock"];
+
+/// Creates the provider instance for a config entry, or `None` if the
+/// provider type is not supported.
+pub fn create(config: &ProviderConfig, client: Client) -> Option<Arc<dyn Provider>> {
+    match config.provider_type.as_str() {
+        "cloudflare" => Some(Arc::new(cloudflare::Cloudflare::new(config.clone(), client))),
+        "mock" => Some(Arc::new(mock::Mock::new(config.clone()))),
+        _ => None,
+    }
+}
+
+/// Creates instances for every supported provider in the config, keyed by name.
+/// Providers without timeout overrides share one client (and its connection pool).
+pub fn create_all(config: &Config) -> Result<HashMap<String, Arc<dyn Provider>>> {
+    let shared_client = build_client(&config.server, None)?;
+    let mut providers = HashMap::new();
+
+    for provider_config in &config.providers {
+        let client = if provider_config.has_timeout_overrides() {
+            build_client(&config.server, Some(provider_config))?
+        } else {
+            shared_client.clone()
+        };
+        if let Some(instance) = create(provider_config, client) {
+            providers.insert(provider_config.name.clone(), instance);
+        }
+    }
+
+    Ok(providers)
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::debug;
+
+use crate::breaker::CircuitBreaker;
+use crate::provider::{shared_error, ChangeKind, DnsUpdateResult, RecordOptions};
+use crate::shared::SharedState;
+
+/// Above this many tracked hosts, idle entries are dropped
+const MAX_IDLE_HOSTS: usize = 10_000;
+
+/// Serializes updates to the same host, so concurrent requests cannot race
+/// (e.g. both creating a record), and lets requests that queued behind an
+/// identical update reuse its result instead of repeating it. Updates of
+/// different hosts wait for a free slot under the global and per-provider
+/// concurrency limits. With `[redis]` the same goes for other instances.
+/// Providers whose circuit is open are not called at all.
+pub struct UpdateQueue {
+    hosts: Mutex<HashMap<String, Arc<HostSlot>>>,
+    /// Provider calls in flight across all providers (`None` when unlimited)
+    global: Option<Semaphore>,
+    /// Provider calls in flight per provider name
+    providers: HashMap<String, Semaphore>,
+    shared: Arc<SharedState>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+#[derive(Default)]
+struct HostSlot {
+    /// Number of updates finished on this host
+    completed: AtomicU64,
+    /// Held for the duration of an update; the last successful one
+    last: tokio::sync::Mutex<Option<Completed>>,
+}
+
+struct Completed {
+    /// IP address or CNAME target written
+    content: String,
+    ttl: u32,
+    proxied: bool,
+    result: DnsUpdateResult,
+}
+
+impl UpdateQueue {
+    /// `global` and the values of `providers` cap concurrent provider calls, 0 meaning unlimited.
+    pub fn new(global: usize, providers: HashMap<String, usize>, shared: Arc<SharedState>, breaker: Arc<CircuitBreaker>) -> Self {
+        Self {
+            hosts: Mutex::default(),
+            global: (global > 0).then(|| Semaphore::new(global)),
+            providers: providers
+                .into_iter()
+                .filter(|(_, limit)| *limit > 0)
+                .map(|(name, limit)| (name, Semaphore::new(limit)))
+                .collect(),
+            shared,
+            breaker,
+        }
+    }
+
+    /// Runs `update` once no other update for `provider`/`host` is in flight.
+    /// `content` is the IP address or CNAME target the update writes.
+    pub async fn run<F, Fut>(&self, provider: &str, host: &str, content: &str, options: &RecordOptions, update: F) -> Result<DnsUpdateResult>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<DnsUpdateResult>>,
+    {
+        self.breaker.allow(provider)?;
+        let slot = self.slot(&format!("{}/{}", provider, host.to_ascii_lowercase()));
+
+        let seen = slot.completed.load(Ordering::Acquire);
+        let mut last = slot.last.lock().await;
+
+        // Another update finished while we waited; if it did exactly what we
+        // were about to do, its result is ours too
+        if slot.completed.load(Ordering::Acquire) != seen {
+            if let Some(ref done) = *last {
+                if done.content == content && done.ttl == options.ttl && done.proxied == options.proxied {
+                    debug!("Coalesced update of {} to {} with a concurrent request", host, content);
+                    // The change itself belongs to the request that made it
+                    return Ok(DnsUpdateResult {
+                        change: ChangeKind::NoChange,
+                        ..done.result.clone()
+                    });
+                }
+            }
+        }
+
+        // Permits cover only the provider call, not the wait for this host
+        let _provider = permit(self.providers.get(provider), provider).await;
+        let _global = permit(self.global.as_ref(), provider).await;
+        let result = self.shared.exclusive(provider, host, content, options, update).await;
+        self.breaker.record(provider, &result);
+        *last = result.as_ref().ok().map(|result| Completed {
+            content: content.to_string(),
+            ttl: options.ttl,
+            proxied: options.proxied,
+            result: result.clone(),
+        });
+        slot.completed.fetch_add(1, Ordering::Release);
+
+        result
+    }
+
+    /// Runs `update`, which changes the records of all `hosts` in one go,
+    /// once no other update for any of them is in flight. Unlike [`Self::run`]
+    /// there is nothing to coalesce with, and other instances are not waited for.
+    pub async fn run_batch<F, Fut>(&self, provider: &str, hosts: &[&str], update: F) -> Vec<Result<DnsUpdateResult>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Vec<Result<DnsUpdateResult>>>,
+    {
+        if let Err(e) = self.breaker.allow(provider) {
+            return hosts.iter().map(|_| Err(shared_error(&e))).collect();
+        }
+
+        // Always locked in the same order, so two batches can't wait on each other
+        let mut keys: Vec<String> = hosts.iter().map(|host| format!("{}/{}", provider, host.to_ascii_lowercase())).collect();
+        keys.sort();
+        keys.dedup();
+        let slots: Vec<Arc<HostSlot>> = keys.iter().map(|key| self.slot(key)).collect();
+        let mut held = Vec::with_capacity(slots.len());
+        for slot in &slots {
+            held.push(slot.last.lock().await);
+        }
+
+        let _provider = permit(self.providers.get(provider), provider).await;
+        let _global = permit(self.global.as_ref(), provider).await;
+        let results = update().await;
+        // One call, so one outcome: it worked if any update went through
+        if let Some(result) = results.iter().find(|result| result.is_ok()).or(results.first()) {
+            self.breaker.record(provider, result);
+        }
+
+        // Later updates of these hosts must not reuse a result from before the batch
+        for last in &mut held {
+            **last = None;
+        }
+        for slot in &slots {
+            slot.completed.fetch_add(1, Ordering::Release);
+        }
+        results
+    }
+
+    fn slot(&self, key: &str) -> Arc<HostSlot> {
+        let mut hosts = self.hosts.lock().unwrap();
+        if hosts.len() > MAX_IDLE_HOSTS {
+            // Only the map holds idle slots
+            hosts.retain(|_, slot| Arc::strong_count(slot) > 1);
+        }
+        hosts.entry(key.to_string()).or_default().clone()
+    }
+}
+
+async fn permit<'a>(semaphore: Option<&'a Semaphore>, provider: &str) -> Option<SemaphorePermit<'a>> {
+    let semaphore = semaphore?;
+    if semaphore.available_permits() == 0 {
+        debug!("Update for {} waits for a free slot", provider);
+    }
+    // The semaphores are never closed
+    semaphore.acquire().await.ok()
+}
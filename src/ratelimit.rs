@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of tracked buckets above which idle buckets are pruned
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Token-bucket rate limiter keyed by an arbitrary string (client IP, access key, ...)
+pub struct RateLimiter {
+    /// Maximum burst size, equal to the per-minute allowance
+    capacity: f64,
+    /// Tokens added per second
+    refill_per_sec: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `per_minute` requests per key, or `None` if disabled (0).
+    pub fn per_minute(per_minute: u32) -> Option<Self> {
+        if per_minute == 0 {
+            return None;
+        }
+
+        Some(Self {
+            capacity: per_minute as f64,
+            refill_per_sec: per_minute as f64 / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Takes one token for `key`, returning how long to wait if the bucket is empty.
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > PRUNE_THRESHOLD {
+            self.prune(&mut buckets, now);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            last_refill: now,
+        });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / self.refill_per_sec;
+            Err(Duration::from_secs_f64(wait))
+        }
+    }
+
+    /// Drops buckets that would have refilled completely, as they carry no state.
+    fn prune(&self, buckets: &mut HashMap<String, Bucket>, now: Instant) {
+        let full_refill = Duration::from_secs_f64(self.capacity / self.refill_per_sec);
+        buckets.retain(|_, bucket| now.duration_since(bucket.last_refill) < full_refill);
+    }
+}
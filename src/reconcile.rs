@@ -0,0 +1,116 @@
+//! Background task that restores records changed outside this service.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time::MissedTickBehavior;
+use tracing::{info, info_span, warn, Instrument};
+
+use crate::api::AppState;
+use crate::provider::{RecordInfo, RecordOptions, RecordType};
+
+/// Starts the reconciliation loop when `[reconcile]` is enabled.
+pub fn spawn(state: Arc<AppState>) {
+    if !state.config.reconcile.enabled {
+        return;
+    }
+
+    let interval = Duration::from_secs(state.config.reconcile.interval_secs);
+    info!("Reconciling managed records every {}s", interval.as_secs());
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // The first tick completes immediately; nothing has been pushed yet
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            // Maintenance holds back every provider call, repairs included
+            if !state.leader.is_leader().await || state.maintenance.is_active() {
+                continue;
+            }
+            reconcile(&state).instrument(info_span!("reconcile")).await;
+        }
+    });
+}
+
+/// Compares every host with a known IP against its provider's records and
+/// writes the IP again where the record no longer holds it.
+async fn reconcile(state: &AppState) {
+    // One listing per provider and pass; `None` if listing failed
+    let mut listings: HashMap<String, Option<Vec<RecordInfo>>> = HashMap::new();
+
+    for (provider_name, host, ip) in state.status.known_ips() {
+        let (Some(provider), Some(provider_config)) =
+            (state.providers.get(&provider_name), state.config.get_provider(&provider_name))
+        else {
+            continue;
+        };
+
+        if !listings.contains_key(&provider_name) {
+            let records = match provider.list_records().await {
+                Ok(records) => Some(records),
+                Err(e) => {
+                    warn!(
+                        "Failed to list records of {} for reconciliation: {}",
+                        provider_name,
+                        state.redactor.redact(&format!("{:#}", e))
+                    );
+                    None
+                }
+            };
+            listings.insert(provider_name.clone(), records);
+        }
+        let Some(records) = &listings[&provider_name] else {
+            continue;
+        };
+
+        let record_type = RecordType::for_ip(&ip).as_str();
+        let current: Vec<&RecordInfo> = records
+            .iter()
+            .filter(|r| r.record_type == record_type && r.name.eq_ignore_ascii_case(&host))
+            .collect();
+        if current.iter().any(|r| r.content.parse::<IpAddr>().ok() == Some(ip)) {
+            continue;
+        }
+
+        let found = current.first().and_then(|r| r.content.parse::<IpAddr>().ok());
+        match current.first() {
+            Some(record) => warn!("Record {} ({}) was changed to {}, restoring IP {}", host, provider_name, record.content, ip),
+            None => warn!("Record {} ({}) was deleted, restoring IP {}", host, provider_name, ip),
+        }
+
+        let settings = provider_config.host_settings(&host);
+        let options = RecordOptions {
+            ttl: settings.ttl,
+            proxied: settings.proxied,
+        };
+        provider.forget(&host);
+        let mut result = state
+            .updates
+            .run(&provider_name, &host, &ip.to_string(), &options, || {
+                let span = info_span!("provider.update_record", provider = %provider_name, host = %host, %ip);
+                provider.update_record(&host, ip, &options).instrument(span)
+            })
+            .await;
+        state.messages.localize(&provider_name, &host, &mut result);
+        let old = current.first().map(|r| r.content.clone());
+        state.audit("reconcile", &provider_name, &host, old, ip.to_string(), &result);
+
+        match result {
+            Ok(result) => {
+                let message = format!("Restored record changed outside ddns-rust: {}", result.message);
+                info!("{}", message);
+                state.status.record_success(&provider_name, &host, ip, None, &result.change, &message);
+                state.notifier.record_drift(&provider_name, &host, found, ip);
+            }
+            Err(e) => {
+                let error = state.redactor.redact(&e.to_string());
+                warn!("Failed to restore record {}: {}", host, state.redactor.redact(&format!("{:#}", e)));
+                state.status.record_failure(&provider_name, &host, ip, &error);
+                state.notifier.record_failure(&provider_name, &host, ip, &error);
+            }
+        }
+    }
+}
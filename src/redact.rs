@@ -0,0 +1,87 @@
+//! Keeps credentials out of logs, status reports and error responses.
+
+use crate::config::Config;
+
+/// Written in place of a removed secret
+const MASK: &str = "[REDACTED]";
+
+/// Query parameters whose values are never logged
+const SECRET_PARAMS: &[&str] = &["key", "token", "access_token", "api_key", "password", "signature"];
+
+/// Shorter values are too likely to appear in ordinary text to be masked
+const MIN_SECRET_LEN: usize = 4;
+
+/// Masks every secret from the configuration wherever it appears in a message
+pub struct Redactor {
+    secrets: Vec<String>,
+}
+
+impl Redactor {
+    pub fn new(config: &Config) -> Self {
+        let mut secrets: Vec<String> = Vec::new();
+        for provider in &config.providers {
+            secrets.push(provider.api_key.clone());
+            secrets.extend(provider.key.clone());
+            secrets.extend(provider.hosts.iter().filter_map(|h| h.key.clone()));
+        }
+        secrets.extend(config.auth.tokens.iter().filter_map(|t| t.token.clone()));
+        secrets.extend(config.ui.password.clone());
+        secrets.extend(config.audit.key.clone());
+        // Anyone with a ping URL can report on the job's behalf
+        for job in &config.poll {
+            secrets.extend(job.ping_url.clone());
+            secrets.extend(job.ping_failure_url.clone());
+        }
+
+        let notifications = &config.notifications;
+        if let Some(ref telegram) = notifications.telegram {
+            secrets.push(telegram.bot_token.clone());
+        }
+        for webhook in [&notifications.discord, &notifications.slack].into_iter().flatten() {
+            secrets.push(webhook.webhook_url.clone());
+        }
+        if let Some(ref email) = notifications.email {
+            secrets.extend(email.password.clone());
+        }
+        if let Some(ref ntfy) = notifications.ntfy {
+            secrets.extend(ntfy.token.clone());
+        }
+        if let Some(ref gotify) = notifications.gotify {
+            secrets.push(gotify.token.clone());
+        }
+        if let Some(ref mqtt) = notifications.mqtt {
+            secrets.extend(mqtt.password.clone());
+        }
+
+        secrets.retain(|s| s.len() >= MIN_SECRET_LEN);
+        // Longest first, so a secret containing another is masked whole
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        secrets.dedup();
+
+        Self { secrets }
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for secret in &self.secrets {
+            if text.contains(secret.as_str()) {
+                text = text.replace(secret.as_str(), MASK);
+            }
+        }
+        text
+    }
+}
+
+/// Replaces the values of credential parameters (`key`, `token`, ...) in a query string.
+pub fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if SECRET_PARAMS.iter().any(|p| p.eq_ignore_ascii_case(name)) => {
+                format!("{}={}", name, MASK)
+            }
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
@@ -0,0 +1,333 @@
+//! `[retry_queue]`: updates that failed because their provider was down are
+//! kept in an SQLite database and retried in the background, with a doubling
+//! delay, until they succeed or expire. A later update of the same host and
+//! record type replaces the waiting one, and any update that reaches the
+//! provider without a transient failure settles it.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::{info, info_span, Instrument};
+
+use crate::api::{apply_update, AppState};
+use crate::config::RetryQueueConfig;
+use crate::provider::RecordType;
+
+/// How often the queue is checked for due updates
+const RETRY_TICK: Duration = Duration::from_secs(1);
+
+/// A failed update waiting for its next attempt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub provider: String,
+    pub host: String,
+    pub ip: IpAddr,
+    pub ttl: Option<u32>,
+    /// Attempts made so far, the first one included
+    pub attempts: u32,
+}
+
+pub struct RetryQueue {
+    #[cfg(feature = "sqlite")]
+    db: Option<db::Db>,
+}
+
+impl RetryQueue {
+    /// Opens (or creates) `[retry_queue].path`, if configured.
+    pub fn open(config: &RetryQueueConfig) -> Result<Self> {
+        #[cfg(feature = "sqlite")]
+        return Ok(Self {
+            db: match config.path {
+                Some(ref path) => Some(db::Db::open(path, config)?),
+                None => None,
+            },
+        });
+
+        #[cfg(not(feature = "sqlite"))]
+        {
+            // Validation refuses a path without the feature
+            let _ = config;
+            Ok(Self {})
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        #[cfg(feature = "sqlite")]
+        return self.db.is_some();
+
+        #[cfg(not(feature = "sqlite"))]
+        false
+    }
+
+    /// Queues a failed update of `host`, replacing one already waiting for
+    /// the same record type. Returns the delay until it is retried, `None` if
+    /// the queue is disabled.
+    pub fn push(&self, provider: &str, host: &str, ip: IpAddr, ttl: Option<u32>, error: &str) -> Option<Duration> {
+        #[cfg(feature = "sqlite")]
+        if let Some(ref db) = self.db {
+            return db.push(provider, host, ip, ttl, error);
+        }
+
+        let _ = (provider, host, ip, ttl, error);
+        None
+    }
+
+    /// Drops the update waiting for `host`'s `record_type` record, if any.
+    pub fn remove(&self, provider: &str, host: &str, record_type: RecordType) {
+        #[cfg(feature = "sqlite")]
+        if let Some(ref db) = self.db {
+            db.remove(provider, host, record_type);
+        }
+
+        let _ = (provider, host, record_type);
+    }
+
+    /// The updates due for another attempt, each pushed back by its next
+    /// delay so that one which doesn't reach the provider isn't retried on
+    /// every tick. Updates past `expire_secs` are dropped.
+    pub fn take_due(&self) -> Vec<Job> {
+        #[cfg(feature = "sqlite")]
+        if let Some(ref db) = self.db {
+            return db.take_due();
+        }
+
+        Vec::new()
+    }
+
+    /// Waiting updates per provider, for `/metrics`.
+    pub fn depth(&self) -> Vec<(String, u64)> {
+        #[cfg(feature = "sqlite")]
+        if let Some(ref db) = self.db {
+            return db.depth();
+        }
+
+        Vec::new()
+    }
+}
+
+/// Retries queued updates as they become due.
+pub fn spawn(state: Arc<AppState>) {
+    if !state.retry_queue.is_enabled() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(RETRY_TICK);
+        loop {
+            ticker.tick().await;
+            for job in state.retry_queue.take_due() {
+                let Some(provider_config) = state.config.get_provider(&job.provider) else {
+                    state.retry_queue.remove(&job.provider, &job.host, RecordType::for_ip(&job.ip));
+                    continue;
+                };
+                info!("Retrying failed update of {} to {} (attempt {})", job.host, job.ip, job.attempts + 1);
+                // Another transient failure queues the update again
+                let _ = apply_update(&state, provider_config, &job.host, job.ip, job.ttl)
+                    .instrument(info_span!("retry", provider = %job.provider, host = %job.host))
+                    .await;
+            }
+        }
+    });
+}
+
+#[cfg(feature = "sqlite")]
+mod db {
+    use std::net::IpAddr;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use anyhow::{Context, Result};
+    use rusqlite::{params, Connection, OptionalExtension};
+    use time::OffsetDateTime;
+    use tracing::warn;
+
+    use super::Job;
+    use crate::config::RetryQueueConfig;
+    use crate::provider::RecordType;
+
+    /// Longest delay between two attempts
+    const MAX_DELAY: Duration = Duration::from_secs(60 * 60);
+
+    pub struct Db {
+        path: String,
+        connection: Mutex<Connection>,
+        initial_delay: Duration,
+        expire: Duration,
+    }
+
+    impl Db {
+        pub fn open(path: &str, config: &RetryQueueConfig) -> Result<Self> {
+            let connection = Connection::open(path).with_context(|| format!("Failed to open retry queue {}", path))?;
+            connection
+                .execute_batch(
+                    "CREATE TABLE IF NOT EXISTS jobs (
+                        provider TEXT NOT NULL,
+                        host TEXT NOT NULL,
+                        record_type TEXT NOT NULL,
+                        ip TEXT NOT NULL,
+                        ttl INTEGER,
+                        attempts INTEGER NOT NULL,
+                        first_failed INTEGER NOT NULL,
+                        next_attempt INTEGER NOT NULL,
+                        last_error TEXT NOT NULL,
+                        PRIMARY KEY (provider, host, record_type)
+                    )",
+                )
+                .with_context(|| format!("Failed to set up retry queue {}", path))?;
+            Ok(Self {
+                path: path.to_string(),
+                connection: Mutex::new(connection),
+                initial_delay: Duration::from_secs(config.initial_delay_secs),
+                expire: Duration::from_secs(config.expire_secs),
+            })
+        }
+
+        /// Delay after the `attempts`th failed attempt.
+        fn delay(&self, attempts: u32) -> Duration {
+            self.initial_delay
+                .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+                .min(MAX_DELAY.max(self.initial_delay))
+        }
+
+        pub fn push(&self, provider: &str, host: &str, ip: IpAddr, ttl: Option<u32>, error: &str) -> Option<Duration> {
+            let connection = self.connection.lock().unwrap();
+            let record_type = RecordType::for_ip(&ip).to_string();
+            let result = (|| {
+                let attempts: u32 = connection
+                    .query_row(
+                        "SELECT attempts FROM jobs WHERE provider = ?1 AND host = ?2 AND record_type = ?3",
+                        params![provider, host, record_type],
+                        |row| row.get(0),
+                    )
+                    .optional()?
+                    .unwrap_or(0)
+                    + 1;
+                let delay = self.delay(attempts);
+                let now = now();
+                // The first failure is kept, so a host failing for long enough expires
+                connection.execute(
+                    "INSERT INTO jobs (provider, host, record_type, ip, ttl, attempts, first_failed, next_attempt, last_error)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                     ON CONFLICT (provider, host, record_type) DO UPDATE SET
+                        ip = excluded.ip, ttl = excluded.ttl, attempts = excluded.attempts,
+                        next_attempt = excluded.next_attempt, last_error = excluded.last_error",
+                    params![
+                        provider,
+                        host,
+                        record_type,
+                        ip.to_string(),
+                        ttl,
+                        attempts,
+                        now,
+                        now + delay.as_secs() as i64,
+                        error
+                    ],
+                )?;
+                Ok::<_, rusqlite::Error>(delay)
+            })();
+
+            match result {
+                Ok(delay) => Some(delay),
+                Err(e) => {
+                    warn!("Failed to queue update of {} in {}: {}", host, self.path, e);
+                    None
+                }
+            }
+        }
+
+        pub fn remove(&self, provider: &str, host: &str, record_type: RecordType) {
+            let connection = self.connection.lock().unwrap();
+            if let Err(e) = connection.execute(
+                "DELETE FROM jobs WHERE provider = ?1 AND host = ?2 AND record_type = ?3",
+                params![provider, host, record_type.to_string()],
+            ) {
+                warn!("Failed to remove update of {} from {}: {}", host, self.path, e);
+            }
+        }
+
+        pub fn take_due(&self) -> Vec<Job> {
+            let mut connection = self.connection.lock().unwrap();
+            let now = now();
+            let result = (|| {
+                let tx = connection.transaction()?;
+                let expired = {
+                    let mut statement = tx.prepare(
+                        "DELETE FROM jobs WHERE first_failed <= ?1 RETURNING host, ip, attempts, last_error",
+                    )?;
+                    let rows = statement.query_map(params![now - self.expire.as_secs() as i64], |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, u32>(2)?, row.get::<_, String>(3)?))
+                    })?;
+                    rows.collect::<Result<Vec<_>, _>>()?
+                };
+                for (host, ip, attempts, error) in expired {
+                    warn!("Giving up on the update of {} to {} after {} attempt(s): {}", host, ip, attempts, error);
+                }
+
+                let due = {
+                    let mut statement = tx.prepare(
+                        "SELECT provider, host, ip, ttl, attempts FROM jobs WHERE next_attempt <= ?1 ORDER BY next_attempt",
+                    )?;
+                    let rows = statement.query_map(params![now], |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, String>(2)?,
+                            row.get::<_, Option<u32>>(3)?,
+                            row.get::<_, u32>(4)?,
+                        ))
+                    })?;
+                    rows.collect::<Result<Vec<_>, _>>()?
+                };
+
+                let mut jobs = Vec::new();
+                for (provider, host, ip, ttl, attempts) in due {
+                    let Ok(ip) = ip.parse::<IpAddr>() else {
+                        tx.execute(
+                            "DELETE FROM jobs WHERE provider = ?1 AND host = ?2 AND ip = ?3",
+                            params![provider, host, ip],
+                        )?;
+                        continue;
+                    };
+                    tx.execute(
+                        "UPDATE jobs SET next_attempt = ?1 WHERE provider = ?2 AND host = ?3 AND record_type = ?4",
+                        params![
+                            now + self.delay(attempts + 1).as_secs() as i64,
+                            provider,
+                            host,
+                            RecordType::for_ip(&ip).to_string()
+                        ],
+                    )?;
+                    jobs.push(Job { provider, host, ip, ttl, attempts });
+                }
+                tx.commit()?;
+                Ok::<_, rusqlite::Error>(jobs)
+            })();
+
+            result.unwrap_or_else(|e| {
+                warn!("Failed to read retry queue {}: {}", self.path, e);
+                Vec::new()
+            })
+        }
+
+        pub fn depth(&self) -> Vec<(String, u64)> {
+            let connection = self.connection.lock().unwrap();
+            let result = (|| {
+                let mut statement =
+                    connection.prepare("SELECT provider, COUNT(*) FROM jobs GROUP BY provider ORDER BY provider")?;
+                let rows = statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?)))?;
+                rows.collect::<Result<Vec<_>, _>>()
+            })();
+
+            result.unwrap_or_else(|e| {
+                warn!("Failed to read retry queue {}: {}", self.path, e);
+                Vec::new()
+            })
+        }
+    }
+
+    fn now() -> i64 {
+        OffsetDateTime::now_utc().unix_timestamp()
+    }
+}
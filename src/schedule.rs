@@ -0,0 +1,146 @@
+//! When `[[poll]]` jobs run: a fixed interval or a five-field cron expression.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use time::{Date, OffsetDateTime, Time};
+
+/// Give up looking for the next match this far ahead (e.g. `0 0 30 2 *`)
+const MAX_YEARS_AHEAD: i32 = 5;
+
+/// How often a job runs
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// Every fixed interval, the first run right away
+    Every(Duration),
+    Cron(Cron),
+}
+
+impl Schedule {
+    /// Time to wait after `now` for the next run, `None` if there is none.
+    pub fn delay(&self, now: OffsetDateTime, first: bool) -> Option<Duration> {
+        match self {
+            Schedule::Every(_) if first => Some(Duration::ZERO),
+            Schedule::Every(interval) => Some(*interval),
+            Schedule::Cron(cron) => {
+                let next = cron.next_after(now)?;
+                Some((next - now).try_into().unwrap_or_default())
+            }
+        }
+    }
+}
+
+/// A `minute hour day-of-month month day-of-week` expression evaluated in UTC.
+/// Fields take `*`, numbers, ranges (`1-5`), steps (`*/15`, `0-30/10`) and
+/// comma-separated lists of those; day of week 0 and 7 are Sunday.
+#[derive(Debug, Clone)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day of month / day of week were given as `*...`. As in classic
+    /// cron, a day matches either field when both are restricted.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("expected 5 fields (minute hour day month weekday), got \"{}\"", expression));
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        // 7 is another name for Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+}
+
+impl Cron {
+    /// The first matching minute after `after`.
+    pub fn next_after(&self, after: OffsetDateTime) -> Option<OffsetDateTime> {
+        let start = after.replace_time(Time::from_hms(after.hour(), after.minute(), 0).ok()?);
+        let mut t = start + time::Duration::minutes(1);
+        let limit = after.year() + MAX_YEARS_AHEAD;
+
+        while t.year() <= limit {
+            if !bit(self.months, u8::from(t.month()) as u32) {
+                let (year, month) = match t.month() {
+                    time::Month::December => (t.year() + 1, time::Month::January),
+                    month => (t.year(), month.next()),
+                };
+                t = t.replace_date(Date::from_calendar_date(year, month, 1).ok()?).replace_time(Time::MIDNIGHT);
+            } else if !self.day_matches(t) {
+                t = t.replace_time(Time::MIDNIGHT) + time::Duration::days(1);
+            } else if !bit(self.hours, t.hour() as u32) {
+                t = t.replace_time(Time::from_hms(t.hour(), 0, 0).ok()?) + time::Duration::hours(1);
+            } else if !bit(self.minutes, t.minute() as u32) {
+                t += time::Duration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, t: OffsetDateTime) -> bool {
+        let day = bit(self.days, t.day() as u32);
+        let weekday = bit(self.weekdays, t.weekday().number_days_from_sunday() as u32);
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
+
+fn bit(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parses one cron field into a bit set of the values it matches.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, Some(step)),
+                _ => return Err(format!("invalid step in \"{}\"", part)),
+            },
+            None => (part, None),
+        };
+
+        let number = |s: &str| s.parse::<u32>().map_err(|_| format!("invalid value \"{}\"", part));
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            // `5/15` runs from 5 to the end of the range
+            None if step.is_some() => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if start < min || end > max || start > end {
+            return Err(format!("\"{}\" is outside {}-{}", part, min, max));
+        }
+
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
@@ -0,0 +1,196 @@
+//! `[scripting]`: a Rhai script deciding about updates, for policies the
+//! config can't express. Each of its functions is optional:
+//!
+//! - `transform_host(host, provider)` returns the host to update instead
+//! - `choose_provider(host, provider)` returns the provider to update through
+//! - `validate(request)` returns `false` or a reason to refuse an update
+//! - `after_update(change)` is handed the outcome of every provider call
+//!
+//! A script that fails refuses the update rather than letting it through.
+
+use std::net::IpAddr;
+
+use anyhow::Result;
+
+use crate::audit::Change;
+use crate::config::ScriptingConfig;
+
+/// An update about to be made, as `validate` sees it
+pub struct Request<'a> {
+    pub provider: &'a str,
+    pub host: &'a str,
+    /// `A`, `AAAA` or `CNAME`; a set of records has the type of its first address
+    pub record_type: &'a str,
+    /// The address, comma-separated addresses or CNAME target
+    pub value: &'a str,
+    pub client_ip: Option<IpAddr>,
+    /// Who the request was authorized as, e.g. `token:home-router`
+    pub actor: Option<String>,
+}
+
+/// What `validate` decided
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    Refuse(String),
+}
+
+pub struct Scripts {
+    #[cfg(feature = "scripting")]
+    script: Option<engine::Script>,
+}
+
+impl Scripts {
+    /// Compiles `[scripting].path`, if configured.
+    pub fn load(config: &ScriptingConfig) -> Result<Self> {
+        #[cfg(feature = "scripting")]
+        return Ok(Self {
+            script: match config.path {
+                Some(ref path) => Some(engine::Script::load(path, config.max_operations)?),
+                None => None,
+            },
+        });
+
+        #[cfg(not(feature = "scripting"))]
+        {
+            // Validation refuses a path without the feature
+            let _ = config;
+            Ok(Self {})
+        }
+    }
+
+    /// The provider and host an update of `host` via `provider` is made for,
+    /// after `transform_host` and then `choose_provider`.
+    pub fn route(&self, provider: &str, host: &str) -> Result<(String, String), String> {
+        #[cfg(feature = "scripting")]
+        if let Some(ref script) = self.script {
+            let host = script.call_string("transform_host", host, provider)?.unwrap_or_else(|| host.to_string());
+            let provider = script.call_string("choose_provider", &host, provider)?.unwrap_or_else(|| provider.to_string());
+            return Ok((provider, host));
+        }
+
+        Ok((provider.to_string(), host.to_string()))
+    }
+
+    /// Asks `validate` whether the update may go ahead.
+    pub fn validate(&self, request: &Request) -> Result<Verdict, String> {
+        #[cfg(feature = "scripting")]
+        if let Some(ref script) = self.script {
+            return script.validate(request);
+        }
+
+        let _ = request;
+        Ok(Verdict::Allow)
+    }
+
+    /// Hands a change to `after_update`; failures are only logged.
+    pub fn after_update(&self, change: &Change) {
+        #[cfg(feature = "scripting")]
+        if let Some(ref script) = self.script {
+            script.after_update(change);
+        }
+
+        let _ = change;
+    }
+}
+
+#[cfg(feature = "scripting")]
+mod engine {
+    use anyhow::{Context, Result};
+    use rhai::{Dynamic, Engine, FuncArgs, Map, Scope, AST};
+    use tracing::{debug, info, warn};
+
+    use super::{Request, Verdict};
+    use crate::audit::Change;
+    use crate::provider::ChangeKind;
+
+    pub struct Script {
+        engine: Engine,
+        ast: AST,
+    }
+
+    impl Script {
+        pub fn load(path: &str, max_operations: u64) -> Result<Self> {
+            let mut engine = Engine::new();
+            engine.set_max_operations(max_operations);
+            engine.on_print(|text| info!("Script: {}", text));
+            engine.on_debug(|text, _, _| debug!("Script: {}", text));
+
+            let ast = engine
+                .compile_file(path.into())
+                .with_context(|| format!("Failed to load script {}", path))?;
+            Ok(Self { engine, ast })
+        }
+
+        /// Calls `name(host, provider)` expecting a string, `None` if the
+        /// script has no such function or it returns `()`.
+        pub fn call_string(&self, name: &str, host: &str, provider: &str) -> Result<Option<String>, String> {
+            let Some(result) = self.call(name, 2, (host.to_string(), provider.to_string()))? else {
+                return Ok(None);
+            };
+            if result.is_unit() {
+                return Ok(None);
+            }
+            result
+                .into_string()
+                .map(Some)
+                .map_err(|found| format!("{}() returned {}, expected a string", name, found))
+        }
+
+        pub fn validate(&self, request: &Request) -> Result<Verdict, String> {
+            let mut map = Map::new();
+            map.insert("provider".into(), request.provider.into());
+            map.insert("host".into(), request.host.into());
+            map.insert("record_type".into(), request.record_type.into());
+            map.insert("ip".into(), request.value.into());
+            map.insert("client_ip".into(), optional(request.client_ip.map(|ip| ip.to_string())));
+            map.insert("actor".into(), optional(request.actor.clone()));
+
+            let Some(result) = self.call("validate", 1, (map,))? else {
+                return Ok(Verdict::Allow);
+            };
+            if result.is_unit() || result.as_bool() == Ok(true) {
+                return Ok(Verdict::Allow);
+            }
+            if result.as_bool() == Ok(false) {
+                return Ok(Verdict::Refuse("refused by policy script".to_string()));
+            }
+            match result.into_string() {
+                Ok(reason) => Ok(Verdict::Refuse(reason)),
+                Err(found) => Err(format!("validate() returned {}, expected a bool or a string", found)),
+            }
+        }
+
+        pub fn after_update(&self, change: &Change) {
+            let mut map = Map::new();
+            map.insert("action".into(), change.action.into());
+            map.insert("provider".into(), change.provider.into());
+            map.insert("host".into(), change.host.into());
+            map.insert("old".into(), optional(change.old.clone()));
+            map.insert("new".into(), change.new.clone().into());
+            map.insert("success".into(), change.success.into());
+            map.insert("changed".into(), change.change.as_ref().is_some_and(ChangeKind::is_change).into());
+            map.insert("change".into(), optional(change.change.as_ref().map(|c| c.as_str().to_string())));
+            map.insert("message".into(), change.message.into());
+
+            if let Err(e) = self.call("after_update", 1, (map,)) {
+                warn!("Script failed: {}", e);
+            }
+        }
+
+        /// Calls `name` if the script defines it with `arity` parameters.
+        fn call(&self, name: &str, arity: usize, args: impl FuncArgs) -> Result<Option<Dynamic>, String> {
+            if !self.ast.iter_functions().any(|f| f.name == name && f.params.len() == arity) {
+                return Ok(None);
+            }
+            self.engine
+                .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, name, args)
+                .map(Some)
+                .map_err(|e| format!("{}(): {}", name, e))
+        }
+    }
+
+    fn optional(value: Option<String>) -> Dynamic {
+        value.map_or(Dynamic::UNIT, Dynamic::from)
+    }
+}
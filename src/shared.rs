@@ -0,0 +1,358 @@
+//! `[redis]`: state shared by several instances behind a load balancer. The
+//! last pushed record per host, the rate limit counters and a lock per host
+//! live in Redis, so two instances neither race each other nor repeat an
+//! update the other just made. Redis being unreachable only costs that
+//! protection: each call logs a warning and the instance carries on alone.
+
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::config::{RedisConfig, ServerConfig};
+use crate::provider::{DnsUpdateResult, RecordOptions};
+use crate::state::HostState;
+
+pub struct SharedState {
+    #[cfg(feature = "redis")]
+    redis: Option<backend::Redis>,
+}
+
+impl SharedState {
+    /// Prepares the client for `[redis].url`, if configured. The connection
+    /// is made on first use.
+    pub fn new(config: &RedisConfig, server: &ServerConfig) -> Result<Self> {
+        #[cfg(feature = "redis")]
+        return Ok(Self {
+            redis: match config.url {
+                Some(ref url) => Some(backend::Redis::new(url, config, server)?),
+                None => None,
+            },
+        });
+
+        #[cfg(not(feature = "redis"))]
+        {
+            // Validation refuses a URL without the feature
+            let _ = (config, server);
+            Ok(Self {})
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        #[cfg(feature = "redis")]
+        return self.redis.is_some();
+
+        #[cfg(not(feature = "redis"))]
+        false
+    }
+
+    /// The record last pushed for `host` by any instance.
+    pub async fn get(&self, provider: &str, host: &str) -> Option<HostState> {
+        #[cfg(feature = "redis")]
+        if let Some(ref redis) = self.redis {
+            return redis.get(provider, host).await;
+        }
+
+        let _ = (provider, host);
+        None
+    }
+
+    pub async fn set(&self, provider: &str, host: &str, state: &HostState) {
+        #[cfg(feature = "redis")]
+        if let Some(ref redis) = self.redis {
+            redis.set(provider, host, state).await;
+        }
+
+        let _ = (provider, host, state);
+    }
+
+    /// Forgets a host whose record is in an unknown state.
+    pub async fn remove(&self, provider: &str, host: &str) {
+        #[cfg(feature = "redis")]
+        if let Some(ref redis) = self.redis {
+            redis.remove(provider, host).await;
+        }
+
+        let _ = (provider, host);
+    }
+
+    /// Counts a request for `key` in the current minute, returning how long
+    /// to wait once more than `per_minute` were made across all instances.
+    pub async fn check_rate(&self, kind: &str, key: &str, per_minute: u32) -> Result<(), Duration> {
+        #[cfg(feature = "redis")]
+        if let Some(ref redis) = self.redis {
+            return redis.check_rate(kind, key, per_minute).await;
+        }
+
+        let _ = (kind, key, per_minute);
+        Ok(())
+    }
+
+    /// Takes or renews the lease `name` for `token`, returning whether this
+    /// instance holds it. A Redis error counts as not holding it.
+    pub async fn hold_lease(&self, name: &str, token: &str, lease: Duration) -> bool {
+        #[cfg(feature = "redis")]
+        if let Some(ref redis) = self.redis {
+            return redis.hold_lease(name, token, lease).await;
+        }
+
+        let _ = (name, token, lease);
+        false
+    }
+
+    /// Runs `update` holding the lock of `provider`/`host`, after any other
+    /// instance's update of it. When that one wrote exactly what `update`
+    /// would, its result is reused instead.
+    pub async fn exclusive<F, Fut>(&self, provider: &str, host: &str, content: &str, options: &RecordOptions, update: F) -> Result<DnsUpdateResult>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<DnsUpdateResult>>,
+    {
+        #[cfg(feature = "redis")]
+        if let Some(ref redis) = self.redis {
+            return redis.exclusive(provider, host, content, options, update).await;
+        }
+
+        let _ = (provider, host, content, options);
+        update().await
+    }
+}
+
+#[cfg(feature = "redis")]
+mod backend {
+    use std::future::Future;
+    use std::time::Duration;
+
+    use anyhow::{Context, Result};
+    use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+    use redis::{AsyncCommands, Client, RedisResult};
+    use serde::{Deserialize, Serialize};
+    use sha2::{Digest, Sha256};
+    use time::OffsetDateTime;
+    use tokio::sync::OnceCell;
+    use tracing::{debug, warn};
+
+    use crate::config::{RedisConfig, ServerConfig};
+    use crate::provider::{ChangeKind, DnsUpdateResult, RecordOptions};
+    use crate::state::HostState;
+
+    /// Deletes the lock only if this instance still holds it
+    const RELEASE: &str = "if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) else return 0 end";
+
+    /// Takes a free lease or renews one already held, returning 1 if held
+    const LEASE: &str = "local holder = redis.call('GET', KEYS[1]) \
+        if not holder then redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2]) return 1 end \
+        if holder == ARGV[1] then redis.call('PEXPIRE', KEYS[1], ARGV[2]) return 1 end \
+        return 0";
+
+    /// How often a held lock is tried again
+    const LOCK_POLL: Duration = Duration::from_millis(100);
+
+    pub struct Redis {
+        client: Client,
+        connection: OnceCell<ConnectionManager>,
+        connection_config: ConnectionManagerConfig,
+        prefix: String,
+        lock_timeout: Duration,
+    }
+
+    /// The last update of a host, as the lock holder left it
+    #[derive(Serialize, Deserialize)]
+    struct Completed {
+        /// Tells this update from an earlier one with the same content
+        id: String,
+        content: String,
+        ttl: u32,
+        proxied: bool,
+        message: String,
+        record_id: Option<String>,
+    }
+
+    impl Redis {
+        pub fn new(url: &str, config: &RedisConfig, server: &ServerConfig) -> Result<Self> {
+            Ok(Self {
+                client: Client::open(url).context("Invalid redis.url")?,
+                connection: OnceCell::new(),
+                connection_config: ConnectionManagerConfig::new()
+                    .set_connection_timeout(Duration::from_secs(server.connect_timeout_secs))
+                    .set_response_timeout(Duration::from_secs(server.timeout_secs))
+                    .set_number_of_retries(1),
+                prefix: config.key_prefix.clone(),
+                lock_timeout: Duration::from_secs(config.lock_timeout_secs),
+            })
+        }
+
+        async fn connection(&self) -> RedisResult<ConnectionManager> {
+            self.connection
+                .get_or_try_init(|| ConnectionManager::new_with_config(self.client.clone(), self.connection_config.clone()))
+                .await
+                .cloned()
+        }
+
+        fn key(&self, kind: &str, provider: &str, host: &str) -> String {
+            format!("{}{}:{}/{}", self.prefix, kind, provider, host.to_ascii_lowercase())
+        }
+
+        pub async fn get(&self, provider: &str, host: &str) -> Option<HostState> {
+            let result: RedisResult<Option<String>> = async { self.connection().await?.get(self.key("state", provider, host)).await }.await;
+            match result {
+                Ok(value) => value.and_then(|value| serde_json::from_str(&value).ok()),
+                Err(e) => {
+                    warn!("Failed to read the state of {} from Redis: {}", host, e);
+                    None
+                }
+            }
+        }
+
+        pub async fn set(&self, provider: &str, host: &str, state: &HostState) {
+            let value = serde_json::to_string(state).unwrap_or_default();
+            let result: RedisResult<()> = async { self.connection().await?.set(self.key("state", provider, host), value).await }.await;
+            if let Err(e) = result {
+                warn!("Failed to write the state of {} to Redis: {}", host, e);
+            }
+        }
+
+        pub async fn remove(&self, provider: &str, host: &str) {
+            let result: RedisResult<()> = async { self.connection().await?.del(self.key("state", provider, host)).await }.await;
+            if let Err(e) = result {
+                warn!("Failed to remove the state of {} from Redis: {}", host, e);
+            }
+        }
+
+        /// Fixed one-minute windows, keyed by a hash so access keys don't end up in Redis.
+        pub async fn check_rate(&self, kind: &str, key: &str, per_minute: u32) -> Result<(), Duration> {
+            let now = OffsetDateTime::now_utc().unix_timestamp();
+            let hash: String = Sha256::digest(key.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+            let counter = format!("{}ratelimit:{}:{}:{}", self.prefix, kind, hash, now / 60);
+
+            let result: RedisResult<u32> = async {
+                let mut connection = self.connection().await?;
+                let (count, _): (u32, bool) = redis::pipe().incr(&counter, 1).expire(&counter, 120).query_async(&mut connection).await?;
+                Ok(count)
+            }
+            .await;
+            match result {
+                Ok(count) if count > per_minute => Err(Duration::from_secs((60 - now % 60) as u64)),
+                Ok(_) => Ok(()),
+                Err(e) => {
+                    warn!("Failed to count the request in Redis, not rate limiting it: {}", e);
+                    Ok(())
+                }
+            }
+        }
+
+        pub async fn hold_lease(&self, name: &str, token: &str, lease: Duration) -> bool {
+            let key = format!("{}{}", self.prefix, name);
+            let result: RedisResult<i64> = async {
+                let mut connection = self.connection().await?;
+                redis::cmd("EVAL")
+                    .arg(LEASE)
+                    .arg(1)
+                    .arg(&key)
+                    .arg(token)
+                    .arg(lease.as_millis() as u64)
+                    .query_async(&mut connection)
+                    .await
+            }
+            .await;
+            match result {
+                Ok(held) => held == 1,
+                Err(e) => {
+                    warn!("Failed to renew the {} lease in Redis: {}", name, e);
+                    false
+                }
+            }
+        }
+
+        pub async fn exclusive<F, Fut>(&self, provider: &str, host: &str, content: &str, options: &RecordOptions, update: F) -> Result<DnsUpdateResult>
+        where
+            F: FnOnce() -> Fut,
+            Fut: Future<Output = Result<DnsUpdateResult>>,
+        {
+            let lock = self.key("lock", provider, host);
+            let last = self.key("last", provider, host);
+            let mut connection = match self.connection().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Failed to reach Redis, updating {} without the shared lock: {}", host, e);
+                    return update().await;
+                }
+            };
+
+            let token = uuid::Uuid::new_v4().to_string();
+            let seen = completed(&mut connection, &last).await.map(|done| done.id);
+            let started = tokio::time::Instant::now();
+            loop {
+                let acquired: RedisResult<Option<String>> = redis::cmd("SET")
+                    .arg(&lock)
+                    .arg(&token)
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(self.lock_timeout.as_millis() as u64)
+                    .query_async(&mut connection)
+                    .await;
+                match acquired {
+                    Ok(Some(_)) => break,
+                    Ok(None) if started.elapsed() < self.lock_timeout => tokio::time::sleep(LOCK_POLL).await,
+                    Ok(None) => anyhow::bail!("Timed out waiting for another instance updating {}", host),
+                    Err(e) => {
+                        warn!("Failed to take the Redis lock of {}, updating without it: {}", host, e);
+                        return update().await;
+                    }
+                }
+            }
+
+            // Another instance finished while we waited; if it did exactly what
+            // we were about to do, its result is ours too
+            let done = completed(&mut connection, &last).await;
+            let result = match done {
+                Some(done)
+                    if Some(&done.id) != seen.as_ref()
+                        && done.content == content
+                        && done.ttl == options.ttl
+                        && done.proxied == options.proxied =>
+                {
+                    debug!("Coalesced update of {} to {} with another instance", host, content);
+                    Ok(DnsUpdateResult {
+                        success: true,
+                        change: ChangeKind::NoChange,
+                        new: Some(done.content),
+                        message: done.message,
+                        record_id: done.record_id,
+                    })
+                }
+                _ => {
+                    let result = update().await;
+                    if let Ok(ref result) = result {
+                        let done = Completed {
+                            id: token.clone(),
+                            content: content.to_string(),
+                            ttl: options.ttl,
+                            proxied: options.proxied,
+                            message: result.message.clone(),
+                            record_id: result.record_id.clone(),
+                        };
+                        let written: RedisResult<()> = connection
+                            .set_ex(&last, serde_json::to_string(&done).unwrap_or_default(), self.lock_timeout.as_secs() * 2)
+                            .await;
+                        if let Err(e) = written {
+                            warn!("Failed to record the update of {} in Redis: {}", host, e);
+                        }
+                    }
+                    result
+                }
+            };
+
+            let released: RedisResult<i64> = redis::cmd("EVAL").arg(RELEASE).arg(1).arg(&lock).arg(&token).query_async(&mut connection).await;
+            if let Err(e) = released {
+                warn!("Failed to release the Redis lock of {}, it expires in {}s: {}", host, self.lock_timeout.as_secs(), e);
+            }
+            result
+        }
+    }
+
+    async fn completed(connection: &mut ConnectionManager, key: &str) -> Option<Completed> {
+        let value: Option<String> = connection.get(key).await.ok()?;
+        serde_json::from_str(&value?).ok()
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+/// Last pushed IP and record ID per host, persisted to `server.state_file`
+/// so "no change" detection survives restarts.
+pub struct StateStore {
+    path: PathBuf,
+    hosts: Mutex<BTreeMap<String, HostState>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostState {
+    pub ip: IpAddr,
+    pub ttl: u32,
+    pub proxied: bool,
+    #[serde(default)]
+    pub record_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StateFile {
+    /// Keyed by `provider/host`
+    hosts: BTreeMap<String, HostState>,
+}
+
+impl StateStore {
+    /// Loads the state file, starting empty if it does not exist yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        let hosts = match fs::read_to_string(path) {
+            Ok(content) => match serde_json::from_str::<StateFile>(&content) {
+                Ok(file) => {
+                    info!("Loaded state for {} host(s) from {}", file.hosts.len(), path.display());
+                    file.hosts
+                }
+                // A corrupt state file only costs a provider lookup, don't refuse to start
+                Err(e) => {
+                    warn!("Ignoring unreadable state file {}: {}", path.display(), e);
+                    BTreeMap::new()
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read state file: {}", path.display())),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            hosts: Mutex::new(hosts),
+        })
+    }
+
+    pub fn get(&self, provider: &str, host: &str) -> Option<HostState> {
+        self.hosts.lock().unwrap().get(&key(provider, host)).cloned()
+    }
+
+    /// All stored hosts as `(provider, host, state)`.
+    pub fn entries(&self) -> Vec<(String, String, HostState)> {
+        self.hosts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(k, v)| k.split_once('/').map(|(p, h)| (p.to_string(), h.to_string(), v.clone())))
+            .collect()
+    }
+
+    pub fn set(&self, provider: &str, host: &str, state: HostState) {
+        let mut hosts = self.hosts.lock().unwrap();
+        if hosts.get(&key(provider, host)) == Some(&state) {
+            return;
+        }
+        hosts.insert(key(provider, host), state);
+        self.save(&hosts);
+    }
+
+    /// Forgets a host whose record is in an unknown state (e.g. after a failed update).
+    pub fn remove(&self, provider: &str, host: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+        if hosts.remove(&key(provider, host)).is_some() {
+            self.save(&hosts);
+        }
+    }
+
+    /// Stores several hosts at once, e.g. from `ddns-rust import`, failing
+    /// if the file can't be written.
+    pub fn set_all(&self, entries: impl IntoIterator<Item = (String, String, HostState)>) -> Result<()> {
+        let mut hosts = self.hosts.lock().unwrap();
+        for (provider, host, state) in entries {
+            hosts.insert(key(&provider, &host), state);
+        }
+        self.write(&hosts)
+            .with_context(|| format!("Failed to write state file {}", self.path.display()))
+    }
+
+    fn save(&self, hosts: &BTreeMap<String, HostState>) {
+        if let Err(e) = self.write(hosts) {
+            warn!("Failed to write state file {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn write(&self, hosts: &BTreeMap<String, HostState>) -> Result<()> {
+        let file = StateFile { hosts: hosts.clone() };
+        // Write to a temporary file and rename, so a crash never leaves a truncated file
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&file)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn key(provider: &str, host: &str) -> String {
+    format!("{}/{}", provider, host.to_ascii_lowercase())
+}
@@ -0,0 +1,263 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::config::Config;
+use crate::provider::{Capabilities, ChangeKind, Provider};
+use crate::breaker::{CircuitBreaker, CircuitReport};
+use crate::throttle::{Throttle, ThrottleReport};
+
+/// Number of update attempts kept in the history
+const HISTORY_SIZE: usize = 100;
+
+/// Tracks update outcomes per provider and host for the `/status` endpoint.
+pub struct StatusTracker {
+    started: Instant,
+    /// Unix time of `started`
+    started_at: i64,
+    providers: Mutex<HashMap<String, ProviderStatus>>,
+    /// Most recent update attempts, oldest first
+    history: Mutex<VecDeque<HistoryEntry>>,
+    /// Successful updates per provider and kind of change, for `/metrics`
+    changes: Mutex<BTreeMap<(String, &'static str), u64>>,
+}
+
+/// One update attempt, successful or not
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub time: String,
+    pub provider: String,
+    pub host: String,
+    pub ip: IpAddr,
+    pub success: bool,
+    /// What the provider did, absent on failures
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<ChangeKind>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct ProviderStatus {
+    /// Most recent failure for any host of this provider
+    last_error: Option<UpdateError>,
+    hosts: BTreeMap<String, HostStatus>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct HostStatus {
+    /// IP the record was last successfully set to
+    ip: Option<IpAddr>,
+    last_success: Option<String>,
+    /// Whether the last successful update was seen on DNS (`None` if not checked)
+    verified: Option<bool>,
+    last_error: Option<UpdateError>,
+    /// Unix times of `last_success` and `last_error`, for `/metrics`
+    #[serde(skip)]
+    success_at: Option<i64>,
+    #[serde(skip)]
+    failure_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct UpdateError {
+    time: String,
+    ip: IpAddr,
+    error: String,
+}
+
+/// When a host was last updated, for `/metrics`
+pub struct HostFreshness {
+    pub provider: String,
+    pub host: String,
+    pub ip: Option<IpAddr>,
+    /// Unix time of the last successful update, changed or not
+    pub success_at: Option<i64>,
+    pub failure_at: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct StatusReport {
+    version: &'static str,
+    uptime_secs: u64,
+    providers: Vec<ProviderReport>,
+}
+
+#[derive(Serialize)]
+struct ProviderReport {
+    name: String,
+    #[serde(rename = "type")]
+    provider_type: String,
+    #[serde(flatten)]
+    status: ProviderStatus,
+    /// `None` for unsupported provider types
+    capabilities: Option<Capabilities>,
+    /// Set while the provider's rate limit holds updates back
+    #[serde(skip_serializing_if = "Option::is_none")]
+    throttled: Option<ThrottleReport>,
+    /// Set while the provider's circuit breaker is open
+    #[serde(skip_serializing_if = "Option::is_none")]
+    circuit: Option<CircuitReport>,
+}
+
+impl StatusTracker {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            started_at: OffsetDateTime::now_utc().unix_timestamp(),
+            providers: Mutex::new(HashMap::new()),
+            history: Mutex::new(VecDeque::new()),
+            changes: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record_success(&self, provider: &str, host: &str, ip: IpAddr, verified: Option<bool>, change: &ChangeKind, message: &str) {
+        let time = now();
+        let at = OffsetDateTime::now_utc().unix_timestamp();
+        {
+            let mut providers = self.providers.lock().unwrap();
+            let status = providers.entry(provider.to_string()).or_default();
+            let host = status.hosts.entry(host.to_ascii_lowercase()).or_default();
+            host.ip = Some(ip);
+            host.last_success = Some(time.clone());
+            host.success_at = Some(at);
+            host.verified = verified;
+        }
+        *self.changes.lock().unwrap().entry((provider.to_string(), change.as_str())).or_default() += 1;
+        self.push_history(time, provider, host, ip, Some(change.clone()), message);
+    }
+
+    /// Sets the known IP of a host without recording an update, e.g. from the state file.
+    pub fn seed(&self, provider: &str, host: &str, ip: IpAddr) {
+        let mut providers = self.providers.lock().unwrap();
+        let status = providers.entry(provider.to_string()).or_default();
+        status.hosts.entry(host.to_ascii_lowercase()).or_default().ip = Some(ip);
+    }
+
+    /// IP a host's record was last set to, if known.
+    pub fn last_ip(&self, provider: &str, host: &str) -> Option<IpAddr> {
+        let providers = self.providers.lock().unwrap();
+        providers.get(provider)?.hosts.get(&host.to_ascii_lowercase())?.ip
+    }
+
+    /// Every host with a known IP, as `(provider, host, ip)`.
+    pub fn known_ips(&self) -> Vec<(String, String, IpAddr)> {
+        let providers = self.providers.lock().unwrap();
+        providers
+            .iter()
+            .flat_map(|(provider, status)| {
+                status
+                    .hosts
+                    .iter()
+                    .filter_map(move |(host, s)| Some((provider.clone(), host.clone(), s.ip?)))
+            })
+            .collect()
+    }
+
+    pub fn record_failure(&self, provider: &str, host: &str, ip: IpAddr, error: &str) {
+        let failure = UpdateError {
+            time: now(),
+            ip,
+            error: error.to_string(),
+        };
+        let time = failure.time.clone();
+        let at = OffsetDateTime::now_utc().unix_timestamp();
+        {
+            let mut providers = self.providers.lock().unwrap();
+            let status = providers.entry(provider.to_string()).or_default();
+            let host = status.hosts.entry(host.to_ascii_lowercase()).or_default();
+            host.last_error = Some(failure.clone());
+            host.failure_at = Some(at);
+            status.last_error = Some(failure);
+        }
+        self.push_history(time, provider, host, ip, None, error);
+    }
+
+    /// Adds an attempt, successful if it has a `change`.
+    fn push_history(&self, time: String, provider: &str, host: &str, ip: IpAddr, change: Option<ChangeKind>, message: &str) {
+        let mut history = self.history.lock().unwrap();
+        if history.len() == HISTORY_SIZE {
+            history.pop_front();
+        }
+        history.push_back(HistoryEntry {
+            time,
+            provider: provider.to_string(),
+            host: host.to_string(),
+            ip,
+            success: change.is_some(),
+            change,
+            message: message.to_string(),
+        });
+    }
+
+    /// Every host that was updated or has a known IP, in provider and host order.
+    pub fn freshness(&self) -> Vec<HostFreshness> {
+        let providers = self.providers.lock().unwrap();
+        let mut hosts: Vec<HostFreshness> = providers
+            .iter()
+            .flat_map(|(provider, status)| {
+                status.hosts.iter().map(move |(host, s)| HostFreshness {
+                    provider: provider.clone(),
+                    host: host.clone(),
+                    ip: s.ip,
+                    success_at: s.success_at,
+                    failure_at: s.failure_at,
+                })
+            })
+            .collect();
+        hosts.sort_by(|a, b| (&a.provider, &a.host).cmp(&(&b.provider, &b.host)));
+        hosts
+    }
+
+    /// Unix time the tracker was created, i.e. the server started.
+    pub fn started_at(&self) -> i64 {
+        self.started_at
+    }
+
+    /// Successful updates per provider and kind of change, as `(provider, kind, count)`.
+    pub fn change_counts(&self) -> Vec<(String, &'static str, u64)> {
+        let changes = self.changes.lock().unwrap();
+        changes.iter().map(|((provider, kind), count)| (provider.clone(), *kind, *count)).collect()
+    }
+
+    /// Recent update attempts, newest first.
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.history.lock().unwrap().iter().rev().cloned().collect()
+    }
+
+    /// Status of every configured provider, in config order.
+    pub fn report(
+        &self,
+        config: &Config,
+        throttle: &Throttle,
+        breaker: &CircuitBreaker,
+        instances: &HashMap<String, Arc<dyn Provider>>,
+    ) -> StatusReport {
+        let providers = self.providers.lock().unwrap();
+
+        StatusReport {
+            version: env!("CARGO_PKG_VERSION"),
+            uptime_secs: self.started.elapsed().as_secs(),
+            providers: config
+                .providers
+                .iter()
+                .map(|p| ProviderReport {
+                    name: p.name.clone(),
+                    provider_type: p.provider_type.clone(),
+                    status: providers.get(&p.name).cloned().unwrap_or_default(),
+                    capabilities: instances.get(&p.name).map(|provider| provider.capabilities()),
+                    throttled: throttle.report(&p.name),
+                    circuit: breaker.report(&p.name),
+                })
+                .collect(),
+        }
+    }
+}
+
+fn now() -> String {
+    OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default()
+}
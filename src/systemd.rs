@@ -0,0 +1,106 @@
+//! systemd integration: a listening socket passed by socket activation
+//! (`LISTEN_FDS`), and readiness and watchdog notifications (`NOTIFY_SOCKET`).
+//! Everything is a no-op when not started by systemd or not on Unix.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::{debug, warn};
+
+/// First file descriptor systemd passes (`SD_LISTEN_FDS_START`)
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// The sockets systemd opened for us, if started by a `.socket` unit.
+#[cfg(unix)]
+pub fn listeners() -> Result<Vec<std::net::TcpListener>> {
+    use std::os::unix::io::FromRawFd;
+
+    // The variables are meant for our process only, not a parent that exported them
+    let for_us = std::env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok()) == Some(std::process::id());
+    let count = std::env::var("LISTEN_FDS").ok().and_then(|n| n.parse::<i32>().ok()).unwrap_or(0);
+    if !for_us {
+        return Ok(Vec::new());
+    }
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            // SAFETY: systemd hands over descriptors from LISTEN_FDS_START on, owned by this process
+            let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+pub fn listeners() -> Result<Vec<std::net::TcpListener>> {
+    Ok(Vec::new())
+}
+
+/// Sends a state such as `READY=1` to systemd; `Ok(false)` when not supervised.
+#[cfg(unix)]
+pub fn notify(state: &str) -> Result<bool> {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    let socket = UnixDatagram::unbound()?;
+    match path.to_str().and_then(|p| p.strip_prefix('@')) {
+        // An abstract socket name (Linux only)
+        Some(name) => {
+            #[cfg(target_os = "linux")]
+            {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                socket.send_to_addr(state.as_bytes(), &addr)?;
+            }
+            #[cfg(not(target_os = "linux"))]
+            anyhow::bail!("abstract NOTIFY_SOCKET @{} is only supported on Linux", name);
+        }
+        None => {
+            socket.send_to(state.as_bytes(), &path)?;
+        }
+    }
+    debug!("Notified systemd: {}", state);
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+pub fn notify(_state: &str) -> Result<bool> {
+    Ok(false)
+}
+
+/// How often to ping the watchdog: half of `WATCHDOG_USEC`, if it is set for us.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Some(pid) = std::env::var("WATCHDOG_PID").ok().and_then(|pid| pid.parse::<u32>().ok()) {
+        if pid != std::process::id() {
+            return None;
+        }
+    }
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok().filter(|&usec| usec > 0)?;
+    Some(Duration::from_micros(usec / 2))
+}
+
+/// Reports readiness and keeps the watchdog fed from the runtime, so a hung
+/// event loop stops the pings and systemd restarts the service.
+pub fn ready() {
+    if let Err(e) = notify("READY=1") {
+        warn!("Failed to notify systemd: {:#}", e);
+    }
+
+    let Some(interval) = watchdog_interval() else {
+        return;
+    };
+    debug!("Pinging the systemd watchdog every {:?}", interval);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = notify("WATCHDOG=1") {
+                warn!("Failed to ping the systemd watchdog: {:#}", e);
+            }
+        }
+    });
+}
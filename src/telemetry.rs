@@ -0,0 +1,86 @@
+//! Logging setup and optional OpenTelemetry trace export.
+
+use anyhow::Result;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::Config;
+
+/// Keeps the trace exporter alive; dropping it flushes spans that are still buffered.
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+/// Installs the global subscriber: log lines filtered by `RUST_LOG` or
+/// `server.log_level`, plus OTLP span export when `telemetry.otlp_endpoint` is set.
+pub fn init(config: &Config) -> Result<Telemetry> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.server.log_level));
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer());
+
+    #[cfg(feature = "otel")]
+    if let Some(ref endpoint) = config.telemetry.otlp_endpoint {
+        use opentelemetry::trace::TracerProvider as _;
+
+        let provider = otel::tracer_provider(endpoint, &config.telemetry)?;
+        let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+        registry.with(tracing_opentelemetry::layer().with_tracer(tracer)).init();
+        tracing::info!("Exporting traces to {}", endpoint);
+        return Ok(Telemetry { provider: Some(provider) });
+    }
+
+    registry.init();
+    Ok(Telemetry {
+        #[cfg(feature = "otel")]
+        provider: None,
+    })
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush traces: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use anyhow::{Context, Result};
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+    use opentelemetry_sdk::Resource;
+
+    use crate::config::TelemetryConfig;
+
+    pub fn tracer_provider(endpoint: &str, config: &TelemetryConfig) -> Result<SdkTracerProvider> {
+        // The endpoint is the collector's base URL, as with OTEL_EXPORTER_OTLP_ENDPOINT
+        let endpoint = endpoint.trim_end_matches('/');
+        let url = if endpoint.ends_with("/v1/traces") {
+            endpoint.to_string()
+        } else {
+            format!("{}/v1/traces", endpoint)
+        };
+
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(url)
+            .build()
+            .context("Failed to create OTLP exporter")?;
+
+        // Sample whole requests: provider call spans follow their request's decision
+        let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio)));
+
+        Ok(SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_sampler(sampler)
+            .with_resource(Resource::builder().with_service_name(config.service_name.clone()).build())
+            .build())
+    }
+}
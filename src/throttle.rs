@@ -0,0 +1,128 @@
+//! Backs off from providers that answer with a rate limit: until their
+//! `Retry-After` has passed, updates are queued instead of sent, then retried
+//! in the background.
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::{info, info_span, Instrument};
+
+use crate::api::{apply_update, AppState};
+
+/// How often queued updates are checked for being due
+const RETRY_TICK: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+pub struct Throttle {
+    providers: Mutex<HashMap<String, Throttled>>,
+}
+
+struct Throttled {
+    until: Instant,
+    /// `until` as a timestamp for `/status`
+    until_utc: OffsetDateTime,
+    /// Latest wanted IP and TTL override per host
+    pending: BTreeMap<String, (IpAddr, Option<u32>)>,
+}
+
+/// A throttled provider on `/status`
+#[derive(Debug, Clone, Serialize)]
+pub struct ThrottleReport {
+    pub until: String,
+    /// Hosts with an update waiting
+    pub pending: Vec<String>,
+}
+
+/// An update to retry once its provider's throttle has passed
+pub struct Deferred {
+    pub provider: String,
+    pub host: String,
+    pub ip: IpAddr,
+    pub ttl: Option<u32>,
+}
+
+impl Throttle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time left until `provider` may be called again, `None` if it is not throttled.
+    pub fn remaining(&self, provider: &str) -> Option<Duration> {
+        let providers = self.providers.lock().unwrap();
+        let left = providers.get(provider)?.until.checked_duration_since(Instant::now())?;
+        (!left.is_zero()).then_some(left)
+    }
+
+    /// Stops calls to `provider` for `retry_after`, keeping updates already queued.
+    pub fn throttle(&self, provider: &str, retry_after: Duration) {
+        let mut providers = self.providers.lock().unwrap();
+        let entry = providers.entry(provider.to_string()).or_insert_with(|| Throttled {
+            until: Instant::now(),
+            until_utc: OffsetDateTime::now_utc(),
+            pending: BTreeMap::new(),
+        });
+        entry.until = Instant::now() + retry_after;
+        entry.until_utc = OffsetDateTime::now_utc() + retry_after;
+    }
+
+    /// Queues an update of a throttled provider, replacing an older one for the same host.
+    pub fn defer(&self, provider: &str, host: &str, ip: IpAddr, ttl: Option<u32>) {
+        let mut providers = self.providers.lock().unwrap();
+        if let Some(throttled) = providers.get_mut(provider) {
+            throttled.pending.insert(host.to_string(), (ip, ttl));
+        }
+    }
+
+    /// Removes the providers whose throttle has passed, returning their queued updates.
+    pub fn take_due(&self) -> Vec<Deferred> {
+        let now = Instant::now();
+        let mut providers = self.providers.lock().unwrap();
+        let due: Vec<String> = providers.iter().filter(|(_, t)| t.until <= now).map(|(name, _)| name.clone()).collect();
+
+        due.into_iter()
+            .flat_map(|provider| {
+                let throttled = providers.remove(&provider).unwrap();
+                throttled.pending.into_iter().map(move |(host, (ip, ttl))| Deferred {
+                    provider: provider.clone(),
+                    host,
+                    ip,
+                    ttl,
+                })
+            })
+            .collect()
+    }
+
+    pub fn report(&self, provider: &str) -> Option<ThrottleReport> {
+        let providers = self.providers.lock().unwrap();
+        let throttled = providers.get(provider)?;
+        Some(ThrottleReport {
+            until: throttled.until_utc.format(&Rfc3339).unwrap_or_default(),
+            pending: throttled.pending.keys().cloned().collect(),
+        })
+    }
+}
+
+/// Retries queued updates as their providers' throttles pass.
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(RETRY_TICK);
+        loop {
+            ticker.tick().await;
+            for deferred in state.throttle.take_due() {
+                let Some(provider_config) = state.config.get_provider(&deferred.provider) else {
+                    continue;
+                };
+                info!("Retrying queued update of {} to {}", deferred.host, deferred.ip);
+                // A renewed rate limit queues the update again
+                let _ = apply_update(&state, provider_config, &deferred.host, deferred.ip, deferred.ttl)
+                    .instrument(info_span!("retry", provider = %deferred.provider, host = %deferred.host))
+                    .await;
+            }
+        }
+    });
+}
@@ -0,0 +1,134 @@
+//! `[udp]`: a datagram listener for microcontrollers and battery-powered
+//! devices that can't afford a TLS handshake and an HTTP exchange to report
+//! their address. A datagram is one line of text,
+//!
+//! ```text
+//! <provider> <host> <ip or -> <timestamp> <signature>
+//! ```
+//!
+//! signed with the host's key as `hex(HMAC-SHA256("<provider> <host> <ip or
+//! -> <timestamp>", key))`, where `-` stands for the datagram's source
+//! address. Each validly signed one is answered with `OK <ip>` or `ERR
+//! <reason>`, which a device is free to ignore; anything else is dropped
+//! silently, as its source address may be forged.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use axum::response::Response;
+use tokio::net::UdpSocket;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::api::{apply_update, reject_source_ip, route, AppState};
+use crate::auth::SignatureCheck;
+use crate::hostname;
+
+/// Longest datagram read; anything longer is cut off and fails to parse
+const MAX_DATAGRAM: usize = 512;
+
+/// Binds `[udp].listen`, if configured, and answers datagrams in the background.
+pub fn spawn(state: Arc<AppState>) -> Result<()> {
+    let Some(ref listen) = state.config.udp.listen else {
+        return Ok(());
+    };
+
+    let socket = std::net::UdpSocket::bind(listen).with_context(|| format!("Failed to listen on udp://{}", listen))?;
+    socket.set_nonblocking(true)?;
+    let socket = Arc::new(UdpSocket::from_std(socket)?);
+    info!("Accepting signed update datagrams on udp://{}", socket.local_addr()?);
+
+    tokio::spawn(async move {
+        let mut buffer = [0u8; MAX_DATAGRAM];
+        loop {
+            let (len, source) = match socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("Failed to receive update datagram: {}", e);
+                    continue;
+                }
+            };
+            let datagram = String::from_utf8_lossy(&buffer[..len]).into_owned();
+            let state = state.clone();
+            let socket = socket.clone();
+            tokio::spawn(
+                async move {
+                    let Some(reply) = handle(&state, &datagram, source).await else {
+                        return;
+                    };
+                    // Never send more than was received, whoever the source really is
+                    if let Err(e) = socket.send_to(&reply.as_bytes()[..reply.len().min(len)], source).await {
+                        warn!("Failed to answer update datagram: {}", e);
+                    }
+                }
+                .instrument(info_span!("udp", source = %source)),
+            );
+        }
+    });
+
+    Ok(())
+}
+
+/// Checks and applies one datagram, returning the reply, or nothing until
+/// its signature checked out.
+async fn handle(state: &AppState, datagram: &str, source: SocketAddr) -> Option<String> {
+    let source = source.ip().to_canonical();
+    if let Some(ref limiter) = state.ip_limiter {
+        let checked = match state.shared.is_enabled() {
+            true => state.shared.check_rate("ip", &source.to_string(), state.config.server.rate_limit_per_ip).await,
+            false => limiter.check(&source.to_string()),
+        };
+        if checked.is_err() {
+            warn!("Rate limit exceeded for IP: {}", source);
+            return None;
+        }
+    }
+
+    let fields: Vec<&str> = datagram.split_whitespace().collect();
+    let [provider, host, ip, timestamp, signature] = fields[..] else {
+        debug!("Dropped malformed update datagram");
+        return None;
+    };
+    let target = match ip {
+        "-" => source,
+        ip => ip.parse::<IpAddr>().ok()?,
+    };
+
+    let mut provider_name = provider.to_string();
+    let mut host_name = hostname::normalize(host).ok()?;
+    if route(state, &mut provider_name, &mut host_name).is_some() {
+        return None;
+    }
+    let provider_config = state.config.get_provider(&provider_name)?;
+    if reject_source_ip(state, provider_config, Some(source)).is_some() {
+        return None;
+    }
+
+    // Only a signature authorizes a datagram; a spoofed source address is cheap
+    let check = match provider_config.host_settings(&host_name).key {
+        Some(key) => state.signatures.verify(key, &format!("{} {} {} ", provider, host, ip), timestamp, signature),
+        None => SignatureCheck::Invalid,
+    };
+    if check != SignatureCheck::Valid {
+        warn!("Rejected update datagram for provider {}: {:?}", provider_config.name, check);
+        return None;
+    }
+
+    Some(match apply_update(state, provider_config, &host_name, target, None).await {
+        Ok(_) => format!("OK {}", target),
+        Err(response) => reply(response, target).await,
+    })
+}
+
+/// `OK <ip>`, or `ERR` with the error of a JSON error response.
+async fn reply(response: Response, target: IpAddr) -> String {
+    if response.status().is_success() {
+        return format!("OK {}", target);
+    }
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), MAX_DATAGRAM * 8).await.unwrap_or_default();
+    let error = serde_json::from_slice::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|body| body["error"].as_str().map(str::to_string));
+    format!("ERR {}", error.unwrap_or_else(|| status.to_string()))
+}
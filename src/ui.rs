@@ -0,0 +1,148 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use base64::Engine;
+use serde::Deserialize;
+use tracing::{warn, Span};
+
+use crate::api::{failure_response, invalid_host, perform_update, AppState, ErrorCode, ErrorResponse};
+use crate::auth;
+use crate::hostname;
+
+const INDEX_HTML: &str = include_str!("ui/index.html");
+
+#[derive(Deserialize)]
+struct UpdateForm {
+    provider: String,
+    host: String,
+    ip: String,
+}
+
+/// Dashboard routes, all behind HTTP Basic auth with the `[ui]` credentials.
+pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/ui", get(index))
+        .route("/ui/api/status", get(status))
+        .route("/ui/api/records/{provider}", get(records))
+        .route("/ui/api/update", post(update))
+        .route_layer(middleware::from_fn_with_state(state, basic_auth))
+}
+
+async fn basic_auth(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let credentials = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Basic "))
+        .and_then(|v| base64::engine::general_purpose::STANDARD.decode(v.trim()).ok())
+        .and_then(|v| String::from_utf8(v).ok());
+
+    let valid = match credentials.as_deref().and_then(|c| c.split_once(':')) {
+        Some((username, password)) if auth::constant_time_eq(username, &state.config.ui.username) => {
+            // Hash verification is deliberately slow, keep it off the async workers
+            let ui = state.config.ui.clone();
+            let password = password.to_string();
+            let span = Span::current();
+            tokio::task::spawn_blocking(move || {
+                span.in_scope(|| auth::verify_key(ui.password.as_deref(), ui.password_hash.as_deref(), Some(&password), "ui"))
+            })
+            .await
+            .unwrap_or(false)
+        }
+        _ => false,
+    };
+
+    if !valid {
+        if credentials.is_some() {
+            warn!("Invalid UI credentials");
+        }
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Basic realm=\"ddns-rust\"")],
+            Json(ErrorResponse {
+                success: false,
+                code: ErrorCode::Unauthorized,
+                error: "Authentication required".to_string(),
+            }),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+async fn index() -> impl IntoResponse {
+    Html(INDEX_HTML)
+}
+
+async fn status(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(serde_json::json!({
+        "status": state.status.report(&state.config, &state.throttle, &state.breaker, &state.providers),
+        "history": state.status.history(),
+    }))
+}
+
+async fn records(State(state): State<Arc<AppState>>, Path(provider_name): Path<String>) -> impl IntoResponse {
+    let result = match state.providers.get(&provider_name) {
+        Some(provider) => provider.list_records().await,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    success: false,
+                    code: ErrorCode::ProviderNotFound,
+                    error: format!("Provider not found: {}", provider_name),
+                }),
+            )
+                .into_response();
+        }
+    };
+
+    match result {
+        Ok(records) => Json(serde_json::json!({ "success": true, "records": records })).into_response(),
+        Err(e) => failure_response(&state, "Listing records failed", &e),
+    }
+}
+
+/// Manual update from the dashboard form; the Basic auth login stands in for
+/// the provider key.
+async fn update(State(state): State<Arc<AppState>>, Json(form): Json<UpdateForm>) -> impl IntoResponse {
+    let Ok(ip) = form.ip.trim().parse::<IpAddr>() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                success: false,
+                code: ErrorCode::InvalidIp,
+                error: format!("Invalid IP address: {}", form.ip),
+            }),
+        )
+            .into_response();
+    };
+
+    let Some(provider_config) = state.config.get_provider(&form.provider) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                success: false,
+                code: ErrorCode::ProviderNotFound,
+                error: format!("Provider not found: {}", form.provider),
+            }),
+        )
+            .into_response();
+    };
+
+    let host = match hostname::normalize(form.host.trim()) {
+        Ok(host) => host,
+        Err(reason) => return invalid_host(&reason),
+    };
+
+    perform_update(&state, provider_config, &host, ip).await
+}
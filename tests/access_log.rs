@@ -0,0 +1,82 @@
+//! Configurable access log formats.
+
+mod common;
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use axum::http::StatusCode;
+use ddns_rust::config::Config;
+
+use common::{get, router};
+
+fn config(format: &str) -> String {
+    format!(
+        r#"
+[server]
+access_log_format = '{}'
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#,
+        format
+    )
+}
+
+#[tokio::test]
+async fn serves_requests_with_each_builtin_and_custom_format() {
+    for (i, format) in ["default", "common", "combined", "json", "{ip} {method} {path} -> {status} in {duration_ms}ms"]
+        .into_iter()
+        .enumerate()
+    {
+        let app = router(&format!("access_log_{}", i), &config(format));
+        let (status, _) = get(&app, "/health", &[]).await;
+        assert_eq!(status, StatusCode::OK, "{}", format);
+    }
+}
+
+#[test]
+fn rejects_unknown_placeholders() {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("access_log_invalid.toml");
+    std::fs::write(&path, config("{ip} {bytes}")).unwrap();
+
+    let error = format!("{:#}", Config::load(&path, None).unwrap_err());
+    assert!(error.contains("server.access_log_format: unknown placeholder {bytes}"), "{}", error);
+}
+
+/// Collects formatted log output in memory.
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn redacts_credentials_in_logged_queries() {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .finish();
+    // The test runtime is single-threaded, so the whole request logs here
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let app = router("access_log_redacted", &(config("default") + "key = \"secret-key\"\n"));
+    let (status, _) = get(&app, "/ddns/mock/home.example.com/1.2.3.4?key=secret-key&ttl=60", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    assert!(logs.contains("/ddns/mock/home.example.com/1.2.3.4?key=[REDACTED]&ttl=60"), "{}", logs);
+    assert!(!logs.contains("secret-key"), "{}", logs);
+}
@@ -0,0 +1,105 @@
+//! `[audit]` log of record changes.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use common::{from_peer, get, router, send};
+
+fn read_entries(path: &std::path::Path) -> Vec<Value> {
+    std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn records_who_changed_what() {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("audit_entries.jsonl");
+    let _ = std::fs::remove_file(&path);
+
+    let app = router(
+        "audit_entries",
+        r#"
+[audit]
+path = "audit_entries.jsonl"
+
+[[auth.tokens]]
+name = "router"
+token = "token-1"
+
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+"#,
+    );
+
+    let (status, _) = get(&app, "/ddns/mock/home.example.com/1.2.3.4?key=secret", &[("x-request-id", "req-1")]).await;
+    assert_eq!(status, StatusCode::OK);
+    let request = Request::get("/ddns/mock/home.example.com/5.6.7.8").header("authorization", "Bearer token-1");
+    let (status, _) = send(&app, from_peer(request.body(Body::empty()).unwrap(), "9.9.9.9")).await;
+    assert_eq!(status, StatusCode::OK);
+    // Rejected requests change nothing and are left to the access log
+    let (status, _) = get(&app, "/ddns/mock/home.example.com/5.6.7.9?key=wrong", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let entries = read_entries(&path);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["request_id"], "req-1");
+    assert_eq!(entries[0]["actor"], "key");
+    assert_eq!(entries[0]["action"], "update");
+    assert_eq!(entries[0]["host"], "home.example.com");
+    assert_eq!(entries[0]["old"], Value::Null);
+    assert_eq!(entries[0]["new"], "1.2.3.4");
+    assert_eq!(entries[0]["success"], true);
+    assert_eq!(entries[0]["changed"], true);
+    assert_eq!(entries[0]["change"], "created");
+    assert!(entries[0].get("hash").is_none());
+
+    assert_eq!(entries[1]["actor"], "token:router");
+    assert_eq!(entries[1]["client_ip"], "9.9.9.9");
+    assert_eq!(entries[1]["old"], "1.2.3.4");
+    assert_eq!(entries[1]["new"], "5.6.7.8");
+    assert_eq!(entries[1]["change"], "updated");
+}
+
+#[tokio::test]
+async fn chains_entries_across_restarts() {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("audit_chain.jsonl");
+    let _ = std::fs::remove_file(&path);
+    let config = r#"
+[audit]
+path = "audit_chain.jsonl"
+hash_chain = true
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#;
+
+    let app = router("audit_chain", config);
+    get(&app, "/ddns/mock/a.example.com/1.2.3.4", &[]).await;
+    get(&app, "/ddns/mock/b.example.com/1.2.3.4", &[]).await;
+    // A restarted server continues the chain
+    let app = router("audit_chain", config);
+    get(&app, "/ddns/mock/c.example.com/1.2.3.4", &[]).await;
+
+    let lines = std::fs::read_to_string(&path).unwrap();
+    let mut prev_hash = "0".repeat(64);
+    for line in lines.lines() {
+        let entry: Value = serde_json::from_str(line).unwrap();
+        assert_eq!(entry["prev_hash"], prev_hash.as_str());
+
+        let hash = entry["hash"].as_str().unwrap();
+        let body = line.replace(&format!(r#","hash":"{}""#, hash), "");
+        let digest: String = Sha256::digest(body.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hash, digest);
+        prev_hash = hash.to_string();
+    }
+    assert_eq!(lines.lines().count(), 3);
+}
@@ -0,0 +1,91 @@
+//! `[circuit_breaker]`: a provider that keeps failing is not called until a probe succeeds.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use serde_json::json;
+use tower::ServiceExt;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{cf_page, cf_record, cf_result, get, router};
+
+async fn metrics(app: &Router) -> String {
+    let response = app.clone().oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn fails_fast_while_open_and_closes_after_a_probe() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(503).set_body_string("down for maintenance"))
+        .up_to_n_times(2)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(json!([]), 1, 1)))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("r1", "A", "home.example.com", "1.2.3.4"))))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let app = router(
+        "circuit_breaker",
+        &format!(
+            r#"
+[circuit_breaker]
+failure_threshold = 2
+open_secs = 1
+
+[[providers]]
+name = "cf"
+type = "cloudflare"
+api_key = "test-token"
+api_base = "{}"
+zone_id = "z1"
+"#,
+            server.uri()
+        ),
+    );
+
+    for _ in 0..2 {
+        let (status, body) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{}", body);
+    }
+    let calls = server.received_requests().await.unwrap().len();
+
+    // Open: answered without calling the provider
+    let response = app
+        .clone()
+        .oneshot(Request::get("/ddns/cf/home.example.com/1.2.3.4").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert!(response.headers().contains_key("retry-after"));
+    assert_eq!(server.received_requests().await.unwrap().len(), calls);
+
+    let (_, body) = get(&app, "/status", &[]).await;
+    assert_eq!(body["providers"][0]["circuit"]["state"], "open");
+    assert_eq!(body["providers"][0]["circuit"]["failures"], 2);
+    assert!(metrics(&app).await.contains("ddns_circuit_open{provider=\"cf\"} 1"));
+
+    // Half open: the probe goes through and closes the circuit
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (_, body) = get(&app, "/status", &[]).await;
+    assert!(body["providers"][0].get("circuit").is_none(), "{}", body);
+    assert!(metrics(&app).await.contains("ddns_circuit_open{provider=\"cf\"} 0"));
+}
@@ -0,0 +1,836 @@
+//! End-to-end tests of the HTTP API against a fake Cloudflare API.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::json;
+use tower::ServiceExt;
+use wiremock::matchers::{body_partial_json, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{cf_error, cf_page, cf_record, cf_result, get, router};
+
+fn config(api_base: &str, extra: &str) -> String {
+    format!(
+        r#"
+[[providers]]
+name = "cf"
+type = "cloudflare"
+api_key = "test-token"
+api_base = "{}"
+{}
+"#,
+        api_base, extra
+    )
+}
+
+async fn mock_records(server: &MockServer, zone_id: &str, record_type: &str, records: serde_json::Value) {
+    Mock::given(method("GET"))
+        .and(path(format!("/zones/{}/dns_records", zone_id)))
+        .and(query_param("type", record_type))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(records, 1, 1)))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn creates_missing_record() {
+    let server = MockServer::start().await;
+    mock_records(&server, "z1", "A", json!([])).await;
+    Mock::given(method("POST"))
+        .and(path("/zones/z1/dns_records"))
+        .and(header("authorization", "Bearer test-token"))
+        .and(body_partial_json(json!({ "type": "A", "name": "home.example.com", "content": "1.2.3.4" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("new1", "A", "home.example.com", "1.2.3.4"))))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let app = router("cf_create", &config(&server.uri(), r#"zone_id = "z1""#));
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["record_id"], "new1");
+    assert!(body["message"].as_str().unwrap().starts_with("Created"));
+}
+
+#[tokio::test]
+async fn updates_changed_record() {
+    let server = MockServer::start().await;
+    mock_records(&server, "z1", "A", json!([cf_record("r1", "A", "home.example.com", "1.1.1.1")])).await;
+    Mock::given(method("PUT"))
+        .and(path("/zones/z1/dns_records/r1"))
+        .and(body_partial_json(json!({ "content": "1.2.3.4", "ttl": 1, "proxied": false })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("r1", "A", "home.example.com", "1.2.3.4"))))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let app = router("cf_update", &config(&server.uri(), r#"zone_id = "z1""#));
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["message"], "Updated record home.example.com to IP 1.2.3.4");
+}
+
+#[tokio::test]
+async fn leaves_matching_record_unchanged() {
+    let server = MockServer::start().await;
+    mock_records(&server, "z1", "A", json!([cf_record("r1", "A", "home.example.com", "1.2.3.4")])).await;
+    Mock::given(method("PUT")).respond_with(ResponseTemplate::new(500)).expect(0).mount(&server).await;
+
+    let app = router("cf_nochg", &config(&server.uri(), r#"zone_id = "z1""#));
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["message"], "Record already up to date with IP 1.2.3.4");
+}
+
+#[tokio::test]
+async fn updates_aaaa_record_for_ipv6() {
+    let server = MockServer::start().await;
+    mock_records(&server, "z1", "AAAA", json!([cf_record("r6", "AAAA", "home.example.com", "2001:db8:0:0::1")])).await;
+    Mock::given(method("PUT")).respond_with(ResponseTemplate::new(500)).expect(0).mount(&server).await;
+
+    let app = router("cf_aaaa", &config(&server.uri(), r#"zone_id = "z1""#));
+    // Same address in a different textual form is already up to date
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/2001:db8::1", &[]).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["record_id"], "r6");
+}
+
+#[tokio::test]
+async fn updates_wildcard_records() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(json!([{ "id": "z1", "name": "example.com" }]), 1, 1)))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .and(query_param("name", "*.home.example.com"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(json!([]), 1, 1)))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/zones/z1/dns_records"))
+        .and(body_partial_json(json!({ "type": "A", "name": "*.home.example.com", "content": "1.2.3.4" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("w1", "A", "*.home.example.com", "1.2.3.4"))))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let app = router("cf_wildcard", &config(&server.uri(), ""));
+
+    // Clients may send the `*` label as is or percent-encoded
+    let (status, body) = get(&app, "/ddns/cf/%2A.home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["message"], "Created new record *.home.example.com with IP 1.2.3.4");
+
+    let (status, body) = get(&app, "/ddns/cf/*.home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["record_id"], "w1");
+}
+
+#[tokio::test]
+async fn points_cname_hosts_at_a_hostname() {
+    let server = MockServer::start().await;
+    mock_records(&server, "z1", "CNAME", json!([cf_record("c1", "CNAME", "home.example.com", "old.dyn.example.net")])).await;
+    Mock::given(method("PUT"))
+        .and(path("/zones/z1/dns_records/c1"))
+        .and(body_partial_json(json!({ "type": "CNAME", "name": "home.example.com", "content": "new.dyn.example.net" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("c1", "CNAME", "home.example.com", "new.dyn.example.net"))))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let hosts = r#"zone_id = "z1"
+
+[[providers.hosts]]
+name = "home.example.com"
+record_type = "CNAME"
+"#;
+    let app = router("cf_cname", &config(&server.uri(), hosts));
+
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/New.Dyn.Example.NET", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["message"], "Updated record home.example.com to CNAME new.dyn.example.net");
+
+    // The target must be a hostname
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "record_type_not_enabled");
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/not_a..host", &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_host");
+}
+
+#[tokio::test]
+async fn reports_api_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(cf_error(10000, "Authentication error")))
+        .mount(&server)
+        .await;
+
+    let app = router("cf_error", &config(&server.uri(), r#"zone_id = "z1""#));
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+
+    // Provider details stay in the server log and on /status
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(body["code"], "provider_auth_failed");
+    assert_eq!(body["error"], "DNS update failed (provider rejected the configured credentials)");
+
+    let (_, body) = get(&app, "/status", &[]).await;
+    let error = body["providers"][0]["last_error"]["error"].as_str().unwrap();
+    assert!(error.contains("10000: Authentication error"), "{}", error);
+}
+
+#[tokio::test]
+async fn queues_updates_while_rate_limited() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(
+            ResponseTemplate::new(429)
+                .insert_header("Retry-After", "1")
+                .set_body_json(cf_error(971, "Please wait and consider throttling your request speed")),
+        )
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    mock_records(&server, "z1", "A", json!([])).await;
+    Mock::given(method("POST"))
+        .and(path("/zones/z1/dns_records"))
+        .and(body_partial_json(json!({ "content": "5.6.7.8" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("new1", "A", "home.example.com", "5.6.7.8"))))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let app = router("cf_rate_limited", &config(&server.uri(), r#"zone_id = "z1""#));
+    let request = Request::get("/ddns/cf/home.example.com/1.2.3.4").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert_eq!(response.headers()["retry-after"], "1");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["queued"], true);
+
+    // Further updates wait without calling the provider; the latest one wins
+    let (status, _) = get(&app, "/ddns/cf/home.example.com/5.6.7.8", &[]).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let (_, status) = get(&app, "/status", &[]).await;
+    assert_eq!(status["providers"][0]["throttled"]["pending"], json!(["home.example.com"]));
+
+    let mut provider = serde_json::Value::Null;
+    for _ in 0..300 {
+        let (_, status) = get(&app, "/status", &[]).await;
+        provider = status["providers"][0].clone();
+        if provider["hosts"]["home.example.com"]["ip"] == "5.6.7.8" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(provider["hosts"]["home.example.com"]["ip"], "5.6.7.8", "{}", provider);
+    assert!(provider["throttled"].is_null());
+}
+
+#[tokio::test]
+async fn reports_provider_outages_as_unavailable() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(502).set_body_string("<html>Bad gateway</html>"))
+        .mount(&server)
+        .await;
+
+    let app = router("cf_unavailable", &config(&server.uri(), r#"zone_id = "z1""#));
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["code"], "provider_unavailable");
+}
+
+#[tokio::test]
+async fn rejects_invalid_and_unknown_hosts() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(json!([{ "id": "z1", "name": "example.com" }]), 1, 1)))
+        .mount(&server)
+        .await;
+
+    let app = router("cf_invalid_host", &config(&server.uri(), ""));
+
+    let (status, body) = get(&app, "/ddns/cf/bad_-.host..com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_host");
+
+    let (status, body) = get(&app, "/ddns/cf/home.example.org/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_host");
+    assert_eq!(body["error"], "DNS update failed (No Cloudflare zone found for host: home.example.org)");
+}
+
+#[tokio::test]
+async fn masks_secrets_in_reported_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(cf_error(6003, "Invalid token test-token")))
+        .mount(&server)
+        .await;
+
+    let app = router("cf_error_secret", &config(&server.uri(), r#"zone_id = "z1""#));
+    get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+
+    let (_, body) = get(&app, "/status", &[]).await;
+    let error = body["providers"][0]["last_error"]["error"].as_str().unwrap();
+    assert!(error.contains("Invalid token [REDACTED]"), "{}", error);
+    assert!(!error.contains("test-token"), "{}", error);
+}
+
+#[tokio::test]
+async fn refuses_multiple_records_by_default() {
+    let server = MockServer::start().await;
+    mock_records(
+        &server,
+        "z1",
+        "A",
+        json!([
+            cf_record("r1", "A", "home.example.com", "1.1.1.1"),
+            cf_record("r2", "A", "home.example.com", "2.2.2.2"),
+        ]),
+    )
+    .await;
+    Mock::given(method("PUT")).respond_with(ResponseTemplate::new(500)).expect(0).mount(&server).await;
+
+    let app = router("cf_multiple", &config(&server.uri(), r#"zone_id = "z1""#));
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body["success"], false);
+
+    let (_, body) = get(&app, "/status", &[]).await;
+    let error = body["providers"][0]["last_error"]["error"].as_str().unwrap();
+    assert!(error.contains("Found 2 A records"), "{}", error);
+}
+
+#[tokio::test]
+async fn reconciles_record_sets() {
+    let server = MockServer::start().await;
+    mock_records(
+        &server,
+        "z1",
+        "A",
+        json!([
+            cf_record("r1", "A", "home.example.com", "1.1.1.1"),
+            cf_record("r2", "A", "home.example.com", "9.9.9.9"),
+            cf_record("r3", "A", "home.example.com", "8.8.8.8"),
+        ]),
+    )
+    .await;
+    // Both changes in one batch, reusing the stale r3 for the new address
+    Mock::given(method("POST"))
+        .and(path("/zones/z1/dns_records/batch"))
+        .and(body_partial_json(json!({ "deletes": [{ "id": "r2" }], "puts": [{ "id": "r3", "content": "2.2.2.2" }] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(json!({
+            "deletes": [{ "id": "r2" }],
+            "puts": [cf_record("r3", "A", "home.example.com", "2.2.2.2")],
+        }))))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT")).respond_with(ResponseTemplate::new(500)).expect(0).mount(&server).await;
+    Mock::given(method("DELETE")).respond_with(ResponseTemplate::new(500)).expect(0).mount(&server).await;
+
+    let app = router("cf_record_set", &config(&server.uri(), r#"zone_id = "z1""#));
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.1.1.1,2.2.2.2", &[]).await;
+
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["record_id"], "r1");
+    let message = body["message"].as_str().unwrap();
+    assert!(message.contains("0 created, 1 updated, 1 deleted"), "{}", message);
+
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.1.1.1,nope", &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_ip");
+}
+
+#[tokio::test]
+async fn discovers_zone_across_pages() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .and(query_param("page", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(json!([{ "id": "z-other", "name": "other.com" }]), 1, 2)))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .and(query_param("page", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(json!([{ "id": "z-example", "name": "example.com" }]), 2, 2)))
+        .mount(&server)
+        .await;
+    mock_records(&server, "z-example", "A", json!([])).await;
+    Mock::given(method("POST"))
+        .and(path("/zones/z-example/dns_records"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("new1", "A", "home.example.com", "1.2.3.4"))))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let app = router("cf_discover", &config(&server.uri(), ""));
+    let (status, _) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn lists_records_across_pages() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .and(query_param("type", "A"))
+        .and(query_param("page", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(json!([cf_record("r1", "A", "a.example.com", "1.1.1.1")]), 1, 2)))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .and(query_param("type", "A"))
+        .and(query_param("page", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(json!([cf_record("r2", "A", "b.example.com", "2.2.2.2")]), 2, 2)))
+        .mount(&server)
+        .await;
+    mock_records(&server, "z1", "AAAA", json!([])).await;
+    mock_records(&server, "z1", "CNAME", json!([])).await;
+    mock_records(&server, "z1", "SRV", json!([])).await;
+    mock_records(&server, "z1", "MX", json!([])).await;
+
+    let app = router("cf_list", &config(&server.uri(), r#"zone_id = "z1""#));
+    let (status, body) = get(&app, "/dns/cf/records", &[]).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let names: Vec<&str> = body["records"].as_array().unwrap().iter().map(|r| r["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["a.example.com", "b.example.com"]);
+}
+
+#[tokio::test]
+async fn rejects_bad_credentials_before_calling_cloudflare() {
+    let server = MockServer::start().await;
+
+    let extra = r#"
+zone_id = "z1"
+key = "secret"
+
+[[auth.tokens]]
+name = "reader"
+token = "read-token"
+scopes = ["read"]
+"#;
+    let app = router("cf_auth", &config(&server.uri(), extra));
+
+    let (status, _) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = get(&app, "/ddns/cf/home.example.com/1.2.3.4?key=wrong", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[("authorization", "Bearer nope")]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[("authorization", "Bearer read-token")]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn readiness_check_reports_expired_token_and_is_cached() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/user/tokens/verify"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(json!({ "id": "t1", "status": "expired" }))))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let app = router("cf_ready", &config(&server.uri(), r#"zone_id = "z1""#));
+
+    for _ in 0..2 {
+        let (status, body) = get(&app, "/health/ready", &[]).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["providers"][0]["message"], "API token is expired");
+    }
+}
+
+#[tokio::test]
+async fn caches_record_id_between_updates() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(json!([cf_record("r1", "A", "home.example.com", "1.1.1.1")]), 1, 1)))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/zones/z1/dns_records/r1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("r1", "A", "home.example.com", "1.2.3.4"))))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let app = router("cf_cache", &config(&server.uri(), r#"zone_id = "z1""#));
+    for ip in ["1.2.3.4", "1.2.3.4", "5.6.7.8"] {
+        let (status, body) = get(&app, &format!("/ddns/cf/home.example.com/{}", ip), &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["record_id"], "r1");
+    }
+}
+
+#[tokio::test]
+async fn refreshes_cached_record_id_after_failed_update() {
+    let server = MockServer::start().await;
+    mock_records(&server, "z1", "A", json!([cf_record("r1", "A", "home.example.com", "1.1.1.1")])).await;
+    Mock::given(method("PUT"))
+        .and(path("/zones/z1/dns_records/r1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("r1", "A", "home.example.com", "1.2.3.4"))))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+
+    let app = router("cf_cache_stale", &config(&server.uri(), r#"zone_id = "z1""#));
+    let (status, _) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The record was deleted and recreated elsewhere
+    server.reset().await;
+    mock_records(&server, "z1", "A", json!([cf_record("r2", "A", "home.example.com", "1.2.3.4")])).await;
+    Mock::given(method("PUT"))
+        .and(path("/zones/z1/dns_records/r1"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(cf_error(81044, "Record does not exist.")))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/zones/z1/dns_records/r2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("r2", "A", "home.example.com", "5.6.7.8"))))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/5.6.7.8", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["record_id"], "r2");
+}
+
+#[tokio::test]
+async fn answers_in_plain_text_when_asked() {
+    let server = MockServer::start().await;
+    mock_records(&server, "z1", "A", json!([cf_record("r1", "A", "home.example.com", "1.2.3.4")])).await;
+    let app = router("cf_plain", &config(&server.uri(), "zone_id = \"z1\"\nkey = \"secret\""));
+
+    let plain = |uri: &str, accept: &str| {
+        let request = Request::get(uri).header("accept", accept).body(Body::empty()).unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let content_type = response.headers()["content-type"].to_str().unwrap().to_string();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, content_type, String::from_utf8(body.to_vec()).unwrap())
+        }
+    };
+
+    let (status, content_type, body) = plain("/ddns/cf/home.example.com/1.2.3.4?key=secret&format=plain", "*/*").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(content_type.starts_with("text/plain"));
+    assert_eq!(body, "OK 1.2.3.4");
+
+    let (status, _, body) = plain("/ddns/cf/home.example.com/1.2.3.4?key=wrong", "text/plain").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(body.starts_with("ERR "), "{}", body);
+
+    let (_, content_type, _) = plain("/ddns/cf/home.example.com/1.2.3.4?key=secret", "text/plain, application/json").await;
+    assert!(content_type.starts_with("application/json"));
+}
+
+#[tokio::test]
+async fn managed_only_leaves_unlabeled_records_alone() {
+    let server = MockServer::start().await;
+    let mut manual = cf_record("manual", "A", "home.example.com", "1.1.1.1");
+    manual["comment"] = json!("added by hand");
+    let mut ours = cf_record("ours", "A", "home.example.com", "1.1.1.2");
+    ours["comment"] = json!("managed-by: ddns-rust, site: garage");
+    ours["tags"] = json!(["site:garage"]);
+    mock_records(&server, "z1", "A", json!([manual, ours])).await;
+    Mock::given(method("PUT"))
+        .and(path("/zones/z1/dns_records/ours"))
+        .and(body_partial_json(json!({
+            "content": "1.2.3.4",
+            "comment": "managed-by: ddns-rust, site: garage",
+            "tags": ["site:garage"]
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("ours", "A", "home.example.com", "1.2.3.4"))))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/zones/z1/dns_records/manual"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let extra = r#"zone_id = "z1"
+managed_only = true
+comment = "managed-by: ddns-rust, site: garage"
+tags = ["site:garage"]"#;
+    let app = router("cf_managed_only", &config(&server.uri(), extra));
+    // Two A records, but only one is ours, so multiple_records = "error" does not apply
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["record_id"], "ours");
+}
+
+#[tokio::test]
+async fn refuses_to_create_records_without_create_missing() {
+    let server = MockServer::start().await;
+    mock_records(&server, "z1", "A", json!([])).await;
+    Mock::given(method("POST")).respond_with(ResponseTemplate::new(500)).expect(0).mount(&server).await;
+
+    let app = router("cf_create_missing", &config(&server.uri(), "zone_id = \"z1\"\ncreate_missing = false"));
+    let (status, body) = get(&app, "/ddns/cf/hoem.example.com/1.2.3.4", &[]).await;
+
+    assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+    assert_eq!(body["code"], "not_found");
+    assert!(body["error"].as_str().unwrap().contains("hoem.example.com has no A record"), "{}", body);
+}
+
+#[tokio::test]
+async fn authenticates_with_global_api_key() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .and(header("x-auth-key", "test-token"))
+        .and(header("x-auth-email", "me@example.com"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(json!([]), 1, 1)))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/zones/z1/dns_records"))
+        .and(header("x-auth-key", "test-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("new1", "A", "home.example.com", "1.2.3.4"))))
+        .expect(1)
+        .mount(&server)
+        .await;
+    // Global API Keys are checked against the user, not the token endpoint
+    Mock::given(method("GET"))
+        .and(path("/user"))
+        .and(header("x-auth-email", "me@example.com"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(json!({ "id": "u1", "email": "me@example.com" }))))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(json!({ "id": "z1", "name": "example.com" }))))
+        .mount(&server)
+        .await;
+
+    let extra = "zone_id = \"z1\"\nauth_mode = \"global_key\"\nemail = \"me@example.com\"";
+    let app = router("cf_global_key", &config(&server.uri(), extra));
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (status, body) = get(&app, "/health/ready", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["providers"][0]["message"], "global API key of me@example.com, zone example.com (z1)");
+
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests.iter().all(|r| !r.headers.contains_key("authorization")));
+}
+
+#[tokio::test]
+async fn creates_srv_record_from_its_data() {
+    let server = MockServer::start().await;
+    mock_records(&server, "z1", "SRV", json!([])).await;
+    Mock::given(method("POST"))
+        .and(path("/zones/z1/dns_records"))
+        .and(body_partial_json(json!({
+            "type": "SRV",
+            "name": "_minecraft._tcp.example.com",
+            "data": { "priority": 10, "weight": 5, "port": 25565, "target": "home.example.com" },
+            "priority": 10
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(json!({
+            "id": "srv1",
+            "type": "SRV",
+            "name": "_minecraft._tcp.example.com",
+            "content": "5 25565 home.example.com",
+            "priority": 10,
+            "ttl": 1
+        }))))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut config = config(&server.uri(), r#"zone_id = "z1""#);
+    config.insert_str(0, "[[auth.tokens]]\nname = \"script\"\ntoken = \"token-1\"\n");
+    let app = router("cf_srv", &config);
+    let request = || {
+        Request::post("/api/v1/update/srv")
+            .header("content-type", "application/json")
+            .header("authorization", "Bearer token-1")
+            .body(Body::from(
+                r#"{"provider": "cf", "name": "_minecraft._tcp.example.com", "priority": 10, "weight": 5, "port": 25565, "target": "home.example.com"}"#,
+            ))
+            .unwrap()
+    };
+    let (status, body) = common::send(&app, request()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["record_id"], "srv1");
+
+    // Cached, so the same update needs no request
+    let (_, body) = common::send(&app, request()).await;
+    assert_eq!(body["message"], "Record already up to date with SRV 10 5 25565 home.example.com");
+}
+
+#[tokio::test]
+async fn rejects_ttls_cloudflare_does_not_accept() {
+    let server = MockServer::start().await;
+
+    let app = router("cf_ttl", &config(&server.uri(), "zone_id = \"z1\"\nttl = 10"));
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "unsupported_operation");
+    assert_eq!(body["error"], "TTL 10 is out of range (30 to 86400 seconds, or 1 for automatic)");
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn batches_prefix_updates_of_several_hosts() {
+    let server = MockServer::start().await;
+    Mock::given(path("/v6"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("2001:db8:1:2::99"))
+        .mount(&server)
+        .await;
+    mock_records(&server, "z1", "AAAA", json!([cf_record("r1", "AAAA", "nas.example.com", "2001:db8:9::10")])).await;
+    Mock::given(method("POST"))
+        .and(path("/zones/z1/dns_records/batch"))
+        .and(body_partial_json(json!({
+            "puts": [{ "id": "r1", "content": "2001:db8:1::10" }],
+            "posts": [{ "name": "printer.example.com", "content": "2001:db8:1:2a::20" }],
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(json!({
+            "puts": [cf_record("r1", "AAAA", "nas.example.com", "2001:db8:1::10")],
+            "posts": [cf_record("r2", "AAAA", "printer.example.com", "2001:db8:1:2a::20")],
+        }))))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT")).respond_with(ResponseTemplate::new(500)).expect(0).mount(&server).await;
+
+    let app = router(
+        "cf_batch",
+        &format!(
+            r#"
+[detect]
+ipv6_sources = ["{0}/v6"]
+
+[[poll]]
+provider = "cf"
+hosts = ["nas.example.com", "printer.example.com"]
+record_type = "AAAA"
+interval_secs = 3600
+ipv6_prefix_len = 56
+
+[[providers]]
+name = "cf"
+type = "cloudflare"
+api_key = "test-token"
+api_base = "{0}"
+zone_id = "z1"
+
+[[providers.hosts]]
+name = "nas.example.com"
+ipv6_suffix = "::10"
+
+[[providers.hosts]]
+name = "printer.example.com"
+ipv6_suffix = "::2a:0:0:0:20"
+"#,
+            server.uri()
+        ),
+    );
+
+    let mut hosts = serde_json::Value::Null;
+    for _ in 0..100 {
+        let (_, body) = get(&app, "/status", &[]).await;
+        hosts = body["providers"][0]["hosts"].clone();
+        if !hosts["printer.example.com"]["ip"].is_null() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(hosts["nas.example.com"]["ip"], "2001:db8:1::10");
+    assert_eq!(hosts["printer.example.com"]["ip"], "2001:db8:1:2a::20");
+}
+
+fn modified(mut record: serde_json::Value, modified_on: &str) -> serde_json::Value {
+    record["modified_on"] = json!(modified_on);
+    record
+}
+
+#[tokio::test]
+async fn refuses_to_overwrite_records_changed_elsewhere() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(
+            json!([modified(cf_record("r1", "A", "home.example.com", "1.1.1.1"), "2026-01-01T00:00:00Z")]),
+            1,
+            1,
+        )))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(
+            json!([modified(cf_record("r1", "A", "home.example.com", "9.9.9.9"), "2026-01-03T00:00:00Z")]),
+            1,
+            1,
+        )))
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/zones/z1/dns_records/r1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(modified(
+            cf_record("r1", "A", "home.example.com", "1.2.3.4"),
+            "2026-01-02T00:00:00Z",
+        ))))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let app = router("cf_conflict", &config(&server.uri(), "zone_id = \"z1\"\non_conflict = \"refuse\""));
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    // Someone edited the record in the dashboard meanwhile
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/5.6.7.8", &[]).await;
+    assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+    assert_eq!(body["code"], "conflict");
+    assert_eq!(body["success"], false);
+}
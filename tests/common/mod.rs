@@ -0,0 +1,99 @@
+//! Shared helpers for the integration tests.
+
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderMap, Request, StatusCode};
+use axum::Router;
+use ddns_rust::config::Config;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+/// Writes `content` to a uniquely named TOML file and loads it through `Config::load`.
+pub fn load_config(name: &str, content: &str) -> Config {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.toml", name));
+    std::fs::write(&path, content).unwrap();
+    Config::load(&path, None).unwrap()
+}
+
+/// Builds the router for a config file body.
+pub fn router(name: &str, content: &str) -> Router {
+    ddns_rust::api::create_router(load_config(name, content)).unwrap()
+}
+
+/// Marks `request` as arriving over a connection from `peer`, like the
+/// server's connect info does.
+pub fn from_peer(mut request: Request<Body>, peer: &str) -> Request<Body> {
+    request.extensions_mut().insert(ConnectInfo(SocketAddr::new(peer.parse().unwrap(), 40000)));
+    request
+}
+
+/// Sends a GET request through the router, returning the status and JSON body.
+pub async fn get(router: &Router, uri: &str, headers: &[(&str, &str)]) -> (StatusCode, Value) {
+    let mut request = Request::get(uri);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+
+    send(router, request.body(Body::empty()).unwrap()).await
+}
+
+/// Sends a request through the router, returning the status and JSON body
+/// (`Null` if the body is not JSON).
+pub async fn send(router: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    (status, body)
+}
+
+/// Sends a GET request through the router, returning the status, headers and
+/// body text.
+pub async fn get_text(router: &Router, uri: &str, headers: &[(&str, &str)]) -> (StatusCode, HeaderMap, String) {
+    let mut request = Request::get(uri);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+
+    send_text(router, request.body(Body::empty()).unwrap()).await
+}
+
+/// Sends a request through the router, returning the status, headers and body text.
+pub async fn send_text(router: &Router, request: Request<Body>) -> (StatusCode, HeaderMap, String) {
+    let response = router.clone().oneshot(request).await.unwrap();
+    let (parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+
+    (parts.status, parts.headers, String::from_utf8(body.to_vec()).unwrap())
+}
+
+/// A successful Cloudflare single-object response.
+pub fn cf_result(result: Value) -> Value {
+    json!({ "success": true, "errors": [], "result": result })
+}
+
+/// A successful Cloudflare list response for one page.
+pub fn cf_page(result: Value, page: u32, total_pages: u32) -> Value {
+    json!({
+        "success": true,
+        "errors": [],
+        "result": result,
+        "result_info": { "page": page, "total_pages": total_pages }
+    })
+}
+
+/// A failed Cloudflare response.
+pub fn cf_error(code: i32, message: &str) -> Value {
+    json!({ "success": false, "errors": [{ "code": code, "message": message }], "result": null })
+}
+
+/// A Cloudflare DNS record object.
+pub fn cf_record(id: &str, record_type: &str, name: &str, content: &str) -> Value {
+    json!({ "id": id, "type": record_type, "name": name, "content": content, "ttl": 1, "proxied": false })
+}
@@ -0,0 +1,197 @@
+//! Update endpoints for router and NAS DDNS clients.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use wiremock::matchers::path;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{from_peer, get, get_text, router, send_text};
+
+const CONFIG: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+
+[[providers.hosts]]
+name = "v4.example.com"
+record_type = "A"
+"#;
+
+#[tokio::test]
+async fn fritzbox_updates_ipv4_and_ipv6_in_one_request() {
+    let app = router("compat_fritzbox", CONFIG);
+
+    let (status, body) = get(
+        &app,
+        "/fritzbox/mock?host=home.example.com,v4.example.com&ipv4=1.2.3.4&ipv6=2001:db8::1&key=secret&user=fritz",
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["success"], true);
+    let results = body["results"].as_array().unwrap();
+    let updated: Vec<(&str, &str)> = results
+        .iter()
+        .map(|r| (r["host"].as_str().unwrap(), r["ip"].as_str().unwrap()))
+        .collect();
+    // The A-only host skips the IPv6 address
+    assert_eq!(
+        updated,
+        [("home.example.com", "1.2.3.4"), ("home.example.com", "2001:db8::1"), ("v4.example.com", "1.2.3.4")]
+    );
+    assert!(results.iter().all(|r| r["changed"] == true));
+
+    let (_, records) = get(&app, "/dns/mock/records?key=secret", &[]).await;
+    assert_eq!(records["records"].as_array().unwrap().len(), 3, "{}", records);
+}
+
+#[tokio::test]
+async fn fritzbox_skips_empty_placeholders_and_checks_the_key() {
+    let app = router("compat_fritzbox_key", CONFIG);
+
+    let (status, body) = get(&app, "/fritzbox/mock?host=home.example.com&ipv4=1.2.3.4&ipv6=&key=wrong", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "unauthorized");
+
+    let (status, body) = get(&app, "/fritzbox/mock?host=home.example.com&ipv4=&ipv6=&key=secret", &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "No IP address given");
+
+    let (status, body) = get(&app, "/fritzbox/mock?host=home.example.com&ipv4=2001:db8::1&key=secret", &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_ip");
+
+    let (status, body) = get(&app, "/fritzbox/mock?host=home.example.com&ipv4=1.2.3.4&ipv6=&key=secret", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["results"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn synology_gets_dyndns_return_codes() {
+    let app = router("compat_synology", CONFIG);
+    let update = |query: &str| format!("/nic/update?username=mock&password=secret&{}", query);
+
+    let (status, _, body) = get_text(&app, &update("hostname=nas.example.com&myip=1.2.3.4"), &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "good 1.2.3.4");
+    let (_, _, body) = get_text(&app, &update("hostname=nas.example.com&myip=1.2.3.4"), &[]).await;
+    assert_eq!(body, "nochg 1.2.3.4");
+
+    // Without myip the client's address is used
+    let request = Request::get(update("hostname=nas.example.com")).body(Body::empty()).unwrap();
+    let (_, _, body) = send_text(&app, from_peer(request, "5.6.7.8")).await;
+    assert_eq!(body, "good 5.6.7.8");
+
+    let (status, _, body) = get_text(&app, "/nic/update?username=mock&password=wrong&hostname=nas.example.com&myip=1.2.3.4", &[]).await;
+    assert_eq!((status, body.as_str()), (StatusCode::OK, "badauth"));
+    let (_, _, body) = get_text(&app, "/nic/update?username=other&password=secret&hostname=nas.example.com&myip=1.2.3.4", &[]).await;
+    assert_eq!(body, "badauth");
+    let (_, _, body) = get_text(&app, &update("hostname=not_a_host!&myip=1.2.3.4"), &[]).await;
+    assert_eq!(body, "notfqdn");
+    let (_, _, body) = get_text(&app, &update("hostname=v4.example.com&myip=2001:db8::1"), &[]).await;
+    assert_eq!(body, "nohost");
+}
+
+// Requests as captured from ddclient 3.11 (`protocol=dyndns2`) and inadyn 2.12
+// (`provider default@dyndns.org`) pointed at this server.
+const BASIC_AUTH: &str = "Basic bW9jazpzZWNyZXQ=";
+
+#[tokio::test]
+async fn ddclient_updates_several_hosts() {
+    let app = router("compat_ddclient", CONFIG);
+    let headers = [("authorization", BASIC_AUTH), ("user-agent", "ddclient/3.11.2")];
+
+    let (status, _, body) = get_text(
+        &app,
+        "/nic/update?system=dyndns&hostname=home.example.com,v4.example.com&myip=1.2.3.4",
+        &headers,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "good 1.2.3.4\ngood 1.2.3.4");
+
+    // ddclient 4 sends both families in one myip
+    let (_, _, body) = get_text(
+        &app,
+        "/nic/update?system=dyndns&hostname=home.example.com,v4.example.com,bad_host!&myip=1.2.3.4,2001:db8::1",
+        &headers,
+    )
+    .await;
+    assert_eq!(body, "good 1.2.3.4,2001:db8::1\nnochg 1.2.3.4\nnotfqdn");
+
+    let (_, _, body) = get_text(
+        &app,
+        "/nic/update?system=dyndns&hostname=home.example.com&myip=1.2.3.4",
+        &[("authorization", "Basic bW9jazp3cm9uZw=="), ("user-agent", "ddclient/3.11.2")],
+    )
+    .await;
+    assert_eq!(body, "badauth");
+}
+
+#[tokio::test]
+async fn inadyn_checks_ip_and_updates() {
+    let app = router("compat_inadyn", CONFIG);
+    let request = |uri: &str| {
+        let request = Request::get(uri).header("authorization", BASIC_AUTH).header("user-agent", "inadyn/2.12.0");
+        from_peer(request.body(Body::empty()).unwrap(), "5.6.7.8")
+    };
+
+    let (status, _, body) = send_text(&app, request("/checkip")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Current IP Address: 5.6.7.8"), "{}", body);
+
+    let (_, _, body) = send_text(&app, request("/nic/update?hostname=home.example.com&myip=5.6.7.8")).await;
+    assert_eq!(body, "good 5.6.7.8");
+}
+
+#[tokio::test]
+async fn checkip_answers_lan_clients_with_the_public_address() {
+    let server = MockServer::start().await;
+    Mock::given(path("/ip"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("203.0.113.9"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let app = router(
+        "compat_checkip_proxy",
+        &format!("[detect]\nipv4_sources = [\"{}/ip\"]\ncheckip_proxy = true\n{}", server.uri(), CONFIG),
+    );
+
+    let request = |peer: &str| from_peer(Request::get("/checkip").body(Body::empty()).unwrap(), peer);
+
+    for _ in 0..2 {
+        let (status, _, body) = send_text(&app, request("192.168.1.20")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("Current IP Address: 203.0.113.9"), "{}", body);
+    }
+    // Clients reaching the server from outside see themselves
+    let (_, _, body) = send_text(&app, request("5.6.7.8")).await;
+    assert!(body.contains("Current IP Address: 5.6.7.8"), "{}", body);
+}
+
+#[tokio::test]
+async fn dyndns_refuses_offline_too_many_hosts_and_abuse() {
+    let app = router(
+        "compat_dyndns_codes",
+        &format!("[server]\nrate_limit_per_ip = 3\n{}", CONFIG),
+    );
+    let request = |uri: &str| {
+        let request = Request::get(uri).header("authorization", BASIC_AUTH);
+        from_peer(request.body(Body::empty()).unwrap(), "5.6.7.8")
+    };
+
+    let (_, _, body) = send_text(&app, request("/nic/update?hostname=home.example.com&offline=YES")).await;
+    assert_eq!(body, "!donator");
+
+    let hosts: Vec<String> = (0..21).map(|i| format!("h{}.example.com", i)).collect();
+    let (_, _, body) = send_text(&app, request(&format!("/nic/update?hostname={}", hosts.join(",")))).await;
+    assert_eq!(body, "numhost");
+
+    let (_, _, body) = send_text(&app, request("/nic/update?hostname=home.example.com")).await;
+    assert_eq!(body, "good 5.6.7.8");
+    let (status, _, body) = send_text(&app, request("/nic/update?hostname=home.example.com")).await;
+    assert_eq!((status, body.as_str()), (StatusCode::OK, "abuse"));
+}
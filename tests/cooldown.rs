@@ -0,0 +1,51 @@
+//! Limiting how often one host is sent to the provider.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use tower::ServiceExt;
+
+use common::{get, router};
+
+const CONFIG: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+min_update_interval_secs = 300
+
+[[providers.hosts]]
+name = "chatty.example.com"
+min_update_interval_secs = 0
+"#;
+
+#[tokio::test]
+async fn holds_host_updates_for_the_interval() {
+    let app = router("cooldown", CONFIG);
+
+    let (status, _) = get(&app, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The same address is answered without asking the provider
+    let (status, body) = get(&app, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["message"].as_str().unwrap().contains("updated 0s ago"), "{}", body);
+
+    // A new one has to wait
+    let request = Request::get("/ddns/mock/home.example.com/5.6.7.8").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+    assert!((295..=300).contains(&retry_after), "{}", retry_after);
+    let (_, body) = get(&app, "/ddns/mock/home.example.com/5.6.7.8", &[]).await;
+    assert_eq!(body["code"], "too_soon");
+
+    // Hosts with the interval turned off are updated every time
+    for ip in ["1.2.3.4", "5.6.7.8"] {
+        let (status, _) = get(&app, &format!("/ddns/mock/chatty.example.com/{}", ip), &[]).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (_, debug) = get(&app, "/debug/mock", &[]).await;
+    assert_eq!(debug["updates"].as_array().unwrap().len(), 3, "{}", debug);
+}
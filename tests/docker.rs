@@ -0,0 +1,100 @@
+//! `[docker]` sync of hosts labelled on containers.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::Router;
+use serde_json::json;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{get, router};
+
+async fn records(app: &Router) -> Vec<String> {
+    let (_, body) = get(app, "/dns/mock/records", &[]).await;
+    let mut records: Vec<String> = body["records"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| format!("{} {}", r["name"].as_str().unwrap(), r["content"].as_str().unwrap()))
+        .collect();
+    records.sort();
+    records
+}
+
+/// Polls the records until `done` holds.
+async fn wait_for(app: &Router, done: impl Fn(&[String]) -> bool) -> Vec<String> {
+    let mut current = Vec::new();
+    for _ in 0..150 {
+        current = records(app).await;
+        if done(&current) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    current
+}
+
+#[tokio::test]
+async fn manages_records_of_labelled_containers() {
+    let server = MockServer::start().await;
+    Mock::given(path("/ip"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("1.2.3.4\n"))
+        .mount(&server)
+        .await;
+    // The event stream ends right away, so changes are seen on the next tick
+    Mock::given(method("GET"))
+        .and(path("/events"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/containers/json"))
+        .and(query_param("filters", r#"{"label":["ddns.host"]}"#))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "Names": ["/app"], "Labels": { "ddns.host": "App.example.com, *.example.com" } },
+            { "Names": ["/db"], "Labels": { "ddns.host": "db.example.com", "ddns.provider": "other" } },
+        ])))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    // Then the app container stopped
+    Mock::given(method("GET"))
+        .and(path("/containers/json"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "Names": ["/api"], "Labels": { "ddns.host": "api.example.com" } },
+        ])))
+        .mount(&server)
+        .await;
+
+    let app = router(
+        "docker_sync",
+        &format!(
+            r#"
+[detect]
+ipv4_sources = ["{0}/ip"]
+
+[docker]
+enabled = true
+provider = "mock"
+socket = "tcp://{1}"
+interval_secs = 1
+record_type = "A"
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#,
+            server.uri(),
+            server.address()
+        ),
+    );
+
+    // The db container names an unknown provider
+    let found = wait_for(&app, |records| !records.is_empty()).await;
+    assert_eq!(found, ["app.example.com 1.2.3.4"]);
+
+    let found = wait_for(&app, |records| records.len() == 1 && records[0].starts_with("api.")).await;
+    assert_eq!(found, ["api.example.com 1.2.3.4"]);
+}
@@ -0,0 +1,68 @@
+//! Updating a host's A and AAAA records from one request.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+
+use common::{from_peer, get, router, send};
+
+const CONFIG: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+
+[[providers.hosts]]
+name = "v4.example.com"
+record_type = "A"
+"#;
+
+#[tokio::test]
+async fn updates_both_records_in_one_request() {
+    let app = router("dual", CONFIG);
+
+    let (status, body) = get(
+        &app,
+        "/ddns/mock/home.example.com?ipv4=1.2.3.4&ipv6=2001:db8::1&key=secret",
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["success"], true);
+    assert_eq!(body["host"], "home.example.com");
+    assert_eq!(body["results"][0]["record_type"], "A");
+    assert_eq!(body["results"][0]["ip"], "1.2.3.4");
+    assert_eq!(body["results"][0]["changed"], true);
+    assert_eq!(body["results"][1]["record_type"], "AAAA");
+    assert_eq!(body["results"][1]["ip"], "2001:db8::1");
+
+    let (_, records) = get(&app, "/dns/mock/records?key=secret", &[]).await;
+    assert_eq!(records["records"].as_array().unwrap().len(), 2, "{}", records);
+
+    // An empty placeholder is skipped, and without any address the source IP is used
+    let (_, body) = get(&app, "/api/v1/ddns/mock/home.example.com?ipv4=1.2.3.4&ipv6=&key=secret", &[]).await;
+    assert_eq!(body["results"].as_array().unwrap().len(), 1);
+    assert_eq!(body["results"][0]["changed"], false);
+    let request = Request::get("/ddns/mock/home.example.com?key=secret").body(Body::empty()).unwrap();
+    let (_, body) = send(&app, from_peer(request, "5.6.7.8")).await;
+    assert_eq!(body["results"][0]["ip"], "5.6.7.8");
+}
+
+#[tokio::test]
+async fn checks_both_addresses_before_changing_anything() {
+    let app = router("dual_rejected", CONFIG);
+
+    // The IPv4 half would be fine, but nothing is updated
+    let (status, body) = get(&app, "/ddns/mock/v4.example.com?ipv4=1.2.3.4&ipv6=2001:db8::1&key=secret", &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "record_type_not_enabled");
+    let (status, body) = get(&app, "/ddns/mock/home.example.com?ipv4=2001:db8::1&key=secret", &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_ip");
+    let (status, _) = get(&app, "/ddns/mock/home.example.com?ipv4=1.2.3.4&ipv6=2001:db8::1&key=wrong", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (_, records) = get(&app, "/dns/mock/records?key=secret", &[]).await;
+    assert_eq!(records["records"].as_array().unwrap().len(), 0, "{}", records);
+}
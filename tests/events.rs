@@ -0,0 +1,110 @@
+//! `/events`: provider call outcomes streamed as Server-Sent Events.
+
+mod common;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use futures_util::StreamExt;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tower::ServiceExt;
+
+use common::{get, router, send};
+
+const CONFIG: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+
+[[providers]]
+name = "other"
+type = "mock"
+key = "other-secret"
+"#;
+
+#[tokio::test]
+async fn streams_updates_to_authorized_subscribers() {
+    let app = router("events_stream", CONFIG);
+
+    let (status, _) = get(&app, "/events", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = get(&app, "/events?provider=nope&key=secret", &[]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let response = app
+        .clone()
+        .oneshot(Request::get("/events?provider=mock&key=secret").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let mut stream = response.into_body().into_data_stream();
+
+    // Only the subscribed provider's events arrive
+    get(&app, "/ddns/other/home.example.com/5.6.7.8?key=other-secret", &[]).await;
+    let (status, _) = get(&app, "/ddns/mock/home.example.com/1.2.3.4?key=secret", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let chunk = tokio::time::timeout(Duration::from_secs(5), stream.next()).await.unwrap().unwrap().unwrap();
+    let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+    let data = chunk.strip_prefix("data: ").unwrap_or_else(|| panic!("not an event: {}", chunk));
+    let event: Value = serde_json::from_str(data.trim_end()).unwrap();
+    assert_eq!(event["action"], "update");
+    assert_eq!(event["provider"], "mock");
+    assert_eq!(event["host"], "home.example.com");
+    assert_eq!(event["new"], "1.2.3.4");
+    assert_eq!(event["success"], true);
+    assert_eq!(event["changed"], true);
+    assert_eq!(event["change"], "created");
+}
+
+#[tokio::test]
+async fn checks_a_subscription_to_every_provider_once() {
+    let app = router(
+        "events_every_provider",
+        r#"
+[server]
+rate_limit_per_key = 1
+
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+
+[[providers]]
+name = "other"
+type = "mock"
+key = "secret"
+"#,
+    );
+
+    // One request, one token from the key's bucket
+    let response = app.clone().oneshot(Request::get("/events?key=secret").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // A signature is only good once, however many providers it covers
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs().to_string();
+    let message = format!("GET\n/events\n{}\n{}", hex(&Sha256::digest(b"")), timestamp);
+    let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+    mac.update(message.as_bytes());
+    let signed = || {
+        Request::get("/events")
+            .header("x-timestamp", &timestamp)
+            .header("x-signature", hex(&mac.clone().finalize().into_bytes()))
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = app.clone().oneshot(signed()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let (status, body) = send(&app, signed()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "Signature already used");
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,69 @@
+//! Request limits, strict path decoding and security headers.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use tower::ServiceExt;
+
+use common::{get, router, send};
+
+const CONFIG: &str = r#"
+[server]
+max_body_bytes = 64
+max_uri_length = 100
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#;
+
+#[tokio::test]
+async fn rejects_long_uris_and_large_bodies() {
+    let app = router("hardening_limits", CONFIG);
+
+    let (status, body) = get(&app, &format!("/ddns/mock/{}.example.com/1.2.3.4", "a".repeat(80)), &[]).await;
+    assert_eq!(status, StatusCode::URI_TOO_LONG);
+    assert_eq!(body["code"], "invalid_request");
+
+    let request = Request::post("/api/v1/update")
+        .header("content-type", "application/json")
+        .body(Body::from(format!(r#"{{"provider": "mock", "host": "{}.example.com"}}"#, "a".repeat(60))))
+        .unwrap();
+    let (status, _) = send(&app, request).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn rejects_ambiguous_path_encodings() {
+    let app = router("hardening_paths", CONFIG);
+
+    for uri in [
+        "/ddns/mock/home%2Fexample.com/1.2.3.4",
+        "/ddns/mock/%2e%2e/1.2.3.4",
+        "/ddns/mock/home%00.example.com/1.2.3.4",
+        "/ddns/mock/home%zz.example.com/1.2.3.4",
+        "/ddns/mock/home%ff.example.com/1.2.3.4",
+    ] {
+        let (status, body) = get(&app, uri, &[]).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+        assert_eq!(body["code"], "invalid_request", "{}", uri);
+    }
+
+    let (status, _) = get(&app, "/ddns/mock/home.example.com/2001%3Adb8%3A%3A1", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn sets_security_headers_unless_disabled() {
+    let app = router("hardening_headers", CONFIG);
+    let response = app.oneshot(Request::get("/health").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.headers()["x-content-type-options"], "nosniff");
+    assert_eq!(response.headers()["x-frame-options"], "DENY");
+    assert_eq!(response.headers()["referrer-policy"], "no-referrer");
+    assert_eq!(response.headers()["cache-control"], "no-store");
+
+    let app = router("hardening_no_headers", &CONFIG.replace("[server]", "[server]\nsecurity_headers = false"));
+    let response = app.oneshot(Request::get("/health").body(Body::empty()).unwrap()).await.unwrap();
+    assert!(response.headers().get("x-frame-options").is_none());
+}
@@ -0,0 +1,120 @@
+//! `[hooks]` commands run when a host's address changes.
+
+#![cfg(unix)]
+
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+
+use common::{get, router};
+
+#[tokio::test]
+async fn runs_on_change_with_the_old_and_new_address() {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("hooks_on_change.log");
+    let _ = std::fs::remove_file(&path);
+
+    let app = router(
+        "hooks_on_change",
+        &format!(
+            r#"
+[hooks]
+on_change = 'echo "$DDNS_PROVIDER $DDNS_HOST [$DDNS_OLD_IP] $DDNS_NEW_IP" >> {}'
+
+[[providers]]
+name = "mock"
+type = "mock"
+
+[[providers.hosts]]
+name = "quiet.example.com"
+on_change = ""
+"#,
+            path.display()
+        ),
+    );
+
+    for uri in [
+        "/ddns/mock/home.example.com/1.2.3.4",
+        // Unchanged, so no hook
+        "/ddns/mock/home.example.com/1.2.3.4",
+        "/ddns/mock/home.example.com/5.6.7.8",
+        "/ddns/mock/quiet.example.com/1.2.3.4",
+    ] {
+        let (status, body) = get(&app, uri, &[]).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        // Hooks run in the background; keep their order in the file
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+
+    let mut lines = Vec::new();
+    for _ in 0..50 {
+        lines = std::fs::read_to_string(&path).unwrap_or_default().lines().map(str::to_string).collect();
+        if lines.len() >= 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(
+        lines,
+        ["mock home.example.com [] 1.2.3.4", "mock home.example.com [1.2.3.4] 5.6.7.8"]
+    );
+}
+
+#[tokio::test]
+async fn points_wireguard_peers_at_the_new_address() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let log = dir.join("hooks_wireguard.log");
+    let _ = std::fs::remove_file(&log);
+    // Stands in for `wg`, recording its arguments
+    let wg = dir.join("hooks_fake_wg");
+    std::fs::write(&wg, format!("#!/bin/sh\necho \"$@\" >> {}\n", log.display())).unwrap();
+    std::fs::set_permissions(&wg, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let app = router(
+        "hooks_wireguard",
+        &format!(
+            r#"
+[hooks]
+wg_command = "{}"
+
+[[hooks.wireguard]]
+interface = "wg0"
+peer = "xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg="
+host = "office.example.com"
+port = 51821
+record_type = "AAAA"
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#,
+            wg.display()
+        ),
+    );
+
+    for uri in [
+        // Only the IPv6 address is used for this peer
+        "/ddns/mock/office.example.com/1.2.3.4",
+        "/ddns/mock/office.example.com/2001:db8::1",
+        "/ddns/mock/home.example.com/2001:db8::2",
+    ] {
+        let (status, body) = get(&app, uri, &[]).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    // Commands run in the background; give a stray second one time to show up
+    for _ in 0..50 {
+        if log.exists() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(
+        std::fs::read_to_string(&log).unwrap(),
+        "set wg0 peer xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg= endpoint [2001:db8::1]:51821\n"
+    );
+}
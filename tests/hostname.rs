@@ -0,0 +1,42 @@
+//! Validation and normalization of the `{host}` path segment.
+
+mod common;
+
+use axum::http::StatusCode;
+
+use common::{get, router};
+
+const CONFIG: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+"#;
+
+#[tokio::test]
+async fn normalizes_case_trailing_dots_and_idns() {
+    let app = router("hostname_normalize", CONFIG);
+
+    for uri in ["/ddns/mock/Home.Example.COM./1.2.3.4", "/ddns/mock/b%C3%BCcher.example.com/1.2.3.4"] {
+        let (status, body) = get(&app, uri, &[]).await;
+        assert_eq!(status, StatusCode::OK, "{}: {}", uri, body);
+    }
+
+    let (_, debug) = get(&app, "/debug/mock", &[]).await;
+    let hosts: Vec<_> = debug["updates"].as_array().unwrap().iter().map(|u| u["host"].clone()).collect();
+    assert_eq!(hosts, ["home.example.com", "xn--bcher-kva.example.com"]);
+}
+
+#[tokio::test]
+async fn rejects_malformed_hostnames() {
+    let app = router("hostname_invalid", CONFIG);
+    let long_label = "a".repeat(64);
+
+    for host in ["a..example.com", "-a.example.com", "a%20b.example.com", &format!("{}.example.com", long_label)] {
+        let (status, body) = get(&app, &format!("/ddns/mock/{}/1.2.3.4", host), &[]).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", host);
+        assert_eq!(body["code"], "invalid_host", "{}", host);
+    }
+
+    let (_, debug) = get(&app, "/debug/mock", &[]).await;
+    assert!(debug["updates"].as_array().unwrap().is_empty());
+}
@@ -0,0 +1,125 @@
+//! Replaying the first result to retried updates.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+
+use common::{from_peer, get, get_text, router, send_text};
+
+const CONFIG: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+"#;
+
+#[tokio::test]
+async fn replays_retries_with_the_same_key() {
+    let app = router("idempotency_key", CONFIG);
+    let uri = "/ddns/mock/home.example.com?ipv4=1.2.3.4&key=secret";
+
+    // A failed attempt is not remembered
+    let (status, _) = get(&app, "/ddns/mock/home.example.com?ipv4=1.2.3.4&key=wrong", &[("idempotency-key", "retry-1")]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = get(&app, uri, &[("idempotency-key", "retry-1")]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["results"][0]["changed"], true);
+
+    // The retry gets the original answer instead of updating again
+    let (status, headers, body) = get_text(&app, uri, &[("idempotency-key", "retry-1")]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers.contains_key("idempotent-replayed"));
+    assert!(body.contains(r#""changed":true"#), "{}", body);
+
+    let (_, body) = get(&app, uri, &[("idempotency-key", "retry-2")]).await;
+    assert_eq!(body["results"][0]["changed"], false);
+    let (_, headers, _) = get_text(&app, uri, &[]).await;
+    assert!(!headers.contains_key("idempotent-replayed"));
+}
+
+#[tokio::test]
+async fn keeps_keys_to_their_caller_and_request() {
+    let app = router("idempotency_scoped", CONFIG);
+    let uri = "/ddns/mock/home.example.com?ipv4=1.2.3.4&key=secret";
+
+    let (status, _) = get(&app, uri, &[("idempotency-key", "retry-1")]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Someone without the credentials doesn't get the stored answer
+    let (status, headers, _) = get_text(&app, "/ddns/mock/home.example.com?ipv4=1.2.3.4", &[("idempotency-key", "retry-1")]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(!headers.contains_key("idempotent-replayed"));
+
+    // The same key for another update is a mistake, not a retry
+    let (status, body) = get(&app, "/ddns/mock/home.example.com?ipv4=5.6.7.8&key=secret", &[("idempotency-key", "retry-1")]).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "invalid_request");
+    let (_, records) = get(&app, "/dns/mock/records?key=secret", &[]).await;
+    assert_eq!(records["records"][0]["content"], "1.2.3.4", "{}", records);
+}
+
+#[tokio::test]
+async fn dedupes_identical_requests_when_enabled() {
+    let app = router(
+        "idempotency_dedupe",
+        &format!("[server]\ndedupe_retries = true\n{}", CONFIG),
+    );
+    let uri = "/nic/update?username=mock&password=secret&hostname=home.example.com&myip=1.2.3.4";
+
+    let (_, headers, body) = get_text(&app, uri, &[]).await;
+    assert_eq!(body, "good 1.2.3.4");
+    assert!(!headers.contains_key("idempotent-replayed"));
+    let (_, headers, body) = get_text(&app, uri, &[]).await;
+    assert_eq!(body, "good 1.2.3.4");
+    assert!(headers.contains_key("idempotent-replayed"));
+
+    // DynDNS2 error codes come with status 200 but are not replayed
+    let uri = "/nic/update?username=mock&password=wrong&hostname=home.example.com&myip=1.2.3.4";
+    assert!(!get_text(&app, uri, &[]).await.1.contains_key("idempotent-replayed"));
+    assert!(!get_text(&app, uri, &[]).await.1.contains_key("idempotent-replayed"));
+
+    // Another client address is another request
+    let request = |peer: &str| {
+        let request = Request::get("/nic/update?username=mock&password=secret&hostname=home.example.com");
+        from_peer(request.body(Body::empty()).unwrap(), peer)
+    };
+    let (_, _, body) = send_text(&app, request("5.6.7.8")).await;
+    assert_eq!(body, "good 5.6.7.8");
+    let (_, _, body) = send_text(&app, request("5.6.7.9")).await;
+    assert_eq!(body, "good 5.6.7.9");
+}
+
+#[tokio::test]
+async fn dedupes_only_the_same_callers_requests() {
+    let app = router(
+        "idempotency_dedupe_callers",
+        &format!(
+            "[server]\ndedupe_retries = true\n\n[[auth.tokens]]\nname = \"writer\"\ntoken = \"token-1\"\n\n[[auth.tokens]]\nname = \"reader\"\ntoken = \"token-2\"\nscopes = [\"read\"]\n{}",
+            CONFIG
+        ),
+    );
+    let request = |token: &str| {
+        let request = Request::post("/api/v1/update")
+            .header("content-type", "application/json")
+            .header("authorization", format!("Bearer {}", token))
+            .body(Body::from(r#"{"provider": "mock", "host": "home.example.com", "ip": "1.2.3.4"}"#))
+            .unwrap();
+        from_peer(request, "5.6.7.8")
+    };
+
+    let (status, _, body) = send_text(&app, request("token-1")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    // Same body from the same address, but not the same caller
+    let (status, headers, _) = send_text(&app, request("token-2")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(!headers.contains_key("idempotent-replayed"));
+    let (status, headers, _) = send_text(&app, request("wrong")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(!headers.contains_key("idempotent-replayed"));
+
+    let (_, headers, _) = send_text(&app, request("token-1")).await;
+    assert!(headers.contains_key("idempotent-replayed"));
+}
@@ -0,0 +1,33 @@
+//! Picking an IPv6 address of a local interface.
+
+use std::net::Ipv6Addr;
+
+use ddns_rust::config::Ipv6Selection;
+use ddns_rust::interface::{parse_if_inet6, select};
+
+/// A link-local, a unique local, a deprecated, a privacy and an EUI-64 address on eth0
+const IF_INET6: &str = "\
+00000000000000000000000000000001 01 80 10 80       lo
+fe80000000000000021122fffe334455 02 40 20 80     eth0
+fd000000000000000000000000000002 02 40 00 80     eth0
+20010db8000000010000000000000099 02 40 00 20     eth0
+20010db80000000158c3a1b2c3d4e5f6 02 40 00 01     eth0
+2001db80000000010211 22fffe334455 02 40 00 00     eth0
+2001db80000000010211 22fffe334455 02 40 00 00     wlan0
+20010db800000001021122fffe334455 02 40 00 00     eth0
+";
+
+#[test]
+fn selects_public_addresses_by_kind() {
+    let addresses = parse_if_inet6(IF_INET6);
+    assert_eq!(addresses.len(), 6, "malformed lines are skipped");
+
+    let stable: Ipv6Addr = "2001:db8:0:1:211:22ff:fe33:4455".parse().unwrap();
+    let temporary: Ipv6Addr = "2001:db8:0:1:58c3:a1b2:c3d4:e5f6".parse().unwrap();
+    assert_eq!(select(&addresses, "eth0", Ipv6Selection::Stable), Some(stable));
+    assert_eq!(select(&addresses, "eth0", Ipv6Selection::Eui64), Some(stable));
+    assert_eq!(select(&addresses, "eth0", Ipv6Selection::Temporary), Some(temporary));
+    assert_eq!(select(&addresses, "eth0", Ipv6Selection::Any), Some(temporary));
+    assert_eq!(select(&addresses, "lo", Ipv6Selection::Any), None);
+    assert_eq!(select(&addresses, "wlan0", Ipv6Selection::Any), None);
+}
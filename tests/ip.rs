@@ -0,0 +1,49 @@
+//! The `/ip` echo endpoint and trusted proxies.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::json;
+
+use common::{from_peer, router, send, send_text};
+
+const CONFIG: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+"#;
+
+fn request_from(peer: &str, uri: &str, forwarded_for: &str) -> Request<Body> {
+    from_peer(Request::get(uri).header("x-forwarded-for", forwarded_for).body(Body::empty()).unwrap(), peer)
+}
+
+#[tokio::test]
+async fn echoes_the_client_address_as_text_or_json() {
+    let app = router("ip_echo", CONFIG);
+
+    // Without trusted proxies the forwarded address is ignored
+    let (_, _, body) = send_text(&app, request_from("2001:db8::7", "/ip", "1.2.3.4")).await;
+    assert_eq!(body, "2001:db8::7\n");
+
+    let (status, body) = send(&app, request_from("1.2.3.4", "/ip?format=json", "5.6.7.8")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "ip": "1.2.3.4", "family": "ipv4", "api_version": 1 }));
+    let request = Request::get("/ip").header("accept", "application/json").body(Body::empty()).unwrap();
+    let (_, body) = send(&app, from_peer(request, "1.2.3.4")).await;
+    assert_eq!(body["ip"], "1.2.3.4");
+}
+
+#[tokio::test]
+async fn only_trusted_proxies_set_the_client_address() {
+    let app = router("ip_trusted_proxies", &format!("[server]\ntrusted_proxies = [\"10.0.0.0/8\"]\n{}", CONFIG));
+
+    let (_, _, body) = send_text(&app, request_from("10.0.0.1", "/ip", "1.2.3.4")).await;
+    assert_eq!(body, "1.2.3.4\n");
+    // Anyone else gets the address they connect from
+    let (_, _, body) = send_text(&app, request_from("198.51.100.9", "/ip", "1.2.3.4")).await;
+    assert_eq!(body, "198.51.100.9\n");
+
+    let (status, _) = send(&app, Request::get("/ip").header("x-forwarded-for", "1.2.3.4").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+}
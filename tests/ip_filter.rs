@@ -0,0 +1,44 @@
+//! Refusing to publish private and other non-public addresses.
+
+mod common;
+
+use axum::http::StatusCode;
+
+use common::{get, router};
+
+const CONFIG: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+
+[[providers.hosts]]
+name = "nas.lan.example.com"
+allow_private_ips = true
+"#;
+
+#[tokio::test]
+async fn rejects_non_public_addresses_by_default() {
+    let app = router("ip_filter", CONFIG);
+
+    for ip in ["192.168.1.10", "10.0.0.1", "172.20.0.1", "100.64.0.1", "127.0.0.1", "169.254.1.1", "224.0.0.1", "fd00::1", "fe80::1", "::1"] {
+        let (status, body) = get(&app, &format!("/ddns/mock/home.example.com/{}", ip), &[]).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}: {}", ip, body);
+        assert_eq!(body["code"], "invalid_ip");
+    }
+    let (_, body) = get(&app, "/ddns/mock/home.example.com?ipv4=1.2.3.4&ipv6=fd00::1", &[]).await;
+    assert_eq!(body["error"], "fd00::1 is a unique local address, not allowed for home.example.com (see allow_private_ips)");
+
+    // Neither record was touched
+    let (_, records) = get(&app, "/dns/mock/records", &[]).await;
+    assert_eq!(records["records"].as_array().unwrap().len(), 0, "{}", records);
+}
+
+#[tokio::test]
+async fn allows_private_addresses_where_configured() {
+    let app = router("ip_filter_allowed", CONFIG);
+
+    let (status, body) = get(&app, "/ddns/mock/nas.lan.example.com/192.168.1.10", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _) = get(&app, "/ddns/mock/home.example.com/100.63.255.255", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+}
@@ -0,0 +1,96 @@
+//! `[kubernetes]` sync of annotated Ingress and Service hosts.
+
+#![cfg(feature = "kubernetes")]
+
+mod common;
+
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use wiremock::matchers::{header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{get, router};
+
+fn object(name: &str, hostnames: Option<&str>) -> Value {
+    let annotations = match hostnames {
+        Some(hostnames) => json!({ "ddns-rust.io/hostname": hostnames }),
+        None => json!({}),
+    };
+    json!({ "metadata": { "namespace": "default", "name": name, "annotations": annotations } })
+}
+
+#[tokio::test]
+async fn updates_hosts_annotated_on_ingresses_and_services() {
+    let server = MockServer::start().await;
+    Mock::given(path("/ip"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("1.2.3.4\n"))
+        .mount(&server)
+        .await;
+    // Two pages of Ingresses
+    Mock::given(method("GET"))
+        .and(path("/apis/networking.k8s.io/v1/ingresses"))
+        .and(header("authorization", "Bearer sa-token"))
+        .and(query_param("continue", "page-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "metadata": {},
+            "items": [object("blog", Some("Blog.example.com, bad..name, *.wild.example.com"))],
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/apis/networking.k8s.io/v1/ingresses"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "metadata": { "continue": "page-2" },
+            "items": [object("shop", Some("shop.example.com")), object("plain", None)],
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/services"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "metadata": {},
+            "items": [object("game", Some("game.example.com,shop.example.com"))],
+        })))
+        .mount(&server)
+        .await;
+
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(dir.join("kubernetes_token"), "sa-token\n").unwrap();
+    let app = router(
+        "kubernetes_sync",
+        &format!(
+            r#"
+[detect]
+ipv4_sources = ["{0}/ip"]
+
+[kubernetes]
+enabled = true
+provider = "mock"
+record_type = "A"
+api_server = "{0}"
+token_file = "kubernetes_token"
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#,
+            server.uri()
+        ),
+    );
+
+    // The first sync runs right away
+    let mut hosts = Value::Null;
+    for _ in 0..100 {
+        let (_, body) = get(&app, "/status", &[]).await;
+        hosts = body["providers"][0]["hosts"].clone();
+        if hosts.as_object().is_some_and(|h| h.len() == 3) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let mut names: Vec<&String> = hosts.as_object().unwrap().keys().collect();
+    names.sort();
+    assert_eq!(names, ["blog.example.com", "game.example.com", "shop.example.com"]);
+    assert_eq!(hosts["blog.example.com"]["ip"], "1.2.3.4");
+}
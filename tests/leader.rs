@@ -0,0 +1,79 @@
+//! `[leader]`: of two instances sharing a lock file, only one polls.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::Request;
+use axum::Router;
+use tower::ServiceExt;
+use wiremock::matchers::path;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{get, router};
+
+fn config(api_base: &str, lock_file: &str) -> String {
+    format!(
+        r#"
+[detect]
+ipv4_sources = ["{}/plain"]
+
+[leader]
+election = "file"
+lock_file = "{}"
+
+[[poll]]
+provider = "mock"
+hosts = ["home.example.com"]
+record_type = "A"
+interval_secs = 3600
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#,
+        api_base, lock_file
+    )
+}
+
+async fn metrics(app: &Router) -> String {
+    let response = app.clone().oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+async fn polled(app: &Router) -> bool {
+    let (_, body) = get(app, "/status", &[]).await;
+    !body["providers"][0]["hosts"]["home.example.com"]["ip"].is_null()
+}
+
+#[tokio::test]
+async fn only_the_leader_runs_scheduled_updates() {
+    let server = MockServer::start().await;
+    Mock::given(path("/plain"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("1.2.3.4\n"))
+        .mount(&server)
+        .await;
+    let lock_file = format!("{}/leader.lock", env!("CARGO_TARGET_TMPDIR"));
+    let first = router("leader_first", &config(&server.uri(), &lock_file));
+    let second = router("leader_second", &config(&server.uri(), &lock_file));
+
+    for _ in 0..100 {
+        if polled(&first).await || polled(&second).await {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    // Give the standby time to do what it shouldn't
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let (first_polled, second_polled) = (polled(&first).await, polled(&second).await);
+    assert!(first_polled != second_polled, "first {}, second {}", first_polled, second_polled);
+    let (leader, standby) = match first_polled {
+        true => (&first, &second),
+        false => (&second, &first),
+    };
+    assert!(metrics(leader).await.contains("\nddns_leader 1\n"));
+    assert!(metrics(standby).await.contains("\nddns_leader 0\n"));
+}
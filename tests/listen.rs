@@ -0,0 +1,74 @@
+//! Serving on several listen addresses at once.
+
+mod common;
+
+use std::time::Duration;
+
+use ddns_rust::config::Config;
+
+use common::load_config;
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+#[tokio::test]
+async fn serves_every_listen_address() {
+    // IPv4 and IPv6 wildcards on the same port, plus a second port
+    let (port, other) = (free_port(), free_port());
+    let config = load_config(
+        "listen",
+        &format!(
+            r#"
+[server]
+listen = ["0.0.0.0:{port}", "[::]:{port}", "127.0.0.1:{other}"]
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#
+        ),
+    );
+    let server = tokio::spawn(ddns_rust::run_server(config));
+
+    let client = reqwest::Client::new();
+    for url in [
+        format!("http://127.0.0.1:{}/health", port),
+        format!("http://[::1]:{}/health", port),
+        format!("http://127.0.0.1:{}/health", other),
+    ] {
+        let mut attempt = 0;
+        let response = loop {
+            match client.get(&url).send().await {
+                Ok(response) => break response,
+                Err(_) if attempt < 50 => {
+                    attempt += 1;
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+                Err(e) => panic!("{}: {}", url, e),
+            }
+        };
+        assert!(response.status().is_success(), "{}", url);
+    }
+    assert!(!server.is_finished());
+    server.abort();
+}
+
+#[test]
+fn rejects_duplicate_listen_addresses() {
+    let path = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("listen_duplicate.toml");
+    std::fs::write(
+        &path,
+        r#"
+[server]
+listen = ["0.0.0.0:3000", "0.0.0.0:3000"]
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#,
+    )
+    .unwrap();
+    let error = format!("{:#}", Config::load(&path, None).unwrap_err());
+    assert!(error.contains("server.listen[1]: duplicate address 0.0.0.0:3000"), "{}", error);
+}
@@ -0,0 +1,107 @@
+//! `/admin/maintenance`: updates acknowledged and queued without calling providers.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::json;
+
+use common::{get, router, send};
+
+const CONFIG: &str = r#"
+[[auth.tokens]]
+name = "ops"
+token = "ops-token"
+scopes = ["admin"]
+
+[[auth.tokens]]
+name = "router"
+token = "router-token"
+
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+
+[[providers.hosts]]
+name = "alias.example.com"
+record_type = "CNAME"
+"#;
+
+fn toggle(body: serde_json::Value, token: &str) -> Request<Body> {
+    Request::post("/admin/maintenance")
+        .header("content-type", "application/json")
+        .header("authorization", format!("Bearer {}", token))
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn queues_updates_until_maintenance_ends() {
+    let app = router("maintenance_mode", CONFIG);
+
+    let (status, _) = send(&app, toggle(json!({"enabled": true}), "wrong")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    // Update tokens and provider keys don't open the admin routes
+    let (status, _) = send(&app, toggle(json!({"enabled": true}), "router-token")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = get(&app, "/admin/maintenance?key=secret", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) = send(&app, toggle(json!({"enabled": true, "reason": "zone migration"}), "ops-token")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["enabled"], true);
+
+    // Acknowledged as unchanged; only the latest address per host is kept
+    for ip in ["1.2.3.4", "5.6.7.8"] {
+        let (status, body) = get(&app, &format!("/ddns/mock/home.example.com/{}?key=secret", ip), &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["success"], true);
+        assert_eq!(body["message"], format!("Maintenance mode, update of home.example.com to {} queued", ip));
+    }
+    let (status, body) = get(&app, "/ddns/mock/alias.example.com/home.example.com?key=secret", &[]).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["code"], "maintenance");
+
+    let (_, debug) = get(&app, "/debug/mock?key=secret", &[]).await;
+    assert_eq!(debug["updates"].as_array().unwrap().len(), 0, "{}", debug);
+    let (_, report) = get(&app, "/admin/maintenance", &[("authorization", "Bearer ops-token")]).await;
+    assert_eq!(report["reason"], "zone migration");
+    assert_eq!(report["pending"], 1);
+
+    let (status, body) = send(&app, toggle(json!({"enabled": false}), "ops-token")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["enabled"], false);
+    assert_eq!(body["pending"], 1);
+
+    for _ in 0..50 {
+        let (_, body) = get(&app, "/status", &[]).await;
+        if body["providers"][0]["hosts"]["home.example.com"]["ip"] == "5.6.7.8" {
+            let (_, debug) = get(&app, "/debug/mock?key=secret", &[]).await;
+            assert_eq!(debug["updates"].as_array().unwrap().len(), 1, "{}", debug);
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("queued update was not applied");
+}
+
+#[tokio::test]
+async fn stays_closed_without_an_admin_token() {
+    // No provider key and no `[auth]` tokens: updates are open, admin routes are not
+    let app = router("maintenance_anonymous", "[[providers]]\nname = \"mock\"\ntype = \"mock\"\n");
+
+    let request = Request::post("/admin/maintenance")
+        .header("content-type", "application/json")
+        .body(Body::from(json!({"enabled": true}).to_string()))
+        .unwrap();
+    let (status, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "unauthorized");
+    let (status, _) = get(&app, "/admin/maintenance", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (_, body) = get(&app, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(body["change"]["kind"], "created", "{}", body);
+}
@@ -0,0 +1,55 @@
+//! `[messages]`: localized and custom texts of update results and notifications.
+
+mod common;
+
+use common::{get, load_config, router};
+
+const PROVIDER: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+"#;
+
+#[tokio::test]
+async fn answers_in_the_configured_language() {
+    let app = router("messages_zh", &format!("[messages]\nlanguage = \"zh\"\n{}", PROVIDER));
+
+    let (_, body) = get(&app, "/ddns/mock/home.example.com/1.2.3.4?key=secret", &[]).await;
+    assert_eq!(body["message"], "已新建记录 home.example.com，值为 1.2.3.4");
+    let (_, body) = get(&app, "/ddns/mock/home.example.com/1.2.3.4?key=secret", &[]).await;
+    assert_eq!(body["message"], "记录 home.example.com 已是最新，无需修改");
+}
+
+#[tokio::test]
+async fn custom_messages_replace_only_their_kind() {
+    let config = format!("[messages]\nupdated = \"{{host}} ({{provider}}): {{old}} -> {{new}}\"\n{}", PROVIDER);
+    let app = router("messages_custom", &config);
+
+    let (_, body) = get(&app, "/ddns/mock/home.example.com/1.2.3.4?key=secret", &[]).await;
+    assert_eq!(body["message"], "Created new record home.example.com with IP 1.2.3.4");
+    let (_, body) = get(&app, "/ddns/mock/home.example.com/5.6.7.8?key=secret", &[]).await;
+    assert_eq!(body["message"], "home.example.com (mock): 1.2.3.4 -> 5.6.7.8");
+}
+
+#[test]
+fn localizes_notification_templates_left_at_their_default() {
+    let config = load_config(
+        "messages_notify",
+        &format!(
+            r#"
+[messages]
+language = "zh"
+
+[notifications.telegram]
+bot_token = "token"
+chat_id = "1"
+failure_template = "{{provider}} is down"
+{}"#,
+            PROVIDER
+        ),
+    );
+    let telegram = config.notifications.telegram.unwrap();
+    assert_eq!(telegram.templates.template, "DNS 记录 {host}（{provider}）已从 {old_ip} 变更为 {new_ip}");
+    assert_eq!(telegram.templates.failure_template, "{provider} is down");
+}
@@ -0,0 +1,478 @@
+//! Notifications sent on record changes and repeated failures.
+
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{cf_error, cf_page, cf_record, cf_result, get, router};
+
+fn telegram_config(api_base: &str, provider: &str) -> String {
+    format!(
+        r#"
+[notifications]
+failure_threshold = 2
+
+[notifications.telegram]
+bot_token = "123:abc"
+chat_id = "42"
+api_base = "{}"
+
+[[providers]]
+name = "mock"
+type = "mock"
+{}
+"#,
+        api_base, provider
+    )
+}
+
+async fn mock_telegram(server: &MockServer) {
+    Mock::given(method("POST"))
+        .and(path("/bot123:abc/sendMessage"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true, "result": {} })))
+        .mount(server)
+        .await;
+}
+
+/// JSON bodies received by `server`, waiting up to a second for `count` of them.
+async fn received(server: &MockServer, count: usize) -> Vec<Value> {
+    for _ in 0..100 {
+        let requests = server.received_requests().await.unwrap();
+        if requests.len() >= count {
+            return requests.iter().map(|r| r.body_json().unwrap()).collect();
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("expected {} notification(s)", count);
+}
+
+/// Texts sent to the Telegram mock, once `count` have arrived.
+async fn telegram_messages(server: &MockServer, count: usize) -> Vec<String> {
+    for _ in 0..100 {
+        let messages: Vec<String> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| r.url.path().ends_with("/sendMessage"))
+            .map(|r| r.body_json::<Value>().unwrap()["text"].as_str().unwrap().to_string())
+            .collect();
+        if messages.len() >= count {
+            return messages;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("expected {} Telegram message(s)", count);
+}
+
+#[tokio::test]
+async fn sends_telegram_message_when_record_changes() {
+    let server = MockServer::start().await;
+    mock_telegram(&server).await;
+    let app = router("notify_telegram_change", &telegram_config(&server.uri(), ""));
+
+    for ip in ["1.2.3.4", "1.2.3.4", "5.6.7.8"] {
+        let (status, _) = get(&app, &format!("/ddns/mock/home.example.com/{}", ip), &[]).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let messages = received(&server, 2).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(server.received_requests().await.unwrap().len(), 2, "unchanged update must not notify");
+    assert_eq!(messages[0]["chat_id"], "42");
+    assert_eq!(messages[0]["text"], "DNS record home.example.com (mock) changed from unknown to 1.2.3.4");
+    assert_eq!(messages[1]["text"], "DNS record home.example.com (mock) changed from 1.2.3.4 to 5.6.7.8");
+}
+
+#[tokio::test]
+async fn sends_telegram_message_when_record_set_changes() {
+    let server = MockServer::start().await;
+    mock_telegram(&server).await;
+    let app = router("notify_telegram_set_change", &telegram_config(&server.uri(), ""));
+
+    for ips in ["1.2.3.4,1.2.3.5", "1.2.3.4,1.2.3.5", "5.6.7.8,1.2.3.5"] {
+        let (status, _) = get(&app, &format!("/ddns/mock/home.example.com/{}", ips), &[]).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let messages = received(&server, 2).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(server.received_requests().await.unwrap().len(), 2, "unchanged set must not notify");
+    assert_eq!(messages[0]["text"], "DNS record home.example.com (mock) changed from unknown to 1.2.3.4");
+    assert_eq!(messages[1]["text"], "DNS record home.example.com (mock) changed from 1.2.3.4 to 5.6.7.8");
+}
+
+#[tokio::test]
+async fn sends_telegram_message_once_after_repeated_failures() {
+    let server = MockServer::start().await;
+    mock_telegram(&server).await;
+    let app = router("notify_telegram_failure", &telegram_config(&server.uri(), "fail_every = 1"));
+
+    for _ in 0..3 {
+        let (status, _) = get(&app, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let messages = received(&server, 1).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    let text = messages[0]["text"].as_str().unwrap();
+    assert!(
+        text.starts_with("Updates via mock failed 2 times in a row, last for home.example.com to 1.2.3.4: "),
+        "{}",
+        text
+    );
+}
+
+#[tokio::test]
+async fn alerts_once_per_provider_outage_and_on_recovery() {
+    let server = MockServer::start().await;
+    mock_telegram(&server).await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(500).set_body_json(cf_error(1000, "Internal error")))
+        .up_to_n_times(4)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(json!([]), 1, 1)))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("r1", "A", "a.example.com", "1.2.3.4"))))
+        .mount(&server)
+        .await;
+    let provider = format!("api_key = \"token\"\nzone_id = \"z1\"\napi_base = \"{}\"", server.uri());
+    let config = telegram_config(&server.uri(), &provider).replace("type = \"mock\"", "type = \"cloudflare\"");
+    let app = router("notify_recovery", &config);
+
+    // Several hosts failing during one outage raise a single alert
+    for host in ["a", "b", "a", "b"] {
+        let (status, _) = get(&app, &format!("/ddns/mock/{}.example.com/1.2.3.4", host), &[]).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+    let (status, _) = get(&app, "/ddns/mock/a.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let messages = telegram_messages(&server, 3).await;
+    assert!(messages[0].starts_with("Updates via mock failed 2 times in a row, last for b.example.com"), "{:?}", messages);
+    assert!(messages.contains(&"Updates via mock recovered after 4 failure(s): a.example.com is now 1.2.3.4".to_string()));
+    assert!(messages.contains(&"DNS record a.example.com (mock) changed from unknown to 1.2.3.4".to_string()));
+}
+
+#[tokio::test]
+async fn posts_to_webhooks_selected_per_provider() {
+    let server = MockServer::start().await;
+    for hook in ["/discord", "/slack"] {
+        Mock::given(method("POST"))
+            .and(path(hook))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+    }
+    let config = format!(
+        r#"
+[notifications.discord]
+webhook_url = "{uri}/discord"
+template = "{{host}}: {{old_ip}} -> {{new_ip}}"
+
+[notifications.slack]
+webhook_url = "{uri}/slack"
+
+[[providers]]
+name = "everywhere"
+type = "mock"
+
+[[providers]]
+name = "quiet"
+type = "mock"
+notify = ["slack"]
+"#,
+        uri = server.uri()
+    );
+    let app = router("notify_webhooks", &config);
+
+    let (status, _) = get(&app, "/ddns/everywhere/a.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    received(&server, 2).await;
+    let (status, _) = get(&app, "/ddns/quiet/b.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    received(&server, 3).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 3);
+    let discord: Vec<Value> = requests.iter().filter(|r| r.url.path() == "/discord").map(|r| r.body_json().unwrap()).collect();
+    assert_eq!(discord, [json!({ "content": "a.example.com: unknown -> 1.2.3.4" })]);
+    let slack: Vec<Value> = requests.iter().filter(|r| r.url.path() == "/slack").map(|r| r.body_json().unwrap()).collect();
+    assert_eq!(slack.len(), 2);
+    assert!(slack.contains(&json!({ "text": "DNS record b.example.com (quiet) changed from unknown to 1.2.3.4" })));
+}
+
+/// Accepts one SMTP session on a local port and sends the received message data.
+fn fake_smtp_server() -> (u16, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        writer.write_all(b"220 localhost ESMTP\r\n").unwrap();
+
+        let mut data = String::new();
+        let mut in_data = false;
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 {
+            if in_data {
+                if line == ".\r\n" {
+                    in_data = false;
+                    writer.write_all(b"250 OK\r\n").unwrap();
+                } else {
+                    data.push_str(&line);
+                }
+            } else {
+                let command = line.to_ascii_uppercase();
+                let reply: &[u8] = if command.starts_with("DATA") {
+                    in_data = true;
+                    b"354 Go ahead\r\n"
+                } else if command.starts_with("QUIT") {
+                    writer.write_all(b"221 Bye\r\n").unwrap();
+                    break;
+                } else {
+                    b"250 OK\r\n"
+                };
+                writer.write_all(reply).unwrap();
+            }
+            line.clear();
+        }
+        tx.send(data).unwrap();
+    });
+
+    (port, rx)
+}
+
+#[tokio::test]
+async fn sends_email_when_record_changes() {
+    let (port, rx) = fake_smtp_server();
+    let config = format!(
+        r#"
+[notifications.email]
+server = "127.0.0.1"
+port = {}
+tls = "none"
+from = "ddns <ddns@example.com>"
+to = ["me@example.com"]
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#,
+        port
+    );
+    let app = router("notify_email", &config);
+
+    let (status, _) = get(&app, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let data = tokio::task::spawn_blocking(move || rx.recv_timeout(Duration::from_secs(5)).unwrap())
+        .await
+        .unwrap();
+    assert!(data.contains("Subject: [ddns-rust] home.example.com changed to 1.2.3.4"), "{}", data);
+    assert!(data.contains("To: me@example.com"), "{}", data);
+    assert!(data.contains("DNS record home.example.com (mock) changed from unknown to 1.2.3.4"), "{}", data);
+}
+
+#[tokio::test]
+async fn publishes_to_ntfy_and_gotify() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/ntfy"))
+        .and(header("authorization", "Bearer tk_ntfy"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": "1" })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/gotify/message"))
+        .and(header("x-gotify-key", "app-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": 1 })))
+        .mount(&server)
+        .await;
+    let config = format!(
+        r#"
+[notifications.ntfy]
+url = "{uri}/ntfy"
+topic = "home-ddns"
+token = "tk_ntfy"
+priority = 4
+
+[notifications.gotify]
+url = "{uri}/gotify/"
+token = "app-token"
+title = "DDNS {{host}}"
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#,
+        uri = server.uri()
+    );
+    let app = router("notify_push", &config);
+
+    let (status, _) = get(&app, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    received(&server, 2).await;
+
+    let requests = server.received_requests().await.unwrap();
+    let body = |p: &str| requests.iter().find(|r| r.url.path() == p).unwrap().body_json::<Value>().unwrap();
+    let text = "DNS record home.example.com (mock) changed from unknown to 1.2.3.4";
+    assert_eq!(
+        body("/ntfy"),
+        json!({ "topic": "home-ddns", "title": "ddns-rust", "message": text, "priority": 4 })
+    );
+    assert_eq!(
+        body("/gotify/message"),
+        json!({ "title": "DDNS home.example.com", "message": text, "priority": 5 })
+    );
+}
+
+/// Reads one MQTT packet, returning the first header byte and the body.
+fn read_mqtt_packet(stream: &mut impl Read) -> (u8, Vec<u8>) {
+    let mut byte = [0u8; 1];
+    stream.read_exact(&mut byte).unwrap();
+    let header = byte[0];
+
+    let (mut length, mut shift) = (0usize, 0);
+    loop {
+        stream.read_exact(&mut byte).unwrap();
+        length |= ((byte[0] & 0x7f) as usize) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+
+    let mut body = vec![0u8; length];
+    stream.read_exact(&mut body).unwrap();
+    (header, body)
+}
+
+/// Accepts one MQTT connection and sends the published topic, header flags and payload.
+fn fake_mqtt_broker() -> (u16, mpsc::Receiver<(String, u8, Value)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let (connect, _) = read_mqtt_packet(&mut stream);
+        assert_eq!(connect, 0x10);
+        stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+
+        let (publish, body) = read_mqtt_packet(&mut stream);
+        assert_eq!(publish >> 4, 3);
+        let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+        let topic = String::from_utf8(body[2..2 + topic_len].to_vec()).unwrap();
+        let packet_id = &body[2 + topic_len..4 + topic_len];
+        stream.write_all(&[0x40, 0x02, packet_id[0], packet_id[1]]).unwrap();
+
+        let payload = serde_json::from_slice(&body[4 + topic_len..]).unwrap();
+        tx.send((topic, publish & 0x0f, payload)).unwrap();
+    });
+
+    (port, rx)
+}
+
+#[tokio::test]
+async fn publishes_ip_change_to_mqtt() {
+    let (port, rx) = fake_mqtt_broker();
+    let config = format!(
+        r#"
+[notifications.mqtt]
+host = "127.0.0.1"
+port = {}
+username = "ha"
+password = "secret"
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#,
+        port
+    );
+    let app = router("notify_mqtt", &config);
+
+    let (status, _) = get(&app, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (topic, flags, payload) = tokio::task::spawn_blocking(move || rx.recv_timeout(Duration::from_secs(5)).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(topic, "ddns-rust/mock/home.example.com");
+    // QoS 1, retained
+    assert_eq!(flags, 0b0011);
+    assert_eq!(payload["host"], "home.example.com");
+    assert_eq!(payload["old_ip"], Value::Null);
+    assert_eq!(payload["new_ip"], "1.2.3.4");
+}
+
+#[tokio::test]
+async fn warns_about_expiring_account_token() {
+    let server = MockServer::start().await;
+    mock_telegram(&server).await;
+    let expires = (time::OffsetDateTime::now_utc() + time::Duration::days(3))
+        .format(&time::format_description::well_known::Rfc3339)
+        .unwrap();
+    // Account-owned tokens are verified under their account
+    Mock::given(method("GET"))
+        .and(path("/accounts/acc1/tokens/verify"))
+        .and(header("authorization", "Bearer cf-token"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(cf_result(json!({ "id": "t1", "status": "active", "expires_on": expires }))),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let _app = router(
+        "notify_credentials",
+        &format!(
+            r#"
+[server]
+check_credentials = true
+
+[notifications.telegram]
+bot_token = "123:abc"
+chat_id = "42"
+api_base = "{0}"
+
+[[providers]]
+name = "cf"
+type = "cloudflare"
+api_key = "cf-token"
+api_base = "{0}"
+account_id = "acc1"
+zone_id = "z1"
+"#,
+            server.uri()
+        ),
+    );
+
+    let messages = telegram_messages(&server, 1).await;
+    assert!(
+        messages[0].starts_with("Credentials of cf need attention: API token expires on"),
+        "{}",
+        messages[0]
+    );
+}
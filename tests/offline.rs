@@ -0,0 +1,84 @@
+//! `offline=yes` updates parking a host or deleting its records.
+
+mod common;
+
+use axum::http::StatusCode;
+use axum::Router;
+
+use common::{get, get_text, router};
+
+const CONFIG: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+
+[[providers.hosts]]
+name = "parked.example.com"
+offline = "park"
+offline_ips = ["192.0.2.1", "2001:db8::dead"]
+
+[[providers.hosts]]
+name = "deleted.example.com"
+offline = "delete"
+"#;
+
+async fn records(app: &Router, host: &str) -> Vec<String> {
+    let (_, body) = get(app, "/dns/mock/records?key=secret", &[]).await;
+    body["records"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|r| r["name"] == host)
+        .map(|r| r["content"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn parks_or_deletes_hosts_going_offline() {
+    let app = router("offline", CONFIG);
+    for host in ["parked.example.com", "deleted.example.com"] {
+        get(&app, &format!("/ddns/mock/{}?ipv4=1.2.3.4&ipv6=2001:db8::1&key=secret", host), &[]).await;
+    }
+
+    let (status, body) = get(&app, "/ddns/mock/parked.example.com?offline=yes&key=secret", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["offline"], true);
+    assert_eq!(body["results"][0]["ip"], "192.0.2.1");
+    assert_eq!(body["results"][1]["ip"], "2001:db8::dead");
+    assert_eq!(records(&app, "parked.example.com").await, ["192.0.2.1", "2001:db8::dead"]);
+
+    let (status, body) = get(&app, "/ddns/mock/deleted.example.com?offline=yes&key=secret", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["results"][0]["record_type"], "A");
+    assert_eq!(body["results"][0]["changed"], true);
+    assert!(records(&app, "deleted.example.com").await.is_empty());
+
+    // Coming back online recreates the records
+    get(&app, "/ddns/mock/deleted.example.com/1.2.3.4?key=secret", &[]).await;
+    assert_eq!(records(&app, "deleted.example.com").await, ["1.2.3.4"]);
+
+    // Hosts without an offline setting keep their records
+    let (status, body) = get(&app, "/ddns/mock/home.example.com?offline=yes&key=secret", &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "offline_not_enabled");
+    let (status, _) = get(&app, "/ddns/mock/parked.example.com?offline=yes&key=wrong", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn dyndns_clients_go_offline_per_host() {
+    let app = router("offline_dyndns", CONFIG);
+    get(&app, "/ddns/mock/deleted.example.com/1.2.3.4?key=secret", &[]).await;
+
+    let (_, _, body) = get_text(
+        &app,
+        "/nic/update?username=mock&password=secret&hostname=parked.example.com,deleted.example.com,home.example.com&offline=yes",
+        &[],
+    )
+    .await;
+    assert_eq!(body, "good 192.0.2.1,2001:db8::dead\ngood\n!donator");
+
+    let (_, _, body) = get_text(&app, "/nic/update?username=mock&password=secret&hostname=parked.example.com,deleted.example.com&offline=yes", &[]).await;
+    assert_eq!(body, "nochg 192.0.2.1,2001:db8::dead\nnochg");
+}
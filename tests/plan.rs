@@ -0,0 +1,111 @@
+//! `plan` / `apply`: diffing desired addresses against provider records.
+
+mod common;
+
+use std::net::IpAddr;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use ddns_rust::plan::{self, Action};
+use ddns_rust::provider::{self, RecordOptions};
+
+use common::{load_config, router, send};
+
+#[tokio::test]
+async fn plans_and_applies_changes_to_a_messy_zone() {
+    let config = load_config(
+        "plan_apply",
+        r#"
+[[providers]]
+name = "mock"
+type = "mock"
+
+[[providers.hosts]]
+name = "office.example.com"
+ips = ["1.2.3.4", "1.2.3.5"]
+
+[[providers.hosts]]
+name = "stale.example.com"
+ips = ["10.0.0.1"]
+"#,
+    );
+    let providers = provider::create_all(&config).unwrap();
+    let mock = &providers["mock"];
+    let options = RecordOptions { ttl: 300, proxied: false };
+    let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+    mock.update_record("office.example.com", ip("1.2.3.4"), &options).await.unwrap();
+    mock.update_record("stale.example.com", ip("9.9.9.9"), &options).await.unwrap();
+    // Last pushed, without a record any more
+    let known = vec![("mock".to_string(), "home.example.com".to_string(), ip("5.6.7.8"))];
+
+    let configs: Vec<_> = config.providers.iter().collect();
+    let planned = plan::plan(&configs, &providers, &known).await;
+    let changes: Vec<String> = planned.changes.iter().map(|c| c.to_string()).collect();
+    assert_eq!(
+        changes,
+        [
+            "+ A home.example.com 5.6.7.8",
+            "+ A office.example.com 1.2.3.5",
+            "~ A stale.example.com 9.9.9.9 -> 10.0.0.1",
+        ]
+    );
+    assert_eq!(planned.changes[2].action, Action::Update);
+    assert!(planned.errors.is_empty());
+
+    for target in &planned.targets {
+        plan::apply(mock.as_ref(), &config.providers[0], target).await.unwrap();
+    }
+    let planned = plan::plan(&configs, &providers, &known).await;
+    assert!(planned.changes.is_empty(), "{:?}", planned.changes);
+}
+
+#[tokio::test]
+async fn serves_the_plan_without_applying_it() {
+    let app = router(
+        "plan_admin",
+        r#"
+[[auth.tokens]]
+name = "ops"
+token = "ops-token"
+scopes = ["admin"]
+
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+
+[[providers.hosts]]
+name = "office.example.com"
+ips = ["1.2.3.4"]
+"#,
+    );
+    let plan = |uri: &str| Request::post(uri).header("authorization", "Bearer ops-token").body(Body::empty()).unwrap();
+
+    let (status, _) = send(&app, Request::post("/admin/plan").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    // The provider key doesn't open admin routes
+    let (status, _) = send(&app, Request::post("/admin/plan?key=secret").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, plan("/admin/plan?provider=other")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, body) = send(&app, plan("/admin/plan")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["changes"][0]["action"], "create");
+    assert_eq!(body["changes"][0]["type"], "A");
+    assert_eq!(body["changes"][0]["host"], "office.example.com");
+    assert_eq!(body["changes"][0]["new"], "1.2.3.4");
+    // Planning again finds the same change, as nothing was applied
+    let (_, again) = send(&app, plan("/admin/plan")).await;
+    assert_eq!(again["changes"], body["changes"]);
+}
+
+#[tokio::test]
+async fn refuses_anonymous_plans() {
+    // Without provider keys updates are open, but the plan still needs an admin token
+    let app = router("plan_anonymous", "[[providers]]\nname = \"mock\"\ntype = \"mock\"\n");
+
+    let (status, body) = send(&app, Request::post("/admin/plan").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "unauthorized");
+}
@@ -0,0 +1,212 @@
+//! `[[poll]]` jobs detecting the public IP and updating hosts.
+
+mod common;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use ddns_rust::config::Config;
+use serde_json::Value;
+use wiremock::matchers::path;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{get, router};
+
+fn config(api_base: &str, quorum: &str) -> String {
+    format!(
+        r#"
+[detect]
+ipv4_sources = ["{0}/plain", "{0}/trace", "{0}/bogus"]
+{1}
+
+[[poll]]
+provider = "mock"
+hosts = ["Home.Example.com"]
+record_type = "A"
+interval_secs = 3600
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#,
+        api_base, quorum
+    )
+}
+
+/// An echo service per path: two agree on 1.2.3.4, one reports something else
+async fn mock_sources(server: &MockServer) {
+    Mock::given(path("/plain"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("1.2.3.4\n"))
+        .mount(server)
+        .await;
+    Mock::given(path("/trace"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("fl=1\nh=1.1.1.1\nip=1.2.3.4\nts=1\n"))
+        .mount(server)
+        .await;
+    Mock::given(path("/bogus"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("10.0.0.1"))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn updates_hosts_with_ip_agreed_by_quorum() {
+    let server = MockServer::start().await;
+    mock_sources(&server).await;
+    let app = router("poll_quorum", &config(&server.uri(), ""));
+
+    // Interval jobs run once right away
+    let mut ip = Value::Null;
+    for _ in 0..100 {
+        let (_, body) = get(&app, "/status", &[]).await;
+        ip = body["providers"][0]["hosts"]["home.example.com"]["ip"].clone();
+        if !ip.is_null() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(ip, "1.2.3.4");
+}
+
+#[tokio::test]
+async fn skips_update_without_quorum() {
+    let server = MockServer::start().await;
+    mock_sources(&server).await;
+    let app = router("poll_no_quorum", &config(&server.uri(), "quorum = 3"));
+
+    for _ in 0..100 {
+        if server.received_requests().await.unwrap().len() == 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let (_, body) = get(&app, "/status", &[]).await;
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    assert!(body["providers"][0]["hosts"]["home.example.com"].is_null(), "{}", body);
+}
+
+#[tokio::test]
+async fn combines_delegated_prefix_with_host_suffixes() {
+    let server = MockServer::start().await;
+    Mock::given(path("/v6"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("2001:db8:1:2::99"))
+        .mount(&server)
+        .await;
+
+    let app = router(
+        "poll_prefix",
+        &format!(
+            r#"
+[detect]
+ipv6_sources = ["{}/v6"]
+
+[[poll]]
+provider = "mock"
+hosts = ["nas.example.com", "printer.example.com"]
+record_type = "AAAA"
+interval_secs = 3600
+ipv6_prefix_len = 56
+
+[[providers]]
+name = "mock"
+type = "mock"
+
+[[providers.hosts]]
+name = "nas.example.com"
+ipv6_suffix = "::10"
+
+[[providers.hosts]]
+name = "printer.example.com"
+ipv6_suffix = "::2a:0:0:0:20"
+"#,
+            server.uri()
+        ),
+    );
+
+    let mut hosts = Value::Null;
+    for _ in 0..100 {
+        let (_, body) = get(&app, "/status", &[]).await;
+        hosts = body["providers"][0]["hosts"].clone();
+        if !hosts["printer.example.com"]["ip"].is_null() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(hosts["nas.example.com"]["ip"], "2001:db8:1::10");
+    assert_eq!(hosts["printer.example.com"]["ip"], "2001:db8:1:2a::20");
+}
+
+#[tokio::test]
+async fn pings_health_check_after_each_run() {
+    let server = MockServer::start().await;
+    mock_sources(&server).await;
+    Mock::given(path("/ping/ok"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&server)
+        .await;
+    Mock::given(path("/ping/fail"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&server)
+        .await;
+    let with_pings = |config: String| {
+        config.replace(
+            "interval_secs = 3600\n",
+            &format!("interval_secs = 3600\nping_url = \"{0}/ping/ok\"\nping_failure_url = \"{0}/ping/fail\"\n", server.uri()),
+        )
+    };
+    let _ok = router("poll_ping_ok", &with_pings(config(&server.uri(), "")));
+    // Without a quorum detection fails
+    let _failing = router("poll_ping_fail", &with_pings(config(&server.uri(), "quorum = 3")));
+
+    let mut paths = Vec::new();
+    for _ in 0..100 {
+        paths = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|r| r.url.path().to_string())
+            .filter(|p| p.starts_with("/ping/"))
+            .collect::<Vec<_>>();
+        if paths.len() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    paths.sort();
+    assert_eq!(paths, ["/ping/fail", "/ping/ok"]);
+}
+
+#[test]
+fn rejects_invalid_jobs() {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("poll_invalid.toml");
+    std::fs::write(
+        &path,
+        r#"
+[[poll]]
+provider = "missing"
+hosts = []
+interval_secs = 60
+cron = "not cron"
+
+[[poll]]
+provider = "mock"
+hosts = ["home.example.com"]
+interval_secs = 60
+ipv6_prefix_len = 56
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#,
+    )
+    .unwrap();
+
+    let error = format!("{:#}", Config::load(&path, None).unwrap_err());
+    assert!(error.contains("poll[0].provider: unknown provider \"missing\""), "{}", error);
+    assert!(error.contains("poll[0].hosts: at least one host is required"), "{}", error);
+    assert!(error.contains("poll[0]: exactly one of interval_secs or cron is required"), "{}", error);
+    assert!(error.contains("poll[1].hosts[0]: \"home.example.com\" needs an ipv6_suffix"), "{}", error);
+}
@@ -0,0 +1,66 @@
+//! `[propagation]` checks against a DNS-over-HTTPS resolver.
+
+#![cfg(feature = "propagation")]
+
+mod common;
+
+use std::net::Ipv4Addr;
+
+use axum::http::StatusCode;
+use hickory_resolver::proto::op::{Message, MessageType};
+use hickory_resolver::proto::rr::rdata::A;
+use hickory_resolver::proto::rr::{RData, Record};
+use hickory_resolver::proto::serialize::binary::BinEncodable;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+use common::{get, router};
+
+/// Answers every query with 1.2.3.4.
+fn answer(request: &Request) -> ResponseTemplate {
+    let query = Message::from_vec(&request.body).unwrap();
+    let mut response = query.clone();
+    response.set_message_type(MessageType::Response);
+    let name = query.queries()[0].name().clone();
+    response.add_answer(Record::from_rdata(name, 60, RData::A(A(Ipv4Addr::new(1, 2, 3, 4)))));
+
+    ResponseTemplate::new(200)
+        .insert_header("content-type", "application/dns-message")
+        .set_body_bytes(response.to_bytes().unwrap())
+}
+
+#[tokio::test]
+async fn verifies_records_over_https() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/dns-query"))
+        .and(header("content-type", "application/dns-message"))
+        .respond_with(answer)
+        .mount(&server)
+        .await;
+
+    let app = router(
+        "propagation_doh",
+        &format!(
+            r#"
+[propagation]
+enabled = true
+resolvers = ["{}/dns-query"]
+window_secs = 1
+interval_secs = 1
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#,
+            server.uri()
+        ),
+    );
+
+    let (status, body) = get(&app, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["verified"], true);
+
+    let (_, body) = get(&app, "/ddns/mock/home.example.com/5.6.7.8", &[]).await;
+    assert_eq!(body["verified"], false);
+}
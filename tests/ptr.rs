@@ -0,0 +1,93 @@
+//! `update_ptr`: reverse DNS follows the host's address.
+
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{cf_page, cf_record, cf_result, get, router};
+
+#[tokio::test]
+async fn points_ptr_records_at_updated_hosts() {
+    let app = router(
+        "ptr_mock",
+        r#"
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+
+[[providers.hosts]]
+name = "home.example.com"
+update_ptr = true
+"#,
+    );
+
+    get(&app, "/ddns/mock/home.example.com/1.2.3.4?key=secret", &[]).await;
+    get(&app, "/ddns/mock/home.example.com/2001:db8::1?key=secret", &[]).await;
+    // Only configured hosts get a PTR record
+    get(&app, "/ddns/mock/other.example.com/1.2.3.5?key=secret", &[]).await;
+
+    let (_, body) = get(&app, "/dns/mock/records?key=secret", &[]).await;
+    let ptr: Vec<(&str, &str)> = body["records"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|r| r["type"] == "PTR")
+        .map(|r| (r["name"].as_str().unwrap(), r["content"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        ptr,
+        [
+            ("4.3.2.1.in-addr.arpa", "home.example.com"),
+            ("1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa", "home.example.com"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn writes_ptr_records_to_the_reverse_zone() {
+    let server = MockServer::start().await;
+    for zone_id in ["z1", "rev1"] {
+        Mock::given(method("GET"))
+            .and(path(format!("/zones/{}/dns_records", zone_id)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(json!([]), 1, 1)))
+            .mount(&server)
+            .await;
+    }
+    Mock::given(method("POST"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("a1", "A", "home.example.com", "192.0.2.7"))))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/zones/rev1/dns_records"))
+        .and(body_partial_json(json!({ "type": "PTR", "name": "7.2.0.192.in-addr.arpa", "content": "home.example.com" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("p1", "PTR", "7.2.0.192.in-addr.arpa", "home.example.com"))))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let app = router(
+        "ptr_cloudflare",
+        &format!(
+            r#"
+[[providers]]
+name = "cf"
+type = "cloudflare"
+api_key = "test-token"
+api_base = "{}"
+zone_id = "z1"
+reverse_zone_id = "rev1"
+update_ptr = true
+"#,
+            server.uri()
+        ),
+    );
+
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/192.0.2.7", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
@@ -0,0 +1,83 @@
+//! Concurrent updates to one host, using the in-memory mock provider.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use axum::http::StatusCode;
+
+use common::{get, router};
+
+const CONFIG: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+latency_ms = 100
+"#;
+
+#[tokio::test]
+async fn coalesces_identical_concurrent_updates() {
+    let app = router("queue_coalesce", CONFIG);
+
+    let uri = "/ddns/mock/home.example.com/1.2.3.4";
+    let (a, b, c) = tokio::join!(get(&app, uri, &[]), get(&app, uri, &[]), get(&app, uri, &[]));
+    for (status, body) in [a, b, c] {
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["record_id"], "mock-1");
+    }
+
+    let (_, debug) = get(&app, "/debug/mock", &[]).await;
+    assert_eq!(debug["updates"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn serializes_different_updates_to_one_record() {
+    let app = router("queue_serialize", CONFIG);
+
+    let (a, b) = tokio::join!(
+        get(&app, "/ddns/mock/home.example.com/1.2.3.4", &[]),
+        get(&app, "/ddns/mock/home.example.com/5.6.7.8", &[]),
+    );
+    assert_eq!(a.0, StatusCode::OK);
+    assert_eq!(b.0, StatusCode::OK);
+
+    // Different IPs are not coalesced; the second update finds the first one's record
+    let (_, debug) = get(&app, "/debug/mock", &[]).await;
+    assert_eq!(debug["updates"].as_array().unwrap().len(), 2);
+    assert_eq!(debug["records"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn limits_concurrent_provider_calls() {
+    let app = router(
+        "queue_concurrency",
+        r#"
+[server]
+max_concurrent_updates = 2
+
+[[providers]]
+name = "mock"
+type = "mock"
+latency_ms = 100
+max_concurrent_updates = 1
+
+[[providers]]
+name = "other"
+type = "mock"
+latency_ms = 100
+"#,
+    );
+
+    // One at a time on the limited provider, while the other provider shares the global limit
+    let started = Instant::now();
+    let (a, b, c, d) = tokio::join!(
+        get(&app, "/ddns/mock/a.example.com/1.2.3.4", &[]),
+        get(&app, "/ddns/mock/b.example.com/1.2.3.4", &[]),
+        get(&app, "/ddns/mock/c.example.com/1.2.3.4", &[]),
+        get(&app, "/ddns/other/d.example.com/1.2.3.4", &[]),
+    );
+    for (status, _) in [a, b, c, d] {
+        assert_eq!(status, StatusCode::OK);
+    }
+    assert!(started.elapsed() >= Duration::from_millis(300), "{:?}", started.elapsed());
+}
@@ -0,0 +1,45 @@
+//! `rate_limit_per_ip` / `rate_limit_per_key`.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+
+use common::{from_peer, router, send};
+
+const CONFIG: &str = r#"
+[server]
+rate_limit_per_key = 2
+
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+"#;
+
+fn update_from(peer: &str, key: &str) -> Request<Body> {
+    let request = Request::get(format!("/ddns/mock/home.example.com/1.2.3.4?key={}", key));
+    from_peer(request.body(Body::empty()).unwrap(), peer)
+}
+
+#[tokio::test]
+async fn made_up_keys_share_the_clients_bucket() {
+    let app = router("rate_limit_keys", CONFIG);
+
+    // Every wrong key would otherwise get a bucket of its own
+    for key in ["guess1", "guess2"] {
+        let (status, _) = send(&app, update_from("198.51.100.9", key)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    let (status, body) = send(&app, update_from("198.51.100.9", "guess3")).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["code"], "rate_limited");
+
+    // The valid key is counted on its own, wherever it is used from
+    let (status, body) = send(&app, update_from("198.51.100.9", "secret")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _) = send(&app, update_from("203.0.113.5", "secret")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, update_from("203.0.113.5", "secret")).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
@@ -0,0 +1,96 @@
+//! Background reconciliation of records changed outside the service.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{cf_page, cf_record, cf_result, get, router};
+
+#[tokio::test]
+async fn restores_record_changed_outside_the_service() {
+    let server = MockServer::start().await;
+    // Lookups by name before each write, and the periodic listing, both see the changed record
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .and(query_param("type", "A"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(
+            json!([cf_record("r1", "A", "home.example.com", "9.9.9.9")]),
+            1,
+            1,
+        )))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .and(query_param_is_missing("name"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(json!([]), 1, 1)))
+        .with_priority(10)
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/zones/z1/dns_records/r1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("r1", "A", "home.example.com", "1.2.3.4"))))
+        .expect(2..)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/bot123:abc/sendMessage"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true, "result": {} })))
+        .mount(&server)
+        .await;
+
+    let app = router(
+        "reconcile_drift",
+        &format!(
+            r#"
+[reconcile]
+enabled = true
+interval_secs = 1
+
+[notifications.telegram]
+bot_token = "123:abc"
+chat_id = "42"
+api_base = "{0}"
+
+[[providers]]
+name = "cf"
+type = "cloudflare"
+api_key = "test-token"
+api_base = "{0}"
+zone_id = "z1"
+"#,
+            server.uri()
+        ),
+    );
+
+    let (status, _) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The update's change message, then the drift message after the first pass
+    let mut messages = Vec::new();
+    for _ in 0..300 {
+        messages = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| r.url.path().ends_with("/sendMessage"))
+            .map(|r| r.body_json::<Value>().unwrap()["text"].as_str().unwrap().to_string())
+            .collect();
+        if messages.len() >= 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert!(messages.len() >= 2, "{:?}", messages);
+    assert_eq!(
+        messages[1],
+        "DNS record home.example.com (cf) was changed to 9.9.9.9 outside ddns-rust and has been restored to 1.2.3.4"
+    );
+}
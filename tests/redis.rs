@@ -0,0 +1,193 @@
+//! `[redis]` state shared by two instances, against a minimal in-memory
+//! server speaking the commands ddns-rust sends.
+
+#![cfg(feature = "redis")]
+
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tower::ServiceExt;
+
+use common::{get, router};
+
+type Store = Arc<Mutex<HashMap<String, String>>>;
+
+/// Serves GET, SET (with NX), SETEX, DEL, INCRBY, EXPIRE and the lease and
+/// lock release scripts; expiry is ignored. Returns the address.
+async fn fake_redis() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let store = Store::default();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(serve(stream, store.clone()));
+        }
+    });
+    address
+}
+
+async fn serve(stream: TcpStream, store: Store) {
+    let (read, mut write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+    while let Some(args) = read_command(&mut reader).await {
+        let reply = execute(&store, &args);
+        if write.write_all(reply.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn read_command(reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>) -> Option<Vec<String>> {
+    let mut line = String::new();
+    reader.read_line(&mut line).await.ok()?;
+    let count: usize = line.trim().strip_prefix('*')?.parse().ok()?;
+    let mut args = Vec::new();
+    for _ in 0..count {
+        line.clear();
+        reader.read_line(&mut line).await.ok()?;
+        line.clear();
+        reader.read_line(&mut line).await.ok()?;
+        args.push(line.trim_end_matches("\r\n").to_string());
+    }
+    Some(args)
+}
+
+fn execute(store: &Store, args: &[String]) -> String {
+    let mut store = store.lock().unwrap();
+    let bulk = |value: Option<&String>| match value {
+        Some(value) => format!("${}\r\n{}\r\n", value.len(), value),
+        None => "$-1\r\n".to_string(),
+    };
+    match args[0].to_ascii_uppercase().as_str() {
+        "GET" => bulk(store.get(&args[1])),
+        "SET" if args.iter().any(|a| a.eq_ignore_ascii_case("NX")) && store.contains_key(&args[1]) => "$-1\r\n".to_string(),
+        "SET" => {
+            store.insert(args[1].clone(), args[2].clone());
+            "+OK\r\n".to_string()
+        }
+        "SETEX" => {
+            store.insert(args[1].clone(), args[3].clone());
+            "+OK\r\n".to_string()
+        }
+        "DEL" => format!(":{}\r\n", u8::from(store.remove(&args[1]).is_some())),
+        "INCRBY" => {
+            let value = store.get(&args[1]).map_or(0, |v| v.parse::<i64>().unwrap()) + args[2].parse::<i64>().unwrap();
+            store.insert(args[1].clone(), value.to_string());
+            format!(":{}\r\n", value)
+        }
+        "EXPIRE" => ":1\r\n".to_string(),
+        // The leader lease script
+        "EVAL" if args[1].contains("PEXPIRE") => {
+            let held = match store.get(&args[3]) {
+                None => {
+                    store.insert(args[3].clone(), args[4].clone());
+                    true
+                }
+                Some(holder) => holder == &args[4],
+            };
+            format!(":{}\r\n", u8::from(held))
+        }
+        // The lock release script
+        "EVAL" => {
+            let held = store.get(&args[3]) == Some(&args[4]);
+            if held {
+                store.remove(&args[3]);
+            }
+            format!(":{}\r\n", u8::from(held))
+        }
+        _ => "-ERR unknown command\r\n".to_string(),
+    }
+}
+
+fn config(redis: &str) -> String {
+    format!(
+        r#"
+[server]
+rate_limit_per_key = 3
+
+[redis]
+url = "redis://{}"
+
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+"#,
+        redis
+    )
+}
+
+#[tokio::test]
+async fn instances_share_state_and_rate_limits() {
+    // Counters are per minute, keep the test within one
+    let second_of_minute = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() % 60;
+    if second_of_minute >= 58 {
+        tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    }
+
+    let redis = fake_redis().await;
+    let first = router("redis_first", &config(&redis));
+    let second = router("redis_second", &config(&redis));
+
+    let (status, body) = get(&first, "/ddns/mock/home.example.com/1.2.3.4?key=secret", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(body["message"].as_str().unwrap().starts_with("Created"), "{}", body);
+
+    // The second instance knows the record is up to date without asking its provider
+    let (status, body) = get(&second, "/ddns/mock/home.example.com/1.2.3.4?key=secret", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["message"], "Record already up to date with IP 1.2.3.4");
+    let (_, body) = get(&second, "/debug/mock?key=secret", &[]).await;
+    assert_eq!(body["updates"], serde_json::json!([]), "{}", body);
+
+    // That was the key's third request across both instances
+    let (status, _) = get(&first, "/ddns/mock/home.example.com/1.2.3.4?key=secret", &[]).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn concurrent_updates_on_two_instances_reach_one_provider() {
+    let redis = fake_redis().await;
+    let config = config(&redis)
+        .replace("rate_limit_per_key = 3", "rate_limit_per_key = 0")
+        .replace("key = \"secret\"", "key = \"secret\"\nlatency_ms = 300");
+    let first = router("redis_lock_first", &config);
+    let second = router("redis_lock_second", &config);
+
+    let uri = "/ddns/mock/home.example.com/1.2.3.4?key=secret";
+    let ((first_status, _), (second_status, _)) = tokio::join!(get(&first, uri, &[]), get(&second, uri, &[]));
+    assert_eq!((first_status, second_status), (StatusCode::OK, StatusCode::OK));
+
+    let mut updates = 0;
+    for app in [&first, &second] {
+        let (_, body) = get(app, "/debug/mock?key=secret", &[]).await;
+        updates += body["updates"].as_array().unwrap().len();
+    }
+    assert_eq!(updates, 1);
+}
+
+#[tokio::test]
+async fn one_instance_holds_the_leader_lease() {
+    let redis = fake_redis().await;
+    let config = config(&redis).replace("[[providers]]", "[leader]\nelection = \"redis\"\n\n[[providers]]");
+    let first = router("redis_leader_first", &config);
+    let second = router("redis_leader_second", &config);
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let mut leaders = Vec::new();
+    for app in [&first, &second] {
+        let response = app.clone().oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        leaders.push(body.lines().find(|l| l.starts_with("ddns_leader ")).unwrap().to_string());
+    }
+    leaders.sort();
+    assert_eq!(leaders, ["ddns_leader 0", "ddns_leader 1"]);
+}
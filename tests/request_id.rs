@@ -0,0 +1,43 @@
+//! Request IDs attached to every response.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::Request;
+use tower::ServiceExt;
+
+use common::router;
+
+const CONFIG: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+"#;
+
+async fn request_id(id: Option<&str>) -> String {
+    let app = router("request_id", CONFIG);
+    let mut request = Request::get("/ddns/mock/home.example.com/1.2.3.4");
+    if let Some(id) = id {
+        request = request.header("x-request-id", id);
+    }
+
+    let response = app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+    response.headers()["x-request-id"].to_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn generates_request_id() {
+    let first = request_id(None).await;
+    let second = request_id(None).await;
+    assert_eq!(first.len(), 36);
+    assert_ne!(first, second);
+}
+
+#[tokio::test]
+async fn keeps_valid_incoming_request_id() {
+    assert_eq!(request_id(Some("edge-7f3a")).await, "edge-7f3a");
+
+    let replaced = request_id(Some("has spaces")).await;
+    assert_ne!(replaced, "has spaces");
+    assert_eq!(replaced.len(), 36);
+}
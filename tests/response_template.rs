@@ -0,0 +1,53 @@
+//! `response` templates replacing the JSON body of `/ddns` updates.
+
+mod common;
+
+use axum::http::StatusCode;
+
+use common::{get_text, router};
+
+const CONFIG: &str = r#"
+[[auth.tokens]]
+name = "camera"
+token = "camera-token"
+
+[auth.tokens.response]
+success = "ok"
+
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+
+[providers.response]
+success = "good {ip} {host}"
+failure = "911 {status} {message}"
+"#;
+
+#[tokio::test]
+async fn renders_provider_and_token_templates() {
+    let app = router("response_template", CONFIG);
+
+    let (status, headers, body) = get_text(&app, "/ddns/mock/cam.example.com/1.2.3.4?key=secret", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["content-type"], "text/plain; charset=utf-8");
+    assert_eq!(body, "good 1.2.3.4 cam.example.com");
+
+    let (status, _, body) = get_text(&app, "/ddns/mock/cam.example.com/1.2.3.4?key=wrong", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body, "911 401 Invalid key");
+
+    // The `/ddns/{provider}/{host}` form with its query addresses
+    let (_, _, body) = get_text(&app, "/api/v1/ddns/mock/cam.example.com?ipv4=5.6.7.8&key=secret", &[]).await;
+    assert_eq!(body, "good 5.6.7.8 cam.example.com");
+
+    // The authorizing token's template wins
+    let (_, _, body) = get_text(&app, "/ddns/mock/cam.example.com/1.2.3.4", &[("authorization", "Bearer camera-token")]).await;
+    assert_eq!(body, "ok");
+
+    // An explicit request for plain text, and other routes, are left alone
+    let (_, _, body) = get_text(&app, "/ddns/mock/cam.example.com/1.2.3.4?key=secret&format=plain", &[]).await;
+    assert_eq!(body, "OK 1.2.3.4");
+    let (_, headers, _) = get_text(&app, "/dns/mock/records?key=secret", &[]).await;
+    assert!(headers["content-type"].to_str().unwrap().starts_with("application/json"));
+}
@@ -0,0 +1,84 @@
+//! `[retry_queue]`: updates failing while the provider is down are retried.
+
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use serde_json::json;
+use tower::ServiceExt;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{cf_page, cf_record, cf_result, get, router};
+
+async fn metrics(app: &Router) -> String {
+    let response = app.clone().oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn retries_updates_failed_during_an_outage() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(503).set_body_string("down for maintenance"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_page(json!([]), 1, 1)))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/zones/z1/dns_records"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(cf_result(cf_record("r1", "A", "home.example.com", "1.2.3.4"))))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("retry_queue.db");
+    let _ = std::fs::remove_file(&db);
+    let app = router(
+        "retry_queue",
+        &format!(
+            r#"
+[retry_queue]
+path = "{}"
+initial_delay_secs = 1
+
+[[providers]]
+name = "cf"
+type = "cloudflare"
+api_key = "test-token"
+api_base = "{}"
+zone_id = "z1"
+"#,
+            db.display(),
+            server.uri()
+        ),
+    );
+
+    let (status, body) = get(&app, "/ddns/cf/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+    assert_eq!(body["queued"], true);
+    assert!(metrics(&app).await.contains("ddns_retry_queue_depth{provider=\"cf\"} 1"));
+
+    let mut text = String::new();
+    for _ in 0..100 {
+        text = metrics(&app).await;
+        if !text.contains("ddns_retry_queue_depth{") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(!text.contains("ddns_retry_queue_depth{"), "{}", text);
+    let (_, body) = get(&app, "/status", &[]).await;
+    assert_eq!(body["providers"][0]["hosts"]["home.example.com"]["ip"], "1.2.3.4");
+}
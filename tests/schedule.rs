@@ -0,0 +1,32 @@
+//! Cron expressions of `[[poll]]` jobs.
+
+use ddns_rust::schedule::Cron;
+use time::macros::datetime;
+
+#[test]
+fn finds_next_matching_minute() {
+    let cron: Cron = "*/15 * * * *".parse().unwrap();
+    assert_eq!(cron.next_after(datetime!(2024-01-01 10:07:30 UTC)), Some(datetime!(2024-01-01 10:15 UTC)));
+    assert_eq!(cron.next_after(datetime!(2024-01-01 23:45 UTC)), Some(datetime!(2024-01-02 00:00 UTC)));
+
+    // Weekdays at 9:30, Sunday given as 7
+    let cron: Cron = "30 9 * * 1-5,7".parse().unwrap();
+    assert_eq!(cron.next_after(datetime!(2024-01-05 10:00 UTC)), Some(datetime!(2024-01-07 09:30 UTC)));
+
+    // Day of month or day of week when both are restricted
+    let cron: Cron = "0 0 15 * 1".parse().unwrap();
+    assert_eq!(cron.next_after(datetime!(2024-01-09 00:00 UTC)), Some(datetime!(2024-01-15 00:00 UTC)));
+    assert_eq!(cron.next_after(datetime!(2024-01-15 00:00 UTC)), Some(datetime!(2024-01-22 00:00 UTC)));
+
+    let cron: Cron = "0 0 29 2 *".parse().unwrap();
+    assert_eq!(cron.next_after(datetime!(2025-01-01 00:00 UTC)), Some(datetime!(2028-02-29 00:00 UTC)));
+    let cron: Cron = "0 0 30 2 *".parse().unwrap();
+    assert_eq!(cron.next_after(datetime!(2025-01-01 00:00 UTC)), None);
+}
+
+#[test]
+fn rejects_invalid_expressions() {
+    for expression in ["* * * *", "60 * * * *", "*/0 * * * *", "5-1 * * * *", "a * * * *", "* * 0 * *"] {
+        assert!(expression.parse::<Cron>().is_err(), "{}", expression);
+    }
+}
@@ -0,0 +1,70 @@
+//! `[scripting]` policy functions.
+
+#![cfg(feature = "scripting")]
+
+mod common;
+
+use axum::http::StatusCode;
+
+use common::{get, router};
+
+const SCRIPT: &str = r#"
+fn transform_host(host, provider) {
+    let name = host;
+    if name.ends_with(".lan") {
+        name.replace(".lan", ".example.com");
+    }
+    name
+}
+
+fn choose_provider(host, provider) {
+    if host.starts_with("v6.") { "six" } else { provider }
+}
+
+fn validate(request) {
+    if request.host.starts_with("blocked.") {
+        return "blocked hosts can't be updated";
+    }
+    !request.ip.starts_with("10.")
+}
+"#;
+
+#[tokio::test]
+async fn routes_and_validates_updates() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(dir.join("scripting_policy.rhai"), SCRIPT).unwrap();
+
+    let app = router(
+        "scripting_policy",
+        r#"
+[scripting]
+path = "scripting_policy.rhai"
+
+[[providers]]
+name = "mock"
+type = "mock"
+allow_private_ips = true
+
+[[providers]]
+name = "six"
+type = "mock"
+"#,
+    );
+
+    let (status, body) = get(&app, "/ddns/mock/home.lan/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (_, records) = get(&app, "/dns/mock/records", &[]).await;
+    assert_eq!(records["records"][0]["name"], "home.example.com");
+
+    let (status, _) = get(&app, "/ddns/mock/v6.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, records) = get(&app, "/dns/six/records", &[]).await;
+    assert_eq!(records["records"][0]["name"], "v6.example.com");
+
+    let (status, body) = get(&app, "/ddns/mock/blocked.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "blocked hosts can't be updated");
+    let (status, body) = get(&app, "/ddns/mock/home.example.com/10.0.0.1", &[]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "refused by policy script");
+}
@@ -0,0 +1,118 @@
+//! HMAC-signed requests (`X-Signature` / `X-Timestamp`).
+
+mod common;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use common::{router, send, send_text};
+
+const CONFIG: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+require_signature = true
+"#;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The signature of a `method` request to `target` with `body`, stamped now.
+fn sign(method: &str, target: &str, body: &str) -> (String, String) {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs().to_string();
+    let message = format!("{}\n{}\n{}\n{}", method, target, hex(&Sha256::digest(body)), timestamp);
+    let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+    mac.update(message.as_bytes());
+    (timestamp, hex(&mac.finalize().into_bytes()))
+}
+
+fn request(method: &str, target: &str, body: &str, (timestamp, signature): &(String, String)) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(target)
+        .header("content-type", "application/json")
+        .header("x-timestamp", timestamp)
+        .header("x-signature", signature)
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn signs_method_path_query_and_body() {
+    let app = router("signature", CONFIG);
+
+    let target = "/ddns/mock/home.example.com?ipv4=1.2.3.4";
+    let signature = sign("GET", target, "");
+    let (status, body) = send(&app, request("GET", target, "", &signature)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, body) = send(&app, request("GET", target, "", &signature)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "Signature already used");
+
+    // The query is part of what was signed
+    let signature = sign("GET", target, "");
+    let (status, _) = send(&app, request("GET", "/ddns/mock/home.example.com?ipv4=6.6.6.6", "", &signature)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let update = r#"{"provider": "mock", "host": "home.example.com", "ip": "5.6.7.8"}"#;
+    let signature = sign("POST", "/api/v1/update", update);
+    let (status, body) = send(&app, request("POST", "/api/v1/update", update, &signature)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["ip"], "5.6.7.8");
+
+    // So is the body, and the method
+    let signature = sign("POST", "/api/v1/update", update);
+    let changed = r#"{"provider": "mock", "host": "other.example.com", "ip": "6.6.6.6"}"#;
+    let (status, body) = send(&app, request("POST", "/api/v1/update", changed, &signature)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "Invalid signature");
+    let signature = sign("POST", target, "");
+    let (status, _) = send(&app, request("GET", target, "", &signature)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn tenant_requests_sign_their_full_path() {
+    let app = router(
+        "signature_tenant",
+        r#"
+[[tenants]]
+name = "alice"
+
+[[tenants.providers]]
+name = "home"
+type = "mock"
+key = "secret"
+"#,
+    );
+
+    let target = "/t/alice/ddns/home/home.example.com/1.2.3.4";
+    let (status, body) = send(&app, request("GET", target, "", &sign("GET", target, ""))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    // The path as the tenant's routes see it is not what the client sent
+    let target = "/t/alice/ddns/home/home.example.com/5.6.7.8";
+    let signature = sign("GET", "/ddns/home/home.example.com/5.6.7.8", "");
+    let (status, _) = send(&app, request("GET", target, "", &signature)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn one_signature_covers_every_host_of_a_request() {
+    let app = router("signature_hosts", CONFIG);
+
+    let target = "/fritzbox/mock?host=a.example.com,b.example.com&ipv4=1.2.3.4";
+    let (status, body) = send(&app, request("GET", target, "", &sign("GET", target, ""))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["results"].as_array().unwrap().len(), 2);
+
+    let target = "/nic/update?username=mock&hostname=a.example.com,b.example.com&myip=5.6.7.8";
+    let (_, _, body) = send_text(&app, request("GET", target, "", &sign("GET", target, ""))).await;
+    assert_eq!(body, "good 5.6.7.8\ngood 5.6.7.8");
+}
@@ -0,0 +1,46 @@
+//! Server and provider `allowed_ips` / `denied_ips`.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+
+use common::{from_peer, router, send};
+
+const CONFIG: &str = r#"
+[server]
+allowed_ips = ["203.0.113.0/24"]
+trusted_proxies = ["10.0.0.0/8"]
+
+[[providers]]
+name = "mock"
+type = "mock"
+denied_ips = ["203.0.113.66"]
+"#;
+
+fn update_from(peer: &str, forwarded_for: Option<&str>) -> Request<Body> {
+    let mut request = Request::get("/ddns/mock/home.example.com/1.2.3.4");
+    if let Some(forwarded_for) = forwarded_for {
+        request = request.header("x-forwarded-for", forwarded_for);
+    }
+    from_peer(request.body(Body::empty()).unwrap(), peer)
+}
+
+#[tokio::test]
+async fn filters_on_the_peer_unless_it_is_a_trusted_proxy() {
+    let app = router("source_ip", CONFIG);
+
+    let (status, body) = send(&app, update_from("203.0.113.5", None)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, body) = send(&app, update_from("203.0.113.66", None)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "forbidden");
+
+    // A forwarded address from anyone else doesn't get past the lists
+    let (status, _) = send(&app, update_from("198.51.100.9", Some("203.0.113.5"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(&app, update_from("10.0.0.1", Some("203.0.113.5"))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, update_from("10.0.0.1", Some("198.51.100.9"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,90 @@
+//! `server.state_file` persistence across router (i.e. process) restarts.
+
+mod common;
+
+use axum::http::StatusCode;
+
+use common::{get, router};
+
+#[tokio::test]
+async fn skips_unchanged_update_after_restart() {
+    let state_path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("state_restart.json");
+    let _ = std::fs::remove_file(&state_path);
+
+    let config = r#"
+[server]
+state_file = "state_restart.json"
+
+[[providers]]
+name = "mock"
+type = "mock"
+"#;
+
+    let first = router("state_restart", config);
+    let (status, _) = get(&first, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(state_path.exists());
+
+    // A new router gets a fresh (empty) mock provider, so any provider call would show up
+    let second = router("state_restart", config);
+    let (status, body) = get(&second, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["record_id"], "mock-1");
+
+    let (_, debug) = get(&second, "/debug/mock", &[]).await;
+    assert!(debug["updates"].as_array().unwrap().is_empty());
+
+    let (_, body) = get(&second, "/status", &[]).await;
+    assert_eq!(body["providers"][0]["hosts"]["home.example.com"]["ip"], "1.2.3.4");
+
+    // A changed IP still goes to the provider
+    get(&second, "/ddns/mock/home.example.com/5.6.7.8", &[]).await;
+    let (_, debug) = get(&second, "/debug/mock", &[]).await;
+    assert_eq!(debug["updates"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn exports_and_imports_hosts_with_their_state() {
+    let tmp = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let _ = std::fs::remove_file(tmp.join("state_export_old.json"));
+    let _ = std::fs::remove_file(tmp.join("state_export_new.json"));
+    let config = |state_file: &str| {
+        format!(
+            r#"
+[server]
+state_file = "{}"
+
+[[providers]]
+name = "mock"
+type = "mock"
+
+[[providers.hosts]]
+name = "nas.example.com"
+"#,
+            state_file
+        )
+    };
+
+    let old = router("state_export_old", &config("state_export_old.json"));
+    get(&old, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+
+    let export = ddns_rust::hosts::export(&common::load_config("state_export_old", &config("state_export_old.json"))).unwrap();
+    let json = serde_json::to_value(&export).unwrap();
+    assert_eq!(json["version"], 1);
+    assert_eq!(json["providers"][0]["hosts"][0]["name"], "home.example.com");
+    assert_eq!(json["providers"][0]["hosts"][0]["configured"], false);
+    assert_eq!(json["providers"][0]["hosts"][0]["state"]["ip"], "1.2.3.4");
+    assert_eq!(json["providers"][0]["hosts"][1]["name"], "nas.example.com");
+    assert!(json["providers"][0]["hosts"][1].get("state").is_none());
+
+    // A fresh install without the host block knows the record after the import
+    let new_config = common::load_config("state_export_new", "[server]\nstate_file = \"state_export_new.json\"\n\n[[providers]]\nname = \"mock\"\ntype = \"mock\"\n");
+    let summary = ddns_rust::hosts::import(&new_config, &serde_json::from_value(json).unwrap()).unwrap();
+    assert_eq!(summary.restored, 1);
+    assert_eq!(summary.warnings, ["mock: host nas.example.com has no [[providers.hosts]] block here"]);
+
+    let new = ddns_rust::api::create_router(new_config).unwrap();
+    get(&new, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+    let (_, debug) = get(&new, "/debug/mock", &[]).await;
+    assert!(debug["updates"].as_array().unwrap().is_empty());
+}
@@ -0,0 +1,81 @@
+//! `/status` reporting, using the in-memory mock provider.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use tower::ServiceExt;
+
+use common::{get, router};
+
+#[tokio::test]
+async fn reports_last_update_and_error_per_host() {
+    let app = router(
+        "status",
+        r#"
+[[providers]]
+name = "mock"
+type = "mock"
+fail_every = 2
+"#,
+    );
+
+    get(&app, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+    let (status, _) = get(&app, "/ddns/mock/home.example.com/5.6.7.8", &[]).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+    let (status, body) = get(&app, "/status", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+
+    let provider = &body["providers"][0];
+    assert_eq!(provider["name"], "mock");
+    assert_eq!(provider["type"], "mock");
+
+    let host = &provider["hosts"]["home.example.com"];
+    assert_eq!(host["ip"], "1.2.3.4");
+    assert!(host["last_success"].is_string());
+    assert_eq!(host["last_error"]["ip"], "5.6.7.8");
+    assert_eq!(provider["last_error"], host["last_error"]);
+    assert_eq!(provider["capabilities"]["record_types"], serde_json::json!(["A", "AAAA", "CNAME", "SRV", "MX", "PTR"]));
+    assert_eq!(provider["capabilities"]["delete"], true);
+}
+
+#[tokio::test]
+async fn exports_freshness_metrics() {
+    let app = router(
+        "status_metrics",
+        r#"
+[[providers]]
+name = "mock"
+type = "mock"
+fail_every = 2
+"#,
+    );
+
+    get(&app, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+    get(&app, "/ddns/mock/home.example.com/5.6.7.8", &[]).await;
+
+    let response = app
+        .clone()
+        .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain; version=0.0.4"));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+
+    let value = |prefix: &str| -> i64 {
+        let line = body.lines().find(|l| l.starts_with(prefix)).unwrap_or_else(|| panic!("{} missing:\n{}", prefix, body));
+        line.rsplit(' ').next().unwrap().parse().unwrap()
+    };
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64;
+    let updated = value(r#"ddns_last_update_timestamp_seconds{provider="mock",host="home.example.com"}"#);
+    assert!((now - updated).abs() < 60);
+    assert!(value(r#"ddns_last_failure_timestamp_seconds{provider="mock",host="home.example.com"}"#) >= updated);
+    assert!(value("ddns_start_time_seconds") <= updated);
+    // The failed update left the record at its last address
+    assert_eq!(value(r#"ddns_record_ip_info{provider="mock",host="home.example.com",ip="1.2.3.4",type="A"}"#), 1);
+    assert!(body.contains("# TYPE ddns_record_ip_info gauge"));
+}
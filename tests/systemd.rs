@@ -0,0 +1,35 @@
+//! sd_notify messages and the watchdog interval.
+
+#![cfg(unix)]
+
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use ddns_rust::systemd;
+
+// One test, as it changes the process environment
+#[test]
+fn notifies_systemd_and_reads_the_watchdog() {
+    for var in ["NOTIFY_SOCKET", "WATCHDOG_USEC", "WATCHDOG_PID"] {
+        std::env::remove_var(var);
+    }
+    assert!(!systemd::notify("READY=1").unwrap());
+
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("notify.sock");
+    let _ = std::fs::remove_file(&path);
+    let socket = UnixDatagram::bind(&path).unwrap();
+    std::env::set_var("NOTIFY_SOCKET", &path);
+
+    assert!(systemd::notify("READY=1").unwrap());
+    let mut buf = [0; 64];
+    let n = socket.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"READY=1");
+
+    assert_eq!(systemd::watchdog_interval(), None);
+    std::env::set_var("WATCHDOG_USEC", "30000000");
+    assert_eq!(systemd::watchdog_interval(), Some(Duration::from_secs(15)));
+    // Meant for another process
+    std::env::set_var("WATCHDOG_PID", "1");
+    assert_eq!(systemd::watchdog_interval(), None);
+}
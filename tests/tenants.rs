@@ -0,0 +1,144 @@
+//! `[[tenants]]` served under `/t/{tenant}/` with isolated providers and tokens.
+
+mod common;
+
+use std::path::PathBuf;
+
+use axum::http::StatusCode;
+use ddns_rust::config::Config;
+
+use common::{get, load_config, router};
+
+const CONFIG: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+key = "admin-key"
+
+[[tenants]]
+name = "alice"
+
+[[tenants.providers]]
+name = "home"
+type = "mock"
+key = "alice-key"
+
+[[tenants.auth.tokens]]
+name = "router"
+token = "alice-token"
+
+[[tenants]]
+name = "bob"
+
+[[tenants.providers]]
+name = "home"
+type = "mock"
+key = "bob-key"
+"#;
+
+#[tokio::test]
+async fn keeps_tenants_apart() {
+    let app = router("tenants", CONFIG);
+
+    let (status, body) = get(&app, "/t/alice/ddns/home/home.example.com/1.2.3.4?key=alice-key", &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _) = get(&app, "/t/bob/api/v1/ddns/home/home.example.com/5.6.7.8?key=bob-key", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Neither tenant's key nor token opens the other tenant or the top level
+    let (status, _) = get(&app, "/t/bob/ddns/home/home.example.com/9.9.9.9?key=alice-key", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let bearer = [("authorization", "Bearer alice-token")];
+    let (status, _) = get(&app, "/t/bob/ddns/home/home.example.com/9.9.9.9", &bearer).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = get(&app, "/t/alice/ddns/home/home.example.com/2.2.2.2", &bearer).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = get(&app, "/ddns/home/home.example.com/9.9.9.9?key=alice-key", &[]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "provider_not_found");
+    let (status, _) = get(&app, "/t/carol/ddns/home/home.example.com/9.9.9.9?key=alice-key", &[]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Each tenant only sees its own history
+    let (_, body) = get(&app, "/t/alice/status", &bearer).await;
+    assert_eq!(body["providers"].as_array().unwrap().len(), 1);
+    assert_eq!(body["providers"][0]["hosts"]["home.example.com"]["ip"], "2.2.2.2");
+    let (_, body) = get(&app, "/t/bob/status?key=bob-key", &[]).await;
+    assert_eq!(body["providers"][0]["hosts"]["home.example.com"]["ip"], "5.6.7.8");
+    let (_, body) = get(&app, "/status", &[]).await;
+    assert_eq!(body["providers"][0]["name"], "mock");
+    assert!(body["providers"][0]["hosts"].as_object().is_none_or(|hosts| hosts.is_empty()), "{}", body);
+}
+
+#[tokio::test]
+async fn keeps_tenant_status_to_its_callers() {
+    let app = router("tenants_status", CONFIG);
+    get(&app, "/t/alice/ddns/home/home.example.com/1.2.3.4?key=alice-key", &[]).await;
+
+    for uri in ["/t/alice/status", "/t/alice/metrics", "/t/alice/status?key=bob-key", "/t/alice/status?key=admin-key"] {
+        let (status, _) = get(&app, uri, &[]).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+    }
+    let (status, _) = get(&app, "/t/bob/status", &[("authorization", "Bearer alice-token")]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = get(&app, "/t/alice/status?key=alice-key", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["providers"][0]["hosts"]["home.example.com"]["ip"], "1.2.3.4");
+}
+
+#[test]
+fn tenants_only_get_their_own_hooks_and_notifications() {
+    let config = load_config(
+        "tenants_hooks",
+        &format!(
+            r#"
+[hooks]
+on_change = "notify-admin"
+
+[notifications.discord]
+webhook_url = "https://example.com/admin"
+{}
+[tenants.notifications.slack]
+webhook_url = "https://example.com/bob"
+"#,
+            CONFIG
+        ),
+    );
+
+    let alice = config.for_tenant(&config.tenants[0]);
+    assert!(alice.hooks.on_change.is_none());
+    assert!(alice.notifications.discord.is_none());
+    let bob = config.for_tenant(&config.tenants[1]);
+    assert!(bob.notifications.discord.is_none());
+    assert_eq!(bob.notifications.slack.unwrap().webhook_url, "https://example.com/bob");
+}
+
+#[test]
+fn rejects_invalid_tenants() {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("tenants_invalid.toml");
+    std::fs::write(
+        &path,
+        r#"
+[[tenants]]
+name = "a/b"
+
+[[tenants.providers]]
+name = "home"
+type = "mock"
+
+[[tenants]]
+name = "bob"
+
+[tenants.hooks]
+timeout_secs = 0
+"#,
+    )
+    .unwrap();
+
+    let error = format!("{:#}", Config::load(&path, None).unwrap_err());
+    assert!(error.contains("tenants[0].name: expected letters, digits, '-' or '_' (got \"a/b\")"), "{}", error);
+    assert!(error.contains("tenants[1].providers: at least one provider is required"), "{}", error);
+    assert!(error.contains("tenants[1].hooks.timeout_secs: must be at least 1"), "{}", error);
+    assert!(!error.contains("- providers:"), "{}", error);
+}
@@ -0,0 +1,73 @@
+//! `[udp]`: signed update datagrams from small devices.
+
+mod common;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::net::UdpSocket;
+
+use common::{get, router};
+
+/// A datagram for `host` signed with `key`, stamped now.
+fn datagram(host: &str, ip: &str, key: &str) -> String {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let message = format!("mock {} {} {}", host, ip, timestamp);
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).unwrap();
+    mac.update(message.as_bytes());
+    let signature: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{} {}\n", message, signature)
+}
+
+async fn send(socket: &UdpSocket, datagram: &str) -> String {
+    socket.send(datagram.as_bytes()).await.unwrap();
+    let mut buffer = [0u8; 512];
+    let len = tokio::time::timeout(Duration::from_secs(5), socket.recv(&mut buffer)).await.unwrap().unwrap();
+    String::from_utf8_lossy(&buffer[..len]).into_owned()
+}
+
+/// Whether `datagram` goes unanswered.
+async fn unanswered(socket: &UdpSocket, datagram: &str) -> bool {
+    socket.send(datagram.as_bytes()).await.unwrap();
+    let mut buffer = [0u8; 512];
+    tokio::time::timeout(Duration::from_millis(300), socket.recv(&mut buffer)).await.is_err()
+}
+
+#[tokio::test]
+async fn applies_signed_datagrams() {
+    // A port free a moment ago
+    let port = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let app = router(
+        "udp_listener",
+        &format!(
+            r#"
+[udp]
+listen = "127.0.0.1:{}"
+
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+allow_private_ips = true
+"#,
+            port
+        ),
+    );
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(("127.0.0.1", port)).await.unwrap();
+
+    // `-` is the datagram's source address
+    let signed = datagram("sensor.example.com", "-", "secret");
+    assert_eq!(send(&socket, &signed).await, "OK 127.0.0.1");
+    let (_, body) = get(&app, "/status", &[]).await;
+    assert_eq!(body["providers"][0]["hosts"]["sensor.example.com"]["ip"], "127.0.0.1");
+
+    // Only signed datagrams are answered, a forged source mustn't get replies
+    assert!(unanswered(&socket, &signed).await);
+    assert!(unanswered(&socket, &datagram("sensor.example.com", "1.2.3.4", "wrong")).await);
+    assert!(unanswered(&socket, "x").await);
+    assert!(unanswered(&socket, "mock bad_host! 1.2.3.4 0 00").await);
+    assert!(unanswered(&socket, "nope sensor.example.com 1.2.3.4 0 00").await);
+    assert_eq!(send(&socket, &datagram("sensor.example.com", "1.2.3.4", "secret")).await, "OK 1.2.3.4");
+}
@@ -0,0 +1,133 @@
+//! `POST /api/v1/update` (and its SRV and MX variants) with a JSON body.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use serde_json::{json, Value};
+
+use common::{from_peer, router, send};
+
+const CONFIG: &str = r#"
+[[auth.tokens]]
+name = "script"
+token = "token-1"
+hosts = ["home.example.com"]
+
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+ttl = 300
+"#;
+
+async fn post(app: &Router, body: &str, token: Option<&str>) -> (StatusCode, Value) {
+    post_to(app, "/api/v1/update", body, token).await
+}
+
+async fn post_to(app: &Router, path: &str, body: &str, token: Option<&str>) -> (StatusCode, Value) {
+    let mut request = Request::post(path).header("content-type", "application/json");
+    if let Some(token) = token {
+        request = request.header("authorization", format!("Bearer {}", token));
+    }
+    send(app, from_peer(request.body(Body::from(body.to_string())).unwrap(), "5.6.7.8")).await
+}
+
+#[tokio::test]
+async fn updates_from_a_json_body() {
+    let app = router("update_json", CONFIG);
+
+    let (status, body) = post(
+        &app,
+        r#"{"provider": "mock", "host": "home.example.com", "ip": "1.2.3.4", "ttl": 120}"#,
+        Some("token-1"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["success"], true);
+    assert_eq!(body["host"], "home.example.com");
+    assert_eq!(body["ip"], "1.2.3.4");
+    assert_eq!(body["record_type"], "A");
+    assert_eq!(body["ttl"], 120);
+    assert_eq!(body["changed"], true);
+
+    // Without an IP the request's source address is used, with the configured TTL
+    let (_, body) = post(&app, r#"{"provider": "mock", "host": "home.example.com"}"#, Some("token-1")).await;
+    assert_eq!(body["ip"], "5.6.7.8");
+    assert_eq!(body["ttl"], 300);
+}
+
+#[tokio::test]
+async fn rejects_bad_requests_and_missing_tokens() {
+    let app = router("update_json_errors", CONFIG);
+
+    let (status, body) = post(&app, r#"{"provider": "mock", "host": "home.example.com"}"#, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "unauthorized");
+
+    let (status, body) = post(&app, r#"{"provider": "mock", "host": "other.example.com"}"#, Some("token-1")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "{}", body);
+
+    let (status, body) = post(&app, r#"{"provider": "mock"}"#, Some("token-1")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_request");
+
+    let (_, body) = post(
+        &app,
+        r#"{"provider": "mock", "host": "home.example.com", "ip": "1.2.3.4", "record_type": "AAAA"}"#,
+        Some("token-1"),
+    )
+    .await;
+    assert_eq!(body, json!({
+        "success": false,
+        "code": "invalid_request",
+        "error": "record_type AAAA does not match IP address 1.2.3.4",
+        "api_version": 1
+    }));
+}
+
+#[tokio::test]
+async fn updates_srv_and_mx_records() {
+    let app = router(
+        "update_json_srv_mx",
+        r#"
+[[auth.tokens]]
+name = "script"
+token = "token-1"
+
+[[providers]]
+name = "mock"
+type = "mock"
+key = "secret"
+"#,
+    );
+
+    let srv = r#"{"provider": "mock", "name": "_minecraft._tcp.example.com", "priority": 10, "weight": 5, "port": 25565, "target": "Home.example.com."}"#;
+    let (status, body) = post_to(&app, "/api/v1/update/srv", srv, Some("token-1")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["message"], "Created new record _minecraft._tcp.example.com with SRV 10 5 25565 home.example.com");
+    let (_, body) = post_to(&app, "/api/v1/update/srv", srv, Some("token-1")).await;
+    assert_eq!(body["message"], "Record already up to date with SRV 10 5 25565 home.example.com");
+
+    let mx = r#"{"provider": "mock", "host": "example.com", "priority": 10, "target": "home.example.com", "ttl": 300}"#;
+    let (status, body) = post_to(&app, "/api/v1/update/mx", mx, Some("token-1")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (_, records) = common::get(&app, "/dns/mock/records?key=secret", &[]).await;
+    let records = records["records"].as_array().unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["type"], "SRV");
+    assert_eq!(records[1]["type"], "MX");
+    assert_eq!(records[1]["content"], "10 home.example.com");
+    assert_eq!(records[1]["ttl"], 300);
+
+    let bad = r#"{"provider": "mock", "host": "example.com", "priority": 10, "target": "not a host"}"#;
+    let (status, body) = post_to(&app, "/api/v1/update/mx", bad, Some("token-1")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_request");
+    // Priorities are 16-bit
+    let bad = r#"{"provider": "mock", "host": "example.com", "priority": 70000, "target": "home.example.com"}"#;
+    let (status, _) = post_to(&app, "/api/v1/update/mx", bad, Some("token-1")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
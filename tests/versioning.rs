@@ -0,0 +1,49 @@
+//! `/api/v1` routes, the unversioned paths and the version in responses.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use tower::ServiceExt;
+
+use common::{get, router};
+
+const CONFIG: &str = r#"
+[[providers]]
+name = "mock"
+type = "mock"
+"#;
+
+#[tokio::test]
+async fn serves_versioned_and_unversioned_paths_alike() {
+    let app = router("versioning", CONFIG);
+
+    let (status, body) = get(&app, "/api/v1/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["success"], true);
+    assert_eq!(body["api_version"], 1);
+
+    let (status, body) = get(&app, "/ddns/mock/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["api_version"], 1);
+
+    let (_, body) = get(&app, "/api/v1/dns/mock/records", &[]).await;
+    assert_eq!(body["records"].as_array().unwrap().len(), 1);
+    assert_eq!(body["api_version"], 1);
+
+    let (status, body) = get(&app, "/api/v1/ddns/missing/home.example.com/1.2.3.4", &[]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "provider_not_found");
+    assert_eq!(body["api_version"], 1);
+}
+
+#[tokio::test]
+async fn plain_text_responses_only_carry_the_header() {
+    let app = router("versioning_plain", CONFIG);
+
+    let request = Request::get("/api/v1/ddns/mock/home.example.com/1.2.3.4?format=plain").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.headers()["x-api-version"], "1");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"OK 1.2.3.4");
+}